@@ -0,0 +1,60 @@
+/* C API for the mass_raytrace library, built with `cargo build --release --features ffi`. */
+#ifndef MASS_RAYTRACE_H
+#define MASS_RAYTRACE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MR_OK 0
+#define MR_ERROR_NULL -1
+#define MR_ERROR_INVALID -2
+#define MR_ERROR_NO_CAMERA -3
+
+#define MR_MATERIAL_LAMBERTIAN 0
+#define MR_MATERIAL_METAL 1
+#define MR_MATERIAL_DIELECTRIC 2
+#define MR_MATERIAL_LIGHT 3
+
+typedef struct MrScene MrScene;
+
+/* fuzz is only read by metals and refraction_index only by dielectrics, lights emit color. */
+typedef struct MrMaterial {
+    uint32_t kind;
+    float color[3];
+    float fuzz;
+    float refraction_index;
+} MrMaterial;
+
+/* Creates an empty scene lit by a sky gradient. */
+MrScene *mr_scene_new(void);
+void mr_scene_free(MrScene *scene);
+
+/* Replaces the sky with a solid background color. */
+int mr_scene_set_background(MrScene *scene, const float color[3]);
+
+int mr_scene_add_sphere(MrScene *scene, const float center[3], float radius,
+                        const MrMaterial *material);
+
+/* positions holds vertex_count xyz triples, indices holds triangle_count index triples. */
+int mr_scene_add_mesh(MrScene *scene, const float *positions, size_t vertex_count,
+                      const uint32_t *indices, size_t triangle_count,
+                      const MrMaterial *material);
+
+/* vertical_fov is in degrees, the aspect ratio comes from the render dimensions. */
+int mr_scene_set_camera(MrScene *scene, const float look_from[3], const float look_at[3],
+                        const float view_up[3], float vertical_fov, float aperture,
+                        float focus_distance);
+
+/* Writes width * height * 3 bytes of gamma corrected RGB8 to rgb, top row first. */
+int mr_scene_render(const MrScene *scene, uint32_t width, uint32_t height, uint32_t samples,
+                    uint32_t max_depth, uint8_t *rgb);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
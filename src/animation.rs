@@ -0,0 +1,163 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    /// Holds the key's value until the next key.
+    Step,
+    Linear,
+    /// Eases in and out of the key with a smoothstep.
+    Smooth,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Key {
+    t: f32,
+    value: f32,
+    interpolation: Interpolation,
+}
+
+/// A value keyed over `animation_t`. Before the first key and after the last the curve holds
+/// their values, and each key's interpolation applies up to the following key.
+#[derive(Debug, Clone, Default)]
+pub struct Curve {
+    keys: Vec<Key>,
+}
+
+impl Curve {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn constant(value: f32) -> Self {
+        Self::new().key(0.0, value)
+    }
+
+    pub fn key(self, t: f32, value: f32) -> Self {
+        self.key_with(t, value, Interpolation::Linear)
+    }
+
+    pub fn key_with(mut self, t: f32, value: f32, interpolation: Interpolation) -> Self {
+        let index = self.keys.partition_point(|k| k.t <= t);
+        self.keys.insert(
+            index,
+            Key {
+                t,
+                value,
+                interpolation,
+            },
+        );
+        self
+    }
+
+    /// Swings from `low` up to `high` and back `count` times across `0.0..1.0`.
+    pub fn pulse(low: f32, high: f32, count: u32) -> Self {
+        let mut curve = Self::new();
+        let count = count.max(1);
+        for i in 0..=count * 2 {
+            let value = if i % 2 == 0 { low } else { high };
+            curve = curve.key_with(i as f32 / (count * 2) as f32, value, Interpolation::Smooth);
+        }
+        curve
+    }
+
+    pub fn sample(&self, t: f32) -> f32 {
+        let next = self.keys.partition_point(|k| k.t <= t);
+        match (
+            next.checked_sub(1).map(|i| self.keys[i]),
+            self.keys.get(next),
+        ) {
+            (None, None) => 0.0,
+            (Some(key), None) => key.value,
+            (None, Some(key)) => key.value,
+            (Some(from), Some(to)) => {
+                let span = (to.t - from.t).max(f32::EPSILON);
+                let x = ((t - from.t) / span).min(1.0).max(0.0);
+                let x = match from.interpolation {
+                    Interpolation::Step => 0.0,
+                    Interpolation::Linear => x,
+                    Interpolation::Smooth => x * x * (3.0 - 2.0 * x),
+                };
+                from.value + (to.value - from.value) * x
+            }
+        }
+    }
+}
+
+/// A float shared between a scene and the materials reading it, so materials can be built
+/// once and have their parameters changed between frames.
+#[derive(Debug, Clone)]
+pub struct Param(Arc<AtomicU32>);
+
+impl Param {
+    pub fn new(value: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(value.to_bits())))
+    }
+
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+/// A material parameter that is either fixed or driven through a `Param`.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Constant(f32),
+    Param(Param),
+}
+
+impl Value {
+    pub fn get(&self) -> f32 {
+        match self {
+            Value::Constant(value) => *value,
+            Value::Param(param) => param.get(),
+        }
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Constant(value)
+    }
+}
+
+impl From<Param> for Value {
+    fn from(param: Param) -> Self {
+        Value::Param(param)
+    }
+}
+
+impl From<&Param> for Value {
+    fn from(param: &Param) -> Self {
+        Value::Param(param.clone())
+    }
+}
+
+/// Drives a set of `Param`s from their curves, call `update` once per frame before rendering.
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    tracks: Vec<(Param, Curve)>,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a new `Param` that follows `curve`.
+    pub fn bind(&mut self, curve: Curve) -> Param {
+        let param = Param::new(curve.sample(0.0));
+        self.tracks.push((param.clone(), curve));
+        param
+    }
+
+    pub fn update(&self, animation_t: f32) {
+        for (param, curve) in self.tracks.iter() {
+            param.set(curve.sample(animation_t));
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use mass_raytrace::geom::{BoundingBox, Hit, Intersect};
+use mass_raytrace::material::{Background, Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use mass_raytrace::math::{V3, V4};
+use mass_raytrace::texture::SolidColor;
+use mass_raytrace::world::{Camera, Ray, World};
+
+const HELP: &str = "Commands:
+  move <object> <x> <y> <z>
+  set-material <object> lambertian <r> <g> <b>
+  set-material <object> metal <r> <g> <b> [fuzz]
+  set-material <object> glass [refraction index]
+  set-material <object> light <r> <g> <b>
+  camera fov <degrees>
+  camera aperture <size>
+  camera move <x> <y> <z>
+  reset
+Objects are world indices, given alone or after a name such as obj_12 or sphere_3.";
+
+/// Scene edits typed into stdin while rendering, kept separate from the scene so they can be
+/// applied again to each newly generated world.
+pub struct Console {
+    edits: Arc<Mutex<Edits>>,
+}
+
+#[derive(Default)]
+struct Edits {
+    objects: BTreeMap<usize, ObjectEdit>,
+    camera: CameraEdit,
+}
+
+#[derive(Clone)]
+struct ObjectEdit {
+    offset: V3,
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Default for ObjectEdit {
+    fn default() -> Self {
+        Self {
+            offset: V3::zero(),
+            material: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct CameraEdit {
+    vertical_fov: Option<f32>,
+    aperture: Option<f32>,
+    offset: V3,
+}
+
+impl Default for CameraEdit {
+    fn default() -> Self {
+        Self {
+            vertical_fov: None,
+            aperture: None,
+            offset: V3::zero(),
+        }
+    }
+}
+
+impl Console {
+    /// Reads commands from stdin on a new thread, setting `edited` after each one that changes
+    /// the scene.
+    pub fn spawn(edited: &'static AtomicBool) -> Self {
+        let edits = Arc::new(Mutex::new(Edits::default()));
+
+        {
+            let edits = edits.clone();
+            std::thread::spawn(move || {
+                println!("{}", HELP);
+                let stdin = std::io::stdin();
+                for line in stdin.lock().lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    match edits.lock().unwrap().run(&line) {
+                        Ok(()) => edited.store(true, Ordering::Relaxed),
+                        Err(error) => println!("{}\n{}", error, HELP),
+                    }
+                }
+            });
+        }
+
+        Self { edits }
+    }
+
+    /// Applies every object edit so far to `world`.
+    pub fn apply_world<B: Background>(&self, world: &mut World<B>) {
+        let edits = self.edits.lock().unwrap();
+        for (&index, edit) in edits.objects.iter() {
+            let edit = edit.clone();
+            let found = world.edit_object(index, |object| Arc::new(Edited { object, edit }));
+            if !found {
+                println!("No object {} to edit", index);
+            }
+        }
+    }
+
+    /// Applies every camera edit so far to `camera`.
+    pub fn apply_camera(&self, camera: Camera) -> Camera {
+        let edit = self.edits.lock().unwrap().camera;
+        let camera = camera.with_offset(edit.offset);
+        let camera = match edit.vertical_fov {
+            Some(vertical_fov) => camera.with_vertical_fov(vertical_fov),
+            None => camera,
+        };
+        match edit.aperture {
+            Some(aperture) => camera.with_aperture(aperture),
+            None => camera,
+        }
+    }
+}
+
+impl Edits {
+    fn run(&mut self, line: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["move", object, offset @ ..] => {
+                let offset = vector(offset)?;
+                self.objects
+                    .entry(object_index(object)?)
+                    .or_default()
+                    .offset += offset;
+            }
+            ["set-material", object, material @ ..] => {
+                let material = parse_material(material)?;
+                self.objects
+                    .entry(object_index(object)?)
+                    .or_default()
+                    .material = Some(material);
+            }
+            ["camera", "fov", fov] => self.camera.vertical_fov = Some(fov.parse()?),
+            ["camera", "aperture", aperture] => self.camera.aperture = Some(aperture.parse()?),
+            ["camera", "move", offset @ ..] => self.camera.offset += vector(offset)?,
+            ["reset"] => *self = Edits::default(),
+            _ => return Err(format!("unknown command: {}", line))?,
+        }
+
+        Ok(())
+    }
+}
+
+fn object_index(name: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let index = name.rsplit('_').next().unwrap_or(name);
+    index
+        .parse()
+        .map_err(|_| format!("invalid object: {}", name).into())
+}
+
+fn vector(words: &[&str]) -> Result<V3, Box<dyn std::error::Error>> {
+    match words {
+        [x, y, z] => Ok(V3::new(x.parse()?, y.parse()?, z.parse()?)),
+        _ => Err("expected x y z")?,
+    }
+}
+
+fn parse_material(words: &[&str]) -> Result<Arc<dyn Material>, Box<dyn std::error::Error>> {
+    let color = |words: &[&str]| -> Result<SolidColor, Box<dyn std::error::Error>> {
+        let color = vector(words)?;
+        Ok(SolidColor(V4::new(color.x(), color.y(), color.z(), 1.0)))
+    };
+
+    let material: Arc<dyn Material> = match words {
+        ["lambertian", rgb @ ..] => Arc::new(Lambertian::new(color(rgb)?)),
+        ["metal", r, g, b] => Arc::new(Metal::new(0.0, color(&[r, g, b])?)),
+        ["metal", r, g, b, fuzz] => {
+            Arc::new(Metal::fuzzed(fuzz.parse::<f32>()?, color(&[r, g, b])?))
+        }
+        ["glass"] | ["dielectric"] => Arc::new(Dielectric::new(1.5)),
+        ["glass", ior] | ["dielectric", ior] => Arc::new(Dielectric::new(ior.parse()?)),
+        ["light", rgb @ ..] => Arc::new(DiffuseLight::new(vector(rgb)?)),
+        _ => return Err(format!("unknown material: {}", words.join(" ")))?,
+    };
+
+    Ok(material)
+}
+
+/// An object moved by the console and optionally shaded with a new material.
+struct Edited {
+    object: Arc<dyn Intersect>,
+    edit: ObjectEdit,
+}
+
+impl Intersect for Edited {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let mut moved = ray;
+        moved.origin -= self.edit.offset;
+
+        let mut hit = self.object.intersect(moved, t_min, t_max)?;
+        hit.point += self.edit.offset;
+        if let Some(material) = self.edit.material.as_ref() {
+            hit.material = &**material;
+        }
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.object.bounding_box().map(|bounds| {
+            BoundingBox::new(
+                bounds.minimum() + self.edit.offset,
+                bounds.maximum() + self.edit.offset,
+            )
+        })
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.object.primitive_count()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        let offset = self.edit.offset;
+        let material = self.edit.material.as_ref();
+        self.object.visit_triangles(&mut |[a, b, c], original| {
+            let material = material.map_or(original, |material| &**material);
+            visit([a + offset, b + offset, c + offset], material)
+        });
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.object.memory_bytes()
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt::Write as _;
+use std::sync::Arc;
+
+use crate::math::{Num, V3};
+use crate::pfm::write_pfm;
+use crate::reference::{Crop, ReferenceImage};
+use crate::scenes::{FrameLabels, Randomized, Scene};
+use crate::world::World;
+use crate::InputCollection;
+
+#[derive(Debug, Copy, Clone)]
+pub struct DatasetConfig {
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    pub strata: u32,
+    pub max_depth: u32,
+    pub output: &'static str,
+    pub coverage: Option<CoverageConfig>,
+}
+
+/// Antialiased segmentation, `ranks` is how many overlapping ids are kept per pixel and each
+/// pixel is sampled with a stratified `strata` x `strata` grid.
+#[derive(Debug, Copy, Clone)]
+pub struct CoverageConfig {
+    pub ranks: u32,
+    pub strata: u32,
+}
+
+/// Renders `config.frames` randomized frames, writing for each an RGB image alongside
+/// segmentation, depth and normal AOVs, then a `manifest.json` describing every frame.
+///
+/// Segmentation ids are stored in the red and green channels of a PNG as `id & 0xff` and
+/// `id >> 8`, zero is the background. Depth is the distance from the camera to the primary hit
+/// in world units and is infinite where nothing was hit.
+///
+/// With `config.coverage` each frame also gets `coverage_NN.pfm` images in the style of
+/// cryptomatte. Rank `NN` holds, for every pixel, the id with the `NN`th largest coverage in
+/// red and the fraction of the pixel it covers in green. Ids match the segmentation and the
+/// background is left out, so coverages sum to less than one along its edges.
+pub fn generate(scene: &mut Randomized, config: DatasetConfig) {
+    let input = InputCollection::new();
+    let mut manifest = String::from("{\n  \"frames\": [\n");
+
+    for frame in 0..config.frames {
+        let (mut world, camera) = scene.generate(0.0, frame, &input);
+        let labels = scene.labels().clone();
+        world.build_bvh();
+
+        let world = Arc::new(world);
+        let camera = Arc::new(camera);
+
+        let crop = Crop {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        };
+        let rgb = ReferenceImage::render(
+            world.clone(),
+            camera.clone(),
+            config.width,
+            config.height,
+            crop,
+            config.strata,
+            config.max_depth,
+        );
+
+        let name = format!("frame_{:05}", frame);
+        let path = |suffix: &str| format!("{}/{}_{}", config.output, name, suffix);
+
+        let result = write_rgb(&path("rgb.png"), &rgb, config.width, config.height)
+            .and_then(|_| write_aovs(&path, &world, &camera, config.width, config.height))
+            .and_then(|_| match config.coverage {
+                Some(coverage) => write_coverage(
+                    &path,
+                    &world,
+                    &camera,
+                    config.width,
+                    config.height,
+                    coverage,
+                ),
+                None => Ok(()),
+            });
+        if let Err(error) = result {
+            eprintln!("Unable to save dataset frame {}: {:?}", frame, error);
+        }
+
+        if frame > 0 {
+            manifest.push_str(",\n");
+        }
+        write_frame_manifest(&mut manifest, &name, &labels, config.coverage);
+        println!("dataset: {}/{}", frame + 1, config.frames);
+    }
+
+    manifest.push_str("\n  ]\n}\n");
+
+    let path = format!("{}/manifest.json", config.output);
+    match std::fs::write(&path, manifest) {
+        Ok(()) => println!("Dataset manifest saved to: {}", path),
+        Err(error) => eprintln!("Unable to save dataset manifest: {:?}", error),
+    }
+}
+
+fn write_rgb(
+    path: &str,
+    rgb: &ReferenceImage,
+    width: u32,
+    height: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let component = |c: f64| (c.powf(1.0 / 2.2).min(1.0).max(0.0) * 255.0) as u8;
+    let bytes: Vec<u8> = rgb
+        .pixels()
+        .chunks(width as usize)
+        .rev()
+        .flatten()
+        .flat_map(|p| [component(p[0]), component(p[1]), component(p[2])])
+        .collect();
+
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image::save_buffer_with_format(
+        path,
+        &bytes,
+        width,
+        height,
+        image::ColorType::Rgb8,
+        image::ImageFormat::Png,
+    )?;
+    Ok(())
+}
+
+fn write_aovs<B: crate::material::Background>(
+    path: &dyn Fn(&str) -> String,
+    world: &World<B>,
+    camera: &crate::world::Camera,
+    width: u32,
+    height: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut segmentation = Vec::with_capacity((width * height) as usize);
+    let mut depth = Vec::with_capacity((width * height) as usize);
+    let mut normal = Vec::with_capacity((width * height) as usize);
+
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f32 + 0.5) / (width - 1) as f32;
+            let v = (y as f32 + 0.5) / (height - 1) as f32;
+            let ray = camera.ray(u, v);
+
+            match world.pick(ray) {
+                Some(pick) => {
+                    let id = pick.object as u32 + 1;
+                    segmentation.push(((id & 0xff) as u8, (id >> 8) as u8));
+                    let distance = (pick.point - ray.origin).length();
+                    depth.push([distance, distance, distance]);
+                    normal.push([pick.normal.x(), pick.normal.y(), pick.normal.z()]);
+                }
+                None => {
+                    segmentation.push((0, 0));
+                    depth.push([f32::INFINITY; 3]);
+                    normal.push([0.0; 3]);
+                }
+            }
+        }
+    }
+
+    let segmentation: Vec<u8> = segmentation
+        .chunks(width as usize)
+        .rev()
+        .flatten()
+        .flat_map(|&(low, high)| [low, high, 0])
+        .collect();
+    image::save_buffer_with_format(
+        path("segmentation.png"),
+        &segmentation,
+        width,
+        height,
+        image::ColorType::Rgb8,
+        image::ImageFormat::Png,
+    )?;
+
+    write_pfm(path("depth.pfm"), width, height, depth)?;
+    write_pfm(path("normal.pfm"), width, height, normal)?;
+
+    Ok(())
+}
+
+fn coverage_suffix(rank: u32) -> String {
+    format!("coverage_{:02}.pfm", rank)
+}
+
+/// Writes the ranked coverage images described on `generate`. Rows are split between threads,
+/// each sample is resolved with `World::pick` so ids agree with the segmentation.
+fn write_coverage<B: crate::material::Background>(
+    path: &dyn Fn(&str) -> String,
+    world: &World<B>,
+    camera: &crate::world::Camera,
+    width: u32,
+    height: u32,
+    coverage: CoverageConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ranks = coverage.ranks as usize;
+    let strata = coverage.strata.max(1);
+    let samples = (strata * strata) as f32;
+
+    let threads = num_cpus::get().max(1);
+    let rows_per_thread = (height as usize + threads - 1) / threads;
+    let mut rows: Vec<Vec<[f32; 3]>> = Vec::new();
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..height as usize)
+            .step_by(rows_per_thread.max(1))
+            .map(|start| {
+                let end = (start + rows_per_thread).min(height as usize);
+                scope.spawn(move || {
+                    let mut pixels = Vec::with_capacity((end - start) * width as usize * ranks);
+                    let mut counts: Vec<(u32, u32)> = Vec::new();
+                    for y in start..end {
+                        for x in 0..width {
+                            counts.clear();
+                            for sy in 0..strata {
+                                for sx in 0..strata {
+                                    let jitter_x = (sx as f32 + f32::rand()) / strata as f32;
+                                    let jitter_y = (sy as f32 + f32::rand()) / strata as f32;
+                                    let u = (x as f32 + jitter_x) / (width - 1) as f32;
+                                    let v = (y as f32 + jitter_y) / (height - 1) as f32;
+
+                                    let id = match world.pick(camera.ray(u, v)) {
+                                        Some(pick) => pick.object as u32 + 1,
+                                        None => continue,
+                                    };
+                                    match counts.iter_mut().find(|(i, _)| *i == id) {
+                                        Some((_, count)) => *count += 1,
+                                        None => counts.push((id, 1)),
+                                    }
+                                }
+                            }
+
+                            counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                            for rank in 0..ranks {
+                                pixels.push(match counts.get(rank) {
+                                    Some(&(id, count)) => [id as f32, count as f32 / samples, 0.0],
+                                    None => [0.0; 3],
+                                });
+                            }
+                        }
+                    }
+                    pixels
+                })
+            })
+            .collect();
+
+        rows = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("coverage thread panicked"))
+            .collect();
+    });
+
+    let pixels: Vec<[f32; 3]> = rows.into_iter().flatten().collect();
+    for rank in 0..ranks {
+        write_pfm(
+            path(&coverage_suffix(rank as u32)),
+            width,
+            height,
+            pixels.iter().skip(rank).step_by(ranks).copied(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_frame_manifest(
+    manifest: &mut String,
+    name: &str,
+    labels: &FrameLabels,
+    coverage: Option<CoverageConfig>,
+) {
+    let v3 = |v: V3| format!("[{}, {}, {}]", v.x(), v.y(), v.z());
+
+    let _ = writeln!(manifest, "    {{");
+    for (key, suffix) in [
+        ("rgb", "rgb.png"),
+        ("segmentation", "segmentation.png"),
+        ("depth", "depth.pfm"),
+        ("normal", "normal.pfm"),
+    ] {
+        let _ = writeln!(manifest, "      \"{}\": \"{}_{}\",", key, name, suffix);
+    }
+    if let Some(coverage) = coverage {
+        let ranks: Vec<String> = (0..coverage.ranks)
+            .map(|rank| format!("\"{}_{}\"", name, coverage_suffix(rank)))
+            .collect();
+        let _ = writeln!(manifest, "      \"coverage\": [{}],", ranks.join(", "));
+    }
+    let _ = writeln!(
+        manifest,
+        "      \"camera\": {{ \"look_from\": {}, \"look_at\": {}, \"vertical_fov\": {} }},",
+        v3(labels.look_from),
+        v3(labels.look_at),
+        labels.vertical_fov
+    );
+
+    manifest.push_str("      \"objects\": [\n");
+    for (index, object) in labels.objects.iter().enumerate() {
+        let _ = writeln!(
+            manifest,
+            "        {{ \"id\": {}, \"class\": \"{}\", \"material\": \"{}\" }}{}",
+            object.id,
+            object.class,
+            object.material,
+            if index + 1 < labels.objects.len() {
+                ","
+            } else {
+                ""
+            }
+        );
+    }
+    manifest.push_str("      ]\n    }");
+}
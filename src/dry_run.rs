@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+use mass_raytrace::sampler::RandomSampler;
+
+use crate::geom::Intersect;
+use crate::material::Material;
+use crate::math::V3;
+use crate::paging;
+use crate::scenes::Scene;
+use crate::InputCollection;
+
+const PROBE_WIDTH: u32 = 64;
+const PROBE_HEIGHT: u32 = 36;
+
+/// Loads the first frame of `scene`, builds its BVH and traces a tiny probe image through the
+/// first camera, printing what it found so problems show up before committing to a long
+/// render. The rays per second estimate assumes tracing scales linearly across `threads`.
+pub fn report<S: Scene>(scene: &mut S, max_depth: u32, threads: usize) {
+    let input = InputCollection::new();
+
+    let load_start = Instant::now();
+    let (mut world, views) = scene.generate_views(0.0, 0, &input);
+    let load_time = load_start.elapsed().as_secs_f32();
+
+    let build_start = Instant::now();
+    world.build_bvh();
+    let build_time = build_start.elapsed().as_secs_f32();
+
+    println!("dry run: {}", scene.name());
+    println!("load: {:.2}s, bvh build: {:.2}s", load_time, build_time);
+    println!(
+        "objects: {}, primitives: {}, sampled lights: {}",
+        world.object_count(),
+        world.primitive_count(),
+        world.light_count()
+    );
+    if let Some(memory) = world.bvh_memory() {
+        println!("{}", memory);
+    }
+    if let Some(stats) = world.bvh_stats() {
+        println!("{}", stats);
+        for warning in stats.warnings() {
+            println!("warning: {}", warning);
+        }
+    }
+    println!(
+        "texture memory: {:.2} MiB",
+        paging::resident_bytes() as f64 / (1024.0 * 1024.0)
+    );
+
+    let camera = match views.first() {
+        Some((_, camera)) => camera,
+        None => {
+            println!("no cameras");
+            return;
+        }
+    };
+
+    crate::scene_stats::report(&world, camera);
+
+    let probe_ray = |x: u32, y: u32| {
+        let u = (x as f32 + 0.5) / PROBE_WIDTH as f32;
+        let v = (y as f32 + 0.5) / PROBE_HEIGHT as f32;
+        camera.ray(u, v)
+    };
+
+    // Materials are told apart by address, shared materials are only counted once
+    let mut materials = HashSet::new();
+    let mut misses = 0;
+    for y in 0..PROBE_HEIGHT {
+        for x in 0..PROBE_WIDTH {
+            match world.intersect(probe_ray(x, y), 0.001, f32::INFINITY) {
+                Some(hit) => {
+                    materials.insert(hit.material as *const dyn Material as *const () as usize);
+                }
+                None => misses += 1,
+            }
+        }
+    }
+    println!(
+        "materials visible: {}, background: {:.1}%",
+        materials.len(),
+        misses as f32 / (PROBE_WIDTH * PROBE_HEIGHT) as f32 * 100.0
+    );
+
+    let probe_start = Instant::now();
+    let mut rays = 0;
+    let mut total = V3::zero();
+    for y in 0..PROBE_HEIGHT {
+        for x in 0..PROBE_WIDTH {
+            let (color, depth) =
+                camera.trace(&world, probe_ray(x, y), max_depth, &mut RandomSampler);
+            total += color;
+            rays += (max_depth - depth + 1) as u64;
+        }
+    }
+    let probe_time = probe_start.elapsed().as_secs_f64().max(f64::EPSILON);
+    let rays_per_second = rays as f64 / probe_time;
+
+    let pixels = (PROBE_WIDTH * PROBE_HEIGHT) as f32;
+    println!(
+        "probe: {} rays in {:.2}s, {:.1} rays per path, mean color {:.3} {:.3} {:.3}",
+        rays,
+        probe_time,
+        rays as f32 / pixels,
+        total.x() / pixels,
+        total.y() / pixels,
+        total.z() / pixels
+    );
+    println!(
+        "estimated: {:.0} rays/s per thread, {:.0} rays/s on {} threads",
+        rays_per_second,
+        rays_per_second * threads as f64,
+        threads
+    );
+}
@@ -0,0 +1,160 @@
+use cgmath::{Vector3, Vector4};
+use embree_rs::{CommittedScene, Device, Geometry, IntersectContext, RayHit, Scene, TriangleMesh};
+
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+use crate::geom::{BoundingBox, Hit, Intersect, Mesh};
+use crate::material::Material;
+use crate::math::V3;
+use crate::world::Ray;
+
+struct SharedDevice(Device);
+
+// Embree devices may be used from any thread.
+unsafe impl Send for SharedDevice {}
+unsafe impl Sync for SharedDevice {}
+
+static DEVICE: OnceLock<SharedDevice> = OnceLock::new();
+
+fn device() -> &'static Device {
+    &DEVICE.get_or_init(|| SharedDevice(Device::new())).0
+}
+
+/// A committed Embree scene along with the scene it borrows from, both are released together
+/// when it is dropped.
+struct OwnedScene {
+    committed: ManuallyDrop<CommittedScene<'static>>,
+    scene: *mut Scene<'static>,
+}
+
+impl OwnedScene {
+    fn commit(scene: Scene<'static>) -> Self {
+        let scene = Box::into_raw(Box::new(scene));
+        // The scene stays at this address until `drop` frees it, after the committed scene
+        let committed = unsafe { &*scene }.commit();
+
+        Self {
+            committed: ManuallyDrop::new(committed),
+            scene,
+        }
+    }
+}
+
+impl Deref for OwnedScene {
+    type Target = CommittedScene<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.committed
+    }
+}
+
+impl Drop for OwnedScene {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.committed);
+            drop(Box::from_raw(self.scene));
+        }
+    }
+}
+
+/// A `Mesh` traversed by Embree instead of a `BvhNode`. Embree only finds the closest face,
+/// shading and alpha testing still go through `Mesh`.
+pub struct EmbreeMesh<M: Material> {
+    mesh: Arc<Mesh<M>>,
+    scene: OwnedScene,
+    bounding_box: BoundingBox,
+}
+
+// Embree allows concurrent traversal of a committed scene from any thread.
+unsafe impl<M: Material> Send for EmbreeMesh<M> {}
+unsafe impl<M: Material> Sync for EmbreeMesh<M> {}
+
+impl<M: Material> EmbreeMesh<M> {
+    pub fn new(mesh: Arc<Mesh<M>>) -> Self {
+        let device = device();
+
+        let mut triangles =
+            TriangleMesh::unanimated(device, mesh.faces().len(), mesh.vertices().len());
+        {
+            let mut vertex_buffer = triangles.vertex_buffer.map();
+            for (i, v) in mesh.vertices().iter().enumerate() {
+                vertex_buffer[i] = Vector4::new(v.x(), v.y(), v.z(), 0.0);
+            }
+
+            let mut index_buffer = triangles.index_buffer.map();
+            for (i, &[a, b, c]) in mesh.faces().iter().enumerate() {
+                index_buffer[i] = Vector3::new(a, b, c);
+            }
+        }
+
+        let mut geometry = Geometry::Triangle(triangles);
+        geometry.commit();
+
+        let mut scene = Scene::new(device);
+        scene.attach_geometry(geometry);
+
+        let mut minimum = V3::fill(f32::INFINITY);
+        let mut maximum = V3::fill(f32::NEG_INFINITY);
+        for &v in mesh.vertices() {
+            minimum = minimum.min(v);
+            maximum = maximum.max(v);
+        }
+
+        Self {
+            mesh,
+            scene: OwnedScene::commit(scene),
+            bounding_box: BoundingBox::new(minimum, maximum),
+        }
+    }
+}
+
+impl<M: Material> Intersect for EmbreeMesh<M> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let origin = Vector3::new(ray.origin.x(), ray.origin.y(), ray.origin.z());
+        let direction = Vector3::new(ray.direction.x(), ray.direction.y(), ray.direction.z());
+        let mut context = IntersectContext::incoherent();
+        let mut t_min = t_min;
+
+        // Faces rejected by the alpha test are skipped by casting again from just past them
+        loop {
+            let mut ray_hit = RayHit::new(embree_rs::Ray::segment(origin, direction, t_min, t_max));
+            self.scene.intersect(&mut context, &mut ray_hit);
+
+            if !ray_hit.hit.hit() {
+                return None;
+            }
+
+            let t = ray_hit.ray.tfar;
+            let hit = self
+                .mesh
+                .face_hit(ray_hit.hit.primID, ray, t, ray_hit.hit.u, ray_hit.hit.v);
+
+            if hit.is_some() {
+                return hit;
+            }
+
+            t_min = t + 0.0001;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box)
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.mesh.faces().len()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for triangle in self.mesh.triangles() {
+            triangle.visit_triangles(visit);
+        }
+    }
+
+    /// Embree's own copy of the mesh and its acceleration structure are not included.
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.mesh.memory_bytes()
+    }
+}
@@ -1,7 +1,10 @@
 use std::path::Path;
 use std::sync::Arc;
 
-use crate::material::{Background, CubeMap, Lambertian, Material, Mix, Specular};
+use crate::animation::Value;
+use crate::material::{
+    Approximation, Background, CubeMap, Lambertian, Lobe, Material, Mix, Specular,
+};
 use crate::math::{V2, V3};
 use crate::obj_loader::ObjGroupFilter;
 use crate::texture::{BlendMode, TextureBlend};
@@ -29,15 +32,16 @@ impl ObjGroupFilter for EveFilter {
 }
 
 struct InnerEveMaterial {
-    normal_occlusion: Texture,
-    albedo_roughness: Texture,
-    pmdg: Texture,
+    normal_occlusion: Box<dyn Surface>,
+    albedo_roughness: Box<dyn Surface>,
+    pmdg: Box<dyn Surface>,
     colors: EveMaterialColor,
 }
 
 #[derive(Clone)]
 pub struct EveMaterial {
     inner: Arc<InnerEveMaterial>,
+    glow: Value,
 }
 
 impl EveMaterial {
@@ -47,9 +51,9 @@ impl EveMaterial {
         pmdg: P,
         colors: EveMaterialColor,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let normal_occlusion = Texture::load_png(no, WrapMode::Repeat)?;
-        let albedo_roughness = Texture::load_png(ar, WrapMode::Repeat)?;
-        let pmdg = Texture::load_png(pmdg, WrapMode::Repeat)?;
+        let normal_occlusion = crate::paging::load_png(no, WrapMode::Repeat)?;
+        let albedo_roughness = crate::paging::load_png(ar, WrapMode::Repeat)?;
+        let pmdg = crate::paging::load_png(pmdg, WrapMode::Repeat)?;
 
         let inner = InnerEveMaterial {
             normal_occlusion,
@@ -60,9 +64,16 @@ impl EveMaterial {
 
         Ok(Self {
             inner: Arc::new(inner),
+            glow: Value::Constant(1.0),
         })
     }
 
+    /// Scales the strength of the glow channel, pass a `Param` to animate it.
+    pub fn with_glow<V: Into<Value>>(mut self, glow: V) -> Self {
+        self.glow = glow.into();
+        self
+    }
+
     pub fn normal_occlusion(&self, uv: V2) -> (V3, f32) {
         let pixel = self.inner.normal_occlusion.get_f(uv);
         let occ = pixel.z();
@@ -77,6 +88,27 @@ impl EveMaterial {
         (pixel.contract(), pixel.w())
     }
 
+    /// The painted, dirty hull at `uv` as simpler materials.
+    fn shading(&self, uv: V2) -> Mix<Lambertian<SolidColor>, Specular<SolidColor>> {
+        let (albedo, roughness) = self.albedo_roughness(uv);
+        let (paint, material, dirt, _glow) = self.pmdg(uv);
+
+        let dirt = dirt * 1.0;
+
+        let material_color = self.inner.colors.get(material);
+        let color = (((albedo * material_color * (1.0 - paint)) + (albedo * paint))
+            * (1.0 - dirt.min(1.0)))
+            + (V3::new(0.01, 0.005, 0.0) * dirt);
+
+        let color = SolidColor(color.expand(1.0));
+
+        Mix::new(
+            (roughness + dirt).min(1.0),
+            Lambertian::new(color),
+            Specular::new(1.8, color),
+        )
+    }
+
     pub fn pmdg(&self, uv: V2) -> (f32, f32, f32, f32) {
         let pixel = self.inner.pmdg.get_f(uv);
         let paint = pixel.x();
@@ -89,39 +121,46 @@ impl EveMaterial {
 }
 
 impl Material for EveMaterial {
-    fn scatter(
+    fn sample(
         &self,
         ray: crate::world::Ray,
         hit: &crate::geom::Hit,
-    ) -> Option<crate::material::Scatter> {
-        if let Some(uv) = hit.uv {
-            let (albedo, roughness) = self.albedo_roughness(uv);
-            let (paint, material, dirt, _glow) = self.pmdg(uv);
-
-            let dirt = dirt * 1.0;
+        sampler: &mut dyn crate::sampler::Sampler,
+    ) -> Option<crate::material::BsdfSample> {
+        let uv = hit.uv?;
+        self.shading(uv).sample(ray, hit, sampler)
+    }
 
-            let material_color = self.inner.colors.get(material);
-            let color = (((albedo * material_color * (1.0 - paint)) + (albedo * paint))
-                * (1.0 - dirt.min(1.0)))
-                + (V3::new(0.01, 0.005, 0.0) * dirt);
+    fn eval(&self, ray: crate::world::Ray, hit: &crate::geom::Hit, direction: V3) -> V3 {
+        match hit.uv {
+            Some(uv) => self.shading(uv).eval(ray, hit, direction),
+            None => V3::zero(),
+        }
+    }
 
-            let color = SolidColor(color.expand(1.0));
+    fn eval_lobes(
+        &self,
+        ray: crate::world::Ray,
+        hit: &crate::geom::Hit,
+        direction: V3,
+    ) -> [V3; Lobe::COUNT] {
+        match hit.uv {
+            Some(uv) => self.shading(uv).eval_lobes(ray, hit, direction),
+            None => [V3::zero(); Lobe::COUNT],
+        }
+    }
 
-            Mix::new(
-                (roughness + dirt).min(1.0),
-                Lambertian::new(color),
-                Specular::new(1.8, color),
-            )
-            .scatter(ray, hit)
-        } else {
-            None
+    fn pdf(&self, ray: crate::world::Ray, hit: &crate::geom::Hit, direction: V3) -> f32 {
+        match hit.uv {
+            Some(uv) => self.shading(uv).pdf(ray, hit, direction),
+            None => 0.0,
         }
     }
 
     fn emit(&self, hit: &crate::geom::Hit) -> Option<V3> {
         if let Some(uv) = hit.uv {
             let (_paint, _material, _dirt, glow) = self.pmdg(uv);
-            Some(self.inner.colors.glow * glow * 10.0)
+            Some(self.inner.colors.glow * glow * self.glow.get() * 10.0)
         } else {
             None
         }
@@ -131,6 +170,32 @@ impl Material for EveMaterial {
         let (norm, _occ) = self.normal_occlusion(uv);
         Some(norm)
     }
+
+    fn approximate(&self) -> Approximation {
+        const SAMPLES: u32 = 8;
+        let mut color = V3::zero();
+        let mut roughness = 0.0;
+        for y in 0..SAMPLES {
+            for x in 0..SAMPLES {
+                let uv = V2::new(
+                    (x as f32 + 0.5) / SAMPLES as f32,
+                    (y as f32 + 0.5) / SAMPLES as f32,
+                );
+                let (albedo, sample_roughness) = self.albedo_roughness(uv);
+                let (paint, material, _dirt, _glow) = self.pmdg(uv);
+                let material_color = self.inner.colors.get(material);
+                color = color + albedo * material_color * (1.0 - paint) + albedo * paint;
+                roughness += sample_roughness;
+            }
+        }
+
+        let count = (SAMPLES * SAMPLES) as f32;
+        Approximation {
+            color: color / count,
+            roughness: roughness / count,
+            ..Approximation::default()
+        }
+    }
 }
 
 pub struct EveMaterialColor {
@@ -214,6 +279,10 @@ pub enum Hull {
 }
 
 pub fn load_ship(hull: Hull) -> crate::geom::Model<()> {
+    load_ship_with_glow(hull, 1.0)
+}
+
+pub fn load_ship_with_glow<V: Into<Value>>(hull: Hull, glow: V) -> crate::geom::Model<()> {
     let (material, model) = match hull {
         Hull::Venture => {
             let material = EveMaterial::new(
@@ -327,6 +396,7 @@ pub fn load_ship(hull: Hull) -> crate::geom::Model<()> {
         }
     };
 
+    let material = material.with_glow(glow);
     let tris = crate::obj_loader::ObjLoader::load(
         model,
         crate::obj_loader::obj_fns(V3::new, V3::new, V2::new, |a, b, c| {
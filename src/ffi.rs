@@ -0,0 +1,279 @@
+//! A small C API for embedding the tracer, declared in `include/mass_raytrace.h`.
+//!
+//! A scene is created with `mr_scene_new`, filled with spheres and triangle meshes, given a
+//! camera and rendered into a caller provided RGB8 buffer. Every function taking a scene
+//! pointer accepts null and reports it with `MR_ERROR_NULL`.
+
+use std::os::raw::c_int;
+use std::sync::Arc;
+
+use crate::geom::{Intersect, Mesh, Model, Sphere};
+use crate::material::{
+    Background, Dielectric, DiffuseLight, Lambertian, Material, Metal, SkyBackground,
+    SolidBackground,
+};
+use crate::math::V3;
+use crate::texture::SolidColor;
+use crate::world::{Camera, World};
+
+pub const MR_OK: c_int = 0;
+pub const MR_ERROR_NULL: c_int = -1;
+pub const MR_ERROR_INVALID: c_int = -2;
+pub const MR_ERROR_NO_CAMERA: c_int = -3;
+
+pub const MR_MATERIAL_LAMBERTIAN: u32 = 0;
+pub const MR_MATERIAL_METAL: u32 = 1;
+pub const MR_MATERIAL_DIELECTRIC: u32 = 2;
+pub const MR_MATERIAL_LIGHT: u32 = 3;
+
+/// A material description, `fuzz` is only read by metals and `refraction_index` only by
+/// dielectrics. Lights emit `color`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MrMaterial {
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub fuzz: f32,
+    pub refraction_index: f32,
+}
+
+impl MrMaterial {
+    fn build(&self) -> Option<Box<dyn Material>> {
+        let color = v3(self.color);
+        let material: Box<dyn Material> = match self.kind {
+            MR_MATERIAL_LAMBERTIAN => Box::new(Lambertian::new(SolidColor(color.expand(1.0)))),
+            MR_MATERIAL_METAL => Box::new(Metal::fuzzed(self.fuzz, SolidColor(color.expand(1.0)))),
+            MR_MATERIAL_DIELECTRIC => Box::new(Dielectric::new(self.refraction_index)),
+            MR_MATERIAL_LIGHT => Box::new(DiffuseLight::new(color)),
+            _ => return None,
+        };
+
+        Some(material)
+    }
+}
+
+struct CameraSettings {
+    look_from: V3,
+    look_at: V3,
+    view_up: V3,
+    vertical_fov: f32,
+    aperture: f32,
+    focus_distance: f32,
+}
+
+/// An opaque scene handle. Objects are kept outside of a `World` until render time so the
+/// background can still be changed.
+pub struct MrScene {
+    objects: Vec<Arc<dyn Intersect>>,
+    background: Option<V3>,
+    camera: Option<CameraSettings>,
+}
+
+fn v3(v: [f32; 3]) -> V3 {
+    V3::new(v[0], v[1], v[2])
+}
+
+/// Creates an empty scene lit by a sky gradient, free it with `mr_scene_free`.
+#[no_mangle]
+pub extern "C" fn mr_scene_new() -> *mut MrScene {
+    Box::into_raw(Box::new(MrScene {
+        objects: Vec::new(),
+        background: None,
+        camera: None,
+    }))
+}
+
+/// # Safety
+/// `scene` must be null or a pointer returned by `mr_scene_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_free(scene: *mut MrScene) {
+    if !scene.is_null() {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Replaces the sky with a solid background color.
+///
+/// # Safety
+/// `scene` must be null or a live scene, `color` must point to three floats.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_set_background(
+    scene: *mut MrScene,
+    color: *const [f32; 3],
+) -> c_int {
+    match (scene.as_mut(), color.as_ref()) {
+        (Some(scene), Some(&color)) => {
+            scene.background = Some(v3(color));
+            MR_OK
+        }
+        _ => MR_ERROR_NULL,
+    }
+}
+
+/// # Safety
+/// `scene` must be null or a live scene, `center` must point to three floats and `material`
+/// to an `MrMaterial`.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_add_sphere(
+    scene: *mut MrScene,
+    center: *const [f32; 3],
+    radius: f32,
+    material: *const MrMaterial,
+) -> c_int {
+    let (scene, &center, material) = match (scene.as_mut(), center.as_ref(), material.as_ref()) {
+        (Some(scene), Some(center), Some(material)) => (scene, center, material),
+        _ => return MR_ERROR_NULL,
+    };
+    let material = match material.build() {
+        Some(material) => material,
+        None => return MR_ERROR_INVALID,
+    };
+
+    scene
+        .objects
+        .push(Arc::new(Sphere::new(material, v3(center), radius)));
+    MR_OK
+}
+
+/// Adds an indexed triangle mesh, `positions` holds `vertex_count` xyz triples and `indices`
+/// holds `triangle_count` triples of vertex indices. The data is copied.
+///
+/// # Safety
+/// `scene` must be null or a live scene, `positions` and `indices` must point to at least
+/// `vertex_count * 3` floats and `triangle_count * 3` indices, and `material` to an
+/// `MrMaterial`.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_add_mesh(
+    scene: *mut MrScene,
+    positions: *const f32,
+    vertex_count: usize,
+    indices: *const u32,
+    triangle_count: usize,
+    material: *const MrMaterial,
+) -> c_int {
+    let (scene, material) = match (scene.as_mut(), material.as_ref()) {
+        (Some(scene), Some(material)) if !positions.is_null() && !indices.is_null() => {
+            (scene, material)
+        }
+        _ => return MR_ERROR_NULL,
+    };
+    let material = match material.build() {
+        Some(material) => material,
+        None => return MR_ERROR_INVALID,
+    };
+
+    let positions = std::slice::from_raw_parts(positions, vertex_count * 3);
+    let indices = std::slice::from_raw_parts(indices, triangle_count * 3);
+    if indices.iter().any(|&i| i as usize >= vertex_count) {
+        return MR_ERROR_INVALID;
+    }
+
+    let vertices = positions
+        .chunks_exact(3)
+        .map(|p| V3::new(p[0], p[1], p[2]))
+        .collect();
+    let faces = indices
+        .chunks_exact(3)
+        .map(|f| [f[0], f[1], f[2]])
+        .collect();
+    let mesh = Mesh::new(material, vertices, faces);
+
+    scene.objects.push(Arc::new(Model::from_mesh(mesh)));
+    MR_OK
+}
+
+/// Places the camera, `vertical_fov` is in degrees. The aspect ratio is taken from the
+/// dimensions passed to `mr_scene_render`.
+///
+/// # Safety
+/// `scene` must be null or a live scene, `look_from`, `look_at` and `view_up` must each point
+/// to three floats.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_set_camera(
+    scene: *mut MrScene,
+    look_from: *const [f32; 3],
+    look_at: *const [f32; 3],
+    view_up: *const [f32; 3],
+    vertical_fov: f32,
+    aperture: f32,
+    focus_distance: f32,
+) -> c_int {
+    match (
+        scene.as_mut(),
+        look_from.as_ref(),
+        look_at.as_ref(),
+        view_up.as_ref(),
+    ) {
+        (Some(scene), Some(&look_from), Some(&look_at), Some(&view_up)) => {
+            scene.camera = Some(CameraSettings {
+                look_from: v3(look_from),
+                look_at: v3(look_at),
+                view_up: v3(view_up),
+                vertical_fov,
+                aperture,
+                focus_distance,
+            });
+            MR_OK
+        }
+        _ => MR_ERROR_NULL,
+    }
+}
+
+/// Renders `samples` paths per pixel into `rgb`, `width * height * 3` bytes of gamma corrected
+/// RGB8 with the top row first. Rows are split across every available core, see
+/// `World::render`.
+///
+/// # Safety
+/// `scene` must be null or a live scene and `rgb` must point to `width * height * 3` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mr_scene_render(
+    scene: *const MrScene,
+    width: u32,
+    height: u32,
+    samples: u32,
+    max_depth: u32,
+    rgb: *mut u8,
+) -> c_int {
+    let scene = match scene.as_ref() {
+        Some(scene) if !rgb.is_null() => scene,
+        _ => return MR_ERROR_NULL,
+    };
+    if width == 0 || height == 0 || samples == 0 {
+        return MR_ERROR_INVALID;
+    }
+    let settings = match scene.camera.as_ref() {
+        Some(settings) => settings,
+        None => return MR_ERROR_NO_CAMERA,
+    };
+
+    let background: Box<dyn Background> = match scene.background {
+        Some(color) => Box::new(SolidBackground::new(color)),
+        None => Box::new(SkyBackground),
+    };
+    let mut world = World::new(background);
+    for object in scene.objects.iter() {
+        world.add(object.clone());
+    }
+    world.build_bvh();
+
+    let camera = Camera::new(
+        settings.vertical_fov,
+        settings.look_from,
+        settings.look_at,
+        settings.view_up,
+        width as f32 / height as f32,
+        settings.aperture,
+        settings.focus_distance,
+    );
+
+    let pixels = std::slice::from_raw_parts_mut(rgb, (width * height * 3) as usize);
+    let colors = world.render(&camera, width, height, samples, max_depth);
+    for (pixel, color) in pixels.chunks_mut(3).zip(colors) {
+        for (byte, c) in pixel.iter_mut().zip([color.x(), color.y(), color.z()]) {
+            *byte = (c.powf(1.0 / 2.2).min(1.0).max(0.0) * 255.0) as u8;
+        }
+    }
+
+    MR_OK
+}
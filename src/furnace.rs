@@ -0,0 +1,206 @@
+//! White furnace tests: each material is placed alone inside a uniform white environment. A
+//! convex object that neither absorbs nor emits must come out exactly as bright as the
+//! environment, so any difference from the expected albedo is energy being gained or lost by
+//! the material.
+
+use std::sync::Arc;
+
+use crate::geom::Sphere;
+use crate::material::{
+    Clearcoat, Conductor, Dielectric, Lambertian, Material, Metal, Mix, Principled,
+    SolidBackground, Specular,
+};
+use crate::math::{V3, V4};
+use crate::reference::{Crop, Integrator, ReferenceImage};
+use crate::texture::SolidColor;
+use crate::world::{Camera, World};
+
+const IMAGE_SIZE: u32 = 16;
+const STRATA: u32 = 16;
+const MAX_DEPTH: u32 = 64;
+const TOLERANCE: f64 = 0.01;
+
+/// The mean radiance of `material` filling the view in the furnace.
+fn measure<M: 'static + Material>(material: M) -> f64 {
+    let mut world = World::new(SolidBackground::new(V3::one()));
+    world.add(Sphere::new(material, V3::zero(), 1.0));
+
+    // The sphere subtends ~39 degrees from here, so a 20 degree view sees nothing else
+    let camera = Camera::new(
+        20.0,
+        V3::new(0.0, 0.0, 3.0),
+        V3::zero(),
+        V3::new(0.0, 1.0, 0.0),
+        1.0,
+        0.0,
+        3.0,
+    );
+
+    let crop = Crop {
+        x: 0,
+        y: 0,
+        width: IMAGE_SIZE,
+        height: IMAGE_SIZE,
+    };
+
+    let image = ReferenceImage::render(
+        Arc::new(world),
+        Arc::new(camera),
+        IMAGE_SIZE,
+        IMAGE_SIZE,
+        crop,
+        STRATA,
+        MAX_DEPTH,
+        Integrator::Reference,
+    );
+
+    let mean = image.mean();
+    (mean[0] + mean[1] + mean[2]) / 3.0
+}
+
+fn assert_albedo<M: 'static + Material>(material: M, expected: f64) {
+    let measured = measure(material);
+    assert!(
+        measured <= 1.0 + TOLERANCE,
+        "measured {:.4} gains energy",
+        measured
+    );
+    assert!(
+        (measured - expected).abs() <= TOLERANCE,
+        "measured {:.4} expected {:.4}",
+        measured,
+        expected
+    );
+}
+
+/// Checks a material that loses some energy but never gains any, measuring no more than one and
+/// no less than `floor`. Rough GGX lobes only return the light leaving after a single scatter
+/// off the microfacets, light bouncing between them is lost, so `floor` sits a little under the
+/// single scattering albedo seen by the camera.
+fn assert_lossy<M: 'static + Material>(material: M, floor: f64) {
+    let measured = measure(material);
+    assert!(
+        measured <= 1.0 + TOLERANCE,
+        "measured {:.4} gains energy",
+        measured
+    );
+    assert!(
+        measured >= floor,
+        "measured {:.4} loses more than the floor of {:.4}",
+        measured,
+        floor
+    );
+}
+
+fn white() -> SolidColor {
+    SolidColor(V4::one())
+}
+
+#[test]
+fn lambertian_white() {
+    assert_albedo(Lambertian::new(white()), 1.0);
+}
+
+#[test]
+fn lambertian_grey() {
+    assert_albedo(
+        Lambertian::new(SolidColor(V4::new(0.5, 0.5, 0.5, 1.0))),
+        0.5,
+    );
+}
+
+#[test]
+fn metal_polished() {
+    assert_albedo(Metal::new(0.0, white()), 1.0);
+}
+
+#[test]
+fn metal_fuzz_half() {
+    assert_albedo(Metal::fuzzed(0.5, white()), 1.0);
+}
+
+/// Fuzz this wide scatters about one percent of the view below the surface, where it is lost.
+#[test]
+fn metal_fuzz_full() {
+    assert_albedo(Metal::fuzzed(1.0, white()), 0.99);
+}
+
+#[test]
+fn dielectric() {
+    assert_albedo(Dielectric::new(1.5), 1.0);
+}
+
+#[test]
+fn specular() {
+    assert_albedo(Specular::new(1.5, white()), 1.0);
+}
+
+#[test]
+fn principled_metal() {
+    assert_albedo(
+        Principled::new(white())
+            .with_metallic(1.0)
+            .with_roughness(0.0),
+        1.0,
+    );
+}
+
+#[test]
+fn mix_lambertian_metal() {
+    assert_albedo(
+        Mix::new(0.5, Lambertian::new(white()), Metal::fuzzed(0.3, white())),
+        1.0,
+    );
+}
+
+/// Keeps about 0.91 of the light, the rest is lost bouncing between microfacets.
+#[test]
+fn metal_rough_half() {
+    assert_lossy(Metal::new(0.5, white()), 0.88);
+}
+
+/// A conductor with a refraction index and extinction of zero reflects everything at every angle,
+/// so only the microfacet loss of about 0.09 is left.
+#[test]
+fn conductor_rough_half() {
+    assert_lossy(
+        Conductor::new(V3::zero(), V3::zero()).with_roughness(0.5),
+        0.88,
+    );
+}
+
+/// Keeps about 0.91 of the light over the rough surface on the way in and out.
+#[test]
+fn dielectric_rough_half() {
+    assert_lossy(Dielectric::new(1.5).with_roughness(0.5), 0.88);
+}
+
+/// Keeps about 0.91 of the light, the light the coat reflects back down into the base is lost.
+#[test]
+fn clearcoat_lambertian() {
+    assert_lossy(Clearcoat::new(0.5, Lambertian::new(white())), 0.88);
+}
+
+/// Keeps about 0.92 of the light, like `Clearcoat` the light the specular reflects back down
+/// into the base is lost.
+#[test]
+fn principled_rough_half() {
+    assert_lossy(Principled::new(white()).with_roughness(0.5), 0.89);
+}
+
+/// The glass reflects as well as refracts, nothing else may reflect on top of it.
+#[test]
+fn principled_glass() {
+    assert_albedo(
+        Principled::new(white())
+            .with_transmission(1.0)
+            .with_roughness(0.0),
+        1.0,
+    );
+}
+
+/// Rough glass keeps about 0.91 of the light, like `Dielectric` of the same roughness.
+#[test]
+fn principled_glass_rough_half() {
+    assert_lossy(Principled::new(white()).with_transmission(1.0), 0.88);
+}
@@ -1,16 +1,46 @@
+use std::path::Path;
 use std::sync::Arc;
 
-use super::material::{Isotrophic, Material, Scatter};
-use super::world::Ray;
-use crate::math::{Num, M4, V2, V3};
+use super::material::{Backface, BsdfSample, Isotrophic, Lobe, Material};
+use super::sampler::Sampler;
+use super::world::{Ray, RayKind, TraversalRay};
+use crate::math::{Num, M4, V2, V3, V4};
+use crate::texture::Surface;
+
+mod bvh_cache;
+mod displacement;
+mod grid;
+mod kd_tree;
+#[cfg(feature = "mmap")]
+mod mapped_mesh;
+pub use displacement::Displacement;
+pub use grid::Grid;
+pub use kd_tree::KdTree;
+#[cfg(feature = "mmap")]
+pub use mapped_mesh::MappedMesh;
 
 pub struct Hit<'a> {
     pub point: V3,
     pub normal: V3,
     pub uv: Option<V2>,
+    /// Interpolated vertex color, materials multiply it into their albedo.
+    pub color: Option<V3>,
+    /// Direction of increasing u across the surface, where it has texture coordinates.
+    /// Anisotropic materials orient their roughness along it.
+    pub tangent: Option<V3>,
+    /// Direction of increasing v across the surface, alongside `tangent`.
+    pub bitangent: Option<V3>,
     pub t: f32,
     pub front_face: bool,
     pub material: &'a dyn Material,
+    /// The index of the world object that was hit, set by the `World` for light linking.
+    pub object: Option<usize>,
+    /// Width of the ray's footprint in texture coordinates, zero where the surface has none or
+    /// the ray has no footprint.
+    pub footprint: f32,
+    /// The hit point in the space of the object itself, before any `Instance` placed it, for
+    /// textures that stay fixed to a moving object.
+    pub local_point: V3,
 }
 
 impl<'a> Hit<'a> {
@@ -23,18 +53,132 @@ impl<'a> Hit<'a> {
         };
     }
 
-    pub fn scatter(&self, ray: Ray) -> Option<Scatter> {
-        self.material.scatter(ray, &self)
+    /// Whether this hit is on the back of a surface its material culls, rays should pass
+    /// through it.
+    fn culled(&self) -> bool {
+        !self.front_face && self.material.backface() == Backface::Cull
+    }
+
+    /// Whether the ray carries on through this hit, because it is culled or the material's
+    /// alpha cuts the surface out here. Hits without texture coordinates test the alpha at
+    /// the origin of the texture.
+    pub fn passes_through(&self) -> bool {
+        self.culled() || !self.material.alpha_test(self.uv.unwrap_or(V2::zero()))
+    }
+
+    /// Whether this hit is on the back of a surface its material leaves black.
+    fn absorbed(&self) -> bool {
+        !self.front_face && self.material.backface() == Backface::Black
+    }
+
+    pub fn sample(&self, ray: Ray, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        if self.absorbed() {
+            return None;
+        }
+        self.material.sample(ray, &self, sampler)
+    }
+
+    /// The fraction of light arriving from `direction` scattered back along `ray`.
+    pub fn eval(&self, ray: Ray, direction: V3) -> V3 {
+        if self.absorbed() {
+            return V3::zero();
+        }
+        self.material.eval(ray, self, direction)
+    }
+
+    /// `eval` split by lobe, indexed by `Lobe::index`.
+    pub fn eval_lobes(&self, ray: Ray, direction: V3) -> [V3; Lobe::COUNT] {
+        if self.absorbed() {
+            return [V3::zero(); Lobe::COUNT];
+        }
+        self.material.eval_lobes(ray, self, direction)
+    }
+
+    /// A ray leaving this hit in the direction of `sample`, continuing the time and fade of
+    /// `ray`, of the kind matching the lobe that picked it and with this hit's object as its
+    /// source.
+    pub fn spawn_ray(&self, ray: Ray, sample: &BsdfSample) -> Ray {
+        Ray::new(self.point, sample.direction)
+            .with_time(ray.time)
+            .with_fade(ray.fade)
+            .with_kind(RayKind::scattered(sample.lobe))
+            .with_source(self.object)
+            .with_footprint(ray.footprint_at(self.t))
+            .with_spread(ray.spread)
+    }
+
+    /// The color of `surface` at the hit, filtered over the ray's footprint.
+    pub fn texel<S: Surface + ?Sized>(&self, surface: &S) -> V4 {
+        surface.get_point(
+            self.local_point,
+            self.uv.unwrap_or(V2::zero()),
+            self.footprint,
+        )
     }
 
     pub fn emit(&self) -> V3 {
+        if self.absorbed() {
+            return V3::zero();
+        }
         self.material.emit(&self).unwrap_or(V3::zero())
     }
+
+    pub fn light_group(&self) -> usize {
+        self.material.light_group()
+    }
 }
 
 pub trait Intersect: Send + Sync {
     fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>>;
     fn bounding_box(&self) -> Option<BoundingBox>;
+
+    /// Acceleration structures override this to reuse `ray`'s precomputed inverse direction
+    /// through every level of traversal.
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect(ray.ray, t_min, t_max)
+    }
+
+    /// The number of primitives, such as triangles or spheres, this object is made of.
+    fn primitive_count(&self) -> usize {
+        1
+    }
+
+    /// Passes each triangle of this object to `visit` with the material it is shaded with, for
+    /// exporting scenes. Spheres and cuboids are tessellated, objects without a surface such as
+    /// volumes pass nothing.
+    fn visit_triangles(&self, _visit: &mut dyn FnMut([V3; 3], &dyn Material)) {}
+
+    /// Bytes of memory held by this object. Data shared through an `Arc`, such as the model
+    /// behind several instances, is counted in full by each of them.
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+impl<I: Intersect + ?Sized> Intersect for Arc<I> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        I::intersect(self, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        I::bounding_box(self)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        I::intersect_traversal(self, ray, t_min, t_max)
+    }
+
+    fn primitive_count(&self) -> usize {
+        I::primitive_count(self)
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        I::visit_triangles(self, visit)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        I::memory_bytes(self)
+    }
 }
 
 pub struct Sphere<M: Material> {
@@ -66,29 +210,38 @@ impl<M: Material> Intersect for Sphere<M> {
         } else {
             let sqrt_d = discriminant.sqrt();
 
-            let mut root = (-half_b - sqrt_d) / a;
-            if root < t_min || t_max < root {
-                root = (-half_b + sqrt_d) / a;
-                if root < t_min || t_max < root {
-                    return None;
-                }
-            }
-
-            let point = ray.at(root);
-            let normal = (point - self.center) / self.radius;
-
-            let mut hit = Hit {
-                point,
-                normal,
-                t: root,
-                uv: None,
-                front_face: false,
-                material: &self.material,
-            };
-
-            hit.set_face_normal(ray, normal);
-
-            Some(hit)
+            // The far side shows through where the near side is culled or cut out
+            let roots = [(-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a];
+            roots
+                .iter()
+                .filter(|&&root| root >= t_min && root <= t_max)
+                .find_map(|&root| {
+                    let point = ray.at(root);
+                    let normal = (point - self.center) / self.radius;
+
+                    let mut hit = Hit {
+                        point,
+                        normal,
+                        t: root,
+                        uv: None,
+                        color: None,
+                        tangent: None,
+                        bitangent: None,
+                        front_face: false,
+                        material: &self.material,
+                        object: None,
+                        footprint: 0.0,
+                        local_point: point,
+                    };
+
+                    hit.set_face_normal(ray, normal);
+
+                    if hit.passes_through() {
+                        None
+                    } else {
+                        Some(hit)
+                    }
+                })
         }
     }
 
@@ -98,109 +251,491 @@ impl<M: Material> Intersect for Sphere<M> {
             self.center + V3::fill(self.radius.abs()),
         ))
     }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        const RINGS: u32 = 16;
+        const SEGMENTS: u32 = 32;
+
+        let point = |ring: u32, segment: u32| {
+            let theta = std::f32::consts::PI * ring as f32 / RINGS as f32;
+            let phi = 2.0 * std::f32::consts::PI * segment as f32 / SEGMENTS as f32;
+            let direction = V3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                -theta.sin() * phi.sin(),
+            );
+            self.center + direction * self.radius
+        };
+
+        for ring in 0..RINGS {
+            for segment in 0..SEGMENTS {
+                let a = point(ring, segment);
+                let b = point(ring + 1, segment);
+                let c = point(ring + 1, segment + 1);
+                let d = point(ring, segment + 1);
+                if ring != 0 {
+                    visit([a, b, d], &self.material);
+                }
+                if ring != RINGS - 1 {
+                    visit([b, c, d], &self.material);
+                }
+            }
+        }
+    }
 }
 
+/// A bounding volume hierarchy stored as a flat arena of nodes, with the items it holds kept
+/// in traversal order.
 pub struct BvhNode {
-    left: Option<Box<dyn Intersect>>,
-    right: Option<Box<dyn Intersect>>,
+    nodes: Vec<FlatNode>,
+    items: Vec<Box<dyn Intersect>>,
+}
+
+#[derive(Copy, Clone)]
+struct FlatNode {
     bounding_box: BoundingBox,
+    left: BvhChild,
+    right: BvhChild,
+}
+
+#[derive(Copy, Clone)]
+enum BvhChild {
+    Node(u32),
+    Item(u32),
+    Empty,
+}
+
+/// The memory held by a single `BvhNode`, excluding the items themselves and any nested
+/// acceleration structures they contain.
+#[derive(Copy, Clone, Debug)]
+pub struct BvhMemory {
+    pub nodes: usize,
+    pub items: usize,
+    pub bytes: usize,
+}
+
+impl std::fmt::Display for BvhMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bvh: {} nodes, {} items, {:.2} MiB",
+            self.nodes,
+            self.items,
+            self.bytes as f64 / (1024.0 * 1024.0)
+        )
+    }
 }
 
+/// The shape of a single `BvhNode`. Overlap is the surface area shared by the two children of
+/// each node relative to the node itself, averaged over nodes with two children, higher values
+/// mean rays more often have to descend both sides. SAH cost is the expected number of nodes
+/// and items a ray through the root's bounds tests, by the surface area heuristic.
+#[derive(Copy, Clone, Debug)]
+pub struct BvhStats {
+    pub max_depth: usize,
+    pub leaves: usize,
+    pub mean_leaf_depth: f32,
+    pub overlap: f32,
+    pub sah_cost: f32,
+    /// The most primitives held by a single item.
+    pub largest_leaf: usize,
+    pub mean_leaf_primitives: f32,
+    /// Whether the root's bounds are finite, an infinite or NaN bound leaves every node above it
+    /// useless.
+    pub finite: bool,
+}
+
+/// Tolerated depth beyond a balanced tree before a `BvhStats` warns about it.
+const DEPTH_SLACK: usize = 16;
+/// Average overlap between siblings that a `BvhStats` warns about.
+const OVERLAP_WARNING: f32 = 0.6;
+/// Items held directly by a `BvhNode` before a `BvhStats` suggests grouping them.
+const ITEM_WARNING: usize = 100_000;
+
+impl std::fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "bvh: depth {} (mean leaf {:.1}), {} leaves ({:.1} primitives mean, {} max), \
+             {:.1}% overlap, sah cost {:.1}",
+            self.max_depth,
+            self.mean_leaf_depth,
+            self.leaves,
+            self.mean_leaf_primitives,
+            self.largest_leaf,
+            self.overlap * 100.0,
+            self.sah_cost
+        )
+    }
+}
+
+impl BvhStats {
+    /// The depth of a perfectly balanced tree over the same number of leaves.
+    pub fn balanced_depth(&self) -> usize {
+        (self.leaves.max(1) as f32).log2().ceil() as usize + 1
+    }
+
+    /// Explains shapes that usually mean a sudden slowdown, with what tends to cause them.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if !self.finite {
+            warnings.push(String::from(
+                "bvh bounds are not finite, an object has an infinite or NaN bounding box so every \
+                 ray tests it and its neighbours, check for degenerate transforms or NaN vertices",
+            ));
+        }
+        if self.max_depth > self.balanced_depth() + DEPTH_SLACK {
+            warnings.push(format!(
+                "bvh is {} levels deep where {} would be balanced, long chains like this come from \
+                 many objects with the same bounds, such as instances left at the origin or nested \
+                 copies of one model; give them distinct transforms or merge them into one Model",
+                self.max_depth,
+                self.balanced_depth()
+            ));
+        }
+        if self.overlap > OVERLAP_WARNING {
+            warnings.push(format!(
+                "bvh siblings overlap by {:.0}% on average so rays descend both sides, usually \
+                 large objects such as floors mixed in with small ones; split large meshes or \
+                 group the small objects into a Model",
+                self.overlap * 100.0
+            ));
+        }
+        if self.leaves > ITEM_WARNING {
+            warnings.push(format!(
+                "bvh holds {} objects directly, building and traversing is faster with repeated \
+                 geometry grouped into Models and placed with instances",
+                self.leaves
+            ));
+        }
+
+        warnings
+    }
+}
+
+/// Preorder layout token for a node with both children, anything else is an item index.
+const LAYOUT_NODE: u32 = u32::MAX;
+/// Preorder layout token for a node with only a left child.
+const LAYOUT_LEFT_NODE: u32 = u32::MAX - 1;
+
 impl BvhNode {
-    pub fn new(mut items: Vec<Box<dyn Intersect>>) -> Self {
-        let axis = fastrand::u8(0..3);
-
-        let compare = match axis {
-            0 => compare_x,
-            1 => compare_y,
-            2 => compare_z,
-            _ => unreachable!(),
-        };
+    pub fn new(items: Vec<Box<dyn Intersect>>) -> Self {
+        let layout = BvhNode::layout(&items);
+        BvhNode::from_layout(&layout, items)
+    }
 
-        let (left, right) = if items.len() == 1 {
-            (items.pop(), None)
-        } else if items.len() == 2 {
-            let a = items.pop().unwrap();
-            let b = items.pop().unwrap();
-            if compare(&a, &b) {
-                (Some(a), Some(b))
-            } else {
-                (Some(b), Some(a))
+    /// Loads the tree layout for `items` from the on-disk cache under `key`, building and
+    /// caching it if no usable entry exists. Bounding boxes are always recomputed from the
+    /// items, so a stale entry can only make traversal slower, never incorrect.
+    pub fn cached(key: u64, items: Vec<Box<dyn Intersect>>) -> Self {
+        let layout = match bvh_cache::load(key, items.len()) {
+            Some(layout) => layout,
+            None => {
+                let layout = BvhNode::layout(&items);
+                if let Err(error) = bvh_cache::store(key, items.len(), &layout) {
+                    eprintln!("Unable to write bvh cache: {:?}", error);
+                }
+                layout
             }
+        };
+
+        BvhNode::from_layout(&layout, items)
+    }
+
+    /// Computes the tree shape as preorder tokens over item indices, independent of the items
+    /// themselves so that it can be cached.
+    fn layout(items: &[Box<dyn Intersect>]) -> Vec<u32> {
+        let bounds: Vec<BoundingBox> = items
+            .iter()
+            .map(|i| i.bounding_box().expect("Missing bounding box in bvh"))
+            .collect();
+
+        let mut layout = Vec::with_capacity(items.len() * 2);
+        layout_node(&bounds, (0..items.len() as u32).collect(), &mut layout);
+        layout
+    }
+
+    fn from_layout(layout: &[u32], items: Vec<Box<dyn Intersect>>) -> Self {
+        let mut source: Vec<Option<Box<dyn Intersect>>> = items.into_iter().map(Some).collect();
+        let mut bvh = BvhNode {
+            nodes: Vec::with_capacity(source.len()),
+            items: Vec::with_capacity(source.len()),
+        };
+
+        let mut tokens = layout.iter().copied();
+        let root = tokens.next().expect("Empty bvh layout");
+        bvh.assemble_node(root, &mut tokens, &mut source);
+        bvh
+    }
+
+    fn assemble_node(
+        &mut self,
+        token: u32,
+        tokens: &mut impl Iterator<Item = u32>,
+        source: &mut [Option<Box<dyn Intersect>>],
+    ) -> u32 {
+        let index = self.nodes.len();
+        self.nodes.push(FlatNode {
+            bounding_box: BoundingBox::new(V3::zero(), V3::zero()),
+            left: BvhChild::Empty,
+            right: BvhChild::Empty,
+        });
+
+        let left = self.assemble_child(tokens, source);
+        let right = if token == LAYOUT_NODE {
+            self.assemble_child(tokens, source)
         } else {
-            items.sort_by(|a, b| {
-                if compare(a, b) {
-                    std::cmp::Ordering::Less
-                } else {
-                    std::cmp::Ordering::Greater
-                }
-            });
-            let mid = items.len() / 2;
-            let back_half = items.split_off(mid);
-            (
-                Some(Box::new(BvhNode::new(items)) as Box<dyn Intersect>),
-                Some(Box::new(BvhNode::new(back_half)) as Box<dyn Intersect>),
-            )
+            BvhChild::Empty
         };
 
-        let bounding_box = match (
-            left.as_ref().and_then(|l| l.bounding_box()),
-            right.as_ref().and_then(|r| r.bounding_box()),
-        ) {
+        let bounding_box = match (self.child_bounds(left), self.child_bounds(right)) {
             (Some(left), Some(right)) => left.join(right),
             (Some(left), None) => left,
             (None, Some(right)) => right,
             _ => unreachable!("Missing bounding box in bvh"),
         };
 
-        Self {
+        self.nodes[index] = FlatNode {
+            bounding_box,
             left,
             right,
-            bounding_box,
+        };
+
+        index as u32
+    }
+
+    fn assemble_child(
+        &mut self,
+        tokens: &mut impl Iterator<Item = u32>,
+        source: &mut [Option<Box<dyn Intersect>>],
+    ) -> BvhChild {
+        let token = tokens.next().expect("Truncated bvh layout");
+        if token == LAYOUT_NODE || token == LAYOUT_LEFT_NODE {
+            BvhChild::Node(self.assemble_node(token, tokens, source))
+        } else {
+            let item = source[token as usize]
+                .take()
+                .expect("Item repeated in bvh layout");
+            self.items.push(item);
+            BvhChild::Item(self.items.len() as u32 - 1)
         }
     }
-}
 
-fn compare_x(left: &Box<dyn Intersect>, right: &Box<dyn Intersect>) -> bool {
-    match (left.bounding_box(), right.bounding_box()) {
-        (Some(left), Some(right)) => left.minimum.x() < right.minimum.x(),
-        _ => unreachable!("Missing bounding box in bvh"),
+    fn child_bounds(&self, child: BvhChild) -> Option<BoundingBox> {
+        match child {
+            BvhChild::Node(index) => Some(self.nodes[index as usize].bounding_box),
+            BvhChild::Item(index) => self.items[index as usize].bounding_box(),
+            BvhChild::Empty => None,
+        }
     }
-}
 
-fn compare_y(left: &Box<dyn Intersect>, right: &Box<dyn Intersect>) -> bool {
-    match (left.bounding_box(), right.bounding_box()) {
-        (Some(left), Some(right)) => left.minimum.y() < right.minimum.y(),
-        _ => unreachable!("Missing bounding box in bvh"),
+    pub fn memory_usage(&self) -> BvhMemory {
+        BvhMemory {
+            nodes: self.nodes.len(),
+            items: self.items.len(),
+            bytes: std::mem::size_of::<Self>()
+                + self.nodes.capacity() * std::mem::size_of::<FlatNode>()
+                + self.items.capacity() * std::mem::size_of::<Box<dyn Intersect>>(),
+        }
     }
-}
 
-fn compare_z(left: &Box<dyn Intersect>, right: &Box<dyn Intersect>) -> bool {
-    match (left.bounding_box(), right.bounding_box()) {
-        (Some(left), Some(right)) => left.minimum.z() < right.minimum.z(),
-        _ => unreachable!("Missing bounding box in bvh"),
+    pub fn stats(&self) -> BvhStats {
+        let mut max_depth = 0;
+        let mut leaves = 0;
+        let mut leaf_depths = 0;
+        let mut overlap = 0.0;
+        let mut split_nodes = 0;
+        let mut sah_cost = 0.0;
+        let mut largest_leaf = 0;
+        let mut leaf_primitives = 0;
+
+        let root_area = self.nodes[0].bounding_box.surface_area();
+        let finite = root_area.is_finite();
+        let relative_area = |bounds: BoundingBox| {
+            if finite && root_area > 0.0 {
+                bounds.surface_area() / root_area
+            } else {
+                1.0
+            }
+        };
+
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index as usize];
+            max_depth = max_depth.max(depth);
+            sah_cost += relative_area(node.bounding_box);
+
+            let child_box = |child| match child {
+                BvhChild::Node(n) => Some(self.nodes[n as usize].bounding_box),
+                BvhChild::Item(i) => self.items[i as usize].bounding_box(),
+                BvhChild::Empty => None,
+            };
+            if let (Some(left), Some(right)) = (child_box(node.left), child_box(node.right)) {
+                let area = node.bounding_box.surface_area();
+                if area > 0.0 {
+                    overlap += left.intersection(right).map_or(0.0, |b| b.surface_area()) / area;
+                }
+                split_nodes += 1;
+            }
+
+            for child in [node.left, node.right] {
+                match child {
+                    BvhChild::Node(n) => stack.push((n, depth + 1)),
+                    BvhChild::Item(i) => {
+                        let item = &self.items[i as usize];
+                        let primitives = item.primitive_count();
+                        leaves += 1;
+                        leaf_depths += depth;
+                        largest_leaf = largest_leaf.max(primitives);
+                        leaf_primitives += primitives;
+                        sah_cost += item.bounding_box().map_or(1.0, relative_area);
+                    }
+                    BvhChild::Empty => (),
+                }
+            }
+        }
+
+        BvhStats {
+            max_depth,
+            leaves,
+            mean_leaf_depth: leaf_depths as f32 / leaves.max(1) as f32,
+            overlap: overlap / split_nodes.max(1) as f32,
+            sah_cost,
+            largest_leaf,
+            mean_leaf_primitives: leaf_primitives as f32 / leaves.max(1) as f32,
+            finite,
+        }
     }
-}
 
-impl Intersect for BvhNode {
-    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
-        if self.bounding_box.hit(ray, t_min, t_max) {
-            let left_hit = self
-                .left
-                .as_ref()
-                .and_then(|left| left.intersect(ray, t_min, t_max));
+    fn intersect_node(
+        &self,
+        index: u32,
+        ray: &TraversalRay,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Hit<'_>> {
+        let node = &self.nodes[index as usize];
+        if node.bounding_box.hit(ray, t_min, t_max) {
+            let left_hit = self.intersect_child(node.left, ray, t_min, t_max);
             let t_max = left_hit.as_ref().map(|l| l.t).unwrap_or(t_max);
-            self.right
-                .as_ref()
-                .and_then(|r| r.intersect(ray, t_min, t_max))
+            self.intersect_child(node.right, ray, t_min, t_max)
                 .or(left_hit)
         } else {
             None
         }
     }
 
+    fn intersect_child(
+        &self,
+        child: BvhChild,
+        ray: &TraversalRay,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Hit<'_>> {
+        match child {
+            BvhChild::Node(index) => self.intersect_node(index, ray, t_min, t_max),
+            BvhChild::Item(index) => {
+                self.items[index as usize].intersect_traversal(ray, t_min, t_max)
+            }
+            BvhChild::Empty => None,
+        }
+    }
+}
+
+/// Checks that `layout` is a complete tree using each of `item_count` items exactly once.
+fn valid_layout(layout: &[u32], item_count: usize) -> bool {
+    if !matches!(layout.first(), Some(&LAYOUT_NODE) | Some(&LAYOUT_LEFT_NODE)) {
+        return false;
+    }
+
+    let mut seen = vec![false; item_count];
+    let mut pending = 1;
+    for &token in layout {
+        if pending == 0 {
+            return false;
+        }
+        pending -= 1;
+
+        match token {
+            LAYOUT_NODE => pending += 2,
+            LAYOUT_LEFT_NODE => pending += 1,
+            index => match seen.get_mut(index as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => return false,
+            },
+        }
+    }
+
+    pending == 0 && seen.iter().all(|&s| s)
+}
+
+fn layout_node(bounds: &[BoundingBox], mut indices: Vec<u32>, layout: &mut Vec<u32>) {
+    let axis = fastrand::u8(0..3);
+    let key = |index: u32| {
+        let minimum = bounds[index as usize].minimum;
+        match axis {
+            0 => minimum.x(),
+            1 => minimum.y(),
+            _ => minimum.z(),
+        }
+    };
+
+    if indices.len() == 1 {
+        layout.push(LAYOUT_LEFT_NODE);
+        layout.push(indices[0]);
+    } else if indices.len() == 2 {
+        let (a, b) = (indices[1], indices[0]);
+        layout.push(LAYOUT_NODE);
+        if key(a) < key(b) {
+            layout.extend([a, b]);
+        } else {
+            layout.extend([b, a]);
+        }
+    } else {
+        indices.sort_by(|&a, &b| {
+            key(a)
+                .partial_cmp(&key(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mid = indices.len() / 2;
+        let back_half = indices.split_off(mid);
+        layout.push(LAYOUT_NODE);
+        layout_node(bounds, indices, layout);
+        layout_node(bounds, back_half, layout);
+    }
+}
+impl Accelerator for BvhNode {
+    fn build(items: Vec<Box<dyn Intersect>>) -> Self {
+        Self::new(items)
+    }
+}
+
+impl Intersect for BvhNode {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_node(0, ray, t_min, t_max)
+    }
+
     fn bounding_box(&self) -> Option<BoundingBox> {
-        Some(self.bounding_box)
+        Some(self.nodes[0].bounding_box)
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.items.iter().map(|i| i.primitive_count()).sum()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for item in self.items.iter() {
+            item.visit_triangles(visit);
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.memory_usage().bytes + self.items.iter().map(|i| i.memory_bytes()).sum::<usize>()
     }
 }
 
@@ -215,29 +750,28 @@ impl BoundingBox {
         Self { minimum, maximum }
     }
 
-    pub fn hit(&self, ray: Ray, t_min: f32, t_max: f32) -> bool {
-        let v_min = (self.minimum - ray.origin) / ray.direction;
-        let v_max = (self.maximum - ray.origin) / ray.direction;
-
-        let min: V3 = v_min.min(v_max);
-        let max: V3 = v_min.max(v_max);
+    pub fn hit(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> bool {
+        let bounds = [self.minimum, self.maximum];
+        let origin = ray.ray.origin;
+        let inv = ray.inv_direction;
+        let [neg_x, neg_y, neg_z] = ray.negative;
 
-        let t_min = min.x().max(t_min);
-        let t_max = max.x().min(t_max);
+        let t_min = t_min.max((bounds[neg_x as usize].x() - origin.x()) * inv.x());
+        let t_max = t_max.min((bounds[1 - neg_x as usize].x() - origin.x()) * inv.x());
 
         if t_max < t_min {
             return false;
         }
 
-        let t_min = min.y().max(t_min);
-        let t_max = max.y().min(t_max);
+        let t_min = t_min.max((bounds[neg_y as usize].y() - origin.y()) * inv.y());
+        let t_max = t_max.min((bounds[1 - neg_y as usize].y() - origin.y()) * inv.y());
 
         if t_max < t_min {
             return false;
         }
 
-        let t_min = min.z().max(t_min);
-        let t_max = max.z().min(t_max);
+        let t_min = t_min.max((bounds[neg_z as usize].z() - origin.z()) * inv.z());
+        let t_max = t_max.min((bounds[1 - neg_z as usize].z() - origin.z()) * inv.z());
 
         if t_max < t_min {
             return false;
@@ -246,6 +780,18 @@ impl BoundingBox {
         true
     }
 
+    pub fn transform(&self, transform: M4) -> Self {
+        let mut minimum = V3::fill(f32::INFINITY);
+        let mut maximum = V3::fill(f32::NEG_INFINITY);
+
+        for corner in self.corners().map(|c| transform.transform_point(c)) {
+            minimum = minimum.min(corner);
+            maximum = maximum.max(corner);
+        }
+
+        BoundingBox::new(minimum, maximum)
+    }
+
     pub fn join(&self, other: BoundingBox) -> Self {
         let minimum = self.minimum.min(other.minimum);
         let maximum = self.maximum.max(other.maximum);
@@ -253,6 +799,57 @@ impl BoundingBox {
         Self::new(minimum, maximum)
     }
 
+    pub fn minimum(&self) -> V3 {
+        self.minimum
+    }
+
+    pub fn maximum(&self) -> V3 {
+        self.maximum
+    }
+
+    pub fn center(&self) -> V3 {
+        (self.minimum + self.maximum) / 2.0
+    }
+
+    pub fn size(&self) -> V3 {
+        self.maximum - self.minimum
+    }
+
+    pub fn contains(&self, point: V3) -> bool {
+        point.x() >= self.minimum.x()
+            && point.y() >= self.minimum.y()
+            && point.z() >= self.minimum.z()
+            && point.x() <= self.maximum.x()
+            && point.y() <= self.maximum.y()
+            && point.z() <= self.maximum.z()
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x() * size.y() + size.y() * size.z() + size.z() * size.x())
+    }
+
+    /// The region shared with `other`, `None` if they don't overlap.
+    pub fn intersection(&self, other: BoundingBox) -> Option<BoundingBox> {
+        if self.overlaps(other) {
+            Some(BoundingBox::new(
+                self.minimum.max(other.minimum),
+                self.maximum.min(other.maximum),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn overlaps(&self, other: BoundingBox) -> bool {
+        self.minimum.x() <= other.maximum.x()
+            && self.minimum.y() <= other.maximum.y()
+            && self.minimum.z() <= other.maximum.z()
+            && self.maximum.x() >= other.minimum.x()
+            && self.maximum.y() >= other.minimum.y()
+            && self.maximum.z() >= other.minimum.z()
+    }
+
     pub fn corners(&self) -> impl Iterator<Item = V3> {
         let mut corner = 0;
         let min = self.minimum;
@@ -272,22 +869,230 @@ impl BoundingBox {
     }
 }
 
-pub struct Model<M: Material> {
+/// A group of primitives behind an acceleration structure. The structure is type erased by
+/// default, `Model::build` keeps a concrete `Accelerator` when it needs to be inspected.
+pub struct Model<M: Material, A: ?Sized + Intersect = dyn Intersect> {
     material: Option<M>,
-    triangles: Arc<BvhNode>,
+    /// The materials that `MaterialIndex` primitives are shaded with.
+    materials: Arc<Vec<Box<dyn Material>>>,
+    triangles: Arc<A>,
+}
+
+impl<M: Clone + Material, A: ?Sized + Intersect> Clone for Model<M, A> {
+    fn clone(&self) -> Self {
+        Self {
+            material: self.material.clone(),
+            materials: self.materials.clone(),
+            triangles: self.triangles.clone(),
+        }
+    }
+}
+
+impl<M: Material, A: ?Sized + Intersect> Model<M, A> {
+    /// Shades primitives with a `MaterialIndex` by the material at that index of `materials`.
+    pub fn with_materials(self, materials: Vec<Box<dyn Material>>) -> Self {
+        Self {
+            materials: Arc::new(materials),
+            ..self
+        }
+    }
+
+    /// Swaps the `MaterialIndex` of a hit for the material it stands in for, tilting the normal
+    /// by that material's normal map. Returns whether the ray stops at the hit.
+    fn resolve_material<'a>(&'a self, ray: Ray, hit: &mut Hit<'a>) -> bool {
+        let material = match hit.material.model_index() {
+            Some(index) => &*self.materials[index as usize],
+            None => return true,
+        };
+        hit.material = material;
+
+        let normal = if hit.front_face {
+            hit.normal
+        } else {
+            -hit.normal
+        };
+        let normal = match (
+            hit.uv.and_then(|uv| material.normal(uv)),
+            hit.tangent,
+            hit.bitangent,
+        ) {
+            (Some(tan_normal), Some(tangent), Some(bitangent)) => {
+                tangent * tan_normal.x() + bitangent * tan_normal.y() + normal * tan_normal.z()
+            }
+            _ => normal,
+        };
+        hit.set_face_normal(ray, normal);
+
+        !hit.passes_through()
+    }
+}
+
+/// An acceleration structure that can be built over an arbitrary set of primitives.
+pub trait Accelerator: Intersect {
+    fn build(items: Vec<Box<dyn Intersect>>) -> Self
+    where
+        Self: Sized;
+}
+
+/// The acceleration structure a `Model` organizes its primitives with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Acceleration {
+    Bvh,
+    Grid,
+    KdTree,
+}
+
+impl Acceleration {
+    pub fn build(self, items: Vec<Box<dyn Intersect>>) -> Arc<dyn Intersect> {
+        match self {
+            Acceleration::Bvh => Arc::new(BvhNode::build(items)),
+            Acceleration::Grid => Arc::new(Grid::build(items)),
+            Acceleration::KdTree => Arc::new(KdTree::build(items)),
+        }
+    }
+}
+
+impl<A: 'static + Accelerator> Model<(), A> {
+    pub fn build<T: IntoIterator<Item = Triangle<TM>>, TM: 'static + Material>(
+        triangles: T,
+    ) -> Self {
+        let triangles = triangles
+            .into_iter()
+            .map(|t| Box::new(t) as Box<dyn Intersect>)
+            .collect();
+
+        Self {
+            triangles: Arc::new(A::build(triangles)),
+            material: None,
+            materials: Arc::default(),
+        }
+    }
+
+    pub fn accelerator(&self) -> &A {
+        &self.triangles
+    }
+}
+
+impl<M: Material, A: 'static + Accelerator> From<Model<M, A>> for Model<M> {
+    fn from(model: Model<M, A>) -> Self {
+        Self {
+            material: model.material,
+            materials: model.materials,
+            triangles: model.triangles,
+        }
+    }
 }
 
 impl Model<()> {
     pub fn new<T: IntoIterator<Item = Triangle<TM>>, TM: 'static + Material>(triangles: T) -> Self {
+        Self::new_with(Acceleration::Bvh, triangles)
+    }
+
+    pub fn new_with<T: IntoIterator<Item = Triangle<TM>>, TM: 'static + Material>(
+        acceleration: Acceleration,
+        triangles: T,
+    ) -> Self {
         let triangles = triangles
             .into_iter()
             .map(|t| Box::new(t) as Box<dyn Intersect>)
             .collect();
+
+        Self::from_objects(acceleration, triangles)
+    }
+
+    /// Builds a BVH model whose tree is cached on disk, keyed by the contents of the `source`
+    /// file the triangles were loaded from.
+    pub fn new_cached<
+        P: AsRef<Path>,
+        T: IntoIterator<Item = Triangle<TM>>,
+        TM: 'static + Material,
+    >(
+        source: P,
+        triangles: T,
+    ) -> Self {
+        let triangles = triangles
+            .into_iter()
+            .map(|t| Box::new(t) as Box<dyn Intersect>)
+            .collect();
+
+        Self {
+            triangles: Arc::new(cached_bvh(source.as_ref(), triangles)),
+            material: None,
+            materials: Arc::default(),
+        }
+    }
+
+    /// Groups arbitrary objects, such as spheres, into a single model.
+    pub fn from_objects(acceleration: Acceleration, objects: Vec<Box<dyn Intersect>>) -> Self {
+        Self {
+            triangles: acceleration.build(objects),
+            material: None,
+            materials: Arc::default(),
+        }
+    }
+
+    #[cfg(not(feature = "embree"))]
+    pub fn from_mesh<TM: 'static + Material>(mesh: Mesh<TM>) -> Self {
+        let mesh = Arc::new(mesh);
+        let triangles = mesh
+            .triangles()
+            .map(|t| Box::new(t) as Box<dyn Intersect>)
+            .collect();
         let triangles = Arc::new(BvhNode::new(triangles));
 
         Self {
             triangles,
             material: None,
+            materials: Arc::default(),
+        }
+    }
+
+    /// Like `from_mesh`, with the tree cached on disk keyed by the contents of `source`.
+    #[cfg(not(feature = "embree"))]
+    pub fn from_mesh_cached<P: AsRef<Path>, TM: 'static + Material>(
+        source: P,
+        mesh: Mesh<TM>,
+    ) -> Self {
+        let mesh = Arc::new(mesh);
+        let triangles = mesh
+            .triangles()
+            .map(|t| Box::new(t) as Box<dyn Intersect>)
+            .collect();
+
+        Self {
+            triangles: Arc::new(cached_bvh(source.as_ref(), triangles)),
+            material: None,
+            materials: Arc::default(),
+        }
+    }
+
+    /// Embree builds its own acceleration structure, so there is nothing to cache.
+    #[cfg(feature = "embree")]
+    pub fn from_mesh_cached<P: AsRef<Path>, TM: 'static + Material>(
+        _source: P,
+        mesh: Mesh<TM>,
+    ) -> Self {
+        Self::from_mesh(mesh)
+    }
+
+    #[cfg(feature = "embree")]
+    pub fn from_mesh<TM: 'static + Material>(mesh: Mesh<TM>) -> Self {
+        let triangles = Arc::new(crate::embree::EmbreeMesh::new(Arc::new(mesh)));
+
+        Self {
+            triangles,
+            material: None,
+            materials: Arc::default(),
+        }
+    }
+}
+
+fn cached_bvh(source: &Path, items: Vec<Box<dyn Intersect>>) -> BvhNode {
+    match bvh_cache::source_key(source) {
+        Ok(key) => BvhNode::cached(key, items),
+        Err(error) => {
+            eprintln!("Unable to hash {}: {:?}", source.display(), error);
+            BvhNode::new(items)
         }
     }
 }
@@ -306,121 +1111,275 @@ impl<M: 'static + Clone + Material> Model<M> {
         Self {
             triangles,
             material: Some(material),
+            materials: Arc::default(),
         }
     }
 
     pub fn instance(&self, translation: V3, rotation: V3, scale: V3) -> Instance<()> {
-        Instance::new(self.triangles.clone(), translation, rotation, scale)
+        Instance::new(self.shared(), translation, rotation, scale)
+    }
+
+    pub fn instance_transform(&self, transform: M4) -> Instance<()> {
+        Instance::from_transform(self.shared(), transform)
+    }
+
+    /// The primitives for instances to share, along with any materials they index into.
+    fn shared(&self) -> Arc<dyn Intersect> {
+        if self.materials.is_empty() {
+            self.triangles.clone()
+        } else {
+            Arc::new(Model::<()> {
+                material: None,
+                materials: self.materials.clone(),
+                triangles: self.triangles.clone(),
+            })
+        }
     }
 }
 
-impl<M: Material> Intersect for Model<M> {
+impl<M: Material, A: ?Sized + Intersect> Intersect for Model<M, A> {
     fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
-        let hit = self.triangles.intersect(ray, t_min, t_max);
-        if let Some(mut hit) = hit {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let mut t_min = t_min;
+
+        // Faces cut out or culled by the model's material, or the materials they index, are
+        // skipped by casting again from just past them
+        loop {
+            let mut hit = self.triangles.intersect_traversal(ray, t_min, t_max)?;
             if let Some(material) = self.material.as_ref() {
                 hit.material = material;
+                if !hit.passes_through() {
+                    return Some(hit);
+                }
+            } else if self.resolve_material(ray.ray, &mut hit) {
+                return Some(hit);
             }
-            Some(hit)
-        } else {
-            None
+
+            t_min = hit.t + 0.0001;
         }
     }
 
     fn bounding_box(&self) -> Option<BoundingBox> {
         self.triangles.bounding_box()
     }
+
+    fn primitive_count(&self) -> usize {
+        self.triangles.primitive_count()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        match self.material.as_ref() {
+            Some(material) => self
+                .triangles
+                .visit_triangles(&mut |vertices, _| visit(vertices, material)),
+            None => self
+                .triangles
+                .visit_triangles(&mut |vertices, material| match material.model_index() {
+                    Some(index) => visit(vertices, &*self.materials[index as usize]),
+                    None => visit(vertices, material),
+                }),
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.triangles.memory_bytes()
+    }
 }
 
+/// A transformed reference to a shared object. Distances inside the object, such as a
+/// `Volume`'s density, are measured in the object's own space.
 pub struct Instance<M: Material> {
-    triangles: Arc<BvhNode>,
+    object: Arc<dyn Intersect>,
     material: Option<M>,
     transform: M4,
     inv_transform: M4,
-    bounding_box: BoundingBox,
+    normal_transform: M4,
+    motion: Option<M4>,
+    fade: Option<(f32, f32)>,
+    bounding_box: Option<BoundingBox>,
 }
 
 impl<M: Material> Instance<M> {
-    pub fn new(triangles: Arc<BvhNode>, translation: V3, rotation: V3, scale: V3) -> Self {
-        let inv_translation = translation * -1.0;
-        let inv_rotation = rotation * -1.0;
-        let inv_scale = 1.0 / scale;
-
+    pub fn new(object: Arc<dyn Intersect>, translation: V3, rotation: V3, scale: V3) -> Self {
         let translation = M4::translation(translation);
-        let inv_translation = M4::translation(inv_translation);
 
         let rotation_x = M4::rotate_x(rotation.x());
         let rotation_y = M4::rotate_y(rotation.y());
         let rotation_z = M4::rotate_z(rotation.z());
-
-        let inv_rotation_x = M4::rotate_x(inv_rotation.x());
-        let inv_rotation_y = M4::rotate_y(inv_rotation.y());
-        let inv_rotation_z = M4::rotate_z(inv_rotation.z());
-
         let rotation = rotation_x * rotation_y * rotation_z;
-        let inv_rotation = inv_rotation_z * inv_rotation_y * inv_rotation_x;
 
         let scale = M4::scale(scale);
-        let inv_scale = M4::scale(inv_scale);
 
-        let transform = translation * rotation * scale;
-        let inv_transform = inv_scale * inv_rotation * inv_translation;
+        Self::from_transform(object, translation * rotation * scale)
+    }
 
-        let mut minimum = V3::fill(f32::INFINITY);
-        let mut maximum = V3::fill(f32::NEG_INFINITY);
+    /// Instances a single object such as a `Sphere` or `Volume`, use `new` with a cloned `Arc`
+    /// to share one object between several instances.
+    pub fn of<I: 'static + Intersect>(object: I, translation: V3, rotation: V3, scale: V3) -> Self {
+        Self::new(Arc::new(object), translation, rotation, scale)
+    }
 
-        for corner in triangles
-            .bounding_box
-            .corners()
-            .map(|c| transform.transform_point(c))
-        {
-            minimum = minimum.min(corner);
-            maximum = maximum.max(corner);
-        }
+    pub fn from_transform(object: Arc<dyn Intersect>, transform: M4) -> Self {
+        let inv_transform = transform
+            .inverse()
+            .expect("instance transform is not invertible");
+        let normal_transform = inv_transform.transpose();
 
-        let bounding_box = BoundingBox::new(minimum, maximum);
+        let bounding_box = object
+            .bounding_box()
+            .map(|bounding_box| bounding_box.transform(transform));
 
         Self {
-            triangles,
+            object,
             material: None,
             transform,
             inv_transform,
+            normal_transform,
+            motion: None,
+            fade: None,
             bounding_box,
         }
     }
 
-    pub fn with_material<IM: Material>(self, material: IM) -> Instance<IM> {
-        Instance {
-            triangles: self.triangles,
-            material: Some(material),
-            transform: self.transform,
-            inv_transform: self.inv_transform,
-            bounding_box: self.bounding_box,
-        }
+    /// Moves the instance from its transform at time 0.0 to `end` at time 1.0. The matrices are
+    /// interpolated directly, so large rotations within one shutter interval will shear.
+    pub fn with_motion(mut self, end: M4) -> Self {
+        let start = self.transform;
+        self.bounding_box = self.object.bounding_box().map(|bounding_box| {
+            bounding_box
+                .transform(start)
+                .join(bounding_box.transform(end))
+        });
+        self.motion = Some(end);
+        self
+    }
+
+    /// Applies `parent` on top of the existing transform.
+    pub fn transformed(self, parent: M4) -> Self {
+        let motion = self.motion;
+        let mut instance = Self::from_transform(self.object, parent * self.transform);
+        if let Some(end) = motion {
+            instance = instance.with_motion(parent * end);
+        }
+
+        Self {
+            material: self.material,
+            fade: self.fade,
+            ..instance
+        }
+    }
+
+    /// Stochastically fades the instance, only a fraction `opacity` of camera paths will see
+    /// it. Averaged over samples this dissolves the instance in or out without any blending in
+    /// the materials.
+    pub fn with_opacity(self, opacity: f32) -> Self {
+        self.with_fade_band(0.0, opacity)
+    }
+
+    /// Shows the instance only to camera paths whose fade sample lies in `start..end`. Giving
+    /// two LOD levels adjacent bands, such as `0.0..1.0 - t` and `1.0 - t..1.0`, cross-dissolves
+    /// between them with every path seeing exactly one of the two.
+    pub fn with_fade_band(mut self, start: f32, end: f32) -> Self {
+        self.fade = if start <= 0.0 && end >= 1.0 {
+            None
+        } else {
+            Some((start, end))
+        };
+        self
+    }
+
+    pub fn transform(&self) -> M4 {
+        self.transform
+    }
+
+    pub fn with_material<IM: Material>(self, material: IM) -> Instance<IM> {
+        Instance {
+            object: self.object,
+            material: Some(material),
+            transform: self.transform,
+            inv_transform: self.inv_transform,
+            normal_transform: self.normal_transform,
+            motion: self.motion,
+            fade: self.fade,
+            bounding_box: self.bounding_box,
+        }
+    }
+}
+
+impl<M: Material> Intersect for Instance<M> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        if let Some((start, end)) = self.fade {
+            if ray.fade < start || ray.fade >= end {
+                return None;
+            }
+        }
+
+        let (transform, inv_transform, normal_transform) = match self.motion {
+            Some(end) if ray.time != 0.0 => {
+                let transform = self.transform.lerp(end, ray.time.min(1.0).max(0.0));
+                let inv_transform = transform.inverse()?;
+                (transform, inv_transform, inv_transform.transpose())
+            }
+            _ => (self.transform, self.inv_transform, self.normal_transform),
+        };
+
+        let ray = Ray {
+            origin: inv_transform.transform_point(ray.origin),
+            direction: inv_transform.transform_vector(ray.direction),
+            ..ray
+        };
+        let mut t_min = t_min;
+
+        // Faces the instance's material cuts out or culls are skipped by casting again from just
+        // past them
+        loop {
+            let mut hit = self.object.intersect(ray, t_min, t_max)?;
+            hit.point = transform.transform_point(hit.point);
+            hit.normal = normal_transform.transform_vector(hit.normal).unit();
+            hit.tangent = hit
+                .tangent
+                .map(|tangent| transform.transform_vector(tangent).unit());
+            hit.bitangent = hit
+                .bitangent
+                .map(|bitangent| transform.transform_vector(bitangent).unit());
+            match self.material.as_ref() {
+                Some(material) => {
+                    hit.material = material;
+                    if !hit.passes_through() {
+                        return Some(hit);
+                    }
+                }
+                None => return Some(hit),
+            }
+
+            t_min = hit.t + 0.0001;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.bounding_box
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.object.primitive_count()
     }
-}
 
-impl<M: Material> Intersect for Instance<M> {
-    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
-        let ray = Ray::new(
-            self.inv_transform.transform_point(ray.origin),
-            self.inv_transform.transform_vector(ray.direction),
-        );
-        let hit = self.triangles.intersect(ray, t_min, t_max);
-        if let Some(mut hit) = hit {
-            hit.point = self.transform.transform_point(hit.point);
-            hit.normal = self.transform.transform_vector(hit.normal).unit();
-            if let Some(material) = self.material.as_ref() {
-                hit.material = material;
+    /// Triangles are placed with the transform at time 0.0.
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        self.object.visit_triangles(&mut |vertices, material| {
+            let vertices = vertices.map(|v| self.transform.transform_point(v));
+            match self.material.as_ref() {
+                Some(instance_material) => visit(vertices, instance_material),
+                None => visit(vertices, material),
             }
-            Some(hit)
-        } else {
-            None
-        }
+        });
     }
 
-    fn bounding_box(&self) -> Option<BoundingBox> {
-        Some(self.bounding_box)
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.object.memory_bytes()
     }
 }
 
@@ -431,12 +1390,20 @@ struct UV {
     uv_c: V2,
 }
 
+#[derive(Copy, Clone, Debug)]
+struct Colors {
+    color_a: V3,
+    color_b: V3,
+    color_c: V3,
+}
+
 #[derive(Clone)]
 pub struct Triangle<M: Material> {
     vertex_a: V3,
     vertex_b: V3,
     vertex_c: V3,
     uvs: Option<UV>,
+    colors: Option<Colors>,
     material: M,
     normal_a: V3,
     normal_b: V3,
@@ -457,6 +1424,7 @@ impl<M: Material> Triangle<M> {
             vertex_b,
             vertex_c,
             uvs: None,
+            colors: None,
             normal_a: normal,
             normal_b: normal,
             normal_c: normal,
@@ -487,6 +1455,7 @@ impl<M: Material> Triangle<M> {
             vertex_b,
             vertex_c,
             uvs: Some(UV { uv_a, uv_b, uv_c }),
+            colors: None,
             normal_a,
             normal_b,
             normal_c,
@@ -495,11 +1464,36 @@ impl<M: Material> Triangle<M> {
         }
     }
 
+    pub fn with_colors(mut self, color_a: V3, color_b: V3, color_c: V3) -> Self {
+        self.colors = Some(Colors {
+            color_a,
+            color_b,
+            color_c,
+        });
+        self
+    }
+
     pub fn vertices(&self) -> (V3, V3, V3) {
         (self.vertex_a, self.vertex_b, self.vertex_c)
     }
 }
 
+/// The width in texture coordinates of `ray`'s footprint where it meets a triangle with edges
+/// `ab` and `ac` at `t`, stretched by how steeply it meets the surface.
+fn uv_footprint(ray: Ray, t: f32, ab: V3, ac: V3, uv_ab: V2, uv_ac: V2) -> f32 {
+    let width = ray.footprint_at(t);
+    let face = ab.cross(ac);
+    let area = face.length();
+    if width <= 0.0 || area <= 0.0 {
+        return 0.0;
+    }
+
+    let uv_area = (uv_ab.x() * uv_ac.y() - uv_ab.y() * uv_ac.x()).abs();
+    let cos = (face.dot(ray.direction) / (area * ray.direction.length())).abs();
+
+    width * (uv_area / area).sqrt() / cos.max(0.01)
+}
+
 impl<M: Material> Intersect for Triangle<M> {
     fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
         let ab = self.vertex_b - self.vertex_a;
@@ -548,8 +1542,9 @@ impl<M: Material> Intersect for Triangle<M> {
 
         let normal = self.normal_a * a0 + self.normal_b * a1 + self.normal_c * a2;
 
-        let (normal, uv) = if let Some(uvs) = &self.uvs {
+        let (normal, uv, footprint) = if let Some(uvs) = &self.uvs {
             let uv = uvs.uv_a * a0 + uvs.uv_b * a1 + uvs.uv_c * a2;
+            let footprint = uv_footprint(ray, t, ab, ac, uvs.uv_b - uvs.uv_a, uvs.uv_c - uvs.uv_a);
 
             let normal = if let Some(tan_normal) = self.material.normal(uv) {
                 self.tangent * tan_normal.x()
@@ -559,28 +1554,37 @@ impl<M: Material> Intersect for Triangle<M> {
                 normal
             };
 
-            (normal, Some(uv))
+            (normal, Some(uv), footprint)
         } else {
-            (normal, None)
+            (normal, None, 0.0)
         };
 
-        if let Some(uv) = &uv {
-            if !self.material.alpha_test(*uv) {
-                return None;
-            }
-        }
+        let color = self
+            .colors
+            .as_ref()
+            .map(|c| c.color_a * a0 + c.color_b * a1 + c.color_c * a2);
 
         let mut hit = Hit {
             point,
             normal,
             t,
             uv,
+            color,
+            tangent: uv.map(|_| self.tangent),
+            bitangent: uv.map(|_| self.bitangent),
             front_face: false,
             material: &self.material,
+            object: None,
+            footprint,
+            local_point: point,
         };
 
         hit.set_face_normal(ray, normal);
 
+        if hit.passes_through() {
+            return None;
+        }
+
         Some(hit)
     }
 
@@ -590,65 +1594,262 @@ impl<M: Material> Intersect for Triangle<M> {
 
         Some(BoundingBox::new(min, max))
     }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        visit(
+            [self.vertex_a, self.vertex_b, self.vertex_c],
+            &self.material,
+        );
+    }
 }
 
-pub struct Volume<I: Intersect> {
-    neg_inv_density: f32,
-    target: I,
-    material: Isotrophic,
+pub struct Mesh<M: Material> {
+    material: M,
+    vertices: Vec<V3>,
+    normals: Option<Vec<V3>>,
+    uvs: Option<Vec<V2>>,
+    faces: Vec<[u32; 3]>,
 }
 
-impl<I: Intersect> Volume<I> {
-    pub fn new(target: I, density: f32, albedo: V3) -> Self {
+impl<M: Material> Mesh<M> {
+    pub fn new(material: M, vertices: Vec<V3>, faces: Vec<[u32; 3]>) -> Self {
         Self {
-            target,
-            neg_inv_density: -1.0 / density,
-            material: Isotrophic::new(albedo),
+            material,
+            vertices,
+            normals: None,
+            uvs: None,
+            faces,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<V3>) -> Self {
+        assert_eq!(self.vertices.len(), normals.len());
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: Vec<V2>) -> Self {
+        assert_eq!(self.vertices.len(), uvs.len());
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn triangles(self: &Arc<Self>) -> impl Iterator<Item = MeshTriangle<M>> + '_ {
+        (0..self.faces.len() as u32).map(move |face| MeshTriangle {
+            mesh: self.clone(),
+            face,
+        })
+    }
+
+    /// Shades the hit at barycentric `u`, `v` on `face`, returns `None` if the ray passes
+    /// through it.
+    pub(crate) fn face_hit(&self, face: u32, ray: Ray, t: f32, u: f32, v: f32) -> Option<Hit<'_>> {
+        let [index_a, index_b, index_c] = self.faces[face as usize];
+        let (vertex_a, vertex_b, vertex_c) = self.face_vertices(face);
+        let ab = vertex_b - vertex_a;
+        let ac = vertex_c - vertex_a;
+
+        let a0 = 1.0 - u - v;
+        let a1 = u;
+        let a2 = v;
+
+        let normal = if let Some(normals) = &self.normals {
+            normals[index_a as usize] * a0
+                + normals[index_b as usize] * a1
+                + normals[index_c as usize] * a2
+        } else {
+            ab.cross(ac).unit()
+        };
+
+        let (normal, uv, tangent, bitangent, footprint) = if let Some(uvs) = &self.uvs {
+            let uv_a = uvs[index_a as usize];
+            let uv_b = uvs[index_b as usize];
+            let uv_c = uvs[index_c as usize];
+            let uv = uv_a * a0 + uv_b * a1 + uv_c * a2;
+
+            let uv_ab = uv_b - uv_a;
+            let uv_ac = uv_c - uv_a;
+            let r = (1.0 / (uv_ab.x() * uv_ac.y() - uv_ab.y() * uv_ac.x()))
+                .min(1.0)
+                .max(-1.0);
+            let tangent = (ab * uv_ac.y() - ac * uv_ab.y()) * r;
+            let bitangent = (ac * uv_ab.x() - ab * uv_ac.x()) * r;
+
+            let normal = if let Some(tan_normal) = self.material.normal(uv) {
+                tangent * tan_normal.x() + bitangent * tan_normal.y() + normal * tan_normal.z()
+            } else {
+                normal
+            };
+
+            let footprint = uv_footprint(ray, t, ab, ac, uv_ab, uv_ac);
+
+            (normal, Some(uv), Some(tangent), Some(bitangent), footprint)
+        } else {
+            (normal, None, None, None, 0.0)
+        };
+
+        let mut hit = Hit {
+            point: ray.at(t),
+            normal,
+            t,
+            uv,
+            color: None,
+            tangent,
+            bitangent,
+            front_face: false,
+            material: &self.material,
+            object: None,
+            footprint,
+            local_point: ray.at(t),
+        };
+
+        hit.set_face_normal(ray, normal);
+
+        if hit.passes_through() {
+            return None;
         }
+
+        Some(hit)
+    }
+
+    pub(crate) fn vertices(&self) -> &[V3] {
+        &self.vertices
+    }
+
+    pub(crate) fn faces(&self) -> &[[u32; 3]] {
+        &self.faces
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self.vertices.capacity() * std::mem::size_of::<V3>()
+            + self.normals.as_ref().map_or(0, |n| n.capacity()) * std::mem::size_of::<V3>()
+            + self.uvs.as_ref().map_or(0, |uv| uv.capacity()) * std::mem::size_of::<V2>()
+            + self.faces.capacity() * std::mem::size_of::<[u32; 3]>()
+    }
+
+    fn face_vertices(&self, face: u32) -> (V3, V3, V3) {
+        let [a, b, c] = self.faces[face as usize];
+        (
+            self.vertices[a as usize],
+            self.vertices[b as usize],
+            self.vertices[c as usize],
+        )
     }
 }
 
-impl<I: Intersect> Intersect for Volume<I> {
+pub struct MeshTriangle<M: Material> {
+    mesh: Arc<Mesh<M>>,
+    face: u32,
+}
+
+impl<M: Material> MeshTriangle<M> {
+    pub fn vertices(&self) -> (V3, V3, V3) {
+        self.mesh.face_vertices(self.face)
+    }
+}
+
+impl<M: Material> Intersect for MeshTriangle<M> {
     fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
-        let mut hit_enter = self
-            .target
-            .intersect(ray, f32::NEG_INFINITY, f32::INFINITY)?;
+        let mesh = &*self.mesh;
+        let (vertex_a, vertex_b, vertex_c) = mesh.face_vertices(self.face);
 
-        let mut hit_exit = self
-            .target
-            .intersect(ray, hit_enter.t + 0.0001, f32::INFINITY)?;
+        let ab = vertex_b - vertex_a;
+        let ac = vertex_c - vertex_a;
 
-        if hit_enter.t < t_min {
-            hit_enter.t = t_min;
-        }
-        if hit_exit.t > t_max {
-            hit_exit.t = t_max;
+        let p_vec = ray.direction.cross(ac);
+        let det = ab.dot(p_vec);
+
+        if det.abs() < 0.000001 {
+            return None;
         }
 
-        if hit_enter.t >= hit_exit.t {
+        let inv_det = 1.0 / det;
+
+        let t_vec = ray.origin - vertex_a;
+        let u = t_vec.dot(p_vec) * inv_det;
+        if u < 0.0 || u > 1.0 {
             return None;
         }
 
-        if hit_enter.t < 0.0 {
-            hit_enter.t = 0.0;
+        let q_vec = t_vec.cross(ab);
+        let v = ray.direction.dot(q_vec) * inv_det;
+        if v < 0.0 || v + u > 1.0 {
+            return None;
         }
 
-        let ray_length = ray.direction.length();
-        let distince_inside_target = (hit_exit.t - hit_enter.t) * ray_length;
-        let hit_distance = f32::rand().ln() * self.neg_inv_density;
+        let t = ac.dot(q_vec) * inv_det;
 
-        if hit_distance > distince_inside_target {
+        if t < t_min || t > t_max {
             return None;
         }
 
-        let t = hit_enter.t + hit_distance / ray_length;
+        mesh.face_hit(self.face, ray, t, u, v)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let (vertex_a, vertex_b, vertex_c) = self.vertices();
+        let min = vertex_a.min(vertex_b).min(vertex_c);
+        let max = vertex_a.max(vertex_b).max(vertex_c);
+
+        Some(BoundingBox::new(min, max))
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        let (vertex_a, vertex_b, vertex_c) = self.vertices();
+        visit([vertex_a, vertex_b, vertex_c], &self.mesh.material);
+    }
+
+    /// Each triangle counts its share of the mesh.
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.mesh.memory_bytes() / self.mesh.faces.len().max(1)
+    }
+}
+
+pub struct Volume<I: Intersect> {
+    neg_inv_density: f32,
+    target: I,
+    material: Isotrophic,
+}
+
+impl<I: Intersect> Volume<I> {
+    pub fn new(target: I, density: f32, albedo: V3) -> Self {
+        Self {
+            target,
+            neg_inv_density: -1.0 / density,
+            material: Isotrophic::new(albedo),
+        }
+    }
+}
+
+impl<I: Intersect> Intersect for Volume<I> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let ray_length = ray.direction.length();
+        let mut hit_distance = f32::rand().ln() * self.neg_inv_density;
+
+        let t = walk_intervals(&self.target, ray, t_min, t_max, |t_enter, t_exit| {
+            let distance_inside_target = (t_exit - t_enter) * ray_length;
+            if hit_distance > distance_inside_target {
+                hit_distance -= distance_inside_target;
+                None
+            } else {
+                Some(t_enter + hit_distance / ray_length)
+            }
+        })?;
+
         let hit = Hit {
             point: ray.at(t),
             normal: V3::new(1.0, 0.0, 0.0),
             uv: None,
+            color: None,
+            tangent: None,
+            bitangent: None,
             t,
             front_face: true,
             material: &self.material,
+            object: None,
+            footprint: 0.0,
+            local_point: ray.at(t),
         };
 
         Some(hit)
@@ -657,4 +1858,284 @@ impl<I: Intersect> Intersect for Volume<I> {
     fn bounding_box(&self) -> Option<BoundingBox> {
         self.target.bounding_box()
     }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) - std::mem::size_of_val(&self.target)
+            + self.target.memory_bytes()
+    }
+}
+
+pub struct Cuboid<M: Material> {
+    bounds: BoundingBox,
+    material: M,
+}
+
+impl<M: Material> Cuboid<M> {
+    pub fn new(material: M, minimum: V3, maximum: V3) -> Self {
+        Self {
+            bounds: BoundingBox::new(minimum.min(maximum), minimum.max(maximum)),
+            material,
+        }
+    }
+}
+
+impl<M: Material> Intersect for Cuboid<M> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let v_min = (self.bounds.minimum - ray.origin) / ray.direction;
+        let v_max = (self.bounds.maximum - ray.origin) / ray.direction;
+
+        let near = v_min.min(v_max);
+        let far = v_min.max(v_max);
+
+        let t_near = near.x().max(near.y()).max(near.z());
+        let t_far = far.x().min(far.y()).min(far.z());
+
+        if t_far < t_near {
+            return None;
+        }
+
+        // The far side shows through where the near side is culled or cut out
+        let sides = [(t_near, near), (t_far, far)];
+        sides
+            .iter()
+            .filter(|&&(t, _)| t >= t_min && t <= t_max)
+            .find_map(|&(t, planes)| {
+                let normal = if planes.x() == t {
+                    V3::new(1.0, 0.0, 0.0)
+                } else if planes.y() == t {
+                    V3::new(0.0, 1.0, 0.0)
+                } else {
+                    V3::new(0.0, 0.0, 1.0)
+                };
+                let point = ray.at(t);
+                let center = (self.bounds.minimum + self.bounds.maximum) / 2.0;
+                let outward_normal = if (point - center).dot(normal) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+
+                let mut hit = Hit {
+                    point,
+                    normal: outward_normal,
+                    t,
+                    uv: None,
+                    color: None,
+                    tangent: None,
+                    bitangent: None,
+                    front_face: false,
+                    material: &self.material,
+                    object: None,
+                    footprint: 0.0,
+                    local_point: point,
+                };
+
+                hit.set_face_normal(ray, outward_normal);
+
+                if hit.passes_through() {
+                    None
+                } else {
+                    Some(hit)
+                }
+            })
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.bounds)
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        let (min, max) = (self.bounds.minimum, self.bounds.maximum);
+        let corner = |i: usize| {
+            V3::new(
+                if i & 1 == 0 { min.x() } else { max.x() },
+                if i & 2 == 0 { min.y() } else { max.y() },
+                if i & 4 == 0 { min.z() } else { max.z() },
+            )
+        };
+
+        // Corners of each face wound counter clockwise seen from outside
+        const FACES: [[usize; 4]; 6] = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ];
+        for [a, b, c, d] in FACES {
+            visit([corner(a), corner(b), corner(c)], &self.material);
+            visit([corner(a), corner(c), corner(d)], &self.material);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Density {
+    Uniform(f32),
+    /// `density` on the y = 0 plane of the gradient's space, falling off exponentially above it.
+    Falloff {
+        density: f32,
+        falloff: f32,
+        inv_transform: M4,
+    },
+}
+
+impl Density {
+    pub fn uniform(density: f32) -> Self {
+        Density::Uniform(density)
+    }
+
+    /// Ground fog that thins out by a factor of e every `1.0 / falloff` units above the y = 0
+    /// plane of `transform`.
+    pub fn falloff(density: f32, falloff: f32, transform: M4) -> Self {
+        Density::Falloff {
+            density,
+            falloff,
+            inv_transform: transform
+                .inverse()
+                .expect("density transform is not invertible"),
+        }
+    }
+
+    pub fn at(&self, point: V3) -> f32 {
+        match *self {
+            Density::Uniform(density) => density,
+            Density::Falloff {
+                density,
+                falloff,
+                inv_transform,
+            } => {
+                let height = inv_transform.transform_point(point).y();
+                density * (-falloff * height).exp()
+            }
+        }
+    }
+
+    /// The density is exponential along a single axis, so its maximum within a box is always
+    /// at one of the corners.
+    fn max_in(&self, bounds: BoundingBox) -> f32 {
+        bounds
+            .corners()
+            .map(|c| self.at(c))
+            .fold(0.0, |max: f32, d| max.max(d))
+    }
+}
+
+/// Participating media bounded by `boundary` with densities measured in world units. Varying
+/// densities are sampled with delta tracking against the densest point of the bounds.
+pub struct Fog<I: Intersect> {
+    boundary: I,
+    density: Density,
+    majorant: f32,
+    material: Isotrophic,
+}
+
+impl<I: Intersect> Fog<I> {
+    pub fn new(boundary: I, density: Density, albedo: V3) -> Self {
+        let bounds = boundary
+            .bounding_box()
+            .expect("fog boundary must be bounded");
+        let majorant = density.max_in(bounds);
+
+        Self {
+            boundary,
+            density,
+            majorant,
+            material: Isotrophic::new(albedo),
+        }
+    }
+}
+
+impl<I: Intersect> Intersect for Fog<I> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        let ray_length = ray.direction.length();
+
+        walk_intervals(&self.boundary, ray, t_min, t_max, |t_enter, t_exit| {
+            let mut t = t_enter;
+            loop {
+                t -= (1.0 - f32::rand()).ln() / (self.majorant * ray_length);
+                if t >= t_exit {
+                    return None;
+                }
+
+                let point = ray.at(t);
+                if f32::rand() * self.majorant < self.density.at(point) {
+                    return Some(Hit {
+                        point,
+                        normal: V3::new(1.0, 0.0, 0.0),
+                        uv: None,
+                        color: None,
+                        tangent: None,
+                        bitangent: None,
+                        t,
+                        front_face: true,
+                        material: &self.material,
+                        object: None,
+                        footprint: 0.0,
+                        local_point: point,
+                    });
+                }
+            }
+        })
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.boundary.bounding_box()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) - std::mem::size_of_val(&self.boundary)
+            + self.boundary.memory_bytes()
+    }
+}
+
+/// Walks the spans of `ray` that lie inside the closed surface `target`, clipped to `t_min`
+/// and `t_max`, until `visit` returns a value. Every boundary crossing is followed so
+/// non-convex targets that the ray enters several times are handled, crossings are matched by
+/// nesting depth so overlapping shells are treated as one interior.
+fn walk_intervals<I: Intersect + ?Sized, T>(
+    target: &I,
+    ray: Ray,
+    t_min: f32,
+    t_max: f32,
+    mut visit: impl FnMut(f32, f32) -> Option<T>,
+) -> Option<T> {
+    let t_min = t_min.max(0.0);
+    let mut depth = 0i32;
+    let mut entered = f32::NEG_INFINITY;
+    let mut t = f32::NEG_INFINITY;
+
+    while let Some(hit) = target.intersect(ray, t, f32::INFINITY) {
+        if hit.front_face {
+            if depth == 0 {
+                entered = hit.t;
+            }
+            depth += 1;
+        } else {
+            depth -= 1;
+            if depth <= 0 {
+                depth = 0;
+                let t_enter = entered.max(t_min);
+                let t_exit = hit.t.min(t_max);
+                if t_enter < t_exit {
+                    if let Some(result) = visit(t_enter, t_exit) {
+                        return Some(result);
+                    }
+                }
+            }
+        }
+
+        if hit.t >= t_max {
+            break;
+        }
+
+        t = hit.t + 0.0001;
+    }
+
+    None
 }
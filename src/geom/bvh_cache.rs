@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+const CACHE_DIR: &str = "./cache";
+const MAGIC: &[u8; 4] = b"BVHC";
+const VERSION: u32 = 1;
+
+/// Derives a cache key from the contents of the file a model was loaded from.
+pub fn source_key<P: AsRef<Path>>(path: P) -> std::io::Result<u64> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut buf = [0; 64 * 1024];
+
+    // FNV-1a, stable across builds unlike the std hasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        for &byte in &buf[..read] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    Ok(hash)
+}
+
+fn cache_path(key: u64) -> PathBuf {
+    Path::new(CACHE_DIR).join(format!("bvh_{:016x}.bin", key))
+}
+
+/// Returns the cached layout for `key`, or `None` if it is missing, unreadable, or was built
+/// for a different number of items.
+pub(super) fn load(key: u64, item_count: usize) -> Option<Vec<u32>> {
+    let mut file = BufReader::new(File::open(cache_path(key)).ok()?);
+
+    let read = |file: &mut BufReader<File>| -> std::io::Result<Option<Vec<u32>>> {
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC
+            || file.read_u32::<LittleEndian>()? != VERSION
+            || file.read_u64::<LittleEndian>()? != key
+            || file.read_u64::<LittleEndian>()? != item_count as u64
+        {
+            return Ok(None);
+        }
+
+        let len = file.read_u64::<LittleEndian>()? as usize;
+        let mut layout = vec![0; len];
+        file.read_u32_into::<LittleEndian>(&mut layout)?;
+        Ok(Some(layout))
+    };
+
+    let layout = read(&mut file).ok().flatten()?;
+    if super::valid_layout(&layout, item_count) {
+        Some(layout)
+    } else {
+        None
+    }
+}
+
+pub(super) fn store(key: u64, item_count: usize, layout: &[u32]) -> std::io::Result<()> {
+    std::fs::create_dir_all(CACHE_DIR)?;
+    let mut file = BufWriter::new(File::create(cache_path(key))?);
+
+    file.write_all(MAGIC)?;
+    file.write_u32::<LittleEndian>(VERSION)?;
+    file.write_u64::<LittleEndian>(key)?;
+    file.write_u64::<LittleEndian>(item_count as u64)?;
+    file.write_u64::<LittleEndian>(layout.len() as u64)?;
+    for &token in layout {
+        file.write_u32::<LittleEndian>(token)?;
+    }
+
+    file.flush()
+}
@@ -0,0 +1,151 @@
+use super::Triangle;
+use crate::material::Material;
+use crate::math::{V2, V3};
+use crate::texture::Surface;
+
+/// A point of a subdivided triangle with its normal, uv and vertex color.
+type Corner = (V3, V3, V2, Option<V3>);
+
+/// Moves the surface of triangles along their normals by a grayscale height map, splitting
+/// them into a grid of smaller triangles first so the detail shows in silhouettes and shadows.
+/// Triangles without texture coordinates are left as they are.
+pub struct Displacement<S: Surface> {
+    height: S,
+    scale: f32,
+    midlevel: f32,
+    segments: u32,
+}
+
+impl<S: Surface> Displacement<S> {
+    /// Raises white texels of `height` by `scale`, every edge is split into `2^levels`
+    /// segments.
+    pub fn new(height: S, scale: f32, levels: u32) -> Self {
+        Self {
+            height,
+            scale,
+            midlevel: 0.0,
+            segments: 1 << levels.min(8),
+        }
+    }
+
+    /// The height left in place, darker texels are pushed in below the surface.
+    pub fn with_midlevel(self, midlevel: f32) -> Self {
+        Self { midlevel, ..self }
+    }
+
+    /// Subdivides and displaces `triangle`, triangles sharing an edge are split at the same
+    /// points so they stay joined.
+    pub fn displace<M: Material + Clone>(&self, triangle: &Triangle<M>) -> Vec<Triangle<M>> {
+        let uvs = match triangle.uvs {
+            Some(uvs) => uvs,
+            None => return vec![triangle.clone()],
+        };
+
+        let ab = triangle.vertex_b - triangle.vertex_a;
+        let ac = triangle.vertex_c - triangle.vertex_a;
+        let uv_ab = uvs.uv_b - uvs.uv_a;
+        let uv_ac = uvs.uv_c - uvs.uv_a;
+        let det = uv_ab.x() * uv_ac.y() - uv_ab.y() * uv_ac.x();
+        // The change in position along u and v, which the slope of the heights tilts
+        let derivatives = if det.abs() > f32::EPSILON {
+            Some((
+                (ab * uv_ac.y() - ac * uv_ab.y()) / det,
+                (ac * uv_ab.x() - ab * uv_ac.x()) / det,
+            ))
+        } else {
+            None
+        };
+
+        let n = self.segments;
+        let rows: Vec<Vec<Corner>> = (0..=n)
+            .map(|j| {
+                (0..=n - j)
+                    .map(|i| {
+                        let a1 = i as f32 / n as f32;
+                        let a2 = j as f32 / n as f32;
+                        let a0 = 1.0 - a1 - a2;
+
+                        let point = triangle.vertex_a * a0
+                            + triangle.vertex_b * a1
+                            + triangle.vertex_c * a2;
+                        let normal = (triangle.normal_a * a0
+                            + triangle.normal_b * a1
+                            + triangle.normal_c * a2)
+                            .unit();
+                        let uv = uvs.uv_a * a0 + uvs.uv_b * a1 + uvs.uv_c * a2;
+                        let color = triangle
+                            .colors
+                            .map(|c| c.color_a * a0 + c.color_b * a1 + c.color_c * a2);
+
+                        let point = point + normal * self.offset(uv);
+                        let normal = derivatives.map_or(normal, |(dp_du, dp_dv)| {
+                            self.displaced_normal(uv, normal, dp_du, dp_dv)
+                        });
+
+                        (point, normal, uv, color)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let corner = |i: u32, j: u32| rows[j as usize][i as usize];
+        let build = |a: Corner, b: Corner, c: Corner| {
+            let split = Triangle::with_norms_and_uvs(
+                triangle.material.clone(),
+                (a.0, a.1, a.2),
+                (b.0, b.1, b.2),
+                (c.0, c.1, c.2),
+            );
+            match (a.3, b.3, c.3) {
+                (Some(color_a), Some(color_b), Some(color_c)) => {
+                    split.with_colors(color_a, color_b, color_c)
+                }
+                _ => split,
+            }
+        };
+
+        let mut triangles = Vec::with_capacity((n * n) as usize);
+        for j in 0..n {
+            for i in 0..n - j {
+                triangles.push(build(corner(i, j), corner(i + 1, j), corner(i, j + 1)));
+                if i + j + 1 < n {
+                    triangles.push(build(
+                        corner(i + 1, j),
+                        corner(i + 1, j + 1),
+                        corner(i, j + 1),
+                    ));
+                }
+            }
+        }
+
+        triangles
+    }
+
+    /// How far the surface at `uv` moves along its normal.
+    fn offset(&self, uv: V2) -> f32 {
+        (self.height.get_f(uv).x() - self.midlevel) * self.scale
+    }
+
+    /// The normal of the displaced surface, from the central difference of the offsets a texel
+    /// either side of `uv`.
+    fn displaced_normal(&self, uv: V2, normal: V3, dp_du: V3, dp_dv: V3) -> V3 {
+        let du = 1.0 / self.height.width().max(1) as f32;
+        let dv = 1.0 / self.height.height().max(1) as f32;
+        let slope_u =
+            (self.offset(uv + V2::new(du, 0.0)) - self.offset(uv - V2::new(du, 0.0))) / (2.0 * du);
+        let slope_v =
+            (self.offset(uv + V2::new(0.0, dv)) - self.offset(uv - V2::new(0.0, dv))) / (2.0 * dv);
+
+        let displaced = (dp_du + normal * slope_u).cross(dp_dv + normal * slope_v);
+        if displaced.near_zero() {
+            return normal;
+        }
+
+        let displaced = displaced.unit();
+        if displaced.dot(normal) < 0.0 {
+            -displaced
+        } else {
+            displaced
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use super::{Accelerator, BoundingBox, Hit, Intersect};
+use crate::material::Material;
+use crate::math::V3;
+use crate::world::{Ray, TraversalRay};
+
+const MAX_RESOLUTION: usize = 128;
+
+/// A uniform voxel grid where each cell lists the primitives overlapping it, traversed with a
+/// 3D DDA. Suits evenly distributed content better than a BVH.
+pub struct Grid {
+    items: Vec<Box<dyn Intersect>>,
+    cell_offsets: Vec<u32>,
+    cell_items: Vec<u32>,
+    resolution: [usize; 3],
+    bounds: BoundingBox,
+    cell_size: [f32; 3],
+}
+
+impl Grid {
+    pub fn new(items: Vec<Box<dyn Intersect>>) -> Self {
+        let item_bounds: Vec<BoundingBox> = items
+            .iter()
+            .map(|i| i.bounding_box().expect("Missing bounding box in grid"))
+            .collect();
+
+        let bounds = item_bounds
+            .iter()
+            .copied()
+            .reduce(|a, b| a.join(b))
+            .expect("Grid requires at least one item");
+
+        let size = bounds.size();
+        let size = [size.x(), size.y(), size.z()];
+        let max_extent = size[0].max(size[1]).max(size[2]).max(f32::EPSILON);
+        let cells_per_unit = (3.0 * items.len() as f32).cbrt() / max_extent;
+
+        let mut resolution = [1; 3];
+        let mut cell_size = [0.0; 3];
+        for axis in 0..3 {
+            resolution[axis] =
+                ((size[axis] * cells_per_unit).round() as usize).clamp(1, MAX_RESOLUTION);
+            cell_size[axis] = (size[axis] / resolution[axis] as f32).max(f32::EPSILON);
+        }
+
+        let cell_count = resolution[0] * resolution[1] * resolution[2];
+        let mut cells = vec![Vec::new(); cell_count];
+        let minimum = bounds.minimum();
+        let minimum = [minimum.x(), minimum.y(), minimum.z()];
+
+        let cell_of = |value: f32, axis: usize| {
+            (((value - minimum[axis]) / cell_size[axis]) as usize).min(resolution[axis] - 1)
+        };
+
+        for (index, item) in item_bounds.iter().enumerate() {
+            let (lo, hi) = (item.minimum(), item.maximum());
+            let (lo, hi) = ([lo.x(), lo.y(), lo.z()], [hi.x(), hi.y(), hi.z()]);
+
+            for z in cell_of(lo[2], 2)..=cell_of(hi[2], 2) {
+                for y in cell_of(lo[1], 1)..=cell_of(hi[1], 1) {
+                    for x in cell_of(lo[0], 0)..=cell_of(hi[0], 0) {
+                        let cell = (z * resolution[1] + y) * resolution[0] + x;
+                        cells[cell].push(index as u32);
+                    }
+                }
+            }
+        }
+
+        let mut cell_offsets = Vec::with_capacity(cell_count + 1);
+        let mut cell_items = Vec::new();
+        cell_offsets.push(0);
+        for cell in cells {
+            cell_items.extend(cell);
+            cell_offsets.push(cell_items.len() as u32);
+        }
+
+        Self {
+            items,
+            cell_offsets,
+            cell_items,
+            resolution,
+            bounds,
+            cell_size,
+        }
+    }
+
+    fn cell(&self, x: usize, y: usize, z: usize) -> &[u32] {
+        let cell = (z * self.resolution[1] + y) * self.resolution[0] + x;
+        let start = self.cell_offsets[cell] as usize;
+        let end = self.cell_offsets[cell + 1] as usize;
+        &self.cell_items[start..end]
+    }
+}
+
+impl Intersect for Grid {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let origin = ray.ray.origin;
+        let origin = [origin.x(), origin.y(), origin.z()];
+        let direction = ray.ray.direction;
+        let direction = [direction.x(), direction.y(), direction.z()];
+        let inv_direction = ray.inv_direction;
+        let inv_direction = [inv_direction.x(), inv_direction.y(), inv_direction.z()];
+        let minimum = self.bounds.minimum();
+        let minimum = [minimum.x(), minimum.y(), minimum.z()];
+        let maximum = self.bounds.maximum();
+        let maximum = [maximum.x(), maximum.y(), maximum.z()];
+
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            let t0 = (minimum[axis] - origin[axis]) * inv_direction[axis];
+            let t1 = (maximum[axis] - origin[axis]) * inv_direction[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+
+        if t_exit < t_enter {
+            return None;
+        }
+
+        let mut cell = [0isize; 3];
+        let mut step = [0isize; 3];
+        let mut t_next = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            let position = origin[axis] + direction[axis] * t_enter;
+            let index = ((position - minimum[axis]) / self.cell_size[axis]) as isize;
+            cell[axis] = index.clamp(0, self.resolution[axis] as isize - 1);
+
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                let boundary = minimum[axis] + (cell[axis] + 1) as f32 * self.cell_size[axis];
+                t_next[axis] = (boundary - origin[axis]) * inv_direction[axis];
+                t_delta[axis] = self.cell_size[axis] * inv_direction[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                let boundary = minimum[axis] + cell[axis] as f32 * self.cell_size[axis];
+                t_next[axis] = (boundary - origin[axis]) * inv_direction[axis];
+                t_delta[axis] = -self.cell_size[axis] * inv_direction[axis];
+            }
+        }
+
+        let mut closest: Option<Hit> = None;
+        let mut closest_so_far = t_max;
+
+        loop {
+            for &index in self.cell(cell[0] as usize, cell[1] as usize, cell[2] as usize) {
+                if let Some(hit) =
+                    self.items[index as usize].intersect_traversal(ray, t_min, closest_so_far)
+                {
+                    closest_so_far = hit.t;
+                    closest = Some(hit);
+                }
+            }
+
+            let axis = if t_next[0] < t_next[1] {
+                if t_next[0] < t_next[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_next[1] < t_next[2] {
+                1
+            } else {
+                2
+            };
+
+            // Items can span cells, so a hit only ends the walk once it lies within this cell
+            if closest.is_some() && closest_so_far <= t_next[axis] {
+                return closest;
+            }
+
+            if t_next[axis] > t_exit {
+                return closest;
+            }
+
+            cell[axis] += step[axis];
+            if cell[axis] < 0 || cell[axis] >= self.resolution[axis] as isize {
+                return closest;
+            }
+            t_next[axis] += t_delta[axis];
+        }
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.bounds)
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.items.iter().map(|i| i.primitive_count()).sum()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for item in self.items.iter() {
+            item.visit_triangles(visit);
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self.cell_offsets.capacity() * std::mem::size_of::<u32>()
+            + self.cell_items.capacity() * std::mem::size_of::<u32>()
+            + self.items.iter().map(|i| i.memory_bytes()).sum::<usize>()
+    }
+}
+
+impl Accelerator for Grid {
+    fn build(items: Vec<Box<dyn Intersect>>) -> Self {
+        Self::new(items)
+    }
+}
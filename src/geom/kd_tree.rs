@@ -0,0 +1,320 @@
+use super::{Accelerator, BoundingBox, Hit, Intersect};
+use crate::material::Material;
+use crate::math::V3;
+use crate::world::{Ray, TraversalRay};
+
+const MAX_LEAF_ITEMS: usize = 4;
+const SPLIT_BINS: usize = 16;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECT_COST: f32 = 1.0;
+
+enum KdNode {
+    /// The child below the split is always the next node, only the one above is stored.
+    Interior {
+        axis: usize,
+        split: f32,
+        above: u32,
+    },
+    Leaf {
+        start: u32,
+        count: u32,
+    },
+}
+
+/// A kd-tree built with a binned surface area heuristic. Primitives straddling a split plane are
+/// referenced from both sides, so unlike a BVH the node regions never overlap and traversal can
+/// stop at the first leaf that contains a hit.
+pub struct KdTree {
+    items: Vec<Box<dyn Intersect>>,
+    nodes: Vec<KdNode>,
+    leaf_items: Vec<u32>,
+    bounds: BoundingBox,
+}
+
+impl KdTree {
+    pub fn new(items: Vec<Box<dyn Intersect>>) -> Self {
+        let item_bounds: Vec<BoundingBox> = items
+            .iter()
+            .map(|i| i.bounding_box().expect("Missing bounding box in kd-tree"))
+            .collect();
+
+        let bounds = item_bounds
+            .iter()
+            .copied()
+            .reduce(|a, b| a.join(b))
+            .expect("KdTree requires at least one item");
+
+        let max_depth = (8.0 + 1.3 * (items.len() as f32).log2()).round() as usize;
+
+        let mut tree = Self {
+            items,
+            nodes: Vec::new(),
+            leaf_items: Vec::new(),
+            bounds,
+        };
+
+        let indices = (0..item_bounds.len() as u32).collect();
+        tree.build(&item_bounds, indices, bounds, max_depth);
+
+        tree
+    }
+
+    fn build(
+        &mut self,
+        item_bounds: &[BoundingBox],
+        indices: Vec<u32>,
+        bounds: BoundingBox,
+        depth: usize,
+    ) {
+        let split = if indices.len() > MAX_LEAF_ITEMS && depth > 0 {
+            find_split(item_bounds, &indices, bounds)
+        } else {
+            None
+        };
+
+        let (axis, split) = match split {
+            Some(split) => split,
+            None => {
+                self.nodes.push(KdNode::Leaf {
+                    start: self.leaf_items.len() as u32,
+                    count: indices.len() as u32,
+                });
+                self.leaf_items.extend(indices);
+                return;
+            }
+        };
+
+        let (below, above): (Vec<u32>, Vec<u32>) = (
+            indices
+                .iter()
+                .copied()
+                .filter(|&i| axis_of(item_bounds[i as usize].minimum(), axis) <= split)
+                .collect(),
+            indices
+                .iter()
+                .copied()
+                .filter(|&i| axis_of(item_bounds[i as usize].maximum(), axis) >= split)
+                .collect(),
+        );
+
+        let (below_bounds, above_bounds) = split_bounds(bounds, axis, split);
+
+        let node = self.nodes.len();
+        self.nodes.push(KdNode::Interior {
+            axis,
+            split,
+            above: 0,
+        });
+
+        self.build(item_bounds, below, below_bounds, depth - 1);
+
+        let above_index = self.nodes.len() as u32;
+        if let KdNode::Interior { above, .. } = &mut self.nodes[node] {
+            *above = above_index;
+        }
+
+        self.build(item_bounds, above, above_bounds, depth - 1);
+    }
+
+    fn leaf(&self, start: u32, count: u32) -> &[u32] {
+        &self.leaf_items[start as usize..(start + count) as usize]
+    }
+}
+
+/// Picks the cheapest of `SPLIT_BINS` evenly spaced planes on each axis, or `None` if no split
+/// is cheaper than leaving the items in a leaf.
+fn find_split(
+    item_bounds: &[BoundingBox],
+    indices: &[u32],
+    bounds: BoundingBox,
+) -> Option<(usize, f32)> {
+    let area = bounds.surface_area();
+    if area <= 0.0 {
+        return None;
+    }
+
+    let minimum = bounds.minimum();
+    let size = bounds.size();
+    let leaf_cost = INTERSECT_COST * indices.len() as f32;
+
+    let mut best: Option<(usize, f32)> = None;
+    let mut best_cost = leaf_cost;
+
+    for axis in 0..3 {
+        let extent = axis_of(size, axis);
+        if extent <= 0.0 {
+            continue;
+        }
+
+        for bin in 1..SPLIT_BINS {
+            let split = axis_of(minimum, axis) + extent * bin as f32 / SPLIT_BINS as f32;
+
+            let mut below = 0;
+            let mut above = 0;
+            for &i in indices {
+                let item = item_bounds[i as usize];
+                if axis_of(item.minimum(), axis) <= split {
+                    below += 1;
+                }
+                if axis_of(item.maximum(), axis) >= split {
+                    above += 1;
+                }
+            }
+
+            let (below_bounds, above_bounds) = split_bounds(bounds, axis, split);
+            let cost = TRAVERSAL_COST
+                + INTERSECT_COST
+                    * (below_bounds.surface_area() / area * below as f32
+                        + above_bounds.surface_area() / area * above as f32);
+
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some((axis, split));
+            }
+        }
+    }
+
+    best
+}
+
+fn split_bounds(bounds: BoundingBox, axis: usize, split: f32) -> (BoundingBox, BoundingBox) {
+    let (minimum, maximum) = (bounds.minimum(), bounds.maximum());
+    let below_maximum = with_axis(maximum, axis, split);
+    let above_minimum = with_axis(minimum, axis, split);
+
+    (
+        BoundingBox::new(minimum, below_maximum),
+        BoundingBox::new(above_minimum, maximum),
+    )
+}
+
+fn axis_of(v: V3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn with_axis(v: V3, axis: usize, value: f32) -> V3 {
+    match axis {
+        0 => V3::new(value, v.y(), v.z()),
+        1 => V3::new(v.x(), value, v.z()),
+        _ => V3::new(v.x(), v.y(), value),
+    }
+}
+
+impl Intersect for KdTree {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let origin = ray.ray.origin;
+        let inv_direction = ray.inv_direction;
+        let minimum = self.bounds.minimum();
+        let maximum = self.bounds.maximum();
+
+        let mut t_enter = t_min;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            let t0 =
+                (axis_of(minimum, axis) - axis_of(origin, axis)) * axis_of(inv_direction, axis);
+            let t1 =
+                (axis_of(maximum, axis) - axis_of(origin, axis)) * axis_of(inv_direction, axis);
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+        }
+
+        if t_exit < t_enter {
+            return None;
+        }
+
+        let mut stack = Vec::new();
+        let mut closest: Option<Hit> = None;
+        let mut closest_so_far = t_max;
+        let (mut node, mut node_min, mut node_max) = (0, t_enter, t_exit);
+
+        loop {
+            // Nodes are visited front to back, so nothing further along can be any closer
+            if closest_so_far < node_min {
+                break;
+            }
+
+            match self.nodes[node] {
+                KdNode::Interior { axis, split, above } => {
+                    let origin = axis_of(origin, axis);
+                    let t_plane = (split - origin) * axis_of(inv_direction, axis);
+
+                    let below_first = origin < split
+                        || (origin == split && axis_of(ray.ray.direction, axis) <= 0.0);
+                    let (first, second) = if below_first {
+                        (node + 1, above as usize)
+                    } else {
+                        (above as usize, node + 1)
+                    };
+
+                    if t_plane.is_nan() || t_plane > node_max || t_plane <= 0.0 {
+                        node = first;
+                    } else if t_plane < node_min {
+                        node = second;
+                    } else {
+                        stack.push((second, t_plane, node_max));
+                        node = first;
+                        node_max = t_plane;
+                    }
+                }
+                KdNode::Leaf { start, count } => {
+                    for &index in self.leaf(start, count) {
+                        if let Some(hit) = self.items[index as usize].intersect_traversal(
+                            ray,
+                            t_min,
+                            closest_so_far,
+                        ) {
+                            closest_so_far = hit.t;
+                            closest = Some(hit);
+                        }
+                    }
+
+                    match stack.pop() {
+                        Some((next, next_min, next_max)) => {
+                            node = next;
+                            node_min = next_min;
+                            node_max = next_max;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.bounds)
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.items.iter().map(|i| i.primitive_count()).sum()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for item in self.items.iter() {
+            item.visit_triangles(visit);
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self.nodes.capacity() * std::mem::size_of::<KdNode>()
+            + self.leaf_items.capacity() * std::mem::size_of::<u32>()
+            + self.items.iter().map(|i| i.memory_bytes()).sum::<usize>()
+    }
+}
+
+impl Accelerator for KdTree {
+    fn build(items: Vec<Box<dyn Intersect>>) -> Self {
+        Self::new(items)
+    }
+}
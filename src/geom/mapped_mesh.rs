@@ -0,0 +1,439 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use memmap2::Mmap;
+
+use super::{BoundingBox, Hit, Intersect};
+use crate::material::{Backface, Material};
+use crate::math::{V2, V3};
+use crate::world::{Ray, TraversalRay};
+
+const MAGIC: &[u8; 4] = b"MMSH";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 32;
+const NODE_WORDS: usize = 8;
+const MAX_LEAF_FACES: usize = 4;
+
+/// Byte offsets of each section of a mapped mesh file. Every section is a run of little endian
+/// 32 bit words, positions and faces are stored as one array per component.
+struct Layout {
+    vertex_count: usize,
+    face_count: usize,
+    node_count: usize,
+    x: usize,
+    y: usize,
+    z: usize,
+    a: usize,
+    b: usize,
+    c: usize,
+    nodes: usize,
+    len: usize,
+}
+
+impl Layout {
+    fn new(vertex_count: usize, face_count: usize, node_count: usize) -> Self {
+        let x = HEADER_LEN;
+        let y = x + vertex_count * 4;
+        let z = y + vertex_count * 4;
+        let a = z + vertex_count * 4;
+        let b = a + face_count * 4;
+        let c = b + face_count * 4;
+        let nodes = c + face_count * 4;
+        let len = nodes + node_count * NODE_WORDS * 4;
+
+        Self {
+            vertex_count,
+            face_count,
+            node_count,
+            x,
+            y,
+            z,
+            a,
+            b,
+            c,
+            nodes,
+            len,
+        }
+    }
+}
+
+/// A triangle mesh read straight out of a memory mapped file, so meshes far larger than RAM can
+/// be rendered with only the pages a ray actually touches being loaded. The file carries its own
+/// BVH whose leaves reference contiguous runs of faces by offset.
+///
+/// Files are produced once with `MappedMesh::write`, which needs the whole mesh in memory.
+/// Only positions are stored, so shading always uses the face normal. Opening a file reads
+/// through its faces and nodes once to check their indices.
+pub struct MappedMesh<M: Material> {
+    map: Mmap,
+    layout: Layout,
+    material: M,
+}
+
+impl<M: Material> MappedMesh<M> {
+    pub fn open<P: AsRef<Path>>(path: P, material: M) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        // The file must not be modified while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < HEADER_LEN || &map[0..4] != MAGIC || read_u32(&map, 4) != VERSION {
+            return Err("Not a mapped mesh file".into());
+        }
+
+        // Every count is of items at least four bytes long, so larger counts can't be right and
+        // would overflow the layout
+        let counts = [read_u64(&map, 8), read_u64(&map, 16), read_u64(&map, 24)];
+        if counts.iter().any(|&count| count > map.len() as u64 / 4) {
+            return Err("Mapped mesh file is truncated".into());
+        }
+
+        let layout = Layout::new(counts[0] as usize, counts[1] as usize, counts[2] as usize);
+
+        if layout.len != map.len() || layout.node_count == 0 {
+            return Err("Mapped mesh file is truncated".into());
+        }
+
+        validate(&map, &layout)?;
+
+        Ok(Self {
+            map,
+            layout,
+            material,
+        })
+    }
+
+    /// Builds a BVH over `faces` and writes it with the mesh to `path`. Faces are reordered so
+    /// that each leaf covers a contiguous range.
+    pub fn write<P: AsRef<Path>>(
+        path: P,
+        vertices: &[V3],
+        faces: &[[u32; 3]],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if faces.is_empty() {
+            return Err("Mapped mesh requires at least one face".into());
+        }
+        if faces
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= vertices.len())
+        {
+            return Err("Mapped mesh face references a missing vertex".into());
+        }
+
+        let bounds: Vec<BoundingBox> = faces
+            .iter()
+            .map(|&[a, b, c]| {
+                let (a, b, c) = (
+                    vertices[a as usize],
+                    vertices[b as usize],
+                    vertices[c as usize],
+                );
+                BoundingBox::new(a.min(b).min(c), a.max(b).max(c))
+            })
+            .collect();
+
+        let mut order: Vec<u32> = (0..faces.len() as u32).collect();
+        let mut nodes = Vec::new();
+        build_node(&bounds, &mut order, 0, &mut nodes);
+
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+
+        file.write_all(MAGIC)?;
+        file.write_u32::<LittleEndian>(VERSION)?;
+        file.write_u64::<LittleEndian>(vertices.len() as u64)?;
+        file.write_u64::<LittleEndian>(faces.len() as u64)?;
+        file.write_u64::<LittleEndian>(nodes.len() as u64)?;
+
+        for axis in 0..3 {
+            for v in vertices {
+                file.write_f32::<LittleEndian>(axis_of(*v, axis))?;
+            }
+        }
+        for corner in 0..3 {
+            for &face in order.iter() {
+                file.write_u32::<LittleEndian>(faces[face as usize][corner])?;
+            }
+        }
+        for node in nodes.iter() {
+            for axis in 0..3 {
+                file.write_f32::<LittleEndian>(axis_of(node.bounds.minimum(), axis))?;
+            }
+            for axis in 0..3 {
+                file.write_f32::<LittleEndian>(axis_of(node.bounds.maximum(), axis))?;
+            }
+            file.write_u32::<LittleEndian>(node.offset)?;
+            file.write_u32::<LittleEndian>(node.count)?;
+        }
+
+        file.flush()?;
+        Ok(())
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.layout.face_count
+    }
+
+    fn vertex(&self, index: u32) -> V3 {
+        let index = index as usize * 4;
+        V3::new(
+            read_f32(&self.map, self.layout.x + index),
+            read_f32(&self.map, self.layout.y + index),
+            read_f32(&self.map, self.layout.z + index),
+        )
+    }
+
+    fn face(&self, face: u32) -> (V3, V3, V3) {
+        let face = face as usize * 4;
+        (
+            self.vertex(read_u32(&self.map, self.layout.a + face)),
+            self.vertex(read_u32(&self.map, self.layout.b + face)),
+            self.vertex(read_u32(&self.map, self.layout.c + face)),
+        )
+    }
+
+    /// Returns the bounds of `node` with its face offset and count, interior nodes have a count
+    /// of zero and their left child immediately follows them.
+    fn node(&self, node: u32) -> (BoundingBox, u32, u32) {
+        let base = self.layout.nodes + node as usize * NODE_WORDS * 4;
+        let word = |i: usize| read_f32(&self.map, base + i * 4);
+        let bounds = BoundingBox::new(
+            V3::new(word(0), word(1), word(2)),
+            V3::new(word(3), word(4), word(5)),
+        );
+
+        (
+            bounds,
+            read_u32(&self.map, base + 24),
+            read_u32(&self.map, base + 28),
+        )
+    }
+
+    fn intersect_face(&self, face: u32, ray: Ray, t_min: f32, t_max: f32) -> Option<(f32, V3)> {
+        let (vertex_a, vertex_b, vertex_c) = self.face(face);
+
+        let ab = vertex_b - vertex_a;
+        let ac = vertex_c - vertex_a;
+
+        let p_vec = ray.direction.cross(ac);
+        let det = ab.dot(p_vec);
+
+        if det.abs() < 0.000001 {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+
+        let t_vec = ray.origin - vertex_a;
+        let u = t_vec.dot(p_vec) * inv_det;
+        if u < 0.0 || u > 1.0 {
+            return None;
+        }
+
+        let q_vec = t_vec.cross(ab);
+        let v = ray.direction.dot(q_vec) * inv_det;
+        if v < 0.0 || v + u > 1.0 {
+            return None;
+        }
+
+        let t = ac.dot(q_vec) * inv_det;
+
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        Some((t, ab.cross(ac).unit()))
+    }
+}
+
+impl<M: Material> Intersect for MappedMesh<M> {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let mut stack = vec![0];
+        let mut closest = None;
+        let mut closest_so_far = t_max;
+        let cull = self.material.backface() == Backface::Cull;
+
+        while let Some(node) = stack.pop() {
+            let (bounds, offset, count) = self.node(node);
+            if !bounds.hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+
+            if count == 0 {
+                stack.push(offset);
+                stack.push(node + 1);
+                continue;
+            }
+
+            for face in offset..offset + count {
+                if let Some((t, normal)) = self.intersect_face(face, ray.ray, t_min, closest_so_far)
+                {
+                    let culled = cull && normal.dot(ray.ray.direction) > 0.0;
+                    if culled || !self.material.alpha_test(V2::zero()) {
+                        continue;
+                    }
+                    closest_so_far = t;
+                    closest = Some(normal);
+                }
+            }
+        }
+
+        let normal = closest?;
+        let mut hit = Hit {
+            point: ray.ray.at(closest_so_far),
+            normal,
+            t: closest_so_far,
+            uv: None,
+            color: None,
+            tangent: None,
+            bitangent: None,
+            front_face: false,
+            material: &self.material,
+            object: None,
+            footprint: 0.0,
+            local_point: ray.ray.at(closest_so_far),
+        };
+        hit.set_face_normal(ray.ray, normal);
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        Some(self.node(0).0)
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.layout.face_count
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for face in 0..self.layout.face_count as u32 {
+            let (a, b, c) = self.face(face);
+            visit([a, b, c], &self.material);
+        }
+    }
+
+    /// The mapped file counts in full, although only the pages touched are resident.
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.map.len()
+    }
+}
+
+/// Checks that every face references a vertex in the file and every node references faces or
+/// child nodes in the file, so traversal can't read out of bounds or loop. Children always come
+/// after their parent.
+fn validate(map: &[u8], layout: &Layout) -> Result<(), Box<dyn std::error::Error>> {
+    for corner in [layout.a, layout.b, layout.c] {
+        for face in 0..layout.face_count {
+            if read_u32(map, corner + face * 4) as usize >= layout.vertex_count {
+                return Err("Mapped mesh face references a missing vertex".into());
+            }
+        }
+    }
+
+    for node in 0..layout.node_count {
+        let base = layout.nodes + node * NODE_WORDS * 4;
+        let offset = read_u32(map, base + 24) as usize;
+        let count = read_u32(map, base + 28) as usize;
+
+        let valid = if count == 0 {
+            offset > node + 1 && offset < layout.node_count
+        } else {
+            offset + count <= layout.face_count
+        };
+        if !valid {
+            return Err("Mapped mesh node references a missing node or face".into());
+        }
+    }
+
+    Ok(())
+}
+
+struct BuildNode {
+    bounds: BoundingBox,
+    offset: u32,
+    count: u32,
+}
+
+/// Appends the subtree over `order` to `nodes` in preorder, `start` is the position of `order`
+/// within the final face ordering.
+fn build_node(bounds: &[BoundingBox], order: &mut [u32], start: u32, nodes: &mut Vec<BuildNode>) {
+    let node_bounds = order
+        .iter()
+        .map(|&i| bounds[i as usize])
+        .reduce(|a, b| a.join(b))
+        .unwrap();
+
+    let index = nodes.len();
+    nodes.push(BuildNode {
+        bounds: node_bounds,
+        offset: start,
+        count: order.len() as u32,
+    });
+
+    if order.len() <= MAX_LEAF_FACES {
+        return;
+    }
+
+    let extent = node_bounds.maximum() - node_bounds.minimum();
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            axis_of(extent, a)
+                .partial_cmp(&axis_of(extent, b))
+                .unwrap_or(Ordering::Equal)
+        })
+        .unwrap();
+    let centroid = |i: u32| {
+        let b = bounds[i as usize];
+        axis_of(b.minimum(), axis) + axis_of(b.maximum(), axis)
+    };
+
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |&a, &b| {
+        centroid(a)
+            .partial_cmp(&centroid(b))
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let (left, right) = order.split_at_mut(mid);
+    build_node(bounds, left, start, nodes);
+    let right_index = nodes.len() as u32;
+    build_node(bounds, right, start + mid as u32, nodes);
+
+    nodes[index].offset = right_index;
+    nodes[index].count = 0;
+}
+
+fn axis_of(v: V3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut word = [0; 8];
+    word.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(word)
+}
+
+fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    f32::from_bits(read_u32(bytes, offset))
+}
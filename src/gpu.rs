@@ -0,0 +1,682 @@
+//! A path tracer running as a wgpu compute shader, enabled with the `gpu` feature.
+//!
+//! The world is flattened into triangles with the `Approximation` of their materials, which
+//! are placed in a BVH built here and uploaded with the materials to storage buffers. The
+//! backgrounds are baked into latitude-longitude maps. Textures are reduced to their average
+//! color and motion, volumes and polarization are ignored, so the CPU tracer stays the
+//! reference and this is for quickly looking around a scene.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::geom::Intersect;
+use crate::material::{Approximation, Background};
+use crate::math::V3;
+use crate::world::{Camera, Ray, World};
+
+/// Triangles held by a BVH leaf before it is split.
+const LEAF_SIZE: usize = 4;
+const WORKGROUP_SIZE: u32 = 8;
+const BACKGROUND_WIDTH: u32 = 512;
+const BACKGROUND_HEIGHT: u32 = 256;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuNode {
+    minimum: [f32; 3],
+    /// The first triangle of a leaf, or the index of an interior node's right child. The left
+    /// child always directly follows its parent.
+    start: u32,
+    maximum: [f32; 3],
+    /// Zero for interior nodes.
+    count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuTriangle {
+    a: [f32; 3],
+    material: u32,
+    b: [f32; 3],
+    _pad_b: u32,
+    c: [f32; 3],
+    _pad_c: u32,
+}
+
+impl GpuTriangle {
+    fn centroid(&self) -> [f32; 3] {
+        let mut centroid = [0.0; 3];
+        for axis in 0..3 {
+            centroid[axis] = (self.a[axis] + self.b[axis] + self.c[axis]) / 3.0;
+        }
+        centroid
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GpuMaterial {
+    color: [f32; 3],
+    metallic: f32,
+    emission: [f32; 3],
+    roughness: f32,
+    /// Zero for opaque materials.
+    refraction_index: f32,
+    _pad: [f32; 3],
+}
+
+impl From<Approximation> for GpuMaterial {
+    fn from(approximation: Approximation) -> Self {
+        GpuMaterial {
+            color: array(approximation.color),
+            metallic: approximation.metallic,
+            emission: array(approximation.emission),
+            roughness: approximation.roughness,
+            refraction_index: approximation.refraction_index.unwrap_or(0.0),
+            _pad: [0.0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Params {
+    origin: [f32; 3],
+    lens_radius: f32,
+    lower_left_corner: [f32; 3],
+    width: u32,
+    horizontal: [f32; 3],
+    height: u32,
+    vertical: [f32; 3],
+    max_depth: u32,
+    u: [f32; 3],
+    seed: u32,
+    v: [f32; 3],
+    node_count: u32,
+}
+
+fn array(v: V3) -> [f32; 3] {
+    [v.x(), v.y(), v.z()]
+}
+
+fn output_size(width: u32, height: u32) -> u64 {
+    (width * height) as u64 * std::mem::size_of::<[f32; 4]>() as u64
+}
+
+/// A world uploaded to the GPU, traced one sample per pixel at a time.
+pub struct GpuTracer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    output_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    params: Params,
+    adapter_name: String,
+}
+
+impl GpuTracer {
+    pub fn new<B: Background>(
+        world: &World<B>,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        max_depth: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        pollster::block_on(Self::create(world, camera, width, height, max_depth))
+    }
+
+    async fn create<B: Background>(
+        world: &World<B>,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        max_depth: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or("No compatible GPU adapter found")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("gpu tracer"),
+                    features: wgpu::Features::empty(),
+                    limits: adapter.limits(),
+                },
+                None,
+            )
+            .await?;
+
+        let (mut triangles, materials) = flatten(world);
+        let mut nodes = Vec::with_capacity(triangles.len() / LEAF_SIZE * 2 + 1);
+        if !triangles.is_empty() {
+            build_node(&mut triangles, 0, &mut nodes);
+        }
+        let node_count = nodes.len() as u32;
+        let background = bake_background(world);
+
+        // Bindings can't be empty, the shader skips traversal when there are no nodes
+        if triangles.is_empty() {
+            triangles.push(GpuTriangle::zeroed());
+            nodes.push(GpuNode::zeroed());
+        }
+
+        let storage = |label, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::STORAGE,
+            })
+        };
+        let node_buffer = storage("bvh nodes", bytemuck::cast_slice(&nodes));
+        let triangle_buffer = storage("triangles", bytemuck::cast_slice(&triangles));
+        let material_buffer = storage("materials", bytemuck::cast_slice(&materials));
+        let background_buffer = storage("background", bytemuck::cast_slice(&background));
+
+        let viewport = camera.viewport();
+        let params = Params {
+            origin: array(viewport.origin),
+            lens_radius: viewport.lens_radius,
+            lower_left_corner: array(viewport.lower_left_corner),
+            width,
+            horizontal: array(viewport.horizontal),
+            height,
+            vertical: array(viewport.vertical),
+            max_depth,
+            u: array(viewport.u),
+            seed: 0,
+            v: array(viewport.v),
+            node_count,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: output_size(width, height),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: output_size(width, height),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("trace"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(TRACE_SRC)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("trace"),
+            layout: None,
+            module: &shader,
+            entry_point: "main",
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("trace"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: triangle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: material_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: background_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: output_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            params_buffer,
+            output_buffer,
+            readback_buffer,
+            params,
+            adapter_name: adapter.get_info().name,
+        })
+    }
+
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    /// Traces one sample for every pixel and reads them back, with rows in the order of
+    /// `Camera::ray`'s `t` so the bottom row comes first.
+    pub fn trace(&mut self) -> Result<Vec<V3>, Box<dyn Error>> {
+        self.params.seed = self.params.seed.wrapping_add(1);
+        self.queue
+            .write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&self.params));
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("trace"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("trace"),
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(
+                (self.params.width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                (self.params.height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.output_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            output_size(self.params.width, self.params.height),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let pixels = {
+            let data = slice.get_mapped_range();
+            bytemuck::cast_slice::<u8, [f32; 4]>(&data)
+                .iter()
+                .map(|p| V3::new(p[0], p[1], p[2]))
+                .collect()
+        };
+        self.readback_buffer.unmap();
+
+        Ok(pixels)
+    }
+}
+
+/// Collects the world's triangles, sharing one material entry between all triangles whose
+/// materials approximate the same.
+fn flatten<B: Background>(world: &World<B>) -> (Vec<GpuTriangle>, Vec<GpuMaterial>) {
+    let mut triangles = Vec::new();
+    let mut materials = Vec::new();
+    let mut material_indices = HashMap::new();
+
+    world.visit_triangles(&mut |[a, b, c], material| {
+        let material = GpuMaterial::from(material.approximate());
+        let key: [u32; 12] = bytemuck::cast(material);
+        let index = *material_indices.entry(key).or_insert_with(|| {
+            materials.push(material);
+            materials.len() as u32 - 1
+        });
+
+        triangles.push(GpuTriangle {
+            a: array(a),
+            material: index,
+            b: array(b),
+            _pad_b: 0,
+            c: array(c),
+            _pad_c: 0,
+        });
+    });
+
+    if materials.is_empty() {
+        materials.push(GpuMaterial::from(Approximation::default()));
+    }
+
+    (triangles, materials)
+}
+
+/// Appends the subtree over `triangles` in preorder, splitting at the median centroid along
+/// the widest axis.
+fn build_node(triangles: &mut [GpuTriangle], first: usize, nodes: &mut Vec<GpuNode>) {
+    let mut minimum = [f32::INFINITY; 3];
+    let mut maximum = [f32::NEG_INFINITY; 3];
+    let mut centroid_minimum = [f32::INFINITY; 3];
+    let mut centroid_maximum = [f32::NEG_INFINITY; 3];
+    for triangle in triangles.iter() {
+        let centroid = triangle.centroid();
+        for axis in 0..3 {
+            for vertex in [triangle.a, triangle.b, triangle.c] {
+                minimum[axis] = minimum[axis].min(vertex[axis]);
+                maximum[axis] = maximum[axis].max(vertex[axis]);
+            }
+            centroid_minimum[axis] = centroid_minimum[axis].min(centroid[axis]);
+            centroid_maximum[axis] = centroid_maximum[axis].max(centroid[axis]);
+        }
+    }
+
+    let index = nodes.len();
+    nodes.push(GpuNode {
+        minimum,
+        start: first as u32,
+        maximum,
+        count: triangles.len() as u32,
+    });
+    if triangles.len() <= LEAF_SIZE {
+        return;
+    }
+
+    let axis = (0..3)
+        .max_by(|&a, &b| {
+            let extent = |axis: usize| centroid_maximum[axis] - centroid_minimum[axis];
+            extent(a)
+                .partial_cmp(&extent(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap_or(0);
+    triangles.sort_by(|a, b| {
+        a.centroid()[axis]
+            .partial_cmp(&b.centroid()[axis])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mid = triangles.len() / 2;
+    let (left, right) = triangles.split_at_mut(mid);
+    build_node(left, first, nodes);
+    nodes[index].start = nodes.len() as u32;
+    nodes[index].count = 0;
+    build_node(right, first + mid, nodes);
+}
+
+/// The lighting background followed by the camera background, each as a latitude-longitude
+/// map with +y at the top.
+fn bake_background<B: Background>(world: &World<B>) -> Vec<[f32; 4]> {
+    let mut texels = Vec::with_capacity((BACKGROUND_WIDTH * BACKGROUND_HEIGHT * 2) as usize);
+    for camera in [false, true] {
+        for y in 0..BACKGROUND_HEIGHT {
+            for x in 0..BACKGROUND_WIDTH {
+                let phi = ((x as f32 + 0.5) / BACKGROUND_WIDTH as f32) * std::f32::consts::PI * 2.0
+                    - std::f32::consts::PI;
+                let theta = ((y as f32 + 0.5) / BACKGROUND_HEIGHT as f32) * std::f32::consts::PI;
+                let direction = V3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let ray = Ray::new(V3::new(0.0, 0.0, 0.0), direction);
+                let color = if camera {
+                    world.camera_background(ray)
+                } else {
+                    world.background(ray)
+                };
+                texels.push([color.x(), color.y(), color.z(), 1.0]);
+            }
+        }
+    }
+    texels
+}
+
+const TRACE_SRC: &str = "
+struct Params {
+    origin: vec3<f32>,
+    lens_radius: f32,
+    lower_left_corner: vec3<f32>,
+    width: u32,
+    horizontal: vec3<f32>,
+    height: u32,
+    vertical: vec3<f32>,
+    max_depth: u32,
+    u: vec3<f32>,
+    seed: u32,
+    v: vec3<f32>,
+    node_count: u32,
+}
+
+struct Node {
+    minimum: vec3<f32>,
+    start: u32,
+    maximum: vec3<f32>,
+    count: u32,
+}
+
+struct Triangle {
+    a: vec3<f32>,
+    material: u32,
+    b: vec3<f32>,
+    c: vec3<f32>,
+}
+
+struct Material {
+    color: vec3<f32>,
+    metallic: f32,
+    emission: vec3<f32>,
+    roughness: f32,
+    refraction_index: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+@group(0) @binding(2) var<storage, read> triangles: array<Triangle>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+@group(0) @binding(4) var<storage, read> background: array<vec4<f32>>;
+@group(0) @binding(5) var<storage, read_write> output: array<vec4<f32>>;
+
+let PI: f32 = 3.14159265;
+let BACKGROUND_WIDTH: u32 = 512u;
+let BACKGROUND_HEIGHT: u32 = 256u;
+
+var<private> rng_state: u32;
+
+fn rand() -> f32 {
+    rng_state = rng_state * 747796405u + 2891336453u;
+    var word = ((rng_state >> ((rng_state >> 28u) + 4u)) ^ rng_state) * 277803737u;
+    word = (word >> 22u) ^ word;
+    return f32(word >> 8u) / 16777216.0;
+}
+
+fn random_unit_vector() -> vec3<f32> {
+    let z = rand() * 2.0 - 1.0;
+    let phi = rand() * 2.0 * PI;
+    let r = sqrt(max(1.0 - z * z, 0.0));
+    return vec3<f32>(r * cos(phi), r * sin(phi), z);
+}
+
+fn random_in_unit_sphere() -> vec3<f32> {
+    return random_unit_vector() * pow(rand(), 1.0 / 3.0);
+}
+
+fn random_in_unit_disk() -> vec2<f32> {
+    let r = sqrt(rand());
+    let phi = rand() * 2.0 * PI;
+    return vec2<f32>(r * cos(phi), r * sin(phi));
+}
+
+fn sample_background(direction: vec3<f32>, camera: bool) -> vec3<f32> {
+    let d = normalize(direction);
+    let u = (atan2(d.z, d.x) + PI) / (2.0 * PI);
+    let v = acos(clamp(d.y, -1.0, 1.0)) / PI;
+    let x = min(u32(u * f32(BACKGROUND_WIDTH)), BACKGROUND_WIDTH - 1u);
+    let y = min(u32(v * f32(BACKGROUND_HEIGHT)), BACKGROUND_HEIGHT - 1u);
+    let layer = select(0u, 1u, camera);
+    return background[(layer * BACKGROUND_HEIGHT + y) * BACKGROUND_WIDTH + x].xyz;
+}
+
+fn hit_box(node: Node, origin: vec3<f32>, inv_direction: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (node.minimum - origin) * inv_direction;
+    let t1 = (node.maximum - origin) * inv_direction;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), 0.001));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), t_max));
+    return near <= far;
+}
+
+// Returns the distance to the triangle, or a negative value on a miss
+fn hit_triangle(triangle: Triangle, origin: vec3<f32>, direction: vec3<f32>) -> f32 {
+    let edge_1 = triangle.b - triangle.a;
+    let edge_2 = triangle.c - triangle.a;
+    let p = cross(direction, edge_2);
+    let det = dot(edge_1, p);
+    if (abs(det) < 1e-8) {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let s = origin - triangle.a;
+    let u = dot(s, p) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+    let q = cross(s, edge_1);
+    let v = dot(direction, q) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    return dot(edge_2, q) * inv_det;
+}
+
+struct Hit {
+    t: f32,
+    triangle: u32,
+}
+
+fn intersect(origin: vec3<f32>, direction: vec3<f32>) -> Hit {
+    var hit = Hit(1e30, 0xffffffffu);
+    if (params.node_count == 0u) {
+        return hit;
+    }
+
+    let inv_direction = 1.0 / direction;
+    var stack: array<u32, 64>;
+    var stack_len = 0u;
+    var index = 0u;
+    loop {
+        let node = nodes[index];
+        if (hit_box(node, origin, inv_direction, hit.t)) {
+            if (node.count == 0u) {
+                if (stack_len < 64u) {
+                    stack[stack_len] = node.start;
+                    stack_len = stack_len + 1u;
+                }
+                index = index + 1u;
+                continue;
+            }
+            for (var i = node.start; i < node.start + node.count; i = i + 1u) {
+                let t = hit_triangle(triangles[i], origin, direction);
+                if (t > 0.001 && t < hit.t) {
+                    hit = Hit(t, i);
+                }
+            }
+        }
+        if (stack_len == 0u) {
+            break;
+        }
+        stack_len = stack_len - 1u;
+        index = stack[stack_len];
+    }
+    return hit;
+}
+
+fn reflectance(cosine: f32, ratio: f32) -> f32 {
+    var r0 = (1.0 - ratio) / (1.0 + ratio);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cosine, 5.0);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.width || id.y >= params.height) {
+        return;
+    }
+    let pixel = id.y * params.width + id.x;
+    rng_state = pixel * 9781u + params.seed * 6271u + 1u;
+
+    let s = (f32(id.x) + rand()) / f32(max(params.width, 2u) - 1u);
+    let t = (f32(id.y) + rand()) / f32(max(params.height, 2u) - 1u);
+    let blur = random_in_unit_disk() * params.lens_radius;
+    let offset = params.u * blur.x + params.v * blur.y;
+    var origin = params.origin + offset;
+    var direction = params.lower_left_corner + params.horizontal * s + params.vertical * t
+        - params.origin - offset;
+
+    var color = vec3<f32>(0.0);
+    var throughput = vec3<f32>(1.0);
+    for (var depth = 0u; depth < params.max_depth; depth = depth + 1u) {
+        let hit = intersect(origin, direction);
+        if (hit.triangle == 0xffffffffu) {
+            color = color + throughput * sample_background(direction, depth == 0u);
+            break;
+        }
+
+        let triangle = triangles[hit.triangle];
+        let material = materials[triangle.material];
+        color = color + throughput * material.emission;
+
+        let unit_direction = normalize(direction);
+        var normal = normalize(cross(triangle.b - triangle.a, triangle.c - triangle.a));
+        let front_face = dot(unit_direction, normal) < 0.0;
+        if (!front_face) {
+            normal = -normal;
+        }
+        origin = origin + direction * hit.t;
+
+        if (material.refraction_index > 0.0) {
+            var ratio = material.refraction_index;
+            if (front_face) {
+                ratio = 1.0 / ratio;
+            }
+            let cos_theta = min(dot(-unit_direction, normal), 1.0);
+            let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+            if (ratio * sin_theta > 1.0 || reflectance(cos_theta, ratio) > rand()) {
+                direction = reflect(unit_direction, normal);
+            } else {
+                direction = refract(unit_direction, normal, ratio);
+            }
+        } else if (rand() < material.metallic) {
+            direction = reflect(unit_direction, normal)
+                + random_in_unit_sphere() * material.roughness;
+            if (dot(direction, normal) <= 0.0) {
+                break;
+            }
+        } else {
+            direction = normal + random_unit_vector();
+            if (all(abs(direction) < vec3<f32>(1e-5))) {
+                direction = normal;
+            }
+        }
+
+        throughput = throughput * material.color;
+        if (all(throughput == vec3<f32>(0.0))) {
+            break;
+        }
+    }
+
+    if (any(color != color)) {
+        color = vec3<f32>(0.0);
+    }
+    output[pixel] = vec4<f32>(color, 1.0);
+}";
@@ -0,0 +1,54 @@
+use std::collections::{HashMap, HashSet};
+
+/// A key or gamepad button a scene can react to. `Button` is held while any gamepad holds it,
+/// `GamepadButton` only for the gamepad with that id.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Input {
+    Key(winit::event::VirtualKeyCode),
+    Button(gilrs::Button),
+    GamepadButton(usize, gilrs::Button),
+}
+
+/// The input currently held down, passed to `Scene::generate` each frame.
+#[derive(Default)]
+pub struct InputCollection {
+    pressed_input: HashSet<Input>,
+    axis_values: HashMap<gilrs::Axis, f32>,
+    gamepad_axis_values: HashMap<(usize, gilrs::Axis), f32>,
+}
+
+impl InputCollection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: Input) {
+        self.pressed_input.insert(key);
+    }
+
+    pub fn unset(&mut self, key: Input) {
+        self.pressed_input.remove(&key);
+    }
+
+    pub fn set_axis(&mut self, axis: gilrs::Axis, value: f32) {
+        self.axis_values.insert(axis, value);
+    }
+
+    /// Sets an axis of gamepad `id`, also making it the value seen through `axis`.
+    pub fn set_gamepad_axis(&mut self, id: usize, axis: gilrs::Axis, value: f32) {
+        self.gamepad_axis_values.insert((id, axis), value);
+        self.set_axis(axis, value);
+    }
+
+    pub fn is_pressed(&self, key: Input) -> bool {
+        self.pressed_input.contains(&key)
+    }
+
+    pub fn axis(&self, axis: gilrs::Axis) -> f32 {
+        *self.axis_values.get(&axis).unwrap_or(&0.0)
+    }
+
+    pub fn gamepad_axis(&self, id: usize, axis: gilrs::Axis) -> f32 {
+        *self.gamepad_axis_values.get(&(id, axis)).unwrap_or(&0.0)
+    }
+}
@@ -0,0 +1,67 @@
+//! A path tracer built by following the Ray Tracing in One Weekend series.
+//!
+//! A render starts from a [`world::World`] filled with [`geom::Intersect`] objects, each with a
+//! [`material::Material`], and a [`world::Camera`] to shoot rays from. Each call to
+//! [`world::Camera::trace`] returns one sample of the light arriving along a ray, averaging
+//! many samples per pixel gives the final image.
+//!
+//! ```no_run
+//! use mass_raytrace::geom::Sphere;
+//! use mass_raytrace::material::{Lambertian, SolidBackground};
+//! use mass_raytrace::math::V3;
+//! use mass_raytrace::sampler::RandomSampler;
+//! use mass_raytrace::texture::SolidColor;
+//! use mass_raytrace::world::{Camera, World};
+//!
+//! let mut world = World::new(SolidBackground::new(V3::new(0.7, 0.8, 1.0)));
+//! let red = Lambertian::new(SolidColor(V3::new(0.8, 0.3, 0.3).expand(1.0)));
+//! world.add(Sphere::new(red, V3::new(0.0, 0.0, -1.0), 0.5));
+//! world.build_bvh();
+//!
+//! let look_from = V3::new(0.0, 0.0, 1.0);
+//! let look_at = V3::new(0.0, 0.0, -1.0);
+//! let up = V3::new(0.0, 1.0, 0.0);
+//! let camera = Camera::new(40.0, look_from, look_at, up, 16.0 / 9.0, 0.0, 2.0);
+//!
+//! let ray = camera.ray(0.5, 0.5);
+//! let (color, _depth) = camera.trace(&world, ray, 50, &mut RandomSampler);
+//! ```
+//!
+//! Models are loaded with [`model_loader::ModelLoader`], and [`scenes`] holds complete scenes
+//! as used by the viewer binary.
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod animation;
+#[cfg(feature = "embree")]
+pub mod embree;
+pub mod eve;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod geom;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod input;
+pub mod json;
+pub mod light;
+#[cfg(feature = "mmap")]
+pub mod mapped;
+pub mod material;
+pub mod math;
+pub mod microfacet;
+pub mod model_loader;
+pub mod obj_export;
+pub mod obj_loader;
+pub mod paging;
+pub mod ply_loader;
+#[cfg(feature = "polarization")]
+pub mod polarization;
+#[cfg(feature = "python")]
+mod python;
+pub mod sampler;
+pub mod scenes;
+pub mod stl_loader;
+pub mod texture;
+pub mod wavefront;
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+pub mod web;
+pub mod world;
@@ -0,0 +1,90 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::pfm::write_pfm;
+use crate::world::RangeSample;
+
+/// Per pixel primary hit distances and return intensities, rows stored bottom first.
+pub struct RangeImage {
+    width: u32,
+    height: u32,
+    samples: Vec<Option<RangeSample>>,
+}
+
+impl RangeImage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            samples: vec![None; (width * height) as usize],
+        }
+    }
+
+    pub fn set_row(&mut self, row: u32, samples: &[Option<RangeSample>]) {
+        let start = (row * self.width) as usize;
+        self.samples[start..start + samples.len()].copy_from_slice(samples);
+    }
+
+    pub fn samples(&self) -> &[Option<RangeSample>] {
+        &self.samples
+    }
+
+    pub fn max_distance(&self) -> f32 {
+        self.samples
+            .iter()
+            .flatten()
+            .map(|s| s.distance)
+            .fold(0.0, f32::max)
+    }
+
+    /// Writes distance and intensity into the first two channels of a PFM, the third channel
+    /// is 1.0 where a surface was hit. Misses have a distance of infinity.
+    pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        write_pfm(
+            path,
+            self.width,
+            self.height,
+            self.samples.iter().map(|s| match s {
+                Some(s) => [s.distance, s.intensity, 1.0],
+                None => [f32::INFINITY, 0.0, 0.0],
+            }),
+        )
+    }
+
+    /// Writes every hit as a point with its intensity in an ASCII PLY point cloud.
+    pub fn write_point_cloud<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let points = self.samples.iter().flatten().count();
+        writeln!(file, "ply")?;
+        writeln!(file, "format ascii 1.0")?;
+        writeln!(file, "element vertex {}", points)?;
+        writeln!(file, "property float x")?;
+        writeln!(file, "property float y")?;
+        writeln!(file, "property float z")?;
+        writeln!(file, "property float intensity")?;
+        writeln!(file, "end_header")?;
+
+        for s in self.samples.iter().flatten() {
+            writeln!(
+                file,
+                "{} {} {} {}",
+                s.point.x(),
+                s.point.y(),
+                s.point.z(),
+                s.intensity
+            )?;
+        }
+
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,700 @@
+//! Emitters that can be sampled directly. A light is also an object in the world, so paths can
+//! still find it by bouncing into it, but the tracer can additionally pick a point on it from
+//! each surface and cast a shadow ray there, which finds small lights far more often. Lights
+//! without a surface, such as a `SpotLight`, are only reached that way, or for distant ones
+//! like a `DirectionalLight`, by rays leaving the scene.
+
+use std::sync::Arc;
+
+use super::geom::{BoundingBox, Hit, Intersect, Sphere};
+use super::material::{DiffuseLight, Material};
+use super::texture::Surface;
+use super::world::Ray;
+use crate::math::{V2, V3};
+
+/// A direction towards a light picked by `Light::sample`.
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    /// Unit direction from the shaded point towards the light.
+    pub direction: V3,
+    /// Distance along `direction` to the point on the light.
+    pub distance: f32,
+    /// The light emitted back along `direction`.
+    pub radiance: V3,
+    /// The density of picking `direction`, over solid angle.
+    pub pdf: f32,
+}
+
+pub trait Light: Send + Sync {
+    /// Picks a direction from `point` towards the light using the uniform numbers in `u`,
+    /// `None` if the light can't be seen from there.
+    fn sample(&self, point: V3, u: V2) -> Option<LightSample>;
+
+    /// The density `sample` picks unit `direction` from `origin` with, and the distance to the
+    /// light along it, `None` if `direction` misses the light.
+    fn pdf(&self, origin: V3, direction: V3) -> Option<(f32, f32)>;
+
+    /// The total light emitted, as luminance. `scene_radius` bounds everything a light outside
+    /// the scene could shine on.
+    fn power(&self, scene_radius: f32) -> f32;
+
+    /// Whether the light arrives from a single direction, so `sample` always returns the same
+    /// one and scattering can never find it. `pdf` is ignored for such lights.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// The light arriving along unit `direction` from a light without a surface, seen by rays
+    /// that leave the scene. Lights with a surface emit through their material instead.
+    fn escaped(&self, _direction: V3) -> V3 {
+        V3::zero()
+    }
+
+    /// The light group that this light is accumulated into.
+    fn light_group(&self) -> usize {
+        0
+    }
+}
+
+/// Scenes holding a list of lights to sample.
+pub trait Lights {
+    fn lights(&self) -> &LightList;
+}
+
+/// The objects a light shines on, by their index in the world. Objects outside the world,
+/// without an index, are lit by every light.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LightLink {
+    All,
+    Only(Vec<usize>),
+    Except(Vec<usize>),
+}
+
+impl LightLink {
+    pub fn shines_on(&self, object: Option<usize>) -> bool {
+        match (self, object) {
+            (LightLink::All, _) | (_, None) => true,
+            (LightLink::Only(objects), Some(object)) => objects.contains(&object),
+            (LightLink::Except(objects), Some(object)) => !objects.contains(&object),
+        }
+    }
+}
+
+/// The lights of a scene, picked in proportion to their power once `weigh_by_power` has been
+/// called and uniformly until then.
+#[derive(Clone, Default)]
+pub struct LightList {
+    lights: Vec<Arc<dyn Light>>,
+    /// The running total of each light's share of the power, empty while picking uniformly.
+    cdf: Vec<f32>,
+    /// The objects each light shines on.
+    links: Vec<LightLink>,
+    /// The world object that is each light's surface, for lights that have one.
+    surfaces: Vec<Option<usize>>,
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, light: Arc<dyn Light>) {
+        self.push_surface(light, None);
+    }
+
+    /// Adds `light` whose surface is the world object at index `surface`.
+    pub fn push_surface(&mut self, light: Arc<dyn Light>, surface: Option<usize>) {
+        self.lights.push(light);
+        self.links.push(LightLink::All);
+        self.surfaces.push(surface);
+        self.cdf.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.links.clear();
+        self.surfaces.clear();
+        self.cdf.clear();
+    }
+
+    /// Limits the light at `index` to the objects `link` allows, returning false if there is no
+    /// such light.
+    pub fn set_link(&mut self, index: usize, link: LightLink) -> bool {
+        match self.links.get_mut(index) {
+            Some(current) => {
+                *current = link;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the light at `index` shines on the world object at index `object`.
+    pub fn shines_on(&self, index: usize, object: Option<usize>) -> bool {
+        self.links
+            .get(index)
+            .map_or(true, |link| link.shines_on(object))
+    }
+
+    /// The index of the light whose surface is the world object at index `object`.
+    pub fn surface_light(&self, object: usize) -> Option<usize> {
+        self.surfaces
+            .iter()
+            .position(|&surface| surface == Some(object))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Light> {
+        self.lights.iter().map(|light| &**light)
+    }
+
+    /// Picks lights in proportion to their power from now on, falling back to uniformly if
+    /// none of them emit anything.
+    pub fn weigh_by_power(&mut self, scene_radius: f32) {
+        let powers: Vec<f32> = self
+            .lights
+            .iter()
+            .map(|light| light.power(scene_radius).max(0.0))
+            .collect();
+        let total: f32 = powers.iter().sum();
+
+        self.cdf.clear();
+        if total > 0.0 && total.is_finite() {
+            let mut sum = 0.0;
+            for power in powers {
+                sum += power / total;
+                self.cdf.push(sum);
+            }
+        }
+    }
+
+    /// Picks a light with the uniform number `u`, returning its index and it with the chance
+    /// it was picked.
+    pub fn pick(&self, u: f32) -> Option<(usize, &dyn Light, f32)> {
+        if self.lights.is_empty() {
+            return None;
+        }
+
+        let index = if self.cdf.is_empty() {
+            ((u * self.lights.len() as f32) as usize).min(self.lights.len() - 1)
+        } else {
+            self.cdf
+                .iter()
+                .position(|&sum| u < sum)
+                .unwrap_or(self.lights.len() - 1)
+        };
+
+        Some((index, &*self.lights[index], self.probability(index)))
+    }
+
+    /// The chance that `pick` returns the light at `index`.
+    pub fn probability(&self, index: usize) -> f32 {
+        match self.cdf.get(index) {
+            Some(sum) if index == 0 => *sum,
+            Some(sum) => sum - self.cdf[index - 1],
+            None => 1.0 / self.lights.len() as f32,
+        }
+    }
+}
+
+/// The multiple importance sampling weight of a technique sampling with density `pdf` against
+/// another with `other`, using the power heuristic.
+pub fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let pdf = pdf * pdf;
+    let other = other * other;
+    if pdf + other == 0.0 {
+        0.0
+    } else {
+        pdf / (pdf + other)
+    }
+}
+
+/// A spherical emitter, sampled over the cone of directions it covers.
+pub struct SphereLight {
+    center: V3,
+    radius: f32,
+    light: DiffuseLight,
+    sphere: Sphere<DiffuseLight>,
+}
+
+impl SphereLight {
+    pub fn new(light: DiffuseLight, center: V3, radius: f32) -> Self {
+        Self {
+            center,
+            radius,
+            sphere: Sphere::new(light.clone(), center, radius),
+            light,
+        }
+    }
+
+    /// The cosine of the half angle of the cone the sphere covers from `point` and one minus
+    /// it, `None` from inside.
+    fn cone(&self, point: V3) -> Option<(f32, f32)> {
+        let distance_squared = (self.center - point).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+
+        let sin_squared = radius_squared / distance_squared;
+        let cos_max = (1.0 - sin_squared).max(0.0).sqrt();
+        // Stays accurate for lights that are small or far away, where cos_max is close to one
+        Some((cos_max, sin_squared / (1.0 + cos_max)))
+    }
+
+    /// Distance from `origin` along unit `direction` to the near side of the sphere.
+    fn distance(&self, origin: V3, direction: V3) -> Option<f32> {
+        let offset = self.center - origin;
+        let along = offset.dot(direction);
+        let discriminant = along * along - (offset.length_squared() - self.radius * self.radius);
+        if discriminant < 0.0 || along <= 0.0 {
+            return None;
+        }
+
+        Some(along - discriminant.sqrt())
+    }
+}
+
+impl Light for SphereLight {
+    fn sample(&self, point: V3, u: V2) -> Option<LightSample> {
+        let (cos_max, one_minus_cos_max) = self.cone(point)?;
+
+        let (direction, cos_theta) =
+            sample_cone((self.center - point).unit(), one_minus_cos_max, u);
+
+        // Directions at the very edge of the cone can miss by rounding, they only graze it
+        let distance = self
+            .distance(point, direction)
+            .unwrap_or_else(|| (self.center - point).length() * cos_max.max(cos_theta));
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.light.radiance(),
+            pdf: cone_pdf(one_minus_cos_max),
+        })
+    }
+
+    fn pdf(&self, origin: V3, direction: V3) -> Option<(f32, f32)> {
+        let (_, one_minus_cos_max) = self.cone(origin)?;
+        let distance = self.distance(origin, direction)?;
+        Some((cone_pdf(one_minus_cos_max), distance))
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        let area = 4.0 * std::f32::consts::PI * self.radius * self.radius;
+        luminance(self.light.radiance()) * area * std::f32::consts::PI
+    }
+
+    fn light_group(&self) -> usize {
+        self.light.light_group()
+    }
+}
+
+impl Intersect for SphereLight {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.sphere.intersect(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.sphere.bounding_box()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        self.sphere.visit_triangles(visit)
+    }
+}
+
+/// A parallelogram emitter with a corner at `corner` and sides `u` and `v`, lit on both faces
+/// and sampled uniformly over its area.
+pub struct QuadLight {
+    corner: V3,
+    u: V3,
+    v: V3,
+    normal: V3,
+    area: f32,
+    light: DiffuseLight,
+}
+
+impl QuadLight {
+    pub fn new(light: DiffuseLight, corner: V3, u: V3, v: V3) -> Self {
+        let cross = u.cross(v);
+        Self {
+            corner,
+            u,
+            v,
+            normal: cross.unit(),
+            area: cross.length(),
+            light,
+        }
+    }
+
+    /// Distance along `ray` to the quad and the position of the hit in its `u` and `v` sides.
+    fn hit(&self, ray: Ray) -> Option<(f32, V2)> {
+        let denominator = self.normal.dot(ray.direction);
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = self.normal.dot(self.corner - ray.origin) / denominator;
+        let planar = ray.at(t) - self.corner;
+        let w = self.u.cross(self.v) / (self.area * self.area);
+        let alpha = w.dot(planar.cross(self.v));
+        let beta = w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some((t, V2::new(alpha, beta)))
+    }
+
+    /// The density of reaching a point on the quad `distance` away along unit `direction`.
+    fn solid_angle_pdf(&self, direction: V3, distance: f32) -> Option<f32> {
+        let cosine = self.normal.dot(direction).abs();
+        if cosine < 1e-6 {
+            return None;
+        }
+
+        Some(distance * distance / (cosine * self.area))
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, point: V3, u: V2) -> Option<LightSample> {
+        let target = self.corner + self.u * u.x() + self.v * u.y();
+        let offset = target - point;
+        let distance = offset.length();
+        let direction = offset / distance;
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.light.radiance(),
+            pdf: self.solid_angle_pdf(direction, distance)?,
+        })
+    }
+
+    fn pdf(&self, origin: V3, direction: V3) -> Option<(f32, f32)> {
+        let (distance, _) = self.hit(Ray::new(origin, direction))?;
+        if distance <= 0.0 {
+            return None;
+        }
+
+        Some((self.solid_angle_pdf(direction, distance)?, distance))
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        // Both faces emit
+        luminance(self.light.radiance()) * 2.0 * self.area * std::f32::consts::PI
+    }
+
+    fn light_group(&self) -> usize {
+        self.light.light_group()
+    }
+}
+
+impl Intersect for QuadLight {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let (t, uv) = self.hit(ray)?;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let mut hit = Hit {
+            point: ray.at(t),
+            normal: self.normal,
+            t,
+            uv: Some(uv),
+            color: None,
+            tangent: Some(self.u),
+            bitangent: Some(self.v),
+            front_face: false,
+            material: &self.light,
+            object: None,
+            footprint: 0.0,
+            local_point: ray.at(t),
+        };
+        hit.set_face_normal(ray, self.normal);
+
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        let corners = [
+            self.corner,
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let bounds = corners[1..].iter().fold(
+            BoundingBox::new(corners[0], corners[0]),
+            |bounds, &corner| bounds.join(BoundingBox::new(corner, corner)),
+        );
+
+        // Keeps the box from being flat when the quad lies in an axis plane
+        let padding = V3::fill(1e-4);
+        Some(BoundingBox::new(
+            bounds.minimum() - padding,
+            bounds.maximum() + padding,
+        ))
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        let a = self.corner;
+        let b = self.corner + self.u;
+        let c = self.corner + self.u + self.v;
+        let d = self.corner + self.v;
+        visit([a, b, c], &self.light);
+        visit([a, c, d], &self.light);
+    }
+}
+
+/// Light arriving from a distant source everywhere in the scene, such as the sun. With no
+/// angular radius it arrives from a single direction and gives hard shadows, otherwise it
+/// covers a cone of directions and shadows soften with distance from the caster.
+#[derive(Clone)]
+pub struct DirectionalLight {
+    /// Unit direction towards the center of the light.
+    direction: V3,
+    /// The cosine of the angular radius and one minus it.
+    cos_max: f32,
+    one_minus_cos_max: f32,
+    light: DiffuseLight,
+}
+
+impl DirectionalLight {
+    /// Lights the scene from `direction` with `light`'s radiance as the irradiance on a
+    /// surface facing it.
+    pub fn new(light: DiffuseLight, direction: V3) -> Self {
+        Self {
+            direction: direction.unit(),
+            cos_max: 1.0,
+            one_minus_cos_max: 0.0,
+            light,
+        }
+    }
+
+    /// Spreads the light over a disc, `radius` being the angular radius, in radians, of the
+    /// light's disc. The sun's is about 0.0047. The irradiance on a surface facing the light
+    /// stays the same.
+    pub fn with_angular_radius(self, radius: f32) -> Self {
+        let radius = radius.clamp(0.0, std::f32::consts::FRAC_PI_2);
+        let half_sin = (radius / 2.0).sin();
+        Self {
+            cos_max: radius.cos(),
+            // Stays accurate for small discs, where the cosine is close to one
+            one_minus_cos_max: 2.0 * half_sin * half_sin,
+            ..self
+        }
+    }
+
+    /// The radiance of the disc, spreading the irradiance over its solid angle. Small discs
+    /// are treated as facing the surface, so this ignores the falloff of the cosine over them.
+    fn disc_radiance(&self) -> V3 {
+        self.light.radiance() * cone_pdf(self.one_minus_cos_max)
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: V3, u: V2) -> Option<LightSample> {
+        if self.is_delta() {
+            return Some(LightSample {
+                direction: self.direction,
+                distance: f32::INFINITY,
+                radiance: self.light.radiance(),
+                pdf: 1.0,
+            });
+        }
+
+        let (direction, _) = sample_cone(self.direction, self.one_minus_cos_max, u);
+        Some(LightSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.disc_radiance(),
+            pdf: cone_pdf(self.one_minus_cos_max),
+        })
+    }
+
+    fn pdf(&self, _origin: V3, direction: V3) -> Option<(f32, f32)> {
+        if self.is_delta() || direction.dot(self.direction) < self.cos_max {
+            return None;
+        }
+
+        Some((cone_pdf(self.one_minus_cos_max), f32::INFINITY))
+    }
+
+    fn power(&self, scene_radius: f32) -> f32 {
+        luminance(self.light.radiance()) * std::f32::consts::PI * scene_radius * scene_radius
+    }
+
+    fn is_delta(&self) -> bool {
+        self.one_minus_cos_max <= 0.0
+    }
+
+    fn escaped(&self, direction: V3) -> V3 {
+        if self.is_delta() || direction.dot(self.direction) < self.cos_max {
+            V3::zero()
+        } else {
+            self.disc_radiance()
+        }
+    }
+
+    fn light_group(&self) -> usize {
+        self.light.light_group()
+    }
+}
+
+/// A point emitter shining a cone of light from `position`, fading out between an inner and
+/// outer angle around its axis, optionally tinted by a texture projected over the cone like a
+/// slide projector.
+#[derive(Clone)]
+pub struct SpotLight {
+    position: V3,
+    /// Unit axis of the cone, followed by two unit vectors across it orienting the projection.
+    tangent: V3,
+    bitangent: V3,
+    direction: V3,
+    cos_inner: f32,
+    cos_outer: f32,
+    tan_outer: f32,
+    light: DiffuseLight,
+    projection: Option<Arc<dyn Surface>>,
+}
+
+impl SpotLight {
+    /// Shines `light` from `position` towards `direction`, at full strength up to `inner` radians
+    /// from the axis and fading to nothing at `outer`. `light`'s radiance is the intensity along
+    /// the axis, the irradiance on a surface facing the light one unit away.
+    pub fn new(light: DiffuseLight, position: V3, direction: V3, inner: f32, outer: f32) -> Self {
+        let outer = outer.clamp(1e-4, std::f32::consts::FRAC_PI_2 - 1e-4);
+        let inner = inner.clamp(0.0, outer);
+        let (tangent, bitangent, direction) = basis(direction.unit());
+        Self {
+            position,
+            tangent,
+            bitangent,
+            direction,
+            cos_inner: inner.cos(),
+            cos_outer: outer.cos(),
+            tan_outer: outer.tan(),
+            light,
+            projection: None,
+        }
+    }
+
+    /// Tints the light by `texture`, stretched over the square just containing the outer cone.
+    pub fn with_projection<S: 'static + Surface>(self, texture: S) -> Self {
+        Self {
+            projection: Some(Arc::new(texture)),
+            ..self
+        }
+    }
+
+    /// The intensity leaving the light along unit `direction`.
+    fn intensity(&self, direction: V3) -> V3 {
+        let cosine = direction.dot(self.direction);
+        if cosine <= self.cos_outer {
+            return V3::zero();
+        }
+
+        let falloff = if cosine >= self.cos_inner {
+            1.0
+        } else {
+            let t = (cosine - self.cos_outer) / (self.cos_inner - self.cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        };
+        let intensity = self.light.radiance() * falloff;
+
+        match self.projection.as_ref() {
+            Some(projection) => {
+                let scale = 0.5 / (cosine * self.tan_outer);
+                let uv = V2::new(
+                    0.5 + direction.dot(self.tangent) * scale,
+                    0.5 - direction.dot(self.bitangent) * scale,
+                );
+                intensity * projection.get_f(uv).contract()
+            }
+            None => intensity,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: V3, _u: V2) -> Option<LightSample> {
+        let offset = self.position - point;
+        let distance = offset.length();
+        let direction = offset / distance;
+        let intensity = self.intensity(-direction);
+        if intensity.near_zero() {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: intensity / (distance * distance),
+            pdf: 1.0,
+        })
+    }
+
+    fn pdf(&self, _origin: V3, _direction: V3) -> Option<(f32, f32)> {
+        None
+    }
+
+    fn power(&self, _scene_radius: f32) -> f32 {
+        // Counts the fading band as half lit
+        let cone = 1.0 - (self.cos_inner + self.cos_outer) / 2.0;
+        luminance(self.light.radiance()) * 2.0 * std::f32::consts::PI * cone
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn light_group(&self) -> usize {
+        self.light.light_group()
+    }
+}
+
+fn luminance(color: V3) -> f32 {
+    color.x() * 0.2126 + color.y() * 0.7152 + color.z() * 0.0722
+}
+
+/// Two unit vectors perpendicular to unit `axis` and each other, followed by `axis`.
+fn basis(axis: V3) -> (V3, V3, V3) {
+    let helper = if axis.x().abs() > 0.9 {
+        V3::new(0.0, 1.0, 0.0)
+    } else {
+        V3::new(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(axis).unit();
+    let bitangent = axis.cross(tangent);
+    (tangent, bitangent, axis)
+}
+
+/// A direction picked uniformly within the cone around unit `axis` whose half angle has a
+/// cosine of one minus `one_minus_cos_max`, with the cosine of its angle to `axis`.
+fn sample_cone(axis: V3, one_minus_cos_max: f32, u: V2) -> (V3, f32) {
+    let cos_theta = 1.0 - u.x() * one_minus_cos_max;
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * std::f32::consts::PI * u.y();
+
+    let (tangent, bitangent, axis) = basis(axis);
+    let direction = (tangent * (phi.cos() * sin_theta)
+        + bitangent * (phi.sin() * sin_theta)
+        + axis * cos_theta)
+        .unit();
+    (direction, cos_theta)
+}
+
+/// The density of `sample_cone` over solid angle.
+fn cone_pdf(one_minus_cos_max: f32) -> f32 {
+    1.0 / (2.0 * std::f32::consts::PI * one_minus_cos_max)
+}
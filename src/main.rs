@@ -1,5 +1,3 @@
-#![cfg_attr(feature = "simd", feature(portable_simd))]
-
 use glium::texture::SrgbTexture2d;
 use glium::{glutin, implement_vertex, uniform, DrawParameters, Program, Surface};
 use glutin::event_loop::EventLoopProxy;
@@ -8,62 +6,200 @@ use winit::event::{Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 
-use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering as AtomicOrdering};
 use std::sync::{Arc, Mutex};
 
-mod eve;
-mod geom;
-mod material;
-mod math;
-mod obj_loader;
-mod ply_loader;
-mod scenes;
-mod stl_loader;
-mod texture;
-mod world;
-
+mod console;
+mod dataset;
+mod dry_run;
+#[cfg(test)]
+mod furnace;
+mod lidar;
+mod overlay;
+mod pacing;
+mod pfm;
+mod preview;
+mod reference;
+mod render_queue;
+mod scene_stats;
+mod sensor;
+mod settings;
+mod tiles;
+
+use mass_raytrace::input::{Input, InputCollection};
+use mass_raytrace::sampler::{RandomSampler, Sampler, SamplerKind};
+use mass_raytrace::{geom, light, material, math, paging, scenes, texture, world};
+
+use lidar::RangeImage;
 use math::{Num, V3};
+use overlay::{FrameInfo, Overlay};
 use scenes::Scene;
+use texture::{Texture, WrapMode};
+use world::{COMPONENTS, COMPONENT_NAMES, LIGHT_GROUPS};
 
 #[derive(Debug)]
 enum UserEvent {
     Update,
     Complete,
     Redraw(Vec<u8>),
+    Preview(preview::Geometry),
     FatalError,
 }
 
 const MAX_DEPTH: u32 = 50;
 
+/// A JSON scene description to render instead of the compiled in scene, also set by passing
+/// `--scene <path>`.
+const SCENE_FILE: Option<&str> = None;
+
 const ASPECT_RATIO: f32 = 16.0 / 9.0;
 const IMAGE_WIDTH: u32 = 1920;
 const IMAGE_HEIGHT: u32 = (IMAGE_WIDTH as f32 / ASPECT_RATIO) as u32;
 
 const ANIMATING: bool = false;
 const EXPORT_FRAMES: bool = false;
+/// Keeps `animation/index.html` listing the exported frames with thumbnails.
+const EXPORT_INDEX: bool = true;
 const FRAMES_PER_SECOND: u32 = 30;
 const ANIMATION_DURATION: u32 = 150000;
 const TOTAL_FRAMES: u32 = FRAMES_PER_SECOND * ANIMATION_DURATION;
 const SAMPLES_PER_FRAME_PER_THREAD: u32 = 1;
+/// Plays animation back in real time instead of a fixed number of samples per frame, each frame
+/// accumulates passes until the next is due at `FRAMES_PER_SECOND`. Frames that can't finish a
+/// pass in time run late. Interactive scenes then advance steadily however fast the machine is.
+const REAL_TIME: bool = false;
+/// The window is redrawn, and gamepads polled, at most this many times a second. Traced images
+/// arriving faster than this are shown as they are when the next redraw is due.
+const PRESENT_FPS: Option<u32> = Some(60);
+/// Stops of exposure added or removed by each press of = or -.
+const EXPOSURE_STEP: f32 = 0.5;
+const SHUTTER: (f32, f32) = (0.0, 0.0);
+/// Shows a prefiltered background when the main camera's depth of field would blur it anyway.
+const BLUR_BACKGROUND: bool = false;
+/// Angle in radians of a linear polarizing filter in front of every camera.
+#[cfg(feature = "polarization")]
+const POLARIZER: Option<f32> = None;
+
+const REFERENCE_CROP: Option<reference::Crop> = None;
+const REFERENCE_STRATA: u32 = 64;
+
+const LIGHT_GROUP_AOVS: bool = false;
+/// The number of passes per render thread that reuse one cached primary hit per pixel and only
+/// trace the secondary bounces, speeding up the first passes of every frame. The cache is shared
+/// by all threads, so each of these passes adds no new antialiasing or depth of field samples.
+/// Not used with component or light group AOVs.
+const HYBRID_PASSES: u32 = 0;
+/// Traces each tile with the `wavefront` tracer, extending every path of the tile one bounce at
+/// a time instead of finishing each pixel before the next. Not used with component AOVs or
+/// hybrid passes, and ignores the polarizer.
+const WAVEFRONT: bool = false;
+/// Passes are split into tiles of this many pixels square, taken by render threads as they
+/// become free. The first pass of each frame is shown as its tiles finish.
+const TILE_SIZE: u32 = 32;
+const TILE_ORDER: tiles::TileOrder = tiles::TileOrder::Scanline;
+/// Places pixel and lens samples, stratified and Sobol samples spread each pixel's samples more
+/// evenly than random ones for less noise at low sample counts.
+const SAMPLER: SamplerKind = SamplerKind::Sobol;
+/// Splits the image into emission, background and direct/indirect diffuse, glossy and
+/// transmission AOVs, saved alongside the image. Takes precedence over light group AOVs.
+const COMPONENT_AOVS: bool = false;
+
+const AUTO_STOP: Option<Convergence> = None;
+
+/// Scales down samples whose brightest channel exceeds this before they are accumulated for
+/// display, trading bias for fewer fireflies.
+const SAMPLE_CLAMP: Option<f32> = None;
+/// Clamps the light arriving at each bounce instead of whole samples, see
+/// `Camera::with_radiance_clamp`, as `(direct, indirect)`. Only applied to the displayed image,
+/// reference renders and `REFERENCE_ACCUMULATION` trace their own unclamped samples.
+const RADIANCE_CLAMP: (Option<f32>, Option<f32>) = (None, None);
+/// Keeps a second accumulation of the raw samples, free of `SAMPLE_CLAMP`, `RADIANCE_CLAMP`, the
+/// sensor response and denoising, saved as a PFM next to every exported image to compare
+/// against. Each sample is traced twice while `RADIANCE_CLAMP` is set.
+const REFERENCE_ACCUMULATION: bool = false;
+
+const LIDAR_OUTPUT: bool = false;
+
+const DATASET: Option<dataset::DatasetConfig> = None;
+
+const MEMORY_BUDGET: Option<usize> = None;
+
+/// Also enabled by passing `--dry-run`.
+const DRY_RUN: bool = false;
+/// Writes the first frame's world to an OBJ file, with materials approximated in an MTL file.
+const EXPORT_OBJ: Option<&str> = None;
+/// Rasterizes the first frame's world before tracing starts, press P to cycle between flat
+/// shading, normal shading and the traced image. Tracing waits until the preview is first hidden.
+const RASTER_PREVIEW: bool = false;
+/// Reads scene edits such as `move obj_12 0 1 0` or `camera fov 35` from stdin while rendering,
+/// restarting accumulation after each one. Type an unknown command to list them.
+const SCENE_CONSOLE: bool = false;
+/// Traces with the wgpu compute shader backend instead of the CPU threads, falling back to the
+/// CPU when no adapter is available. Materials are approximated, see `gpu`.
+#[cfg(feature = "gpu")]
+const GPU_BACKEND: bool = false;
+
+const SENSOR_RESPONSE: Option<&str> = None;
+const SENSOR_NOISE: Option<(f32, f32)> = None;
+const ROLLING_SHUTTER: Option<f32> = None;
+
+const BURN_IN: Option<u32> = None;
+const WATERMARK: Option<(&str, f32)> = None;
+
+/// Viewer preferences such as the display mode and window size are loaded from this file at
+/// startup and saved back to it on exit.
+const SETTINGS_FILE: Option<&str> = Some("settings.json");
 
 const READ_INPUT: bool = false;
 const WRITE_INPUT: bool = false;
 
 static PIXEL_UPDATE_FLAG: AtomicBool = AtomicBool::new(false);
 static QUICK_PASS: AtomicBool = AtomicBool::new(false);
+/// Set when `AUTO_STOP` ends the frame being rendered, cleared as each frame starts.
+static RENDER_CONVERGED: AtomicBool = AtomicBool::new(false);
+/// Set by pressing I, the next finished pass prints per object statistics.
+static SCENE_STATS: AtomicBool = AtomicBool::new(false);
+/// Held while the raster preview is shown on the first frame.
+static PREVIEW_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Holds the render threads before their next tile while set, toggled with space.
+static RENDER_PAUSED: AtomicBool = AtomicBool::new(false);
+/// Set by the scene console, the current frame is abandoned and generated again with the edit.
+static SCENE_EDITED: AtomicBool = AtomicBool::new(false);
 
 fn main() {
+    if let Some(budget) = MEMORY_BUDGET {
+        paging::set_budget(budget);
+    }
+
+    if let Some(config) = DATASET {
+        let mut scene = scenes::Randomized::new(config.width as f32 / config.height as f32, 1);
+        dataset::generate(&mut scene, config);
+        return;
+    }
+
     let event_loop: EventLoop<UserEvent> = EventLoop::with_user_event();
     let event_proxy = Arc::new(Mutex::new(event_loop.create_proxy()));
-    let image = Arc::new(Image::new(IMAGE_WIDTH, IMAGE_HEIGHT));
+    let mut overlay = Overlay::default();
+    if let Some(scale) = BURN_IN {
+        overlay = overlay.with_burn_in(scale);
+    }
+    if let Some((path, opacity)) = WATERMARK {
+        let texture = Texture::load_png(path, WrapMode::Clamp).expect("Unable to load watermark");
+        overlay = overlay.with_watermark(texture, opacity);
+    }
+
+    let settings = load_settings();
+    let image = Arc::new(Image::new(IMAGE_WIDTH, IMAGE_HEIGHT, overlay).with_sensor(sensor()));
+    image.set_exposure(settings.exposure);
     let input = Arc::new(Mutex::new(InputCollection::new()));
 
     {
         let image = image.clone();
         let input = input.clone();
+        let settings = settings.clone();
         std::thread::spawn(move || {
-            let res = std::panic::catch_unwind(|| worker(image, event_proxy.clone(), input));
+            let res =
+                std::panic::catch_unwind(|| worker(image, event_proxy.clone(), input, &settings));
             match res {
                 Err(_err) => event_proxy
                     .lock()
@@ -75,55 +211,275 @@ fn main() {
         });
     }
 
-    run(event_loop, image, input)
+    run(event_loop, image, input, settings)
+}
+
+fn load_settings() -> settings::Settings {
+    let mut settings = settings::Settings::new((IMAGE_WIDTH, IMAGE_HEIGHT));
+    if let Some(path) = SETTINGS_FILE {
+        match settings.clone().load(path) {
+            Ok(loaded) => settings = loaded,
+            Err(error) => eprintln!("Unable to load settings from {}: {}", path, error),
+        }
+    }
+
+    // Saved modes may depend on AOVs that aren't enabled in this build
+    settings.display_mode = match settings.display_mode {
+        DisplayMode::LightGroup(group) if !LIGHT_GROUP_AOVS || group >= LIGHT_GROUPS => {
+            DisplayMode::Default
+        }
+        DisplayMode::Range if !LIDAR_OUTPUT => DisplayMode::Default,
+        mode => mode,
+    };
+
+    settings
+}
+
+fn sensor() -> Option<sensor::Sensor> {
+    if SENSOR_RESPONSE.is_none() && SENSOR_NOISE.is_none() && ROLLING_SHUTTER.is_none() {
+        return None;
+    }
+
+    let mut sensor = sensor::Sensor::default();
+    if let Some(path) = SENSOR_RESPONSE {
+        let response =
+            sensor::ResponseCurves::load(path).expect("Unable to load sensor response curves");
+        sensor = sensor.with_response(response);
+    }
+    if let Some((gain, read_noise)) = SENSOR_NOISE {
+        sensor = sensor.with_noise(gain, read_noise);
+    }
+    if let Some(readout) = ROLLING_SHUTTER {
+        sensor = sensor.with_rolling_shutter(readout);
+    }
+
+    Some(sensor)
 }
 
 fn worker(
     image: Arc<Image>,
     event_proxy: Arc<Mutex<EventLoopProxy<UserEvent>>>,
     input: Arc<Mutex<InputCollection>>,
+    settings: &settings::Settings,
 ) {
+    let scene_file = SCENE_FILE.map(String::from).or_else(|| {
+        let mut args = std::env::args().skip_while(|arg| arg != "--scene");
+        args.next();
+        args.next()
+    });
+    if let Some(path) = scene_file {
+        let scene =
+            scenes::FileScene::load(&path, ASPECT_RATIO).expect("Unable to load scene file");
+        return render_scene(scene, image, event_proxy, input, settings);
+    }
+
+    let scene = scenes::CornellBox::new(ASPECT_RATIO);
+    //let scene = scenes::Eve::new(ASPECT_RATIO);
+    //let scene = scenes::Lucy::new(ASPECT_RATIO);
+    //let scene = scenes::Mario::new(ASPECT_RATIO, READ_INPUT, WRITE_INPUT);
+    //let scene = scenes::Menger::new(ASPECT_RATIO);
+    //let scene = scenes::SphereGrid::new(ASPECT_RATIO);
+
+    render_scene(scene, image, event_proxy, input, settings)
+}
+
+fn render_scene<S: Scene>(
+    mut scene: S,
+    image: Arc<Image>,
+    event_proxy: Arc<Mutex<EventLoopProxy<UserEvent>>>,
+    input: Arc<Mutex<InputCollection>>,
+    settings: &settings::Settings,
+) where
+    S::Background: 'static,
+{
     fastrand::seed(1);
 
     let mut frame = 0;
-    let samples_per_frame = if ANIMATING {
+    let samples_per_frame = if ANIMATING && !REAL_TIME {
         Some(SAMPLES_PER_FRAME_PER_THREAD)
     } else {
         None
     };
+    let mut frame_pacer = pacing::Pacer::new(FRAMES_PER_SECOND);
 
     let start_time = std::time::Instant::now();
+    let mut view_images = vec![image.clone()];
+    let mut render_queue = render_queue::RenderQueue::new("animation", TOTAL_FRAMES);
+    let console = if SCENE_CONSOLE {
+        Some(console::Console::spawn(&SCENE_EDITED))
+    } else {
+        None
+    };
 
-    let mut scene = scenes::CornellBox::new(ASPECT_RATIO);
-    //let mut scene = scenes::Eve::new(ASPECT_RATIO);
-    //let mut scene = scenes::Lucy::new(ASPECT_RATIO);
-    //let mut scene = scenes::Mario::new(ASPECT_RATIO, READ_INPUT, WRITE_INPUT);
-    //let mut scene = scenes::Menger::new(ASPECT_RATIO);
-    //let mut scene = scenes::SphereGrid::new(ASPECT_RATIO);
+    if DRY_RUN || std::env::args().any(|arg| arg == "--dry-run") {
+        dry_run::report(&mut scene, MAX_DEPTH, num_cpus::get());
+        std::process::exit(0);
+    }
 
     while frame < TOTAL_FRAMES {
+        // Each frame converges on its own
+        RENDER_CONVERGED.store(false, AtomicOrdering::Relaxed);
         let animation_t = frame as f32 / TOTAL_FRAMES as f32;
 
-        let (mut world, camera) = {
+        let (mut world, views) = {
             let input = input.lock().unwrap();
-            scene.generate(animation_t, frame, &*input)
+            scene.generate_views(animation_t, frame, &*input)
         };
+        let (view_names, cameras): (Vec<String>, Vec<world::Camera>) = views
+            .into_iter()
+            .enumerate()
+            .map(|(view, (name, camera))| {
+                let camera = match console.as_ref() {
+                    Some(console) if view == 0 => console.apply_camera(camera),
+                    _ => camera,
+                };
+                let camera = camera.with_shutter(SHUTTER.0, SHUTTER.1);
+                #[cfg(feature = "polarization")]
+                let camera = match POLARIZER {
+                    Some(angle) => camera.with_polarizer(angle),
+                    None => camera,
+                };
+                (name, camera)
+            })
+            .unzip();
+
+        // The first view is the one shown in the window, the others render off screen
+        while view_images.len() < cameras.len() {
+            view_images.push(Arc::new(
+                Image::new(IMAGE_WIDTH, IMAGE_HEIGHT, Overlay::default()).with_sensor(sensor()),
+            ));
+        }
 
+        if let Some(crop) = REFERENCE_CROP {
+            let camera = cameras.into_iter().next().expect("Scene has no cameras");
+            reference::validate(
+                world,
+                camera,
+                image.width,
+                image.height,
+                crop,
+                REFERENCE_STRATA,
+                MAX_DEPTH,
+            );
+            break;
+        }
+
+        // Only the displayed samples are clamped, the reference is kept unbiased
+        let cameras: Vec<world::Camera> = cameras
+            .into_iter()
+            .map(|camera| camera.with_radiance_clamp(RADIANCE_CLAMP.0, RADIANCE_CLAMP.1))
+            .collect();
+
+        if let Some(console) = console.as_ref() {
+            console.apply_world(&mut world);
+        }
         world.build_bvh();
+        if BLUR_BACKGROUND {
+            if let Some(camera) = cameras.first() {
+                world.blur_camera_background(camera.background_blur());
+            }
+        }
+        if RASTER_PREVIEW && frame == 0 {
+            if let Some(camera) = cameras.first() {
+                PREVIEW_ACTIVE.store(true, AtomicOrdering::Relaxed);
+                event_proxy
+                    .lock()
+                    .expect("Event proxy posioned")
+                    .send_event(UserEvent::Preview(preview::Geometry::collect(
+                        &world, camera,
+                    )))
+                    .expect("Unable to reach event loop");
+                println!("Showing raster preview, press P until it is hidden to start tracing");
+                while PREVIEW_ACTIVE.load(AtomicOrdering::Relaxed) {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                }
+            }
+        }
+        if frame == 0 {
+            if let Some(memory) = world.bvh_memory() {
+                println!("{}", memory);
+            }
+            for warning in world.bvh_stats().iter().flat_map(|stats| stats.warnings()) {
+                println!("Warning: {}", warning);
+            }
+            if let Some(path) = EXPORT_OBJ {
+                match world.export_obj(path) {
+                    Ok(()) => println!("Exported world to {}", path),
+                    Err(error) => eprintln!("Unable to export world: {:?}", error),
+                }
+            }
+        }
+        if let Some(budget) = paging::budget() {
+            for camera in cameras.iter() {
+                paging::prefetch(&world, camera);
+            }
+            if frame == 0 {
+                println!(
+                    "Texture memory: {} of {} bytes",
+                    paging::resident_bytes(),
+                    budget
+                );
+            }
+        }
+        for image in view_images.iter() {
+            image.set_frame(scene.name(), frame);
+        }
 
         {
-            let image = image.clone();
+            let views = view_images.iter().cloned().zip(cameras).collect();
             let event_proxy = event_proxy.clone();
-            render(image, event_proxy, world, camera, samples_per_frame);
+            let frame_limit = FrameLimit {
+                samples: samples_per_frame,
+                deadline: Some(frame_pacer.deadline()).filter(|_| ANIMATING && REAL_TIME),
+            };
+            render(views, event_proxy, world, frame_limit);
+            if ANIMATING && REAL_TIME {
+                frame_pacer.wait();
+            }
+        }
+
+        if SCENE_EDITED.swap(false, AtomicOrdering::Relaxed) {
+            continue;
+        }
+
+        // A converged animation frame moves on to the next, a still image is done
+        if RENDER_CONVERGED.load(AtomicOrdering::Relaxed) && !ANIMATING {
+            let main_path = settings.export_path();
+            for (view, (image, name)) in view_images.iter().zip(view_names.iter()).enumerate() {
+                let path = if view == 0 {
+                    main_path.clone()
+                } else {
+                    format!("{}_{}.png", main_path.trim_end_matches(".png"), name)
+                };
+                image.dump(&path, DisplayMode::Denoise);
+                println!("Converged image saved to: {}", path);
+                if REFERENCE_ACCUMULATION {
+                    image.dump_reference(path.trim_end_matches(".png"));
+                }
+            }
+            break;
         }
 
         frame += 1;
         if ANIMATING {
             if EXPORT_FRAMES {
-                image.dump(
-                    format!("animation/frame_{:05}.png", frame),
-                    DisplayMode::Denoise,
-                );
+                let mut exported = Vec::new();
+                for (view, (image, name)) in view_images.iter().zip(view_names.iter()).enumerate() {
+                    let path = format!(
+                        "animation/{}",
+                        render_queue::RenderQueue::frame_path(view, name, frame)
+                    );
+                    exported.push((name.as_str(), image.dump(path, DisplayMode::Denoise)));
+                }
+                if EXPORT_INDEX {
+                    render_queue.finish_frame(
+                        frame,
+                        image.samples(),
+                        IMAGE_WIDTH,
+                        IMAGE_HEIGHT,
+                        &exported,
+                    );
+                }
             }
 
             let elapsed_s = start_time.elapsed().as_secs() as f32;
@@ -147,20 +503,537 @@ fn worker(
         .expect("Unable to reach event loop");
 }
 
+/// When a frame stops accumulating passes, without either it renders until it converges or is
+/// interrupted.
+#[derive(Copy, Clone)]
+struct FrameLimit {
+    /// Samples traced by each render thread.
+    samples: Option<u32>,
+    deadline: Option<std::time::Instant>,
+}
+
+impl FrameLimit {
+    fn is_none(&self) -> bool {
+        self.samples.is_none() && self.deadline.is_none()
+    }
+
+    fn reached(&self, samples: u32) -> bool {
+        Some(samples) == self.samples
+            || self
+                .deadline
+                .map_or(false, |deadline| std::time::Instant::now() >= deadline)
+    }
+}
+
+/// Renders every view of `world` together, each camera into its own image. Pixels of all views
+/// are traced by the same threads within a pass so they share the world and its BVH.
 fn render<B: 'static + material::Background>(
-    image: Arc<Image>,
+    views: Vec<(Arc<Image>, world::Camera)>,
     event_proxy: Arc<Mutex<EventLoopProxy<UserEvent>>>,
     world: world::World<B>,
-    camera: world::Camera,
-    frame_limit: Option<u32>,
+    frame_limit: FrameLimit,
 ) {
     let world = Arc::new(world);
-    let camera = Arc::new(camera);
+    let views: Arc<Vec<(Arc<Image>, Arc<world::Camera>)>> = Arc::new(
+        views
+            .into_iter()
+            .map(|(image, camera)| (image, Arc::new(camera)))
+            .collect(),
+    );
     let cpus = num_cpus::get() as i32;
     let cpus = (cpus - 2).max(1);
 
+    for (image, camera) in views.iter() {
+        prepass(image, &world, camera, cpus);
+    }
+
+    if QUICK_PASS.load(AtomicOrdering::Relaxed) {
+        event_proxy
+            .lock()
+            .expect("Event proxy posioned")
+            .send_event(UserEvent::Update)
+            .expect("Unable to reach event loop");
+        return;
+    }
+
+    #[cfg(feature = "gpu")]
+    {
+        if GPU_BACKEND && render_gpu(&views, &event_proxy, &world, frame_limit) {
+            return;
+        }
+    }
+
+    for (image, _) in views.iter() {
+        image.clear();
+    }
+
+    let primary_hits = if HYBRID_PASSES > 0 && !COMPONENT_AOVS && !LIGHT_GROUP_AOVS {
+        views
+            .iter()
+            .map(|(image, camera)| primary_hits(image, &world, camera, cpus))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let primary_hits = &primary_hits;
+
+    let tile_queue = tiles::TileQueue::new(
+        views.iter().map(|(image, _)| (image.width, image.height)),
+        TILE_SIZE,
+        TILE_ORDER,
+    );
+    let pass_buffers: Vec<Mutex<ImageBuffer>> = views
+        .iter()
+        .map(|(image, _)| Mutex::new(image.buffer()))
+        .collect();
+    let barrier = std::sync::Barrier::new(cpus as usize);
+    let pass_start = Mutex::new(std::time::Instant::now());
+    let passes = AtomicU32::new(0);
+    let finished = AtomicBool::new(false);
+    // Each thread used to trace whole passes of its own, keep the same samples per frame
+    let pass_limit = FrameLimit {
+        samples: frame_limit.samples.map(|limit| limit * cpus as u32),
+        ..frame_limit
+    };
+    let hybrid_passes = HYBRID_PASSES * cpus as u32;
+
+    let (tile_queue, pass_buffers, barrier, pass_start, passes, finished) = (
+        &tile_queue,
+        &pass_buffers,
+        &barrier,
+        &pass_start,
+        &passes,
+        &finished,
+    );
+
+    std::thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for i in 0..cpus {
+            let event_proxy = event_proxy.clone();
+            let world = world.clone();
+            let views = views.clone();
+            let mut buffers: Vec<ImageBuffer> =
+                views.iter().map(|(image, _)| image.buffer()).collect();
+            let mut wavefront = mass_raytrace::wavefront::Wavefront::new();
+            let mut sampler = SAMPLER.build();
+
+            let builder = std::thread::Builder::new()
+                .name(format!("render:{}", i))
+                .stack_size(32 * 1024 * 1024);
+
+            let handle = builder
+                .spawn_scoped(scope, move || loop {
+                    let pass = passes.load(AtomicOrdering::Relaxed);
+                    while let Some((index, tile)) = tile_queue.next() {
+                        while RENDER_PAUSED.load(AtomicOrdering::Relaxed) && !render_interrupted() {
+                            std::thread::sleep(std::time::Duration::from_millis(50));
+                        }
+                        if render_interrupted() {
+                            tile_queue.cancel();
+                            break;
+                        }
+
+                        let progress_step = (tile_queue.len() / 10).max(1);
+                        if pass == 0 && pass_limit.is_none() && index % progress_step == 0 {
+                            println!("{:.2}%", index as f64 / tile_queue.len() as f64 * 100.0);
+                        }
+
+                        let view = &views[tile.view];
+                        let image = &view.0;
+                        let buffer = &mut buffers[tile.view];
+                        let hybrid_hits: Option<&Vec<_>> =
+                            primary_hits.get(tile.view).filter(|_| pass < hybrid_passes);
+
+                        if WAVEFRONT && hybrid_hits.is_none() && !COMPONENT_AOVS {
+                            trace_wavefront(
+                                &mut wavefront,
+                                &world,
+                                view,
+                                buffer,
+                                tile,
+                                &mut *sampler,
+                                pass,
+                            );
+                        } else {
+                            trace_tile(
+                                &world,
+                                view,
+                                buffer,
+                                tile,
+                                hybrid_hits,
+                                &mut *sampler,
+                                pass,
+                            );
+                        }
+
+                        if pass == 0 {
+                            image.merge_tile(buffer, tile);
+                            event_proxy
+                                .lock()
+                                .expect("Event proxy posioned")
+                                .send_event(UserEvent::Update)
+                                .expect("Unable to reach event loop");
+                        } else {
+                            pass_buffers[tile.view]
+                                .lock()
+                                .unwrap()
+                                .copy_tile(buffer, tile);
+                        }
+                    }
+
+                    // Passes cut short by a quick pass are dropped rather than merged
+                    if barrier.wait().is_leader() {
+                        let done = tile_queue.is_cancelled()
+                            || finish_pass(
+                                &views,
+                                pass_buffers,
+                                &event_proxy,
+                                &world,
+                                pass_start,
+                                passes,
+                                pass_limit,
+                            );
+                        finished.store(done, AtomicOrdering::Relaxed);
+                        tile_queue.reset();
+                    }
+                    barrier.wait();
+
+                    if finished.load(AtomicOrdering::Relaxed) {
+                        return;
+                    }
+                })
+                .expect("Unable to spawn render thread");
+
+            handles.push(handle);
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    });
+}
+
+/// Whether the pass in progress should stop early, either for a quick pass or a console edit.
+fn render_interrupted() -> bool {
+    QUICK_PASS.load(AtomicOrdering::Relaxed) || SCENE_EDITED.load(AtomicOrdering::Relaxed)
+}
+
+/// Traces one sample for every pixel of `tile`, continuing from the cached `hybrid_hits` if
+/// there are any.
+fn trace_tile<B: material::Background>(
+    world: &world::World<B>,
+    (image, camera): &(Arc<Image>, Arc<world::Camera>),
+    buffer: &mut ImageBuffer,
+    tile: &tiles::Tile,
+    hybrid_hits: Option<&Vec<(world::Ray, Option<geom::Hit>)>>,
+    sampler: &mut dyn Sampler,
+    sample: u32,
+) {
+    let reference_camera = reference_camera(camera);
+    for (x, y) in tile.pixels() {
+        if let Some((ray, hit)) =
+            hybrid_hits.and_then(|hits| hits.get((y * image.width + x) as usize))
+        {
+            sampler.start_pixel_sample((x, y), sample);
+            let (color, depth) = match hit {
+                Some(hit) => camera.trace_from_hit(world, *ray, hit, MAX_DEPTH, sampler),
+                None => camera.trace(world, *ray, MAX_DEPTH, sampler),
+            };
+
+            buffer.set((x, y), color, MAX_DEPTH - depth);
+            let alpha = match hit {
+                Some(_) => 1.0,
+                None => camera.alpha(world, *ray),
+            };
+            buffer.set_alpha((x, y), alpha);
+            if let Some(reference_camera) = reference_camera.as_ref() {
+                let (reference, _depth) = match hit {
+                    Some(hit) => {
+                        reference_camera.trace_from_hit(world, *ray, hit, MAX_DEPTH, sampler)
+                    }
+                    None => reference_camera.trace(world, *ray, MAX_DEPTH, sampler),
+                };
+                buffer.set_reference((x, y), reference);
+            }
+            continue;
+        }
+
+        let ray = pixel_ray(image, camera, (x, y), sampler, sample);
+        if COMPONENT_AOVS {
+            let (components, depth) = camera.trace_components(world, ray, MAX_DEPTH, sampler);
+            let color = components
+                .iter()
+                .fold(V3::zero(), |sum, &component| sum + component);
+
+            buffer.set((x, y), color, MAX_DEPTH - depth);
+            buffer.set_components((x, y), components);
+        } else if LIGHT_GROUP_AOVS {
+            let (groups, depth) = camera.trace_light_groups(world, ray, MAX_DEPTH, sampler);
+            let color = groups.iter().fold(V3::zero(), |sum, &group| sum + group);
+
+            buffer.set((x, y), color, MAX_DEPTH - depth);
+            buffer.set_light_groups((x, y), groups);
+        } else {
+            let (color, depth) = camera.trace(world, ray, MAX_DEPTH, sampler);
+
+            buffer.set((x, y), color, MAX_DEPTH - depth);
+        }
+        buffer.set_alpha((x, y), camera.alpha(world, ray));
+
+        if let Some(reference_camera) = reference_camera.as_ref() {
+            let (reference, _depth) = reference_camera.trace(world, ray, MAX_DEPTH, sampler);
+            buffer.set_reference((x, y), reference);
+        }
+    }
+}
+
+/// An unclamped copy of `camera` to trace the samples of `REFERENCE_ACCUMULATION` with, `None`
+/// when the displayed samples can be accumulated as they are.
+fn reference_camera(camera: &world::Camera) -> Option<world::Camera> {
+    if REFERENCE_ACCUMULATION && camera.is_radiance_clamped() {
+        Some(camera.clone().with_radiance_clamp(None, None))
+    } else {
+        None
+    }
+}
+
+/// Merges a completed pass into the images and reports it, returning whether rendering this
+/// frame is done.
+fn finish_pass<B: material::Background>(
+    views: &[(Arc<Image>, Arc<world::Camera>)],
+    pass_buffers: &[Mutex<ImageBuffer>],
+    event_proxy: &Mutex<EventLoopProxy<UserEvent>>,
+    world: &world::World<B>,
+    pass_start: &Mutex<std::time::Instant>,
+    passes: &AtomicU32,
+    pass_limit: FrameLimit,
+) -> bool {
+    // The first pass was merged tile by tile as it was traced
+    if passes.load(AtomicOrdering::Relaxed) > 0 {
+        for ((image, _), buffer) in views.iter().zip(pass_buffers) {
+            image.merge(&buffer.lock().unwrap());
+        }
+    }
+
+    let mut pass_start = pass_start.lock().unwrap();
+    if pass_limit.is_none() {
+        println!("Frame time: {} ms", pass_start.elapsed().as_millis());
+    }
+    *pass_start = std::time::Instant::now();
+
+    if SCENE_STATS.swap(false, AtomicOrdering::Relaxed) {
+        scene_stats::report(world, &views[0].1);
+    }
+    event_proxy
+        .lock()
+        .expect("Event proxy posioned")
+        .send_event(UserEvent::Update)
+        .expect("Unable to reach event loop");
+
+    let pass = passes.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+    if pass_limit.reached(pass) || QUICK_PASS.load(AtomicOrdering::Relaxed) {
+        return true;
+    }
+
+    if let Some(convergence) = AUTO_STOP {
+        if views.iter().all(|(image, _)| image.converged(convergence)) {
+            println!("Render converged after {} samples", views[0].0.samples());
+            RENDER_CONVERGED.store(true, AtomicOrdering::Relaxed);
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Traces every view on the GPU one sample per pixel at a time, returning false without
+/// touching the images if a tracer couldn't be created so the CPU can take over.
+#[cfg(feature = "gpu")]
+fn render_gpu<B: material::Background>(
+    views: &[(Arc<Image>, Arc<world::Camera>)],
+    event_proxy: &Arc<Mutex<EventLoopProxy<UserEvent>>>,
+    world: &world::World<B>,
+    frame_limit: FrameLimit,
+) -> bool {
+    let mut tracers = Vec::new();
+    for (image, camera) in views.iter() {
+        match mass_raytrace::gpu::GpuTracer::new(
+            world,
+            camera,
+            image.width,
+            image.height,
+            MAX_DEPTH,
+        ) {
+            Ok(tracer) => tracers.push(tracer),
+            Err(error) => {
+                eprintln!("Unable to start gpu tracer, using the cpu: {}", error);
+                return false;
+            }
+        }
+    }
+    if let Some(tracer) = tracers.first() {
+        println!("Tracing on {}", tracer.adapter_name());
+    }
+
+    for (image, _) in views.iter() {
+        image.clear();
+    }
+    let mut buffers: Vec<ImageBuffer> = views.iter().map(|(image, _)| image.buffer()).collect();
+
+    let mut samples = 0;
+    while !frame_limit.reached(samples) {
+        let frame_start = std::time::Instant::now();
+        for ((tracer, buffer), (image, _)) in tracers.iter_mut().zip(buffers.iter_mut()).zip(views)
+        {
+            match tracer.trace() {
+                Ok(pixels) => {
+                    for (index, color) in pixels.into_iter().enumerate() {
+                        let index = index as u32;
+                        buffer.set((index % image.width, index / image.width), color, 0);
+                    }
+                }
+                Err(error) => {
+                    eprintln!("Gpu trace failed: {}", error);
+                    return true;
+                }
+            }
+            image.merge(buffer);
+        }
+
+        if frame_limit.is_none() {
+            println!("Frame time: {} ms", frame_start.elapsed().as_millis());
+        }
+        event_proxy
+            .lock()
+            .expect("Event proxy posioned")
+            .send_event(UserEvent::Update)
+            .expect("Unable to reach event loop");
+
+        samples += 1;
+
+        if render_interrupted() {
+            return true;
+        }
+
+        if let Some(convergence) = AUTO_STOP {
+            if views.iter().all(|(image, _)| image.converged(convergence)) {
+                println!("Render converged after {} samples", views[0].0.samples());
+                RENDER_CONVERGED.store(true, AtomicOrdering::Relaxed);
+                return true;
+            }
+        }
+    }
+
+    true
+}
+
+/// Traces one sample for every pixel of `tile` as a single wavefront batch.
+fn trace_wavefront<B: material::Background>(
+    wavefront: &mut mass_raytrace::wavefront::Wavefront,
+    world: &world::World<B>,
+    (image, camera): &(Arc<Image>, Arc<world::Camera>),
+    buffer: &mut ImageBuffer,
+    tile: &tiles::Tile,
+    sampler: &mut dyn Sampler,
+    sample: u32,
+) {
+    let rays: Vec<((u32, u32), world::Ray)> = tile
+        .pixels()
+        .map(|pixel| (pixel, pixel_ray(image, camera, pixel, sampler, sample)))
+        .collect();
+    let results = wavefront.trace(
+        world,
+        camera,
+        rays.iter().copied(),
+        MAX_DEPTH,
+        sampler,
+        sample,
+    );
+    for ((x, y), result) in tile.pixels().zip(results) {
+        buffer.set((x, y), result.color(), MAX_DEPTH - result.depth);
+        if LIGHT_GROUP_AOVS {
+            buffer.set_light_groups((x, y), result.light_groups);
+        }
+    }
+    for &((x, y), ray) in rays.iter() {
+        buffer.set_alpha((x, y), camera.alpha(world, ray));
+    }
+
+    if let Some(reference_camera) = reference_camera(camera) {
+        let results = wavefront.trace(world, &reference_camera, rays, MAX_DEPTH, sampler, sample);
+        for ((x, y), result) in tile.pixels().zip(results) {
+            buffer.set_reference((x, y), result.color());
+        }
+    }
+}
+
+/// A jittered camera ray for sample `sample` of `pixel`, timed by the sensor's rolling shutter if
+/// it has one.
+fn pixel_ray(
+    image: &Image,
+    camera: &world::Camera,
+    pixel: (u32, u32),
+    sampler: &mut dyn Sampler,
+    sample: u32,
+) -> world::Ray {
+    let (x, y) = pixel;
+    sampler.start_pixel_sample(pixel, sample);
+    let jitter = sampler.get_2d();
+    let u = (x as f32 + jitter.x()) / ((image.width - 1) as f32);
+    let v = (y as f32 + jitter.y()) / ((image.height - 1) as f32);
+    let ray = camera
+        .sample_ray(u, v, sampler)
+        .with_spread(camera.pixel_spread(image.height));
+    match image.sensor.as_ref() {
+        Some(sensor) => ray.with_time(sensor.row_time(ray.time, y, image.height)),
+        None => ray,
+    }
+}
+
+/// Traces one camera ray per pixel of `image` and keeps the ray with its first hit, rows first,
+/// for the hybrid passes to continue from.
+fn primary_hits<'w, B: material::Background>(
+    image: &Image,
+    world: &'w world::World<B>,
+    camera: &world::Camera,
+    cpus: i32,
+) -> Vec<(world::Ray, Option<geom::Hit<'w>>)> {
+    let rows_per_thread = (image.height + cpus as u32 - 1) / cpus as u32;
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..cpus as u32)
+            .map(|i| {
+                scope.spawn(move || {
+                    let rows = (i * rows_per_thread)..((i + 1) * rows_per_thread).min(image.height);
+                    let mut hits = Vec::with_capacity(rows.len() * image.width as usize);
+                    for y in rows {
+                        for x in 0..image.width {
+                            let ray = pixel_ray(image, camera, (x, y), &mut RandomSampler, 0);
+                            hits.push((ray, camera.primary_hit(world, ray)));
+                        }
+                    }
+                    hits
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Fills in the albedo and normal buffers of `image` used by the denoiser.
+fn prepass<B: 'static + material::Background>(
+    image: &Arc<Image>,
+    world: &Arc<world::World<B>>,
+    camera: &Arc<world::Camera>,
+    cpus: i32,
+) {
     let albedo_buf = Arc::new(Mutex::new(FloatBuffer::new(image.width, image.height)));
     let normal_buf = Arc::new(Mutex::new(FloatBuffer::new(image.width, image.height)));
+    let range_buf = Arc::new(Mutex::new(RangeImage::new(image.width, image.height)));
     let row = Arc::new(AtomicU32::new(0));
 
     let mut handles = Vec::new();
@@ -171,12 +1044,14 @@ fn render<B: 'static + material::Background>(
 
         let mut albedo_pixels = Vec::with_capacity(image.width as usize);
         let mut normal_pixels = Vec::with_capacity(image.width as usize);
+        let mut range_pixels = Vec::with_capacity(image.width as usize);
 
         let world = world.clone();
         let camera = camera.clone();
         let image = image.clone();
         let albedo_buf = albedo_buf.clone();
         let normal_buf = normal_buf.clone();
+        let range_buf = range_buf.clone();
         let row = row.clone();
 
         let handle = builder
@@ -185,6 +1060,7 @@ fn render<B: 'static + material::Background>(
                 while y < image.height {
                     albedo_pixels.clear();
                     normal_pixels.clear();
+                    range_pixels.clear();
                     for x in 0..image.width {
                         let u = (x as f32) / ((image.width - 1) as f32);
                         let v = (y as f32) / ((image.height - 1) as f32);
@@ -192,99 +1068,31 @@ fn render<B: 'static + material::Background>(
                         let (albedo, normal) = camera.albedo_normal(&*world, ray);
 
                         albedo_pixels.push(albedo);
-                        normal_pixels.push(normal);
-                    }
-
-                    albedo_buf
-                        .lock()
-                        .unwrap()
-                        .set_row(y, albedo_pixels.as_slice());
-                    normal_buf
-                        .lock()
-                        .unwrap()
-                        .set_row(y, normal_pixels.as_slice());
-                    y = row.fetch_add(1, AtomicOrdering::Acquire);
-                }
-            })
-            .expect("unable to spawn pre-render thread");
-
-        handles.push(handle);
-    }
-
-    for handle in handles {
-        handle.join().unwrap();
-    }
-
-    let albedo_buf = Arc::try_unwrap(albedo_buf).unwrap().into_inner().unwrap();
-    let normal_buf = Arc::try_unwrap(normal_buf).unwrap().into_inner().unwrap();
-
-    image.set_albedo(albedo_buf);
-    image.set_normal(normal_buf);
-
-    if QUICK_PASS.load(AtomicOrdering::Relaxed) {
-        event_proxy
-            .lock()
-            .expect("Event proxy posioned")
-            .send_event(UserEvent::Update)
-            .expect("Unable to reach event loop");
-        return;
-    }
-
-    image.clear();
-    let mut handles = Vec::new();
-    for i in 0..cpus {
-        let event_proxy = event_proxy.clone();
-        let world = world.clone();
-        let camera = camera.clone();
-        let image = image.clone();
-        let mut buffer = image.buffer();
-        let mut first = true;
-
-        let mut frame_limit = frame_limit.clone();
-
-        let builder = std::thread::Builder::new()
-            .name(format!("render:{}", i))
-            .stack_size(32 * 1024 * 1024);
-
-        let handle = builder
-            .spawn(move || {
-                while frame_limit.is_none() || frame_limit != Some(0) {
-                    let frame_start = std::time::Instant::now();
-                    for y in 0..image.height {
-                        if i == 0 && first && frame_limit.is_none() && y % 10 == 0 {
-                            println!("{:.2}%", y as f64 / image.height as f64 * 100.0);
-                        }
-                        for x in 0..image.width {
-                            let u = (x as f32 + f32::rand()) / ((image.width - 1) as f32);
-                            let v = (y as f32 + f32::rand()) / ((image.height - 1) as f32);
-                            let ray = camera.ray(u, v);
-                            let (color, depth) = camera.trace(&*world, ray, MAX_DEPTH);
-
-                            buffer.set((x, y), color, MAX_DEPTH - depth);
-                        }
-                    }
-
-                    first = false;
+                        normal_pixels.push(normal);
 
-                    if frame_limit.is_none() || i == 0 {
-                        println!("Frame time: {} seconds", frame_start.elapsed().as_secs());
+                        if LIDAR_OUTPUT {
+                            range_pixels.push(camera.range(&*world, ray));
+                        }
                     }
 
-                    image.merge(&mut buffer);
-                    event_proxy
+                    albedo_buf
                         .lock()
-                        .expect("Event proxy posioned")
-                        .send_event(UserEvent::Update)
-                        .expect("Unable to reach event loop");
-
-                    frame_limit.as_mut().map(|n| *n -= 1);
-
-                    if QUICK_PASS.load(AtomicOrdering::Relaxed) {
-                        return;
+                        .unwrap()
+                        .set_row(y, albedo_pixels.as_slice());
+                    normal_buf
+                        .lock()
+                        .unwrap()
+                        .set_row(y, normal_pixels.as_slice());
+                    if LIDAR_OUTPUT {
+                        range_buf
+                            .lock()
+                            .unwrap()
+                            .set_row(y, range_pixels.as_slice());
                     }
+                    y = row.fetch_add(1, AtomicOrdering::Acquire);
                 }
             })
-            .expect("Unable to spawn render thread");
+            .expect("unable to spawn pre-render thread");
 
         handles.push(handle);
     }
@@ -292,14 +1100,49 @@ fn render<B: 'static + material::Background>(
     for handle in handles {
         handle.join().unwrap();
     }
+
+    let albedo_buf = Arc::try_unwrap(albedo_buf).unwrap().into_inner().unwrap();
+    let normal_buf = Arc::try_unwrap(normal_buf).unwrap().into_inner().unwrap();
+
+    image.set_albedo(albedo_buf);
+    image.set_normal(normal_buf);
+
+    if LIDAR_OUTPUT {
+        let range_buf = Arc::try_unwrap(range_buf)
+            .ok()
+            .unwrap()
+            .into_inner()
+            .unwrap();
+        image.set_range(range_buf);
+    }
+}
+
+/// Converts `image` for display off the event loop thread, dropped if the previous conversion
+/// is still running.
+fn present(image: Arc<Image>, event_proxy: EventLoopProxy<UserEvent>, display_mode: DisplayMode) {
+    std::thread::spawn(move || {
+        if let Ok(_) = PIXEL_UPDATE_FLAG.compare_exchange(
+            false,
+            true,
+            AtomicOrdering::Acquire,
+            AtomicOrdering::Relaxed,
+        ) {
+            let image_bytes = image.to_rgb_bytes(display_mode);
+            if let Err(err) = event_proxy.send_event(UserEvent::Redraw(image_bytes)) {
+                eprintln!("{}", err);
+            }
+            PIXEL_UPDATE_FLAG.store(false, AtomicOrdering::Release);
+        }
+    });
 }
 
 fn run(
     event_loop: EventLoop<UserEvent>,
     image: Arc<Image>,
     input: Arc<Mutex<InputCollection>>,
+    mut settings: settings::Settings,
 ) -> ! {
-    let window_size = PhysicalSize::new(IMAGE_WIDTH, IMAGE_HEIGHT);
+    let window_size = PhysicalSize::new(settings.window_size.0, settings.window_size.1);
 
     let window_builder = WindowBuilder::new()
         .with_inner_size(window_size)
@@ -308,6 +1151,7 @@ fn run(
     let context_builder = glutin::ContextBuilder::new()
         .with_vsync(true)
         .with_srgb(true)
+        .with_depth_buffer(24)
         .with_gl_profile(glutin::GlProfile::Core)
         .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (4, 2)));
 
@@ -321,61 +1165,80 @@ fn run(
     let mut display_mode = if QUICK_PASS.load(AtomicOrdering::Relaxed) {
         DisplayMode::Albedo
     } else {
-        DisplayMode::Default
+        settings.display_mode
     };
     let event_proxy = event_loop.create_proxy();
 
     let mut texture = None;
+    let mut preview: Option<preview::Preview> = None;
+    let mut last_update = std::time::Instant::now();
+    let mut present_pacer = PRESENT_FPS.map(pacing::Pacer::new);
+    let mut update_pending = false;
 
     let mut gilrs = gilrs::Gilrs::new().unwrap();
 
     event_loop.run(move |event, _window, control_flow| match event {
         Event::MainEventsCleared => {
+            if let Some(pacer) = present_pacer.as_mut() {
+                let due = pacer.tick();
+                *control_flow = ControlFlow::WaitUntil(pacer.deadline());
+                if !due {
+                    return;
+                }
+
+                if update_pending {
+                    update_pending = false;
+                    present(image.clone(), event_proxy.clone(), display_mode);
+                }
+            }
+
+            let dt = last_update.elapsed().as_secs_f32().min(0.1);
+            last_update = std::time::Instant::now();
+            if let Some(preview) = preview.as_mut().filter(|p| p.is_visible()) {
+                if preview.update(&input.lock().unwrap(), dt) {
+                    display.gl_window().window().request_redraw();
+                }
+            }
+
             while let Some(event) = gilrs.next_event() {
                 match event {
                     gilrs::Event {
+                        id,
                         event: gilrs::EventType::AxisChanged(axis, value, _),
                         ..
                     } => {
                         let value = if value.abs() < 0.15 { 0.0 } else { value };
                         let mut input = input.lock().unwrap();
-                        input.set_axis(axis, value);
+                        input.set_gamepad_axis(id.into(), axis, value);
                     }
                     gilrs::Event {
+                        id,
                         event: gilrs::EventType::ButtonPressed(button, _),
                         ..
                     } => {
                         let mut input = input.lock().unwrap();
-                        input.set(Input::Button(button))
+                        input.set(Input::Button(button));
+                        input.set(Input::GamepadButton(id.into(), button));
                     }
                     gilrs::Event {
+                        id,
                         event: gilrs::EventType::ButtonReleased(button, _),
                         ..
                     } => {
                         let mut input = input.lock().unwrap();
-                        input.unset(Input::Button(button))
+                        input.unset(Input::Button(button));
+                        input.unset(Input::GamepadButton(id.into(), button));
                     }
                     _ => (),
                 }
             }
         }
         Event::UserEvent(UserEvent::Update) => {
-            let image = image.clone();
-            let event_proxy = event_proxy.clone();
-            std::thread::spawn(move || {
-                if let Ok(_) = PIXEL_UPDATE_FLAG.compare_exchange(
-                    false,
-                    true,
-                    AtomicOrdering::Acquire,
-                    AtomicOrdering::Relaxed,
-                ) {
-                    let image_bytes = image.to_rgb_bytes(display_mode);
-                    if let Err(err) = event_proxy.send_event(UserEvent::Redraw(image_bytes)) {
-                        eprintln!("{}", err);
-                    }
-                    PIXEL_UPDATE_FLAG.store(false, AtomicOrdering::Release);
-                }
-            });
+            if present_pacer.is_some() {
+                update_pending = true;
+            } else {
+                present(image.clone(), event_proxy.clone(), display_mode);
+            }
         }
         Event::UserEvent(UserEvent::Redraw(frame)) => {
             let data = glium::texture::RawImage2d {
@@ -387,6 +1250,10 @@ fn run(
             texture = Some(SrgbTexture2d::new(&display, data).expect("Unable to create texture"));
             display.gl_window().window().request_redraw();
         }
+        Event::UserEvent(UserEvent::Preview(geometry)) => {
+            preview = Some(preview::Preview::new(&display, geometry));
+            display.gl_window().window().request_redraw();
+        }
         Event::UserEvent(UserEvent::FatalError) => {
             eprintln!("Render thread panic");
             *control_flow = ControlFlow::Exit;
@@ -396,8 +1263,20 @@ fn run(
             event: WindowEvent::CloseRequested,
             ..
         } => {
+            if let Some(path) = SETTINGS_FILE {
+                settings.display_mode = display_mode;
+                if let Err(error) = settings.save(path) {
+                    eprintln!("Unable to save settings to {}: {}", path, error);
+                }
+            }
             *control_flow = ControlFlow::Exit;
         }
+        Event::WindowEvent {
+            event: WindowEvent::Resized(size),
+            ..
+        } => {
+            settings.window_size = (size.width, size.height);
+        }
         Event::WindowEvent {
             event:
                 WindowEvent::KeyboardInput {
@@ -416,21 +1295,69 @@ fn run(
             let initial_display_mode = display_mode;
             match key {
                 VirtualKeyCode::E => {
-                    let path = format!(
-                        "./export/raytrace_{}.png",
-                        std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_else(|e| e.duration())
-                            .as_secs()
-                    );
+                    let path = settings.export_path();
                     image.dump(&path, display_mode);
                     println!("Image saved to: {}", path);
+                    if LIGHT_GROUP_AOVS {
+                        image.dump_light_groups(path.trim_end_matches(".png"));
+                    }
+                    if COMPONENT_AOVS {
+                        image.dump_components(path.trim_end_matches(".png"));
+                    }
+                    if REFERENCE_ACCUMULATION {
+                        image.dump_reference(path.trim_end_matches(".png"));
+                    }
+                    if LIDAR_OUTPUT {
+                        image.dump_lidar(path.trim_end_matches(".png"));
+                    }
                 }
                 VirtualKeyCode::Key1 => display_mode = DisplayMode::Default,
                 VirtualKeyCode::Key2 => display_mode = DisplayMode::Denoise,
                 VirtualKeyCode::Key3 => display_mode = DisplayMode::Depth,
                 VirtualKeyCode::Key4 => display_mode = DisplayMode::Albedo,
                 VirtualKeyCode::Key5 => display_mode = DisplayMode::Normal,
+                VirtualKeyCode::Key6 if LIGHT_GROUP_AOVS => {
+                    display_mode = DisplayMode::LightGroup(0)
+                }
+                VirtualKeyCode::Key7 if LIGHT_GROUP_AOVS => {
+                    display_mode = DisplayMode::LightGroup(1)
+                }
+                VirtualKeyCode::Key8 if LIGHT_GROUP_AOVS => {
+                    display_mode = DisplayMode::LightGroup(2)
+                }
+                VirtualKeyCode::Key9 if LIGHT_GROUP_AOVS => {
+                    display_mode = DisplayMode::LightGroup(3)
+                }
+                VirtualKeyCode::Key0 if LIDAR_OUTPUT => display_mode = DisplayMode::Range,
+                VirtualKeyCode::P => {
+                    if let Some(preview) = preview.as_mut() {
+                        if !preview.cycle() {
+                            PREVIEW_ACTIVE.store(false, AtomicOrdering::Relaxed);
+                        }
+                        display.gl_window().window().request_redraw();
+                    }
+                }
+                VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                    let step = if key == VirtualKeyCode::Minus {
+                        -EXPOSURE_STEP
+                    } else {
+                        EXPOSURE_STEP
+                    };
+                    settings.exposure += step;
+                    image.set_exposure(settings.exposure);
+                    println!("Exposure: {:+.1} stops", settings.exposure);
+                    event_proxy
+                        .send_event(UserEvent::Update)
+                        .expect("Unable to reach event loop");
+                }
+                VirtualKeyCode::Space => {
+                    let paused = !RENDER_PAUSED.fetch_xor(true, AtomicOrdering::Relaxed);
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                VirtualKeyCode::I => {
+                    SCENE_STATS.store(true, AtomicOrdering::Relaxed);
+                    println!("Scene statistics will print after the current pass");
+                }
                 VirtualKeyCode::Grave => {
                     let old_val = QUICK_PASS.fetch_xor(true, AtomicOrdering::Relaxed);
                     if !old_val {
@@ -465,7 +1392,12 @@ fn run(
             input.set(Input::Key(key))
         }
         Event::RedrawRequested(_) => {
-            if let Some(texture) = texture.as_ref() {
+            if let Some(preview) = preview.as_ref().filter(|p| p.is_visible()) {
+                let mut frame = display.draw();
+                let (width, height) = display.get_framebuffer_dimensions();
+                preview.draw(&mut frame, width as f32 / height.max(1) as f32);
+                frame.finish().expect("Unable to finish frame");
+            } else if let Some(texture) = texture.as_ref() {
                 let mut frame = display.draw();
 
                 frame.clear_color(0.0, 0.0, 0.0, 1.0);
@@ -491,44 +1423,13 @@ fn run(
     });
 }
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-pub enum Input {
-    Key(winit::event::VirtualKeyCode),
-    Button(gilrs::Button),
-}
-
-pub struct InputCollection {
-    pressed_input: HashSet<Input>,
-    axis_values: HashMap<gilrs::Axis, f32>,
-}
-
-impl InputCollection {
-    fn new() -> Self {
-        Self {
-            pressed_input: HashSet::new(),
-            axis_values: HashMap::new(),
-        }
-    }
-
-    pub fn set(&mut self, key: Input) {
-        self.pressed_input.insert(key);
-    }
-
-    pub fn unset(&mut self, key: Input) {
-        self.pressed_input.remove(&key);
-    }
-
-    pub fn set_axis(&mut self, axis: gilrs::Axis, value: f32) {
-        self.axis_values.insert(axis, value);
-    }
-
-    pub fn is_pressed(&self, key: Input) -> bool {
-        self.pressed_input.contains(&key)
-    }
-
-    pub fn axis(&self, axis: gilrs::Axis) -> f32 {
-        *self.axis_values.get(&axis).unwrap_or(&0.0)
-    }
+/// Stops a still render once `pixel_fraction` of the pixels have a relative standard error
+/// below `relative_error`.
+#[derive(Debug, Copy, Clone)]
+struct Convergence {
+    relative_error: f32,
+    pixel_fraction: f32,
+    min_samples: u32,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -538,6 +1439,8 @@ enum DisplayMode {
     Depth,
     Albedo,
     Normal,
+    LightGroup(usize),
+    Range,
 }
 
 #[derive(Debug, Clone)]
@@ -576,6 +1479,12 @@ impl FloatBuffer {
 
 struct ImageBuffer {
     pixels: Vec<(V3, u32)>,
+    /// The coverage of each pixel, opaque unless a ray saw a transparent camera background.
+    alpha: Vec<f32>,
+    light_groups: Vec<[V3; LIGHT_GROUPS]>,
+    components: Vec<[V3; COMPONENTS]>,
+    /// Unclamped samples for `REFERENCE_ACCUMULATION`, empty when `pixels` are unclamped.
+    reference: Vec<V3>,
     width: u32,
     height: u32,
 }
@@ -584,6 +1493,10 @@ impl ImageBuffer {
     fn new(width: u32, height: u32) -> Self {
         ImageBuffer {
             pixels: vec![(V3::zero(), 0); (width * height) as usize],
+            alpha: vec![1.0; (width * height) as usize],
+            light_groups: light_group_pixels(width, height),
+            components: component_pixels(width, height),
+            reference: reference_pixels(width, height),
             width,
             height,
         }
@@ -593,27 +1506,133 @@ impl ImageBuffer {
         let index = ((position.1 * self.width) + position.0) as usize;
         self.pixels[index] = (color, depth);
     }
+
+    fn set_alpha(&mut self, position: (u32, u32), alpha: f32) {
+        let index = ((position.1 * self.width) + position.0) as usize;
+        self.alpha[index] = alpha;
+    }
+
+    fn set_light_groups(&mut self, position: (u32, u32), groups: [V3; LIGHT_GROUPS]) {
+        let index = ((position.1 * self.width) + position.0) as usize;
+        self.light_groups[index] = groups;
+    }
+
+    fn set_components(&mut self, position: (u32, u32), components: [V3; COMPONENTS]) {
+        let index = ((position.1 * self.width) + position.0) as usize;
+        self.components[index] = components;
+    }
+
+    fn set_reference(&mut self, position: (u32, u32), color: V3) {
+        let index = ((position.1 * self.width) + position.0) as usize;
+        if let Some(reference) = self.reference.get_mut(index) {
+            *reference = color;
+        }
+    }
+
+    fn copy_tile(&mut self, other: &ImageBuffer, tile: &tiles::Tile) {
+        for (x, y) in tile.pixels() {
+            let index = ((y * self.width) + x) as usize;
+            self.pixels[index] = other.pixels[index];
+            self.alpha[index] = other.alpha[index];
+            if LIGHT_GROUP_AOVS {
+                self.light_groups[index] = other.light_groups[index];
+            }
+            if COMPONENT_AOVS {
+                self.components[index] = other.components[index];
+            }
+            if let Some(&reference) = other.reference.get(index) {
+                self.reference[index] = reference;
+            }
+        }
+    }
+}
+
+fn light_group_pixels(width: u32, height: u32) -> Vec<[V3; LIGHT_GROUPS]> {
+    if LIGHT_GROUP_AOVS {
+        vec![[V3::zero(); LIGHT_GROUPS]; (width * height) as usize]
+    } else {
+        Vec::new()
+    }
+}
+
+fn reference_pixels(width: u32, height: u32) -> Vec<V3> {
+    let clamped = RADIANCE_CLAMP.0.is_some() || RADIANCE_CLAMP.1.is_some();
+    if REFERENCE_ACCUMULATION && clamped {
+        vec![V3::zero(); (width * height) as usize]
+    } else {
+        Vec::new()
+    }
+}
+
+fn component_pixels(width: u32, height: u32) -> Vec<[V3; COMPONENTS]> {
+    if COMPONENT_AOVS {
+        vec![[V3::zero(); COMPONENTS]; (width * height) as usize]
+    } else {
+        Vec::new()
+    }
 }
 
 struct Image {
     pixels: Mutex<(u32, Vec<(V3, u32)>)>,
+    /// The sum of the coverage of every sample.
+    alpha: Mutex<Vec<f32>>,
+    /// The unclamped sum of every sample, empty unless `REFERENCE_ACCUMULATION` is set.
+    reference: Mutex<Vec<V3>>,
+    luminance_squares: Mutex<Vec<f32>>,
+    light_groups: Mutex<Vec<[V3; LIGHT_GROUPS]>>,
+    components: Mutex<Vec<[V3; COMPONENTS]>>,
     width: u32,
     height: u32,
     albedo: Mutex<Option<FloatBuffer>>,
     normal: Mutex<Option<FloatBuffer>>,
+    range: Mutex<Option<RangeImage>>,
+    overlay: Overlay,
+    sensor: Option<sensor::Sensor>,
+    frame_info: Mutex<FrameInfo>,
+    /// Stops of exposure applied when converting to display.
+    exposure: Mutex<f32>,
 }
 
 impl Image {
-    fn new(width: u32, height: u32) -> Self {
+    fn new(width: u32, height: u32, overlay: Overlay) -> Self {
         Image {
             pixels: Mutex::new((0, vec![(V3::zero(), 0); (width * height) as usize])),
+            alpha: Mutex::new(vec![0.0; (width * height) as usize]),
+            reference: Mutex::new(if REFERENCE_ACCUMULATION {
+                vec![V3::zero(); (width * height) as usize]
+            } else {
+                Vec::new()
+            }),
+            luminance_squares: Mutex::new(vec![0.0; (width * height) as usize]),
+            light_groups: Mutex::new(light_group_pixels(width, height)),
+            components: Mutex::new(component_pixels(width, height)),
             width,
             height,
             albedo: Mutex::new(None),
             normal: Mutex::new(None),
+            range: Mutex::new(None),
+            overlay,
+            sensor: None,
+            frame_info: Mutex::new(FrameInfo::default()),
+            exposure: Mutex::new(0.0),
         }
     }
 
+    fn set_exposure(&self, stops: f32) {
+        *self.exposure.lock().unwrap() = stops;
+    }
+
+    fn with_sensor(mut self, sensor: Option<sensor::Sensor>) -> Self {
+        self.sensor = sensor;
+        self
+    }
+
+    fn set_frame(&self, scene: &str, frame: u32) {
+        let mut frame_info = self.frame_info.lock().unwrap();
+        frame_info.scene = scene.to_string();
+        frame_info.frame = frame;
+    }
+
     fn set_albedo(&self, albedo: FloatBuffer) {
         *self.albedo.lock().unwrap() = Some(albedo);
     }
@@ -622,29 +1641,98 @@ impl Image {
         *self.normal.lock().unwrap() = Some(normal);
     }
 
+    fn set_range(&self, range: RangeImage) {
+        *self.range.lock().unwrap() = Some(range);
+    }
+
     fn buffer(&self) -> ImageBuffer {
         ImageBuffer::new(self.width, self.height)
     }
 
     fn merge(&self, buffer: &ImageBuffer) {
         let mut pixels = self.pixels.lock().unwrap();
-        for (&(buf_color, buf_depth), (image_color, image_depth)) in
-            buffer.pixels.iter().zip(pixels.1.iter_mut())
-        {
-            *image_color += buf_color;
+        self.merge_pixels(&mut pixels.1, buffer, 0..buffer.pixels.len());
+        pixels.0 += 1;
+    }
+
+    /// Merges just the pixels of `tile`, counting them as the first sample. Only used for a
+    /// frame's first pass, pixels outside of finished tiles are shown black until it completes.
+    fn merge_tile(&self, buffer: &ImageBuffer, tile: &tiles::Tile) {
+        let mut pixels = self.pixels.lock().unwrap();
+        let indices = tile.pixels().map(|(x, y)| (y * self.width + x) as usize);
+        self.merge_pixels(&mut pixels.1, buffer, indices);
+        pixels.0 = pixels.0.max(1);
+    }
+
+    fn merge_pixels(
+        &self,
+        pixels: &mut [(V3, u32)],
+        buffer: &ImageBuffer,
+        indices: impl Iterator<Item = usize> + Clone,
+    ) {
+        for index in indices.clone() {
+            let (buf_color, buf_depth) = buffer.pixels[index];
+            let (image_color, image_depth) = &mut pixels[index];
+            *image_color += clamp_sample(buf_color);
             *image_depth += buf_depth;
         }
-        pixels.0 += 1;
+
+        let mut alpha = self.alpha.lock().unwrap();
+        for index in indices.clone() {
+            alpha[index] += buffer.alpha[index];
+        }
+
+        let mut reference = self.reference.lock().unwrap();
+        if !reference.is_empty() {
+            for index in indices.clone() {
+                reference[index] += match buffer.reference.get(index) {
+                    Some(&sample) => sample,
+                    None => buffer.pixels[index].0,
+                };
+            }
+        }
+
+        let mut luminance_squares = self.luminance_squares.lock().unwrap();
+        for index in indices.clone() {
+            luminance_squares[index] += luminance(clamp_sample(buffer.pixels[index].0)).powi(2);
+        }
+
+        let mut light_groups = self.light_groups.lock().unwrap();
+        let mut components = self.components.lock().unwrap();
+        for index in indices {
+            if let (Some(buf_groups), Some(image_groups)) =
+                (buffer.light_groups.get(index), light_groups.get_mut(index))
+            {
+                for (&buf_group, image_group) in buf_groups.iter().zip(image_groups.iter_mut()) {
+                    *image_group += buf_group;
+                }
+            }
+
+            if let (Some(buf_components), Some(image_components)) =
+                (buffer.components.get(index), components.get_mut(index))
+            {
+                for (&buf_component, image_component) in
+                    buf_components.iter().zip(image_components.iter_mut())
+                {
+                    *image_component += buf_component;
+                }
+            }
+        }
     }
 
     fn to_rgb_bytes(&self, mode: DisplayMode) -> Vec<u8> {
         let pixels = self.pixels.lock().unwrap();
-        let scale = 1.0 / pixels.0 as f32;
+        let scale = 2.0f32.powf(*self.exposure.lock().unwrap()) / pixels.0 as f32;
         let component = |f_c: f32| ((scale * f_c).powf(1.0 / 2.2).min(1.0).max(0.0));
         let mut pixel_floats = Vec::with_capacity(pixels.1.len() * 3);
 
         let pixel_floats = match mode {
-            DisplayMode::Depth | DisplayMode::Default | DisplayMode::Denoise if pixels.0 == 0 => {
+            DisplayMode::Depth
+            | DisplayMode::Default
+            | DisplayMode::Denoise
+            | DisplayMode::LightGroup(_)
+                if pixels.0 == 0 =>
+            {
                 for _ in 0..pixels.1.len() {
                     pixel_floats.push(0.0);
                     pixel_floats.push(0.0);
@@ -665,10 +1753,19 @@ impl Image {
                 pixel_floats
             }
             DisplayMode::Default => {
-                for (color, _depth) in pixels.1.iter() {
-                    pixel_floats.push(component(color.x()));
-                    pixel_floats.push(component(color.y()));
-                    pixel_floats.push(component(color.z()));
+                if let Some(sensor) = self.sensor.as_ref() {
+                    for (index, (color, _depth)) in pixels.1.iter().enumerate() {
+                        let color = sensor.form(*color * scale, index, pixels.0);
+                        pixel_floats.push(color.x().min(1.0).max(0.0));
+                        pixel_floats.push(color.y().min(1.0).max(0.0));
+                        pixel_floats.push(color.z().min(1.0).max(0.0));
+                    }
+                } else {
+                    for (color, _depth) in pixels.1.iter() {
+                        pixel_floats.push(component(color.x()));
+                        pixel_floats.push(component(color.y()));
+                        pixel_floats.push(component(color.z()));
+                    }
                 }
                 pixel_floats
             }
@@ -681,6 +1778,23 @@ impl Image {
                 self.denoise(&mut pixel_floats);
                 pixel_floats
             }
+            DisplayMode::LightGroup(group) => {
+                let light_groups = self.light_groups.lock().unwrap();
+                if light_groups.is_empty() {
+                    for _ in 0..pixels.1.len() {
+                        pixel_floats.push(0.0);
+                        pixel_floats.push(0.0);
+                        pixel_floats.push(0.0);
+                    }
+                } else {
+                    for groups in light_groups.iter() {
+                        pixel_floats.push(component(groups[group].x()));
+                        pixel_floats.push(component(groups[group].y()));
+                        pixel_floats.push(component(groups[group].z()));
+                    }
+                }
+                pixel_floats
+            }
             DisplayMode::Albedo => {
                 let albedo = self.albedo.lock();
                 if let Ok(Some(albedo)) = albedo.as_deref() {
@@ -697,6 +1811,28 @@ impl Image {
 
                 pixel_floats
             }
+            DisplayMode::Range => {
+                let range = self.range.lock().unwrap();
+                if let Some(range) = range.as_ref() {
+                    let max_distance = range.max_distance().max(f32::EPSILON);
+                    for sample in range.samples() {
+                        let near = sample
+                            .map(|s| 1.0 - (s.distance / max_distance).min(1.0))
+                            .unwrap_or(0.0);
+                        pixel_floats.push(near);
+                        pixel_floats.push(near);
+                        pixel_floats.push(near);
+                    }
+                } else {
+                    for _ in 0..pixels.1.len() {
+                        pixel_floats.push(0.0);
+                        pixel_floats.push(0.0);
+                        pixel_floats.push(0.0);
+                    }
+                }
+
+                pixel_floats
+            }
             DisplayMode::Normal => {
                 let normal = self.normal.lock();
                 if let Ok(Some(normal)) = normal.as_deref() {
@@ -754,32 +1890,224 @@ impl Image {
             *depth = 0;
         }
 
+        for alpha in self.alpha.lock().unwrap().iter_mut() {
+            *alpha = 0.0;
+        }
+
+        for square in self.luminance_squares.lock().unwrap().iter_mut() {
+            *square = 0.0;
+        }
+
+        for reference in self.reference.lock().unwrap().iter_mut() {
+            *reference = V3::zero();
+        }
+
+        for groups in self.light_groups.lock().unwrap().iter_mut() {
+            *groups = [V3::zero(); LIGHT_GROUPS];
+        }
+
+        for components in self.components.lock().unwrap().iter_mut() {
+            *components = [V3::zero(); COMPONENTS];
+        }
+
         pixels.0 = 0;
     }
 
-    fn dump<P: AsRef<std::path::Path>>(&self, path: P, mode: DisplayMode) {
+    /// Saves the image as a PNG, returning the RGB8 pixels that were written.
+    fn dump<P: AsRef<std::path::Path>>(&self, path: P, mode: DisplayMode) -> Vec<u8> {
         let path = path.as_ref();
         let pixel_bytes = self.to_rgb_bytes(mode);
-        let pixel_bytes: Vec<u8> = pixel_bytes
+        let mut pixel_bytes: Vec<u8> = pixel_bytes
             .chunks(3 * self.width as usize)
             .rev()
             .flat_map(|c| c)
             .map(|p| *p)
             .collect();
 
+        let mut frame_info = self.frame_info.lock().unwrap().clone();
+        frame_info.samples = self.pixels.lock().unwrap().0;
+        self.overlay
+            .apply(&mut pixel_bytes, self.width, self.height, &frame_info);
+
+        // Only the beauty passes have a background to see through
+        let alpha = match mode {
+            DisplayMode::Default | DisplayMode::Denoise => self.alpha_bytes(),
+            _ => None,
+        };
+        let (bytes, color_type) = match alpha {
+            Some(alpha) => {
+                let alpha = alpha.chunks(self.width as usize).rev().flat_map(|c| c);
+                let bytes = pixel_bytes
+                    .chunks(3)
+                    .zip(alpha)
+                    .flat_map(|(rgb, &a)| vec![rgb[0], rgb[1], rgb[2], a])
+                    .collect();
+                (bytes, image::ColorType::Rgba8)
+            }
+            None => (pixel_bytes.clone(), image::ColorType::Rgb8),
+        };
+
         std::fs::create_dir_all(&path.parent().expect("input path should have parent"))
             .expect("Unable to create export directory");
         let r = image::save_buffer_with_format(
             path,
-            &pixel_bytes,
+            &bytes,
             self.width,
             self.height,
-            image::ColorType::Rgb8,
+            color_type,
             image::ImageFormat::Png,
         );
         if let Err(error) = r {
             eprintln!("Unable to save image: {:?}", error);
         }
+
+        pixel_bytes
+    }
+}
+
+/// Applies `SAMPLE_CLAMP` to one sample, keeping its hue.
+fn clamp_sample(color: V3) -> V3 {
+    match SAMPLE_CLAMP {
+        Some(limit) => {
+            let brightest = color.x().max(color.y()).max(color.z());
+            if brightest > limit {
+                color * (limit / brightest)
+            } else {
+                color
+            }
+        }
+        None => color,
+    }
+}
+
+fn luminance(color: V3) -> f32 {
+    color.x() * 0.2126 + color.y() * 0.7152 + color.z() * 0.0722
+}
+
+impl Image {
+    fn samples(&self) -> u32 {
+        self.pixels.lock().unwrap().0
+    }
+
+    /// The coverage of each pixel as bytes, rows in the order of `to_rgb_bytes`, or `None`
+    /// when every pixel is opaque.
+    fn alpha_bytes(&self) -> Option<Vec<u8>> {
+        let samples = self.samples().max(1) as f32;
+        let alpha = self.alpha.lock().unwrap();
+        if alpha.iter().all(|&alpha| alpha >= samples) {
+            return None;
+        }
+
+        let bytes = alpha
+            .iter()
+            .map(|&alpha| ((alpha / samples).min(1.0).max(0.0) * 255.0) as u8)
+            .collect();
+        Some(bytes)
+    }
+
+    /// Estimates each pixel's relative standard error from its luminance variance and checks
+    /// it against `convergence`.
+    fn converged(&self, convergence: Convergence) -> bool {
+        let pixels = self.pixels.lock().unwrap();
+        let luminance_squares = self.luminance_squares.lock().unwrap();
+        let samples = pixels.0;
+        if samples < convergence.min_samples.max(2) {
+            return false;
+        }
+
+        let n = samples as f32;
+        let converged_pixels = pixels
+            .1
+            .iter()
+            .zip(luminance_squares.iter())
+            .filter(|(&(color, _), &square)| {
+                let mean = luminance(color) / n;
+                let variance = ((square / n - mean * mean) * n / (n - 1.0)).max(0.0);
+                let standard_error = (variance / n).sqrt();
+                standard_error <= convergence.relative_error * mean.max(0.001)
+            })
+            .count();
+
+        converged_pixels as f32 >= convergence.pixel_fraction * pixels.1.len() as f32
+    }
+
+    /// Writes the LiDAR range image as a PFM and its points as a PLY point cloud next to
+    /// `path_prefix`, if the prepass captured one.
+    fn dump_lidar(&self, path_prefix: &str) {
+        let range = self.range.lock().unwrap();
+        let range = match range.as_ref() {
+            Some(range) => range,
+            None => return,
+        };
+
+        let path = format!("{}_range.pfm", path_prefix);
+        match range.write_pfm(&path) {
+            Ok(()) => println!("Range image saved to: {}", path),
+            Err(error) => eprintln!("Unable to save range image: {:?}", error),
+        }
+
+        let path = format!("{}_points.ply", path_prefix);
+        match range.write_point_cloud(&path) {
+            Ok(()) => println!("Point cloud saved to: {}", path),
+            Err(error) => eprintln!("Unable to save point cloud: {:?}", error),
+        }
+    }
+
+    /// Writes each light group as a linear PFM image next to `path_prefix`, so the lighting
+    /// balance can be adjusted in compositing.
+    fn dump_light_groups(&self, path_prefix: &str) {
+        let pixels = self.pixels.lock().unwrap();
+        let light_groups = self.light_groups.lock().unwrap();
+        let scale = 1.0 / pixels.0.max(1) as f32;
+
+        for group in 0..LIGHT_GROUPS {
+            let path = format!("{}_light_group_{}.pfm", path_prefix, group);
+            let group_pixels = light_groups.iter().map(|groups| {
+                let color = groups[group] * scale;
+                [color.x(), color.y(), color.z()]
+            });
+
+            match pfm::write_pfm(&path, self.width, self.height, group_pixels) {
+                Ok(()) => println!("Light group saved to: {}", path),
+                Err(error) => eprintln!("Unable to save light group: {:?}", error),
+            }
+        }
+    }
+
+    fn dump_reference(&self, path_prefix: &str) {
+        let pixels = self.pixels.lock().unwrap();
+        let reference = self.reference.lock().unwrap();
+        let scale = 1.0 / pixels.0.max(1) as f32;
+
+        let path = format!("{}_reference.pfm", path_prefix);
+        let reference_pixels = reference.iter().map(|&color| {
+            let color = color * scale;
+            [color.x(), color.y(), color.z()]
+        });
+
+        match pfm::write_pfm(&path, self.width, self.height, reference_pixels) {
+            Ok(()) => println!("Reference saved to: {}", path),
+            Err(error) => eprintln!("Unable to save reference: {:?}", error),
+        }
+    }
+
+    fn dump_components(&self, path_prefix: &str) {
+        let pixels = self.pixels.lock().unwrap();
+        let components = self.components.lock().unwrap();
+        let scale = 1.0 / pixels.0.max(1) as f32;
+
+        for (component, name) in COMPONENT_NAMES.iter().enumerate() {
+            let path = format!("{}_{}.pfm", path_prefix, name);
+            let component_pixels = components.iter().map(|components| {
+                let color = components[component] * scale;
+                [color.x(), color.y(), color.z()]
+            });
+
+            match pfm::write_pfm(&path, self.width, self.height, component_pixels) {
+                Ok(()) => println!("Component saved to: {}", path),
+                Err(error) => eprintln!("Unable to save component: {:?}", error),
+            }
+        }
     }
 }
 
@@ -835,3 +2163,62 @@ uniform sampler2D quad_texture;
 void main () {
    f_color = texture(quad_texture, v_uv);
 }";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use material::{Lambertian, SolidBackground, SplitBackground, TransparentBackground};
+    use math::V4;
+    use texture::SolidColor;
+
+    #[test]
+    fn transparent_background_is_written_as_zero_alpha() {
+        let mut world = world::World::new(SplitBackground::new(
+            TransparentBackground,
+            SolidBackground::new(V3::one()),
+        ));
+        world.add(geom::Sphere::new(
+            Lambertian::new(SolidColor(V4::one())),
+            V3::zero(),
+            1.0,
+        ));
+        // Wide enough that the corners look past the sphere in the middle
+        let camera = world::Camera::new(
+            60.0,
+            V3::new(0.0, 0.0, 3.0),
+            V3::zero(),
+            V3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            3.0,
+        );
+
+        let view = (
+            Arc::new(Image::new(8, 8, Overlay::default())),
+            Arc::new(camera),
+        );
+        let tile = tiles::Tile {
+            view: 0,
+            x: 0..8,
+            y: 0..8,
+        };
+        let mut buffer = ImageBuffer::new(8, 8);
+        trace_tile(
+            &world,
+            &view,
+            &mut buffer,
+            &tile,
+            None,
+            &mut RandomSampler,
+            0,
+        );
+        view.0.merge(&buffer);
+
+        let alpha = view
+            .0
+            .alpha_bytes()
+            .expect("the corners should be transparent");
+        assert_eq!(alpha[0], 0);
+        assert_eq!(alpha[3 * 8 + 3], 255);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+/// Below this many items a load is not worth spreading across threads.
+const MIN_PARALLEL: usize = 4096;
+
+pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Mmap> {
+    let file = File::open(path)?;
+    // The file must not be modified while it is mapped
+    unsafe { Mmap::map(&file) }
+}
+
+/// Splits `0..count` into one contiguous range per cpu and maps each range on its own thread,
+/// the results are concatenated in order.
+pub fn parallel_chunks<T: Send, F: Fn(Range<usize>) -> Vec<T> + Sync>(
+    count: usize,
+    chunk_fn: F,
+) -> Vec<T> {
+    let threads = num_cpus::get().max(1);
+    if count < MIN_PARALLEL || threads == 1 {
+        return chunk_fn(0..count);
+    }
+
+    let chunk_len = (count + threads - 1) / threads;
+    let chunk_fn = &chunk_fn;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..count)
+            .step_by(chunk_len)
+            .map(|start| {
+                let end = (start + chunk_len).min(count);
+                scope.spawn(move || chunk_fn(start..end))
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(count);
+        for handle in handles {
+            results.extend(handle.join().unwrap());
+        }
+        results
+    })
+}
+
+pub fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    f32::from_le_bytes(word)
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(word)
+}
@@ -1,19 +1,172 @@
 use std::ops::Neg;
+use std::sync::Arc;
 
 use super::geom::Hit;
+#[cfg(feature = "polarization")]
+use super::polarization::Mueller;
+use super::sampler::Sampler;
 use super::world::Ray;
 use crate::{
+    animation::Value,
     math::{Num, M4, V2, V3},
-    texture::Surface,
+    microfacet::{self, schlick, schlick_weight, Frame, Ggx},
+    texture::{bilinear, Surface, WrapMode},
 };
 
-pub struct Scatter {
-    pub attenuation: V3,
-    pub scattered: Ray,
+/// A direction picked by `Material::sample` for light to arrive from.
+#[derive(Debug, Copy, Clone)]
+pub struct BsdfSample {
+    /// Unit direction leaving the surface, the path continues along it.
+    pub direction: V3,
+    /// What the light arriving from `direction` is multiplied by, `eval` over `pdf` for lobes
+    /// that can be evaluated.
+    pub weight: V3,
+    /// The density over solid angle `direction` was picked with, or the chance of picking a
+    /// delta lobe.
+    pub pdf: f32,
+    /// The kind of event that picked the direction, used to split the image into component
+    /// AOVs.
+    pub lobe: Lobe,
+    /// Whether the direction came from a lobe `eval` and `pdf` don't cover, such as a mirror
+    /// or glass, which no other technique could pick.
+    pub delta: bool,
+}
+
+/// The broad class of a scattering event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    Transmission,
+}
+
+impl Lobe {
+    /// The number of lobes, the length of values split by lobe.
+    pub const COUNT: usize = 3;
+
+    /// The position of this lobe in values split by lobe.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// `value` split by lobe, all of it in this one.
+    pub fn only(self, value: V3) -> [V3; Lobe::COUNT] {
+        let mut lobes = [V3::zero(); Lobe::COUNT];
+        lobes[self.index()] = value;
+        lobes
+    }
+}
+
+/// The sum of a value split by lobe.
+pub fn lobe_total(lobes: [V3; Lobe::COUNT]) -> V3 {
+    lobes.iter().fold(V3::zero(), |total, &lobe| total + lobe)
+}
+
+/// Scales each lobe of `lobes` by `scale`.
+fn scale_lobes(lobes: [V3; Lobe::COUNT], scale: V3) -> [V3; Lobe::COUNT] {
+    let mut scaled = lobes;
+    for lobe in scaled.iter_mut() {
+        *lobe = *lobe * scale;
+    }
+    scaled
+}
+
+/// Adds each lobe of `other` to `lobes`.
+fn add_lobes(lobes: &mut [V3; Lobe::COUNT], other: [V3; Lobe::COUNT]) {
+    for (lobe, other) in lobes.iter_mut().zip(other.iter()) {
+        *lobe += *other;
+    }
+}
+
+/// What a material does where a ray hits the back of its surface, the side the geometric
+/// normal points away from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Backface {
+    /// The back is shaded the same as the front.
+    Shade,
+    /// Rays pass through the back as if it wasn't there.
+    Cull,
+    /// The back absorbs all light and emits none, so light doesn't leak through open meshes.
+    Black,
+}
+
+/// A material reduced to the parameters shared by simpler shading models, used when exporting
+/// scenes to other tools.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Approximation {
+    pub color: V3,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emission: V3,
+    /// The refraction index of transparent materials.
+    pub refraction_index: Option<f32>,
+}
+
+impl Default for Approximation {
+    fn default() -> Self {
+        Self {
+            color: V3::fill(0.8),
+            metallic: 0.0,
+            roughness: 1.0,
+            emission: V3::zero(),
+            refraction_index: None,
+        }
+    }
+}
+
+impl Approximation {
+    /// Blends towards `other` by `ratio`, a transparent material stays transparent.
+    pub fn mix(self, other: Self, ratio: f32) -> Self {
+        Self {
+            color: self.color * (1.0 - ratio) + other.color * ratio,
+            metallic: self.metallic * (1.0 - ratio) + other.metallic * ratio,
+            roughness: self.roughness * (1.0 - ratio) + other.roughness * ratio,
+            emission: self.emission * (1.0 - ratio) + other.emission * ratio,
+            refraction_index: self.refraction_index.or(other.refraction_index),
+        }
+    }
+}
+
+/// The average color of `surface`, taken over a grid of samples.
+fn average_color<S: Surface + ?Sized>(surface: &S) -> V3 {
+    const SAMPLES: u32 = 8;
+    let mut sum = V3::zero();
+    for y in 0..SAMPLES {
+        for x in 0..SAMPLES {
+            let uv = V2::new(
+                (x as f32 + 0.5) / SAMPLES as f32,
+                (y as f32 + 0.5) / SAMPLES as f32,
+            );
+            sum = sum + surface.get_f(uv).contract();
+        }
+    }
+    sum / (SAMPLES * SAMPLES) as f32
 }
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter>;
+    /// Picks a direction for the path arriving along `ray` to continue in, `None` if the light
+    /// is absorbed.
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample>;
+
+    /// The fraction of light arriving from unit `direction` that leaves back along `ray`,
+    /// including the cosine term. Delta lobes such as mirrors and glass can't be reached by
+    /// any other technique and leave this at zero.
+    fn eval(&self, _ray: Ray, _hit: &Hit, _direction: V3) -> V3 {
+        V3::zero()
+    }
+
+    /// `eval` split by the lobe scattering each part of the light, indexed by `Lobe::index`.
+    /// Materials that don't split it scatter all of it diffusely.
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        Lobe::Diffuse.only(self.eval(ray, hit, direction))
+    }
+
+    /// The density over solid angle that `sample` picks unit `direction` with, from the lobes
+    /// covered by `eval`.
+    fn pdf(&self, _ray: Ray, _hit: &Hit, _direction: V3) -> f32 {
+        0.0
+    }
+
     fn emit(&self, _hit: &Hit) -> Option<V3> {
         None
     }
@@ -21,19 +174,295 @@ pub trait Material: Send + Sync {
         None
     }
 
-    fn alpha_test(&self, _uv: V2) -> bool {
-        true
+    /// The coverage of the surface at `uv`, from zero where it is cut out to one where it is
+    /// solid.
+    fn alpha(&self, _uv: V2) -> f32 {
+        1.0
+    }
+
+    /// Whether a ray stops at the surface at `uv`, partly covered surfaces stop a random
+    /// fraction of rays matching their `alpha` so soft edges blend into what is behind them.
+    fn alpha_test(&self, uv: V2) -> bool {
+        let alpha = self.alpha(uv);
+        alpha >= 1.0 || (alpha > 0.0 && f32::rand() < alpha)
+    }
+
+    /// The light group that this material's emission is accumulated into.
+    fn light_group(&self) -> usize {
+        0
+    }
+
+    /// How hits on the back of the surface are treated.
+    fn backface(&self) -> Backface {
+        Backface::Shade
+    }
+
+    /// The index of the material standing in for this one in the `Model` holding the surface,
+    /// set by `MaterialIndex`.
+    fn model_index(&self) -> Option<u32> {
+        None
+    }
+
+    /// How this material changes the polarization of light traveling back along `scattered`
+    /// towards `ray`, in the plane of incidence. Materials depolarize unless they override it.
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, _ray: Ray, _hit: &Hit, _scattered: Ray) -> Mueller {
+        Mueller::depolarizer()
+    }
+
+    /// A rough description of this material for exporters.
+    fn approximate(&self) -> Approximation {
+        Approximation::default()
+    }
+
+    /// A name for this material in reports, its type without module paths.
+    fn name(&self) -> String {
+        short_type_name(std::any::type_name::<Self>())
+    }
+}
+
+/// Strips the module paths from every type in `name`, `a::B<c::D>` becomes `B<D>`.
+fn short_type_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut segment = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            segment.push(c);
+        } else {
+            short.push_str(segment.rsplit("::").next().unwrap_or(""));
+            segment.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(segment.rsplit("::").next().unwrap_or(""));
+    short
+}
+
+impl<M: Material + ?Sized> Material for Box<M> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        M::sample(self, ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        M::eval(self, ray, hit, direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        M::eval_lobes(self, ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        M::pdf(self, ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        M::emit(self, hit)
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        M::normal(self, uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        M::alpha(self, uv)
+    }
+
+    fn light_group(&self) -> usize {
+        M::light_group(self)
+    }
+
+    fn backface(&self) -> Backface {
+        M::backface(self)
+    }
+
+    fn model_index(&self) -> Option<u32> {
+        M::model_index(self)
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        M::polarization(self, ray, hit, scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        M::approximate(self)
+    }
+
+    fn name(&self) -> String {
+        M::name(self)
+    }
+}
+
+#[derive(Default)]
+pub struct MaterialTable {
+    materials: Vec<Box<dyn Material>>,
+}
+
+impl MaterialTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<M: 'static + Material>(&mut self, material: M) -> u32 {
+        self.materials.push(Box::new(material));
+        (self.materials.len() - 1) as u32
+    }
+
+    pub fn get(&self, index: u32) -> &dyn Material {
+        &*self.materials[index as usize]
+    }
+
+    pub fn shared(self) -> Arc<MaterialTable> {
+        Arc::new(self)
+    }
+}
+
+/// Stands in for one of the materials of the `Model` holding the triangle, the model swaps in
+/// the material itself when the triangle is hit. Meshes with many materials keep a single index
+/// per triangle this way.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MaterialIndex(pub u32);
+
+impl Material for MaterialIndex {
+    fn sample(&self, _ray: Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        None
+    }
+
+    fn model_index(&self) -> Option<u32> {
+        Some(self.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct TableMaterial {
+    table: Arc<MaterialTable>,
+    index: u32,
+}
+
+impl TableMaterial {
+    pub fn new(table: Arc<MaterialTable>, index: u32) -> Self {
+        Self { table, index }
+    }
+}
+
+impl Material for TableMaterial {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        self.table.get(self.index).sample(ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.table.get(self.index).eval(ray, hit, direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        self.table.get(self.index).eval_lobes(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.table.get(self.index).pdf(ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        self.table.get(self.index).emit(hit)
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        self.table.get(self.index).normal(uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.table.get(self.index).alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.table.get(self.index).light_group()
+    }
+
+    fn backface(&self) -> Backface {
+        self.table.get(self.index).backface()
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        self.table.get(self.index).polarization(ray, hit, scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.table.get(self.index).approximate()
+    }
+
+    fn name(&self) -> String {
+        self.table.get(self.index).name()
     }
 }
 
 pub trait Background: Send + Sync {
     fn background(&self, ray: Ray) -> V3;
+
+    /// The background seen directly by camera rays, defaults to the lighting background.
+    fn camera_background(&self, ray: Ray) -> V3 {
+        self.background(ray)
+    }
+
+    /// How much of the image the background covers where camera rays see it, zero leaves the
+    /// image transparent there.
+    fn camera_alpha(&self, _ray: Ray) -> f32 {
+        1.0
+    }
+
+    /// The light group that light from the background is accumulated into.
+    fn light_group(&self) -> usize {
+        0
+    }
 }
 
 impl<B: Background + ?Sized> Background for Box<B> {
     fn background(&self, ray: Ray) -> V3 {
         B::background(self, ray)
     }
+
+    fn camera_background(&self, ray: Ray) -> V3 {
+        B::camera_background(self, ray)
+    }
+
+    fn camera_alpha(&self, ray: Ray) -> f32 {
+        B::camera_alpha(self, ray)
+    }
+
+    fn light_group(&self) -> usize {
+        B::light_group(self)
+    }
+}
+
+/// Lights the scene with `lighting` while camera rays see `camera`, allowing an environment
+/// to be lit by an HDRI but shown against a flat color, or the reverse.
+pub struct SplitBackground<C: Background, L: Background> {
+    camera: C,
+    lighting: L,
+}
+
+impl<C: Background, L: Background> SplitBackground<C, L> {
+    pub fn new(camera: C, lighting: L) -> Self {
+        Self { camera, lighting }
+    }
+}
+
+impl<C: Background, L: Background> Background for SplitBackground<C, L> {
+    fn background(&self, ray: Ray) -> V3 {
+        self.lighting.background(ray)
+    }
+
+    fn camera_background(&self, ray: Ray) -> V3 {
+        self.camera.camera_background(ray)
+    }
+
+    fn camera_alpha(&self, ray: Ray) -> f32 {
+        self.camera.camera_alpha(ray)
+    }
+
+    fn light_group(&self) -> usize {
+        self.lighting.light_group()
+    }
 }
 
 pub struct SolidBackground {
@@ -52,6 +481,20 @@ impl Background for SolidBackground {
     }
 }
 
+/// Black and fully transparent, for the camera side of a `SplitBackground` when the render is
+/// to be composited over something else.
+pub struct TransparentBackground;
+
+impl Background for TransparentBackground {
+    fn background(&self, _ray: Ray) -> V3 {
+        V3::zero()
+    }
+
+    fn camera_alpha(&self, _ray: Ray) -> f32 {
+        0.0
+    }
+}
+
 pub struct SkyBackground;
 
 impl Background for SkyBackground {
@@ -62,6 +505,176 @@ impl Background for SkyBackground {
     }
 }
 
+/// Illuminance of the sun outside the atmosphere in kilolux, the units of the Preetham model's
+/// luminance are scaled by it so a sun of strength one gives an irradiance of about one.
+const SUN_ILLUMINANCE: f32 = 128.0;
+
+/// The angular radius of the sun in radians.
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+
+/// A clear daytime sky from the analytic model of Preetham, Shirley and Smits, lit by a sun
+/// towards `sun_direction`. `turbidity` runs from 2 for a very clear sky to around 10 for a
+/// hazy one. The sun itself isn't part of the background, add the light returned by `sun` to
+/// the world alongside it. Directions below the horizon see the color of the horizon.
+pub struct PreethamSky {
+    sun_direction: V3,
+    turbidity: f32,
+    strength: f32,
+    group: usize,
+    /// Perez distribution coefficients for the luminance and the two chromaticities.
+    coefficients: [[f32; 5]; 3],
+    /// The luminance and chromaticities at the zenith, over their distribution there.
+    zenith: [f32; 3],
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: V3, turbidity: f32) -> Self {
+        let sun_direction = sun_direction.unit();
+        let t = turbidity.clamp(1.7, 10.0);
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // The sun is kept just above the horizon, the fits don't hold past it
+        let theta = sun_direction.y().clamp(0.01, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let powers = [theta * theta * theta, theta * theta, theta, 1.0];
+        let chromaticity = |rows: [[f32; 4]; 3]| {
+            let row =
+                |row: [f32; 4]| -> f32 { row.iter().zip(powers.iter()).map(|(a, b)| a * b).sum() };
+            t * t * row(rows[0]) + t * row(rows[1]) + row(rows[2])
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let mut zenith = [luminance.max(0.0), x, y];
+        for (zenith, coefficients) in zenith.iter_mut().zip(coefficients.iter()) {
+            *zenith /= perez(coefficients, 0.0, theta);
+        }
+
+        Self {
+            sun_direction,
+            turbidity: t,
+            strength: 1.0,
+            group: 0,
+            coefficients,
+            zenith,
+        }
+    }
+
+    /// Scales the sky and the sun together, a strength of one giving the sun an irradiance of
+    /// about one outside the atmosphere.
+    pub fn with_strength(self, strength: f32) -> Self {
+        Self { strength, ..self }
+    }
+
+    pub fn with_group(self, group: usize) -> Self {
+        Self { group, ..self }
+    }
+
+    /// A sun matching the sky, dimmed and reddened by the air it passes through.
+    pub fn sun(&self) -> crate::light::DirectionalLight {
+        let color = self.sun_transmittance() * self.strength;
+        crate::light::DirectionalLight::new(
+            DiffuseLight::new(color).with_group(self.group),
+            self.sun_direction,
+        )
+        .with_angular_radius(SUN_ANGULAR_RADIUS)
+    }
+
+    /// The fraction of red, green and blue sunlight reaching the ground, from Rayleigh and
+    /// aerosol scattering over the air mass of the sun's elevation.
+    fn sun_transmittance(&self) -> V3 {
+        let cos_theta = self.sun_direction.y();
+        if cos_theta <= 0.0 {
+            return V3::zero();
+        }
+
+        let degrees = cos_theta.acos().to_degrees();
+        let air_mass = 1.0 / (cos_theta + 0.15 * (93.885 - degrees).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |wavelength: f32| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        };
+
+        V3::new(
+            transmittance(0.65),
+            transmittance(0.57),
+            transmittance(0.475),
+        )
+    }
+}
+
+impl Background for PreethamSky {
+    fn background(&self, ray: Ray) -> V3 {
+        let direction = ray.direction.unit();
+        let direction = V3::new(direction.x(), direction.y().max(0.001), direction.z()).unit();
+        let theta = direction.y().acos();
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let mut xyy = [0.0; 3];
+        for (i, value) in xyy.iter_mut().enumerate() {
+            *value = self.zenith[i] * perez(&self.coefficients[i], theta, gamma);
+        }
+        let [luminance, x, y] = xyy;
+        if y <= 0.0 {
+            return V3::zero();
+        }
+
+        let luminance = luminance * self.strength / SUN_ILLUMINANCE;
+        let big_x = x * luminance / y;
+        let big_z = (1.0 - x - y) * luminance / y;
+        V3::new(
+            (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+            (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+            (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+        )
+    }
+
+    fn light_group(&self) -> usize {
+        self.group
+    }
+}
+
+/// The Perez sky distribution at `theta` from the zenith and `gamma` from the sun.
+fn perez(coefficients: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / theta.cos().max(0.001)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
 pub struct SkySphere<S: Surface> {
     texture: S,
 }
@@ -189,6 +802,108 @@ impl<S: Surface> Background for CubeMap<S> {
     }
 }
 
+/// A low resolution equirectangular capture of a background, prefiltered over a cone of `blur`
+/// radians. Used for the background seen through a lens with shallow depth of field, where
+/// resolving the full detail of the environment would be wasted on a blur.
+pub struct BlurredBackground {
+    width: u32,
+    height: u32,
+    pixels: Vec<V3>,
+}
+
+impl BlurredBackground {
+    const MIN_BLUR: f32 = 0.002;
+    const MAX_WIDTH: u32 = 512;
+    const MIN_WIDTH: u32 = 16;
+    const SAMPLES: u32 = 4;
+
+    /// Captures what camera rays see of `background`, the resolution is chosen so each texel
+    /// spans about the blur. Returns None if the blur is too small to be worth capturing.
+    pub fn capture<B: Background + ?Sized>(background: &B, blur: f32) -> Option<Self> {
+        if blur < Self::MIN_BLUR {
+            return None;
+        }
+
+        let width = ((2.0 * std::f32::consts::PI / blur).ceil() as u32)
+            .max(Self::MIN_WIDTH)
+            .min(Self::MAX_WIDTH);
+        let height = width / 2;
+        let spread = blur.min(std::f32::consts::FRAC_PI_4).tan();
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = V2::new(
+                    x as f32 / (width - 1) as f32,
+                    y as f32 / (height - 1) as f32,
+                );
+                let direction = Self::direction(uv);
+                let tangent = if direction.y().abs() > 0.9 {
+                    V3::new(1.0, 0.0, 0.0)
+                } else {
+                    V3::new(0.0, 1.0, 0.0)
+                };
+                let tangent = direction.cross(tangent).unit();
+                let bitangent = direction.cross(tangent);
+
+                // Stratified over the disk so the capture is identical from frame to frame
+                let mut sum = V3::zero();
+                for i in 0..Self::SAMPLES {
+                    for j in 0..Self::SAMPLES {
+                        let r = ((i as f32 + 0.5) / Self::SAMPLES as f32).sqrt() * spread;
+                        let angle =
+                            (j as f32 + 0.5) / Self::SAMPLES as f32 * 2.0 * std::f32::consts::PI;
+                        let offset = tangent * (r * angle.cos()) + bitangent * (r * angle.sin());
+                        let ray = Ray::new(V3::zero(), direction + offset);
+                        sum = sum + background.camera_background(ray);
+                    }
+                }
+
+                pixels.push(sum / (Self::SAMPLES * Self::SAMPLES) as f32);
+            }
+        }
+
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// The inverse of the `SkySphere` mapping.
+    fn direction(uv: V2) -> V3 {
+        let theta = uv.y() * std::f32::consts::PI;
+        let phi = uv.x() * 2.0 * std::f32::consts::PI - std::f32::consts::PI;
+        V3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin() * -1.0,
+        )
+    }
+}
+
+impl Background for BlurredBackground {
+    fn background(&self, ray: Ray) -> V3 {
+        let p = ray.direction.unit();
+        let theta = (p.y()).acos();
+        let phi = (p.z() * -1.0).atan2(p.x()) + std::f32::consts::PI;
+
+        let uv = V2::new(
+            phi / (2.0 * std::f32::consts::PI),
+            theta / std::f32::consts::PI,
+        );
+
+        bilinear(
+            uv,
+            self.width,
+            self.height,
+            WrapMode::Clamp.into(),
+            |x, y| self.pixels[y * self.width as usize + x].expand(1.0),
+        )
+        .contract()
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Lambertian<S: Surface> {
     surface: S,
@@ -198,133 +913,1088 @@ impl<S: Surface> Lambertian<S> {
     pub fn new(surface: S) -> Self {
         Self { surface }
     }
+
+    fn albedo(&self, hit: &Hit) -> V3 {
+        hit.texel(&self.surface).contract() * hit.color.unwrap_or(V3::one())
+    }
 }
 
 impl<S: Surface> Material for Lambertian<S> {
-    fn scatter(&self, _ray: Ray, hit: &Hit) -> Option<Scatter> {
-        let scatter_direction = hit.normal + V3::random_unit_vector();
-        let scatter_direction = if scatter_direction.near_zero() {
+    fn sample(&self, _ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let direction = hit.normal + V3::unit_vector_from(sampler.get_2d());
+        let direction = if direction.near_zero() {
             hit.normal
         } else {
-            scatter_direction
+            direction.unit()
         };
 
-        let scattered = Ray::new(hit.point, scatter_direction);
-
-        let attenuation = self.surface.get_f(hit.uv.unwrap_or(V2::zero())).contract();
-
-        Some(Scatter {
-            scattered,
-            attenuation,
+        Some(BsdfSample {
+            direction,
+            weight: self.albedo(hit),
+            pdf: hit.normal.dot(direction).max(0.0) / std::f32::consts::PI,
+            lobe: Lobe::Diffuse,
+            delta: false,
         })
     }
 
-    fn alpha_test(&self, uv: V2) -> bool {
-        self.surface.get_f(uv).w() != 0.0
+    fn eval(&self, _ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let cosine = hit.normal.dot(direction).max(0.0);
+        self.albedo(hit) * (cosine / std::f32::consts::PI)
     }
-}
-
-#[derive(Copy, Clone)]
-pub struct DiffuseLight {
-    emit: V3,
-}
 
-impl DiffuseLight {
-    pub fn new(emit: V3) -> Self {
-        Self { emit }
+    fn pdf(&self, _ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        hit.normal.dot(direction).max(0.0) / std::f32::consts::PI
     }
-}
 
-impl Material for DiffuseLight {
-    fn scatter(&self, _ray: Ray, _hit: &Hit) -> Option<Scatter> {
-        None
+    fn alpha(&self, uv: V2) -> f32 {
+        self.surface.get_f(uv).w()
     }
 
-    fn emit(&self, _hit: &Hit) -> Option<V3> {
-        Some(self.emit)
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: average_color(&self.surface),
+            ..Approximation::default()
+        }
     }
 }
 
+/// A rough diffuse surface made of tiny Lambertian facets, following Oren and Nayar. Facets
+/// facing back towards the light brighten it as seen from there, giving the flat look of clay,
+/// concrete and the moon where `Lambertian` would darken towards the edges.
 #[derive(Copy, Clone)]
-pub struct Metal<S: Surface> {
-    fuzz: f32,
+pub struct OrenNayar<S: Surface> {
     surface: S,
+    a: f32,
+    b: f32,
 }
 
-impl<S: Surface> Metal<S> {
-    pub fn new(fuzz: f32, surface: S) -> Self {
-        let fuzz = if fuzz < 1.0 { fuzz } else { 1.0 };
-        Self { fuzz, surface }
+impl<S: Surface> OrenNayar<S> {
+    /// `sigma` is the standard deviation of the facet slopes in radians, zero matches
+    /// `Lambertian`.
+    pub fn new(sigma: f32, surface: S) -> Self {
+        let sigma_squared = sigma * sigma;
+        Self {
+            surface,
+            a: 1.0 - 0.5 * sigma_squared / (sigma_squared + 0.33),
+            b: 0.45 * sigma_squared / (sigma_squared + 0.09),
+        }
+    }
+
+    fn albedo(&self, hit: &Hit) -> V3 {
+        hit.texel(&self.surface).contract() * hit.color.unwrap_or(V3::one())
     }
 }
 
-impl<S: Surface> Material for Metal<S> {
-    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
-        let reflected = ray.direction.unit().reflect(hit.normal);
-        let scattered = Ray::new(
-            hit.point,
-            reflected + (V3::random_in_unit_sphere() * self.fuzz),
-        );
+impl<S: Surface> Material for OrenNayar<S> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let direction = hit.normal + V3::unit_vector_from(sampler.get_2d());
+        let direction = if direction.near_zero() {
+            hit.normal
+        } else {
+            direction.unit()
+        };
 
-        if scattered.direction.dot(hit.normal) > 0.0 {
-            let attenuation = self.surface.get_f(hit.uv.unwrap_or(V2::zero())).contract();
+        let pdf = self.pdf(ray, hit, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
 
-            Some(Scatter {
-                scattered,
-                attenuation,
-            })
+        Some(BsdfSample {
+            direction,
+            weight: self.eval(ray, hit, direction) / pdf,
+            pdf,
+            lobe: Lobe::Diffuse,
+            delta: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let wo = ray.direction.unit().neg();
+        let cos_i = hit.normal.dot(direction);
+        let cos_o = hit.normal.dot(wo).min(1.0);
+        if cos_i <= 0.0 || cos_o <= 0.0 {
+            return V3::zero();
+        }
+
+        let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).max(0.0).sqrt();
+
+        // The cosine of the angle between the two directions around the normal
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            let tangent_i = (direction - hit.normal * cos_i) / sin_i;
+            let tangent_o = (wo - hit.normal * cos_o) / sin_o;
+            tangent_i.dot(tangent_o).max(0.0)
+        } else {
+            0.0
+        };
+
+        // Sine of the larger angle from the normal and tangent of the smaller
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o)
+        };
+
+        let scale = self.a + self.b * cos_phi * sin_alpha * tan_beta;
+        self.albedo(hit) * (scale * cos_i / std::f32::consts::PI)
+    }
+
+    fn pdf(&self, _ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        hit.normal.dot(direction).max(0.0) / std::f32::consts::PI
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.surface.get_f(uv).w()
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: average_color(&self.surface),
+            ..Approximation::default()
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DiffuseLight {
+    emit: V3,
+    strength: Value,
+    group: usize,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: V3) -> Self {
+        Self {
+            emit,
+            strength: Value::Constant(1.0),
+            group: 0,
+        }
+    }
+
+    /// Scales the emitted light, pass a `Param` to animate it.
+    pub fn with_strength<V: Into<Value>>(mut self, strength: V) -> Self {
+        self.strength = strength.into();
+        self
+    }
+
+    pub fn with_group(mut self, group: usize) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// The light emitted from every point in every direction.
+    pub fn radiance(&self) -> V3 {
+        self.emit * self.strength.get()
+    }
+}
+
+impl Material for DiffuseLight {
+    fn sample(&self, _ray: Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        None
+    }
+
+    fn emit(&self, _hit: &Hit) -> Option<V3> {
+        Some(self.radiance())
+    }
+
+    fn light_group(&self) -> usize {
+        self.group
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: V3::zero(),
+            emission: self.radiance(),
+            ..Approximation::default()
+        }
+    }
+}
+
+/// Makes any material glow, emitting the color of `surface` scaled by a strength on top of
+/// whatever `inner` reflects, so parts of an imported mesh can light the scene through an
+/// emission texture. Black texels don't emit.
+pub struct Emissive<M: Material, S: Surface> {
+    inner: M,
+    surface: S,
+    strength: Value,
+    group: usize,
+}
+
+impl<M: Material, S: Surface> Emissive<M, S> {
+    pub fn new(inner: M, surface: S) -> Self {
+        Self {
+            inner,
+            surface,
+            strength: Value::Constant(1.0),
+            group: 0,
+        }
+    }
+
+    /// Scales the emitted light, pass a `Param` to animate it.
+    pub fn with_strength<V: Into<Value>>(mut self, strength: V) -> Self {
+        self.strength = strength.into();
+        self
+    }
+
+    pub fn with_group(mut self, group: usize) -> Self {
+        self.group = group;
+        self
+    }
+}
+
+impl<M: Material, S: Surface> Material for Emissive<M, S> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        self.inner.sample(ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.inner.eval(ray, hit, direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        self.inner.eval_lobes(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.inner.pdf(ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        let emitted = hit.texel(&self.surface).contract() * self.strength.get();
+        match self.inner.emit(hit) {
+            Some(inner) => Some(inner + emitted),
+            None if emitted.near_zero() => None,
+            None => Some(emitted),
+        }
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        self.inner.normal(uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.group
+    }
+
+    fn backface(&self) -> Backface {
+        self.inner.backface()
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        self.inner.polarization(ray, hit, scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            emission: average_color(&self.surface) * self.strength.get(),
+            ..self.inner.approximate()
+        }
+    }
+}
+
+/// Shades both sides of `inner` as its front, so materials that tell the inside from the
+/// outside, such as glass, treat a single sheet of triangles as a thin surface rather than the
+/// boundary of a solid.
+pub struct TwoSided<M: Material> {
+    inner: M,
+}
+
+impl<M: Material> TwoSided<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+/// `hit` as seen from the front.
+fn front_hit<'a>(hit: &Hit<'a>) -> Hit<'a> {
+    Hit {
+        front_face: true,
+        ..*hit
+    }
+}
+
+impl<M: Material> Material for TwoSided<M> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        self.inner.sample(ray, &front_hit(hit), sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.inner.eval(ray, &front_hit(hit), direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        self.inner.eval_lobes(ray, &front_hit(hit), direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.inner.pdf(ray, &front_hit(hit), direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        self.inner.emit(&front_hit(hit))
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        self.inner.normal(uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.inner.light_group()
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        self.inner.polarization(ray, &front_hit(hit), scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.inner.approximate()
+    }
+}
+
+/// Gives `inner` a front only, the back is culled or black depending on `backface`. Game
+/// assets are often open shells only meant to be seen from outside.
+pub struct OneSided<M: Material> {
+    inner: M,
+    backface: Backface,
+}
+
+impl<M: Material> OneSided<M> {
+    pub fn new(inner: M, backface: Backface) -> Self {
+        Self { inner, backface }
+    }
+
+    /// Rays pass through the back of `inner`.
+    pub fn culled(inner: M) -> Self {
+        Self::new(inner, Backface::Cull)
+    }
+
+    /// The back of `inner` absorbs all light.
+    pub fn black(inner: M) -> Self {
+        Self::new(inner, Backface::Black)
+    }
+}
+
+impl<M: Material> Material for OneSided<M> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        self.inner.sample(ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.inner.eval(ray, hit, direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        self.inner.eval_lobes(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.inner.pdf(ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        self.inner.emit(hit)
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        self.inner.normal(uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.inner.light_group()
+    }
+
+    fn backface(&self) -> Backface {
+        self.backface
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        self.inner.polarization(ray, hit, scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.inner.approximate()
+    }
+}
+
+/// Bumps the surface of `inner` by a grayscale height map, tilting its normal by the slope of
+/// the heights found from the texels on either side of each hit.
+pub struct Bump<M: Material, S: Surface> {
+    inner: M,
+    height: S,
+    strength: f32,
+}
+
+impl<M: Material, S: Surface> Bump<M, S> {
+    pub fn new(inner: M, height: S) -> Self {
+        Self {
+            inner,
+            height,
+            strength: 1.0,
+        }
+    }
+
+    /// Scales the slope, by default heights rising by one from texel to texel tilt the normal
+    /// by 45 degrees.
+    pub fn with_strength(self, strength: f32) -> Self {
+        Self { strength, ..self }
+    }
+
+    fn height_at(&self, uv: V2) -> f32 {
+        self.height.get_f(uv).x()
+    }
+}
+
+impl<M: Material, S: Surface> Material for Bump<M, S> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        self.inner.sample(ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.inner.eval(ray, hit, direction)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        self.inner.eval_lobes(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.inner.pdf(ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        self.inner.emit(hit)
+    }
+
+    /// Tilts the normal of `inner`, or the geometric normal, against the central difference of
+    /// the heights a texel either side of `uv`.
+    fn normal(&self, uv: V2) -> Option<V3> {
+        let du = V2::new(1.0 / self.height.width().max(1) as f32, 0.0);
+        let dv = V2::new(0.0, 1.0 / self.height.height().max(1) as f32);
+        let slope_u = (self.height_at(uv + du) - self.height_at(uv - du)) * 0.5 * self.strength;
+        let slope_v = (self.height_at(uv + dv) - self.height_at(uv - dv)) * 0.5 * self.strength;
+
+        let normal = self.inner.normal(uv).unwrap_or(V3::new(0.0, 0.0, 1.0));
+        Some(V3::new(normal.x() - slope_u, normal.y() - slope_v, normal.z()).unit())
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.inner.light_group()
+    }
+
+    fn backface(&self) -> Backface {
+        self.inner.backface()
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        self.inner.polarization(ray, hit, scattered)
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.inner.approximate()
+    }
+}
+
+/// A conductor reflecting its color, rough metals use the GGX microfacet distribution.
+#[derive(Clone)]
+pub struct Metal<S: Surface> {
+    roughness: Value,
+    /// Roughness along the bitangent of the surface when it differs from along the tangent.
+    bitangent_roughness: Option<Value>,
+    /// Whether `roughness` is the fuzz of the original metal rather than GGX roughness.
+    fuzzed: bool,
+    surface: S,
+}
+
+impl<S: Surface> Metal<S> {
+    /// A metal with a perceptual `roughness` from zero for a mirror to one, pass a `Param` to
+    /// animate it.
+    pub fn new<V: Into<Value>>(roughness: V, surface: S) -> Self {
+        Self {
+            roughness: roughness.into(),
+            bitangent_roughness: None,
+            fuzzed: false,
+            surface,
+        }
+    }
+
+    /// A brushed metal with separate roughness along the tangent and bitangent of the surface,
+    /// which follow its texture coordinates. Surfaces without them fall back to an arbitrary
+    /// orientation.
+    pub fn anisotropic<T: Into<Value>, B: Into<Value>>(
+        tangent_roughness: T,
+        bitangent_roughness: B,
+        surface: S,
+    ) -> Self {
+        Self {
+            roughness: tangent_roughness.into(),
+            bitangent_roughness: Some(bitangent_roughness.into()),
+            fuzzed: false,
+            surface,
+        }
+    }
+
+    /// The metal of the Ray Tracing in One Weekend series, reflecting into a sphere of `fuzz`
+    /// around the mirror direction. The fuzz isn't a physical roughness, this is kept so older
+    /// scenes render as they did.
+    pub fn fuzzed<V: Into<Value>>(fuzz: V, surface: S) -> Self {
+        Self {
+            roughness: fuzz.into(),
+            bitangent_roughness: None,
+            fuzzed: true,
+            surface,
+        }
+    }
+
+    /// The roughness along the tangent and bitangent.
+    fn roughness(&self) -> (f32, f32) {
+        let roughness = self.roughness.get().clamp(0.0, 1.0);
+        let bitangent = self
+            .bitangent_roughness
+            .as_ref()
+            .map_or(roughness, |bitangent| bitangent.get().clamp(0.0, 1.0));
+        (roughness, bitangent)
+    }
+
+    fn color(&self, hit: &Hit) -> V3 {
+        hit.texel(&self.surface).contract()
+    }
+
+    /// The GGX distribution of a rough metal, `None` for mirrors and fuzzed metals whose
+    /// reflection is a delta lobe.
+    fn distribution(&self) -> Option<Ggx> {
+        let (tangent, bitangent) = self.roughness();
+        if self.fuzzed || (tangent <= 0.0 && bitangent <= 0.0) {
+            None
+        } else {
+            Some(Ggx::anisotropic(tangent, bitangent))
+        }
+    }
+}
+
+impl<S: Surface> Material for Metal<S> {
+    /// The fuzzed reflection has no density to weigh it by, so it is treated as a delta lobe.
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        if self.fuzzed {
+            let reflected = ray.direction.unit().reflect(hit.normal);
+            let direction = reflected
+                + (V3::in_unit_sphere_from(sampler.get_2d(), sampler.get_1d())
+                    * self.roughness.get().min(1.0));
+
+            return if direction.dot(hit.normal) > 0.0 {
+                Some(BsdfSample {
+                    direction: direction.unit(),
+                    weight: self.color(hit),
+                    pdf: 1.0,
+                    lobe: Lobe::Glossy,
+                    delta: true,
+                })
+            } else {
+                None
+            };
+        }
+
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => {
+                return Some(BsdfSample {
+                    direction: ray.direction.unit().reflect(hit.normal).unit(),
+                    weight: schlick(self.color(hit), wo.z()),
+                    pdf: 1.0,
+                    lobe: Lobe::Glossy,
+                    delta: true,
+                })
+            }
+        };
+
+        let wi = ggx.sample_reflection(wo, sampler.get_2d())?;
+        let (reflection, pdf, m) = ggx.reflection(wo, wi)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.to_world(wi).unit(),
+            weight: schlick(self.color(hit), wo.dot(m)) * (reflection / pdf),
+            pdf,
+            lobe: Lobe::Glossy,
+            delta: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => return V3::zero(),
+        };
+
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        match ggx.reflection(wo, frame.to_local(direction)) {
+            Some((reflection, _, m)) => schlick(self.color(hit), wo.dot(m)) * reflection,
+            None => V3::zero(),
+        }
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        Lobe::Glossy.only(self.eval(ray, hit, direction))
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => return 0.0,
+        };
+
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        ggx.reflection(wo, frame.to_local(direction))
+            .map_or(0.0, |(_, pdf, _)| pdf)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.surface.get_f(uv).w()
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, _ray: Ray, _hit: &Hit, _scattered: Ray) -> Mueller {
+        let (tangent, bitangent) = self.roughness();
+        if tangent > 0.0 || bitangent > 0.0 {
+            Mueller::depolarizer()
         } else {
+            Mueller::mirror()
+        }
+    }
+
+    fn approximate(&self) -> Approximation {
+        let (tangent, bitangent) = self.roughness();
+        Approximation {
+            color: average_color(&self.surface),
+            metallic: 1.0,
+            roughness: (tangent + bitangent) / 2.0,
+            ..Approximation::default()
+        }
+    }
+}
+
+/// A metal described by its measured complex refraction index rather than a color, so its
+/// tint shifts towards white at grazing angles as real metals do. The presets sample tabulated
+/// data at red, green and blue wavelengths.
+#[derive(Copy, Clone, Debug)]
+pub struct Conductor {
+    eta: V3,
+    k: V3,
+    roughness: (f32, f32),
+}
+
+impl Conductor {
+    /// A conductor with refraction index `eta` and extinction coefficient `k` for red, green
+    /// and blue, polished until given a roughness.
+    pub fn new(eta: V3, k: V3) -> Self {
+        Self {
+            eta,
+            k,
+            roughness: (0.0, 0.0),
+        }
+    }
+
+    pub fn gold() -> Self {
+        Self::new(V3::new(0.143, 0.374, 1.442), V3::new(3.983, 2.385, 1.603))
+    }
+
+    pub fn silver() -> Self {
+        Self::new(V3::new(0.155, 0.117, 0.138), V3::new(4.828, 3.122, 2.147))
+    }
+
+    pub fn copper() -> Self {
+        Self::new(V3::new(0.200, 0.924, 1.102), V3::new(3.912, 2.452, 2.142))
+    }
+
+    pub fn aluminum() -> Self {
+        Self::new(V3::new(1.657, 0.880, 0.521), V3::new(9.224, 6.270, 4.837))
+    }
+
+    pub fn iron() -> Self {
+        Self::new(V3::new(2.911, 2.950, 2.585), V3::new(3.089, 2.932, 2.767))
+    }
+
+    /// A preset by its lowercase name.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "gold" => Some(Self::gold()),
+            "silver" => Some(Self::silver()),
+            "copper" => Some(Self::copper()),
+            "aluminum" | "aluminium" => Some(Self::aluminum()),
+            "iron" => Some(Self::iron()),
+            _ => None,
+        }
+    }
+
+    /// Roughens the surface with GGX microfacets of perceptual `roughness`.
+    pub fn with_roughness(self, roughness: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            roughness: (roughness, roughness),
+            ..self
+        }
+    }
+
+    /// Roughens the surface differently along the tangent and bitangent, as for `Metal`.
+    pub fn with_anisotropic_roughness(self, tangent: f32, bitangent: f32) -> Self {
+        Self {
+            roughness: (tangent.clamp(0.0, 1.0), bitangent.clamp(0.0, 1.0)),
+            ..self
+        }
+    }
+
+    fn fresnel(&self, cosine: f32) -> V3 {
+        microfacet::fresnel_conductor(cosine, self.eta, self.k)
+    }
+
+    /// The GGX distribution of a rough conductor, `None` for a mirror.
+    fn distribution(&self) -> Option<Ggx> {
+        let (tangent, bitangent) = self.roughness;
+        if tangent <= 0.0 && bitangent <= 0.0 {
             None
+        } else {
+            Some(Ggx::anisotropic(tangent, bitangent))
         }
     }
+}
 
-    fn alpha_test(&self, uv: V2) -> bool {
-        self.surface.get_f(uv).w() != 0.0
+impl Material for Conductor {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => {
+                return Some(BsdfSample {
+                    direction: ray.direction.unit().reflect(hit.normal).unit(),
+                    weight: self.fresnel(wo.z()),
+                    pdf: 1.0,
+                    lobe: Lobe::Glossy,
+                    delta: true,
+                })
+            }
+        };
+
+        let wi = ggx.sample_reflection(wo, sampler.get_2d())?;
+        let (reflection, pdf, m) = ggx.reflection(wo, wi)?;
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction: frame.to_world(wi).unit(),
+            weight: self.fresnel(wo.dot(m)) * (reflection / pdf),
+            pdf,
+            lobe: Lobe::Glossy,
+            delta: false,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => return V3::zero(),
+        };
+
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        match ggx.reflection(wo, frame.to_local(direction)) {
+            Some((reflection, _, m)) => self.fresnel(wo.dot(m)) * reflection,
+            None => V3::zero(),
+        }
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        Lobe::Glossy.only(self.eval(ray, hit, direction))
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let ggx = match self.distribution() {
+            Some(ggx) => ggx,
+            None => return 0.0,
+        };
+
+        let frame = Frame::oriented(hit.normal, hit.tangent);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        ggx.reflection(wo, frame.to_local(direction))
+            .map_or(0.0, |(_, pdf, _)| pdf)
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, _ray: Ray, _hit: &Hit, _scattered: Ray) -> Mueller {
+        if self.distribution().is_some() {
+            Mueller::depolarizer()
+        } else {
+            Mueller::mirror()
+        }
+    }
+
+    fn approximate(&self) -> Approximation {
+        let (tangent, bitangent) = self.roughness;
+        Approximation {
+            color: self.fresnel(1.0),
+            metallic: 1.0,
+            roughness: (tangent + bitangent) / 2.0,
+            ..Approximation::default()
+        }
     }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Dielectric {
     refraction_index: f32,
+    roughness: f32,
+    absorption: V3,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f32) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            roughness: 0.0,
+            absorption: V3::zero(),
+        }
+    }
+
+    /// Absorbs light inside the material following the Beer-Lambert law, with an `absorption`
+    /// coefficient per unit of distance for red, green and blue, so thick glass comes out
+    /// darker than thin. Each stretch of path inside is taken to run from where it entered to
+    /// the back face it hits, objects placed inside the glass aren't accounted for.
+    pub fn with_absorption(self, absorption: V3) -> Self {
+        Self { absorption, ..self }
+    }
+
+    /// Roughens the surface with GGX microfacets for frosted or sandblasted glass, light passes
+    /// through blurred rather than refracting cleanly.
+    pub fn with_roughness(self, roughness: f32) -> Self {
+        Self {
+            roughness: roughness.clamp(0.0, 1.0),
+            ..self
+        }
     }
 
     fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
-}
 
-impl Material for Dielectric {
-    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
-        let attenuation = V3::fill(1.0);
-        let refraction_ratio = if hit.front_face {
+    /// The index of the side `hit` arrives from over the side it enters.
+    fn refraction_ratio(&self, hit: &Hit) -> f32 {
+        if hit.front_face {
             1.0 / self.refraction_index
         } else {
             self.refraction_index
-        };
+        }
+    }
 
-        let unit_direction = ray.direction.unit();
-        let cos_theta = unit_direction.neg().dot(hit.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    /// The light left after traveling along `ray` inside the material to `hit`, all of it
+    /// when `hit` is where the ray entered.
+    fn transmittance(&self, ray: Ray, hit: &Hit) -> V3 {
+        if hit.front_face || self.absorption.near_zero() {
+            return V3::one();
+        }
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let distance = hit.t * ray.direction.length();
+        V3::new(
+            (-self.absorption.x() * distance).exp(),
+            (-self.absorption.y() * distance).exp(),
+            (-self.absorption.z() * distance).exp(),
+        )
+    }
+
+    /// The fraction of light arriving at `cosine` to the normal that is reflected, one past
+    /// the critical angle.
+    fn fresnel(cosine: f32, refraction_ratio: f32) -> f32 {
+        let sin_theta = (1.0 - cosine * cosine).max(0.0).sqrt();
+        if refraction_ratio * sin_theta > 1.0 {
+            1.0
+        } else {
+            Self::reflectance(cosine, refraction_ratio)
+        }
+    }
+
+    /// The light arriving from local `wi` and leaving along `wo` through the rough surface,
+    /// including the cosine, with the density of sampling `wi`.
+    fn rough(&self, ggx: Ggx, wo: V3, wi: V3, refraction_ratio: f32) -> Option<(f32, f32)> {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return None;
+        }
+
+        if wi.z() > 0.0 {
+            let (reflection, pdf, m) = ggx.reflection(wo, wi)?;
+            let fresnel = Self::fresnel(wo.dot(m), refraction_ratio);
+            return Some((fresnel * reflection, fresnel * pdf));
+        }
+
+        // The microfacet normal that refracts between the two directions
+        let eta = 1.0 / refraction_ratio;
+        let m = (wo + wi * eta).unit();
+        let m = if m.z() < 0.0 { m.neg() } else { m };
+        let cos_o = wo.dot(m);
+        let cos_i = wi.dot(m);
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return None;
+        }
+
+        let transmitted = 1.0 - Self::fresnel(cos_o, refraction_ratio);
+        let denominator = cos_o + eta * cos_i;
+        let jacobian = eta * eta * cos_i.abs() / (denominator * denominator);
+        let value = transmitted * ggx.d(m) * ggx.g(wo, wi) * cos_o * jacobian / wo.z();
+        let pdf = transmitted * ggx.visible_pdf(wo, m) * jacobian;
+        Some((value, pdf))
+    }
+}
+
+impl Material for Dielectric {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let refraction_ratio = self.refraction_ratio(hit);
+        if self.roughness > 0.0 {
+            let ggx = Ggx::new(self.roughness);
+            let frame = Frame::new(hit.normal);
+            let wo = frame.to_local(ray.direction.unit().neg());
+            if wo.z() <= 0.0 {
+                return None;
+            }
 
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > f32::rand() {
-                unit_direction.reflect(hit.normal)
+            let m = ggx.sample_visible(wo, sampler.get_2d());
+            let (wi, lobe) = if Self::fresnel(wo.dot(m), refraction_ratio) > sampler.get_1d() {
+                (microfacet::reflect(wo, m), Lobe::Glossy)
             } else {
-                unit_direction.refract(hit.normal, refraction_ratio)
+                (
+                    microfacet::refract(wo, m, refraction_ratio)?,
+                    Lobe::Transmission,
+                )
             };
 
-        Some(Scatter {
-            attenuation,
-            scattered: Ray::new(hit.point, direction),
-        })
+            let (value, pdf) = self.rough(ggx, wo, wi, refraction_ratio)?;
+            if pdf <= 0.0 {
+                return None;
+            }
+
+            return Some(BsdfSample {
+                direction: frame.to_world(wi).unit(),
+                weight: self.transmittance(ray, hit) * (value / pdf),
+                pdf,
+                lobe,
+                delta: false,
+            });
+        }
+
+        let unit_direction = ray.direction.unit();
+        let cos_theta = unit_direction.neg().dot(hit.normal).min(1.0);
+        let reflectance = Self::fresnel(cos_theta, refraction_ratio);
+
+        let (direction, pdf, lobe) = if reflectance > sampler.get_1d() {
+            (
+                unit_direction.reflect(hit.normal),
+                reflectance,
+                Lobe::Glossy,
+            )
+        } else {
+            (
+                unit_direction.refract(hit.normal, refraction_ratio),
+                1.0 - reflectance,
+                Lobe::Transmission,
+            )
+        };
+
+        Some(BsdfSample {
+            direction: direction.unit(),
+            weight: self.transmittance(ray, hit),
+            pdf,
+            lobe,
+            delta: true,
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        if self.roughness <= 0.0 {
+            return V3::zero();
+        }
+
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        let wi = frame.to_local(direction);
+        self.rough(Ggx::new(self.roughness), wo, wi, self.refraction_ratio(hit))
+            .map_or(V3::zero(), |(value, _)| {
+                self.transmittance(ray, hit) * value
+            })
+    }
+
+    /// Light from above the surface is reflected, from below it is transmitted.
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        let lobe = if direction.dot(hit.normal) > 0.0 {
+            Lobe::Glossy
+        } else {
+            Lobe::Transmission
+        };
+        lobe.only(self.eval(ray, hit, direction))
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        if self.roughness <= 0.0 {
+            return 0.0;
+        }
+
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        let wi = frame.to_local(direction);
+        self.rough(Ggx::new(self.roughness), wo, wi, self.refraction_ratio(hit))
+            .map_or(0.0, |(_, pdf)| pdf)
+    }
+
+    #[cfg(feature = "polarization")]
+    fn polarization(&self, ray: Ray, hit: &Hit, scattered: Ray) -> Mueller {
+        if self.roughness > 0.0 {
+            return Mueller::depolarizer();
+        }
+
+        let cos_theta = ray.direction.unit().neg().dot(hit.normal);
+        let reflected = scattered.direction.dot(hit.normal) > 0.0;
+
+        Mueller::dielectric(cos_theta, self.refraction_ratio(hit), reflected)
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: V3::one(),
+            roughness: self.roughness,
+            refraction_index: Some(self.refraction_index),
+            ..Approximation::default()
+        }
     }
 }
 
@@ -347,82 +2017,451 @@ impl<S: Surface> Specular<S> {
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
-}
 
-impl<S: Surface> Material for Specular<S> {
-    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
-        let attenuation = V3::fill(1.0);
+    /// The chance that `sample` picks the diffuse layer under the coating.
+    fn diffuse_probability(&self, ray: Ray, hit: &Hit) -> f32 {
         let refraction_ratio = if hit.front_face {
             1.0 / self.refraction_index
         } else {
             self.refraction_index
         };
 
-        let unit_direction = ray.direction.unit();
-        let cos_theta = unit_direction.neg().dot(hit.normal).min(1.0);
+        let cos_theta = ray.direction.unit().neg().dot(hit.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        if refraction_ratio * sin_theta > 1.0 {
+            0.0
+        } else {
+            1.0 - Self::reflectance(cos_theta, refraction_ratio)
+        }
+    }
+}
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-
-        let direction =
-            if cannot_refract || Self::reflectance(cos_theta, refraction_ratio) > f32::rand() {
-                unit_direction.reflect(hit.normal)
-            } else {
-                return self.inner.scatter(ray, hit);
-            };
+impl<S: Surface> Material for Specular<S> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let diffuse = self.diffuse_probability(ray, hit);
+        if diffuse > sampler.get_1d() {
+            let sample = self.inner.sample(ray, hit, sampler)?;
+            return Some(BsdfSample {
+                pdf: sample.pdf * diffuse,
+                ..sample
+            });
+        }
 
-        Some(Scatter {
-            attenuation,
-            scattered: Ray::new(hit.point, direction),
+        Some(BsdfSample {
+            direction: ray.direction.unit().reflect(hit.normal),
+            weight: V3::one(),
+            pdf: 1.0 - diffuse,
+            lobe: Lobe::Glossy,
+            delta: true,
         })
     }
 
-    fn alpha_test(&self, uv: V2) -> bool {
-        self.inner.alpha_test(uv)
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        self.inner.eval(ray, hit, direction) * self.diffuse_probability(ray, hit)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        scale_lobes(
+            self.inner.eval_lobes(ray, hit, direction),
+            V3::fill(self.diffuse_probability(ray, hit)),
+        )
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        self.inner.pdf(ray, hit, direction) * self.diffuse_probability(ray, hit)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            roughness: 0.0,
+            ..self.inner.approximate()
+        }
     }
 }
 
 impl Material for () {
-    fn scatter(&self, _ray: Ray, _hit: &Hit) -> Option<Scatter> {
+    fn sample(&self, _ray: Ray, _hit: &Hit, _sampler: &mut dyn Sampler) -> Option<BsdfSample> {
         None
     }
 }
 
 pub struct Mix<MLeft: Material, MRight: Material> {
-    ratio: f32,
+    ratio: Value,
     left: MLeft,
     right: MRight,
 }
 
 impl<MLeft: Material, MRight: Material> Mix<MLeft, MRight> {
-    pub fn new(ratio: f32, left: MLeft, right: MRight) -> Self {
-        Self { ratio, left, right }
+    pub fn new<V: Into<Value>>(ratio: V, left: MLeft, right: MRight) -> Self {
+        Self {
+            ratio: ratio.into(),
+            left,
+            right,
+        }
     }
 }
 impl<MLeft: Material, MRight: Material> Material for Mix<MLeft, MRight> {
-    fn scatter(&self, ray: Ray, hit: &Hit) -> Option<Scatter> {
-        if f32::rand() < self.ratio {
-            self.left.scatter(ray, hit)
+    /// Samples one of the two materials, a direction from either is given the density of the
+    /// whole mix.
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let ratio = self.ratio.get();
+        let (sample, chance) = if sampler.get_1d() < ratio {
+            (self.left.sample(ray, hit, sampler)?, ratio)
         } else {
-            self.right.scatter(ray, hit)
-        }
+            (self.right.sample(ray, hit, sampler)?, 1.0 - ratio)
+        };
+
+        let pdf = if sample.delta {
+            sample.pdf * chance
+        } else {
+            self.pdf(ray, hit, sample.direction)
+        };
+        Some(BsdfSample { pdf, ..sample })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let ratio = self.ratio.get();
+        self.left.eval(ray, hit, direction) * ratio
+            + self.right.eval(ray, hit, direction) * (1.0 - ratio)
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        let ratio = self.ratio.get();
+        let mut lobes = scale_lobes(self.left.eval_lobes(ray, hit, direction), V3::fill(ratio));
+        add_lobes(
+            &mut lobes,
+            scale_lobes(
+                self.right.eval_lobes(ray, hit, direction),
+                V3::fill(1.0 - ratio),
+            ),
+        );
+        lobes
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let ratio = self.ratio.get();
+        self.left.pdf(ray, hit, direction) * ratio
+            + self.right.pdf(ray, hit, direction) * (1.0 - ratio)
     }
 
     fn emit(&self, hit: &Hit) -> Option<V3> {
-        if f32::rand() < self.ratio {
+        if f32::rand() < self.ratio.get() {
             self.left.emit(hit)
         } else {
             self.right.emit(hit)
         }
     }
 
-    fn alpha_test(&self, uv: V2) -> bool {
-        if f32::rand() < self.ratio {
-            self.left.alpha_test(uv)
+    fn alpha(&self, uv: V2) -> f32 {
+        let ratio = self.ratio.get();
+        self.left.alpha(uv) * ratio + self.right.alpha(uv) * (1.0 - ratio)
+    }
+
+    fn approximate(&self) -> Approximation {
+        let ratio = self.ratio.get();
+        self.right.approximate().mix(self.left.approximate(), ratio)
+    }
+}
+
+/// A stack of materials, each layer covering the ones beneath it where its grayscale mask is
+/// white, such as rust over bare metal over paint. Unlike `Mix` the blend can vary across the
+/// surface and any number of layers can be stacked.
+pub struct Layered {
+    base: Box<dyn Material>,
+    layers: Vec<(Box<dyn Material>, Box<dyn Surface>)>,
+}
+
+impl Layered {
+    pub fn new<M: 'static + Material>(base: M) -> Self {
+        Self {
+            base: Box::new(base),
+            layers: Vec::new(),
+        }
+    }
+
+    /// Adds `material` on top of the stack, covering what is beneath it by the red channel of
+    /// `mask`.
+    pub fn with_layer<M: 'static + Material, S: 'static + Surface>(
+        mut self,
+        material: M,
+        mask: S,
+    ) -> Self {
+        self.layers.push((Box::new(material), Box::new(mask)));
+        self
+    }
+
+    /// Passes each layer to `visit` from the top down with the share of the surface it covers
+    /// at `uv`, the shares sum to one.
+    fn visit_layers<'a>(&'a self, uv: V2, mut visit: impl FnMut(&'a dyn Material, f32)) {
+        let mut remaining = 1.0;
+        for (material, mask) in self.layers.iter().rev() {
+            let coverage = mask.get_f(uv).x().clamp(0.0, 1.0);
+            visit(&**material, remaining * coverage);
+            remaining *= 1.0 - coverage;
+        }
+        visit(&*self.base, remaining);
+    }
+}
+
+impl Material for Layered {
+    /// Samples one of the layers by its share, a direction from any is given the density of the
+    /// whole stack.
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let pick = sampler.get_1d();
+        let mut total = 0.0;
+        let mut picked = None;
+        self.visit_layers(hit.uv.unwrap_or(V2::zero()), |material, share| {
+            // Rounding can leave the shares just short of one, the last layer takes the rest
+            if share > 0.0 && (picked.is_none() || total <= pick) {
+                picked = Some((material, share));
+            }
+            total += share;
+        });
+
+        let (material, chance) = picked?;
+        let sample = material.sample(ray, hit, sampler)?;
+        let pdf = if sample.delta {
+            sample.pdf * chance
+        } else {
+            self.pdf(ray, hit, sample.direction)
+        };
+        Some(BsdfSample { pdf, ..sample })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        let mut sum = V3::zero();
+        self.visit_layers(hit.uv.unwrap_or(V2::zero()), |material, share| {
+            if share > 0.0 {
+                sum = sum + material.eval(ray, hit, direction) * share;
+            }
+        });
+        sum
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        let mut sum = [V3::zero(); Lobe::COUNT];
+        self.visit_layers(hit.uv.unwrap_or(V2::zero()), |material, share| {
+            if share > 0.0 {
+                let lobes = material.eval_lobes(ray, hit, direction);
+                add_lobes(&mut sum, scale_lobes(lobes, V3::fill(share)));
+            }
+        });
+        sum
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let mut sum = 0.0;
+        self.visit_layers(hit.uv.unwrap_or(V2::zero()), |material, share| {
+            if share > 0.0 {
+                sum += material.pdf(ray, hit, direction) * share;
+            }
+        });
+        sum
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        let mut sum = None;
+        self.visit_layers(hit.uv.unwrap_or(V2::zero()), |material, share| {
+            if let Some(emitted) = material.emit(hit).filter(|_| share > 0.0) {
+                sum = Some(sum.unwrap_or(V3::zero()) + emitted * share);
+            }
+        });
+        sum
+    }
+
+    /// Blends the normals of the layers by their share, layers without a normal map keep the
+    /// surface normal.
+    fn normal(&self, uv: V2) -> Option<V3> {
+        let mut mapped = false;
+        let mut sum = V3::zero();
+        self.visit_layers(uv, |material, share| {
+            let normal = match material.normal(uv) {
+                Some(normal) => {
+                    mapped = true;
+                    normal
+                }
+                None => V3::new(0.0, 0.0, 1.0),
+            };
+            sum = sum + normal * share;
+        });
+
+        if mapped && !sum.near_zero() {
+            Some(sum.unit())
         } else {
-            self.right.alpha_test(uv)
+            None
+        }
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        let mut sum = 0.0;
+        self.visit_layers(uv, |material, share| sum += material.alpha(uv) * share);
+        sum
+    }
+
+    fn light_group(&self) -> usize {
+        self.base.light_group()
+    }
+
+    fn backface(&self) -> Backface {
+        self.base.backface()
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.layers
+            .iter()
+            .fold(self.base.approximate(), |below, (material, mask)| {
+                below.mix(material.approximate(), average_color(mask).x())
+            })
+    }
+}
+
+/// A thin clear varnish layered over `inner`, such as the lacquer of car paint. Light the coat
+/// doesn't reflect passes through to `inner` and back out again, without being absorbed.
+pub struct Clearcoat<M: Material> {
+    roughness: f32,
+    refraction_index: f32,
+    inner: M,
+}
+
+impl<M: Material> Clearcoat<M> {
+    /// Coats `inner` with a layer of perceptual `roughness` and a refraction index of 1.5.
+    pub fn new(roughness: f32, inner: M) -> Self {
+        Self {
+            roughness: roughness.clamp(0.0, 1.0),
+            refraction_index: 1.5,
+            inner,
         }
     }
+
+    pub fn with_refraction_index(self, refraction_index: f32) -> Self {
+        Self {
+            refraction_index,
+            ..self
+        }
+    }
+
+    /// The fraction of light the coat reflects at `cosine` to its normal.
+    fn reflectance(&self, cosine: f32) -> f32 {
+        let ratio = 1.0 / self.refraction_index;
+        Dielectric::fresnel(cosine.abs().min(1.0), ratio)
+    }
+
+    /// The chance of sampling the coat rather than `inner`, seen from `cos_o`.
+    fn coat_probability(&self, cos_o: f32) -> f32 {
+        self.reflectance(cos_o).clamp(0.1, 0.9)
+    }
+}
+
+impl<M: Material> Material for Clearcoat<M> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        let coat = self.coat_probability(wo.z());
+
+        if sampler.get_1d() < coat {
+            let ggx = Ggx::new(self.roughness);
+            let wi = ggx.sample_reflection(wo, sampler.get_2d())?;
+            let direction = frame.to_world(wi).unit();
+            let pdf = self.pdf(ray, hit, direction);
+            if pdf <= 0.0 {
+                return None;
+            }
+
+            return Some(BsdfSample {
+                direction,
+                weight: self.eval(ray, hit, direction) / pdf,
+                pdf,
+                lobe: Lobe::Glossy,
+                delta: false,
+            });
+        }
+
+        let sample = self.inner.sample(ray, hit, sampler)?;
+        let cos_i = sample.direction.dot(hit.normal);
+        let transmitted = (1.0 - self.reflectance(wo.z())) * (1.0 - self.reflectance(cos_i));
+        if sample.delta {
+            return Some(BsdfSample {
+                weight: sample.weight * (transmitted / (1.0 - coat)),
+                pdf: sample.pdf * (1.0 - coat),
+                ..sample
+            });
+        }
+
+        let pdf = self.pdf(ray, hit, sample.direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            weight: self.eval(ray, hit, sample.direction) / pdf,
+            pdf,
+            ..sample
+        })
+    }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        lobe_total(self.eval_lobes(ray, hit, direction))
+    }
+
+    /// The coat is glossy, the light through it keeps the lobes of `inner`.
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        let wi = frame.to_local(direction);
+
+        let coat = match Ggx::new(self.roughness).reflection(wo, wi) {
+            Some((reflection, _, m)) => self.reflectance(wo.dot(m)) * reflection,
+            None => 0.0,
+        };
+        let transmitted = (1.0 - self.reflectance(wo.z())) * (1.0 - self.reflectance(wi.z()));
+
+        let mut lobes = scale_lobes(
+            self.inner.eval_lobes(ray, hit, direction),
+            V3::fill(transmitted),
+        );
+        lobes[Lobe::Glossy.index()] += V3::fill(coat);
+        lobes
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+        let wi = frame.to_local(direction);
+        let coat = self.coat_probability(wo.z());
+
+        let coat_pdf = Ggx::new(self.roughness)
+            .reflection(wo, wi)
+            .map_or(0.0, |(_, pdf, _)| pdf);
+        coat * coat_pdf + (1.0 - coat) * self.inner.pdf(ray, hit, direction)
+    }
+
+    fn emit(&self, hit: &Hit) -> Option<V3> {
+        self.inner.emit(hit)
+    }
+
+    fn normal(&self, uv: V2) -> Option<V3> {
+        self.inner.normal(uv)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.inner.alpha(uv)
+    }
+
+    fn light_group(&self) -> usize {
+        self.inner.light_group()
+    }
+
+    fn backface(&self) -> Backface {
+        self.inner.backface()
+    }
+
+    fn approximate(&self) -> Approximation {
+        self.inner.approximate()
+    }
 }
 
 pub struct Isotrophic {
@@ -436,10 +2475,389 @@ impl Isotrophic {
 }
 
 impl Material for Isotrophic {
-    fn scatter(&self, _ray: Ray, hit: &Hit) -> Option<Scatter> {
-        Some(Scatter {
-            attenuation: self.albedo,
-            scattered: Ray::new(hit.point, V3::random_in_unit_sphere()),
+    fn sample(&self, _ray: Ray, _hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            direction: V3::unit_vector_from(sampler.get_2d()),
+            weight: self.albedo,
+            pdf: 1.0 / (4.0 * std::f32::consts::PI),
+            lobe: Lobe::Diffuse,
+            delta: false,
+        })
+    }
+
+    fn eval(&self, _ray: Ray, _hit: &Hit, _direction: V3) -> V3 {
+        self.albedo / (4.0 * std::f32::consts::PI)
+    }
+
+    fn pdf(&self, _ray: Ray, _hit: &Hit, _direction: V3) -> f32 {
+        1.0 / (4.0 * std::f32::consts::PI)
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: self.albedo,
+            ..Approximation::default()
+        }
+    }
+}
+
+/// A physically based material in the style of Disney's principled BSDF, covering the
+/// parameters most PBR assets from Blender and glTF are authored with. A diffuse base with an
+/// optional `sheen` for cloth is blended towards metal by `metallic` and towards glass by
+/// `transmission`, under a GGX specular layer and an optional `clearcoat`.
+///
+/// The transmissive share refracts and reflects like a `Dielectric` of the same `roughness`,
+/// the specular layer only covering the opaque share so the glass isn't reflected twice.
+#[derive(Clone)]
+pub struct Principled<S: Surface> {
+    surface: S,
+    metallic: f32,
+    roughness: f32,
+    specular: f32,
+    sheen: f32,
+    sheen_tint: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    transmission: f32,
+    refraction_index: f32,
+}
+
+impl<S: Surface> Principled<S> {
+    /// A rough plastic-like material colored by `surface`.
+    pub fn new(surface: S) -> Self {
+        Self {
+            surface,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            refraction_index: 1.45,
+        }
+    }
+
+    pub fn with_metallic(self, metallic: f32) -> Self {
+        Self {
+            metallic: metallic.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn with_roughness(self, roughness: f32) -> Self {
+        Self {
+            roughness: roughness.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// The strength of the specular reflection of non-metals, 0.5 reflects 4% head on.
+    pub fn with_specular(self, specular: f32) -> Self {
+        Self {
+            specular: specular.max(0.0),
+            ..self
+        }
+    }
+
+    /// Adds a soft reflection at grazing angles for cloth, `tint` moves its color from white
+    /// towards the base color.
+    pub fn with_sheen(self, sheen: f32, tint: f32) -> Self {
+        Self {
+            sheen: sheen.max(0.0),
+            sheen_tint: tint.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// Adds a clear varnish with its own `roughness` over the material.
+    pub fn with_clearcoat(self, clearcoat: f32, roughness: f32) -> Self {
+        Self {
+            clearcoat: clearcoat.clamp(0.0, 1.0),
+            clearcoat_roughness: roughness.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn with_transmission(self, transmission: f32) -> Self {
+        Self {
+            transmission: transmission.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn with_refraction_index(self, refraction_index: f32) -> Self {
+        Self {
+            refraction_index,
+            ..self
+        }
+    }
+
+    fn shading(&self, ray: Ray, hit: &Hit) -> PrincipledShading {
+        let base = hit.texel(&self.surface).contract() * hit.color.unwrap_or(V3::one());
+        let frame = Frame::new(hit.normal);
+        let wo = frame.to_local(ray.direction.unit().neg());
+
+        // Inside a transmissive material only the glass is left to leave through
+        let inside = !hit.front_face && self.transmission > 0.0;
+        let (diffuse, metallic, transmission) = if inside {
+            (0.0, 0.0, 1.0)
+        } else {
+            let dielectric = 1.0 - self.metallic;
+            (
+                dielectric * (1.0 - self.transmission),
+                self.metallic,
+                dielectric * self.transmission,
+            )
+        };
+        let clearcoat = if inside { 0.0 } else { 0.25 * self.clearcoat };
+
+        let mut shading = PrincipledShading {
+            frame,
+            wo,
+            base,
+            f0: (0.08 * self.specular).min(1.0),
+            tint: if inside { V3::one() } else { base },
+            diffuse,
+            metallic,
+            clearcoat,
+            transmission,
+            chances: [0.0; 4],
+        };
+
+        let chances = [
+            diffuse,
+            luminance(shading.specular_fresnel(wo.z())),
+            clearcoat * schlick_weight(wo.z()).max(0.04),
+            transmission,
+        ];
+        let total: f32 = chances.iter().sum();
+        if total > 0.0 {
+            shading.chances = [
+                chances[0] / total,
+                chances[1] / total,
+                chances[2] / total,
+                chances[3] / total,
+            ];
+        }
+        shading
+    }
+
+    /// The glass the transmissive share of the material refracts and reflects through.
+    fn glass(&self) -> Dielectric {
+        Dielectric::new(self.refraction_index).with_roughness(self.roughness)
+    }
+
+    /// The light arriving from local `wi` and leaving along `wo`, from every lobe but the
+    /// glass, including the cosine, split by lobe.
+    fn eval_local(&self, shading: &PrincipledShading, wi: V3) -> [V3; Lobe::COUNT] {
+        let wo = shading.wo;
+        let mut lobes = [V3::zero(); Lobe::COUNT];
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return lobes;
+        }
+
+        let half = (wo + wi).unit();
+        let cos_d = wi.dot(half);
+
+        if shading.diffuse > 0.0 {
+            let retro = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fresnel = (1.0 + (retro - 1.0) * schlick_weight(wi.z()))
+                * (1.0 + (retro - 1.0) * schlick_weight(wo.z()));
+            // The base only gets the light the specular layer lets through, both ways
+            let through = (1.0 - shading.f0).powi(2)
+                * (1.0 - schlick_weight(wi.z()))
+                * (1.0 - schlick_weight(wo.z()));
+            let diffuse = shading.base * (fresnel * through / std::f32::consts::PI);
+
+            let tint = match luminance(shading.base) {
+                luminance if luminance > 0.0 => shading.base / luminance,
+                _ => V3::one(),
+            };
+            let sheen_color = V3::one() * (1.0 - self.sheen_tint) + tint * self.sheen_tint;
+            let sheen = sheen_color * (self.sheen * schlick_weight(cos_d));
+
+            lobes[Lobe::Diffuse.index()] = (diffuse + sheen) * (shading.diffuse * wi.z());
+        }
+
+        if shading.diffuse > 0.0 || shading.metallic > 0.0 {
+            if let Some((reflection, _, m)) = Ggx::new(self.roughness).reflection(wo, wi) {
+                lobes[Lobe::Glossy.index()] += shading.specular_fresnel(wo.dot(m)) * reflection;
+            }
+        }
+
+        if shading.clearcoat > 0.0 {
+            if let Some((reflection, _, m)) = Ggx::new(self.clearcoat_roughness).reflection(wo, wi)
+            {
+                let fresnel = 0.04 + 0.96 * schlick_weight(wo.dot(m));
+                lobes[Lobe::Glossy.index()] += V3::fill(shading.clearcoat * fresnel * reflection);
+            }
+        }
+
+        lobes
+    }
+
+    /// The density of sampling local `wi` from the lobes `eval_local` covers.
+    fn pdf_local(&self, shading: &PrincipledShading, wi: V3) -> f32 {
+        let wo = shading.wo;
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let [diffuse, specular, clearcoat, _] = shading.chances;
+        let mut pdf = diffuse * wi.z() / std::f32::consts::PI;
+        if specular > 0.0 {
+            if let Some((_, lobe_pdf, _)) = Ggx::new(self.roughness).reflection(wo, wi) {
+                pdf += specular * lobe_pdf;
+            }
+        }
+        if clearcoat > 0.0 {
+            if let Some((_, lobe_pdf, _)) = Ggx::new(self.clearcoat_roughness).reflection(wo, wi) {
+                pdf += clearcoat * lobe_pdf;
+            }
+        }
+        pdf
+    }
+
+    /// `eval_local` with the rough glass added, for unit world `direction`.
+    fn eval_shaded(
+        &self,
+        shading: &PrincipledShading,
+        ray: Ray,
+        hit: &Hit,
+        direction: V3,
+    ) -> [V3; Lobe::COUNT] {
+        let mut lobes = self.eval_local(shading, shading.frame.to_local(direction));
+        if shading.transmission > 0.0 {
+            let glass = self.glass().eval_lobes(ray, hit, direction);
+            lobes[Lobe::Glossy.index()] += glass[Lobe::Glossy.index()] * shading.transmission;
+            lobes[Lobe::Transmission.index()] +=
+                glass[Lobe::Transmission.index()] * shading.tint * shading.transmission;
+        }
+        lobes
+    }
+
+    /// `pdf_local` with the rough glass added, for unit world `direction`.
+    fn pdf_shaded(&self, shading: &PrincipledShading, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let mut pdf = self.pdf_local(shading, shading.frame.to_local(direction));
+        let transmission = shading.chances[3];
+        if transmission > 0.0 {
+            pdf += transmission * self.glass().pdf(ray, hit, direction);
+        }
+        pdf
+    }
+}
+
+/// The parameters of a `Principled` material at a hit, with the weight of each lobe and the
+/// chance of sampling the diffuse, specular, clearcoat and transmission lobes in turn.
+struct PrincipledShading {
+    frame: Frame,
+    wo: V3,
+    base: V3,
+    /// The head on reflectance of the dielectric specular.
+    f0: f32,
+    tint: V3,
+    diffuse: f32,
+    metallic: f32,
+    clearcoat: f32,
+    transmission: f32,
+    chances: [f32; 4],
+}
+
+impl PrincipledShading {
+    /// The Fresnel reflectance of the specular lobe at `cosine`, the metal reflecting its base
+    /// color. The glass reflects for itself, so only the opaque dielectric share is counted.
+    fn specular_fresnel(&self, cosine: f32) -> V3 {
+        schlick(V3::fill(self.f0), cosine) * self.diffuse
+            + schlick(self.base, cosine) * self.metallic
+    }
+}
+
+impl<S: Surface> Material for Principled<S> {
+    fn sample(&self, ray: Ray, hit: &Hit, sampler: &mut dyn Sampler) -> Option<BsdfSample> {
+        let shading = self.shading(ray, hit);
+        let [diffuse, specular, clearcoat, transmission] = shading.chances;
+
+        let u = sampler.get_1d();
+        let u2 = sampler.get_2d();
+        let (direction, lobe) = if u < diffuse {
+            let direction = hit.normal + V3::unit_vector_from(u2);
+            let direction = if direction.near_zero() {
+                hit.normal
+            } else {
+                direction.unit()
+            };
+            (direction, Lobe::Diffuse)
+        } else if u < diffuse + specular {
+            let wi = Ggx::new(self.roughness).sample_reflection(shading.wo, u2)?;
+            (shading.frame.to_world(wi).unit(), Lobe::Glossy)
+        } else if u < diffuse + specular + clearcoat || transmission <= 0.0 {
+            let wi = Ggx::new(self.clearcoat_roughness).sample_reflection(shading.wo, u2)?;
+            (shading.frame.to_world(wi).unit(), Lobe::Glossy)
+        } else {
+            let sample = self.glass().sample(ray, hit, sampler)?;
+            if sample.delta {
+                let tint = match sample.lobe {
+                    Lobe::Transmission => shading.tint,
+                    _ => V3::one(),
+                };
+                return Some(BsdfSample {
+                    weight: sample.weight * tint * (shading.transmission / transmission),
+                    pdf: sample.pdf * transmission,
+                    ..sample
+                });
+            }
+            (sample.direction, sample.lobe)
+        };
+
+        let pdf = self.pdf_shaded(&shading, ray, hit, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(BsdfSample {
+            direction,
+            weight: lobe_total(self.eval_shaded(&shading, ray, hit, direction)) / pdf,
+            pdf,
+            lobe,
+            delta: false,
         })
     }
+
+    fn eval(&self, ray: Ray, hit: &Hit, direction: V3) -> V3 {
+        lobe_total(self.eval_lobes(ray, hit, direction))
+    }
+
+    fn eval_lobes(&self, ray: Ray, hit: &Hit, direction: V3) -> [V3; Lobe::COUNT] {
+        let shading = self.shading(ray, hit);
+        self.eval_shaded(&shading, ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: Ray, hit: &Hit, direction: V3) -> f32 {
+        let shading = self.shading(ray, hit);
+        self.pdf_shaded(&shading, ray, hit, direction)
+    }
+
+    fn alpha(&self, uv: V2) -> f32 {
+        self.surface.get_f(uv).w()
+    }
+
+    fn approximate(&self) -> Approximation {
+        Approximation {
+            color: average_color(&self.surface),
+            metallic: self.metallic,
+            roughness: self.roughness,
+            refraction_index: if self.transmission > 0.5 {
+                Some(self.refraction_index)
+            } else {
+                None
+            },
+            ..Approximation::default()
+        }
+    }
+}
+
+fn luminance(color: V3) -> f32 {
+    color.x() * 0.2126 + color.y() * 0.7152 + color.z() * 0.0722
 }
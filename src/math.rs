@@ -108,6 +108,20 @@ impl V3 {
         Self::random_in_unit_sphere().unit()
     }
 
+    /// A point on the unit sphere from the uniform numbers in `u`, keeping stratified numbers
+    /// evenly spread over it.
+    pub fn unit_vector_from(u: V2) -> Self {
+        let z = 1.0 - 2.0 * u.x();
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.y();
+        V3::new(radius * phi.cos(), radius * phi.sin(), z)
+    }
+
+    /// A point inside the unit sphere from the uniform numbers in `u` and `r`.
+    pub fn in_unit_sphere_from(u: V2, r: F) -> Self {
+        Self::unit_vector_from(u) * r.cbrt()
+    }
+
     pub fn near_zero(&self) -> bool {
         self.x().abs() <= 0.00001 && self.y().abs() <= 0.00001 && self.z().abs() <= 0.00001
     }
@@ -222,6 +236,160 @@ impl M4 {
             V4::new(0.0, 0.0, 0.0, 1.0),
         )
     }
+
+    pub fn rotate_axis(axis: V3, angle: F) -> Self {
+        let (sin, cos) = (angle * PI * 2.0).sin_cos();
+        let k = axis.unit();
+        let (x, y, z) = (k.x(), k.y(), k.z());
+        let t = 1.0 - cos;
+
+        M4::new(
+            V4::new(
+                cos + t * x * x,
+                t * x * y + sin * z,
+                t * x * z - sin * y,
+                0.0,
+            ),
+            V4::new(
+                t * x * y - sin * z,
+                cos + t * y * y,
+                t * y * z + sin * x,
+                0.0,
+            ),
+            V4::new(
+                t * x * z + sin * y,
+                t * y * z - sin * x,
+                cos + t * z * z,
+                0.0,
+            ),
+            V4::new(0.0, 0.0, 0.0, 1.0),
+        )
+    }
+
+    /// Places an object at `eye` with its +z axis pointing toward `target`.
+    pub fn look_at(eye: V3, target: V3, up: V3) -> Self {
+        let z = (target - eye).unit();
+        let x = up.cross(z).unit();
+        let y = z.cross(x);
+
+        M4::new(x.expand(0.0), y.expand(0.0), z.expand(0.0), eye.expand(1.0))
+    }
+
+    pub fn lerp(self, other: Self, t: F) -> Self {
+        let a = self.columns();
+        let b = other.columns();
+
+        M4::new(
+            a[0] * (1.0 - t) + b[0] * t,
+            a[1] * (1.0 - t) + b[1] * t,
+            a[2] * (1.0 - t) + b[2] * t,
+            a[3] * (1.0 - t) + b[3] * t,
+        )
+    }
+
+    pub fn inverse(self) -> Option<Self> {
+        let c = self.columns();
+        let m = [
+            c[0].x(),
+            c[0].y(),
+            c[0].z(),
+            c[0].w(),
+            c[1].x(),
+            c[1].y(),
+            c[1].z(),
+            c[1].w(),
+            c[2].x(),
+            c[2].y(),
+            c[2].z(),
+            c[2].w(),
+            c[3].x(),
+            c[3].y(),
+            c[3].z(),
+            c[3].w(),
+        ];
+
+        let mut inv = [0.0; 16];
+
+        inv[0] = m[5] * m[10] * m[15] - m[5] * m[11] * m[14] - m[9] * m[6] * m[15]
+            + m[9] * m[7] * m[14]
+            + m[13] * m[6] * m[11]
+            - m[13] * m[7] * m[10];
+        inv[4] = -m[4] * m[10] * m[15] + m[4] * m[11] * m[14] + m[8] * m[6] * m[15]
+            - m[8] * m[7] * m[14]
+            - m[12] * m[6] * m[11]
+            + m[12] * m[7] * m[10];
+        inv[8] = m[4] * m[9] * m[15] - m[4] * m[11] * m[13] - m[8] * m[5] * m[15]
+            + m[8] * m[7] * m[13]
+            + m[12] * m[5] * m[11]
+            - m[12] * m[7] * m[9];
+        inv[12] = -m[4] * m[9] * m[14] + m[4] * m[10] * m[13] + m[8] * m[5] * m[14]
+            - m[8] * m[6] * m[13]
+            - m[12] * m[5] * m[10]
+            + m[12] * m[6] * m[9];
+        inv[1] = -m[1] * m[10] * m[15] + m[1] * m[11] * m[14] + m[9] * m[2] * m[15]
+            - m[9] * m[3] * m[14]
+            - m[13] * m[2] * m[11]
+            + m[13] * m[3] * m[10];
+        inv[5] = m[0] * m[10] * m[15] - m[0] * m[11] * m[14] - m[8] * m[2] * m[15]
+            + m[8] * m[3] * m[14]
+            + m[12] * m[2] * m[11]
+            - m[12] * m[3] * m[10];
+        inv[9] = -m[0] * m[9] * m[15] + m[0] * m[11] * m[13] + m[8] * m[1] * m[15]
+            - m[8] * m[3] * m[13]
+            - m[12] * m[1] * m[11]
+            + m[12] * m[3] * m[9];
+        inv[13] = m[0] * m[9] * m[14] - m[0] * m[10] * m[13] - m[8] * m[1] * m[14]
+            + m[8] * m[2] * m[13]
+            + m[12] * m[1] * m[10]
+            - m[12] * m[2] * m[9];
+        inv[2] = m[1] * m[6] * m[15] - m[1] * m[7] * m[14] - m[5] * m[2] * m[15]
+            + m[5] * m[3] * m[14]
+            + m[13] * m[2] * m[7]
+            - m[13] * m[3] * m[6];
+        inv[6] = -m[0] * m[6] * m[15] + m[0] * m[7] * m[14] + m[4] * m[2] * m[15]
+            - m[4] * m[3] * m[14]
+            - m[12] * m[2] * m[7]
+            + m[12] * m[3] * m[6];
+        inv[10] = m[0] * m[5] * m[15] - m[0] * m[7] * m[13] - m[4] * m[1] * m[15]
+            + m[4] * m[3] * m[13]
+            + m[12] * m[1] * m[7]
+            - m[12] * m[3] * m[5];
+        inv[14] = -m[0] * m[5] * m[14] + m[0] * m[6] * m[13] + m[4] * m[1] * m[14]
+            - m[4] * m[2] * m[13]
+            - m[12] * m[1] * m[6]
+            + m[12] * m[2] * m[5];
+        inv[3] = -m[1] * m[6] * m[11] + m[1] * m[7] * m[10] + m[5] * m[2] * m[11]
+            - m[5] * m[3] * m[10]
+            - m[9] * m[2] * m[7]
+            + m[9] * m[3] * m[6];
+        inv[7] = m[0] * m[6] * m[11] - m[0] * m[7] * m[10] - m[4] * m[2] * m[11]
+            + m[4] * m[3] * m[10]
+            + m[8] * m[2] * m[7]
+            - m[8] * m[3] * m[6];
+        inv[11] = -m[0] * m[5] * m[11] + m[0] * m[7] * m[9] + m[4] * m[1] * m[11]
+            - m[4] * m[3] * m[9]
+            - m[8] * m[1] * m[7]
+            + m[8] * m[3] * m[5];
+        inv[15] = m[0] * m[5] * m[10] - m[0] * m[6] * m[9] - m[4] * m[1] * m[10]
+            + m[4] * m[2] * m[9]
+            + m[8] * m[1] * m[6]
+            - m[8] * m[2] * m[5];
+
+        let det = m[0] * inv[0] + m[1] * inv[4] + m[2] * inv[8] + m[3] * inv[12];
+
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+
+        Some(M4::new(
+            V4::new(inv[0], inv[1], inv[2], inv[3]) * inv_det,
+            V4::new(inv[4], inv[5], inv[6], inv[7]) * inv_det,
+            V4::new(inv[8], inv[9], inv[10], inv[11]) * inv_det,
+            V4::new(inv[12], inv[13], inv[14], inv[15]) * inv_det,
+        ))
+    }
 }
 
 pub trait Num {
@@ -274,3 +442,228 @@ impl Num for f64 {
         f64::max(*self, other)
     }
 }
+
+/// Checks the vector and matrix types against plain scalar arithmetic, run with both the
+/// default features and `--features simd` to keep the two backends in agreement.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: F = 1e-4;
+
+    fn close(a: F, b: F) -> bool {
+        (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0)
+    }
+
+    fn assert_v3(v: V3, expected: [F; 3]) {
+        let actual = [v.x(), v.y(), v.z()];
+        assert!(
+            actual
+                .iter()
+                .zip(expected.iter())
+                .all(|(&a, &b)| close(a, b)),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_v4(v: V4, expected: [F; 4]) {
+        let actual = [v.x(), v.y(), v.z(), v.w()];
+        assert!(
+            actual
+                .iter()
+                .zip(expected.iter())
+                .all(|(&a, &b)| close(a, b)),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    /// A fixed sequence of values between -10 and 10, the same on every run.
+    fn values(count: usize) -> Vec<F> {
+        let mut state: u32 = 0x2545_f491;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as F / u32::MAX as F) * 20.0 - 10.0
+            })
+            .collect()
+    }
+
+    fn vectors(count: usize) -> Vec<[F; 3]> {
+        values(count * 3)
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect()
+    }
+
+    fn dot(a: [F; 3], b: [F; 3]) -> F {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    fn cross(a: [F; 3], b: [F; 3]) -> [F; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    fn v3(a: [F; 3]) -> V3 {
+        V3::new(a[0], a[1], a[2])
+    }
+
+    #[test]
+    fn fill() {
+        assert_v3(V3::fill(2.5), [2.5, 2.5, 2.5]);
+        assert_v4(V4::fill(-1.5), [-1.5, -1.5, -1.5, -1.5]);
+        assert_eq!(V3::fill(0.0), V3::zero());
+        assert_eq!(V3::fill(1.0), V3::one());
+        assert_eq!(V4::fill(1.0), V4::one());
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(V3::zero(), V3::new(0.0, 0.0, 0.0));
+        assert_eq!(V3::new(1.0, 2.0, 3.0) * 2.0, V3::new(2.0, 4.0, 6.0));
+        assert_eq!(V3::new(1.0, 2.0, 3.0) - V3::one(), V3::new(0.0, 1.0, 2.0));
+        assert_ne!(V3::new(1.0, 2.0, 3.0), V3::new(1.0, 2.0, 4.0));
+        assert_eq!(V4::new(1.0, 2.0, 3.0, 4.0), V4::new(1.0, 2.0, 3.0, 4.0));
+        assert_ne!(V4::new(1.0, 2.0, 3.0, 4.0), V4::new(1.0, 2.0, 3.0, 5.0));
+        assert_eq!(
+            V3::new(1.0, 2.0, 3.0).expand(4.0).contract(),
+            V3::new(1.0, 2.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn length() {
+        assert!(close(V3::new(3.0, 4.0, 12.0).length(), 13.0));
+        assert!(close(V3::new(3.0, 4.0, 12.0).length_squared(), 169.0));
+        for a in vectors(64) {
+            assert!(close(v3(a).length(), dot(a, a).sqrt()));
+        }
+    }
+
+    #[test]
+    fn dot_product() {
+        assert!(close(
+            V3::new(1.0, 2.0, 3.0).dot(V3::new(4.0, -5.0, 6.0)),
+            12.0
+        ));
+        let vectors = vectors(64);
+        for pair in vectors.chunks_exact(2) {
+            assert!(close(v3(pair[0]).dot(v3(pair[1])), dot(pair[0], pair[1])));
+        }
+    }
+
+    #[test]
+    fn cross_product() {
+        assert_v3(
+            V3::new(1.0, 0.0, 0.0).cross(V3::new(0.0, 1.0, 0.0)),
+            [0.0, 0.0, 1.0],
+        );
+        assert_v3(
+            V3::new(1.0, 2.0, 3.0).cross(V3::new(4.0, 5.0, 6.0)),
+            [-3.0, 6.0, -3.0],
+        );
+        let vectors = vectors(64);
+        for pair in vectors.chunks_exact(2) {
+            assert_v3(v3(pair[0]).cross(v3(pair[1])), cross(pair[0], pair[1]));
+        }
+    }
+
+    #[test]
+    fn unit() {
+        assert_v3(
+            V3::new(3.0, 4.0, 12.0).unit(),
+            [3.0 / 13.0, 4.0 / 13.0, 12.0 / 13.0],
+        );
+        for a in vectors(64) {
+            let length = dot(a, a).sqrt();
+            assert_v3(v3(a).unit(), [a[0] / length, a[1] / length, a[2] / length]);
+            assert!(close(v3(a).unit().length(), 1.0));
+        }
+    }
+
+    #[test]
+    fn unit_vector_from() {
+        assert_v3(V3::unit_vector_from(V2::new(0.0, 0.0)), [0.0, 0.0, 1.0]);
+        assert_v3(V3::unit_vector_from(V2::new(0.5, 0.25)), [0.0, 1.0, 0.0]);
+        for u in values(128).chunks_exact(2) {
+            let u = V2::new(u[0] / 20.0 + 0.5, u[1] / 20.0 + 0.5);
+            assert!(close(V3::unit_vector_from(u).length(), 1.0));
+            assert!(V3::in_unit_sphere_from(u, u.x()).length() <= 1.0 + TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn matrix_vector_product() {
+        let m = M4::new(
+            V4::new(1.0, 2.0, 3.0, 4.0),
+            V4::new(5.0, 6.0, 7.0, 8.0),
+            V4::new(9.0, 10.0, 11.0, 12.0),
+            V4::new(13.0, 14.0, 15.0, 16.0),
+        );
+        assert_v4(m * V4::new(1.0, 0.0, 2.0, -1.0), [6.0, 8.0, 10.0, 12.0]);
+        assert_v4(
+            M4::identity() * V4::new(1.0, 2.0, 3.0, 4.0),
+            [1.0, 2.0, 3.0, 4.0],
+        );
+
+        let values = values(16 * 16 + 4 * 16);
+        let (matrices, rest) = values.split_at(16 * 16);
+        for (c, v) in matrices.chunks_exact(16).zip(rest.chunks_exact(4)) {
+            let m = M4::new(
+                V4::new(c[0], c[1], c[2], c[3]),
+                V4::new(c[4], c[5], c[6], c[7]),
+                V4::new(c[8], c[9], c[10], c[11]),
+                V4::new(c[12], c[13], c[14], c[15]),
+            );
+            let expected: Vec<F> = (0..4)
+                .map(|row| (0..4).map(|col| c[col * 4 + row] * v[col]).sum())
+                .collect();
+            assert_v4(
+                m * V4::new(v[0], v[1], v[2], v[3]),
+                [expected[0], expected[1], expected[2], expected[3]],
+            );
+        }
+    }
+
+    #[test]
+    fn matrix_inverse() {
+        let translation = M4::translation(V3::new(1.0, -2.0, 3.0));
+        let inverse = translation.inverse().unwrap();
+        assert_v3(
+            inverse.transform_point(V3::new(1.0, -2.0, 3.0)),
+            [0.0, 0.0, 0.0],
+        );
+        assert_v3(
+            inverse.transform_vector(V3::new(1.0, 1.0, 1.0)),
+            [1.0, 1.0, 1.0],
+        );
+
+        let scale = M4::scale(V3::new(2.0, 4.0, 0.5)).inverse().unwrap();
+        assert_v3(
+            scale.transform_point(V3::new(2.0, 4.0, 0.5)),
+            [1.0, 1.0, 1.0],
+        );
+
+        assert!(M4::scale(V3::new(1.0, 0.0, 1.0)).inverse().is_none());
+
+        let points = vectors(16);
+        let transform = M4::translation(V3::new(0.5, 1.5, -2.0))
+            * M4::rotate_axis(V3::new(1.0, 2.0, 3.0), 0.2)
+            * M4::scale(V3::new(1.5, 0.5, 2.0));
+        let inverse = transform.inverse().unwrap();
+        for p in points {
+            assert_v3(inverse.transform_point(transform.transform_point(v3(p))), p);
+            let v = V4::new(p[0], p[1], p[2], 1.0);
+            assert_v4(inverse * (transform * v), [p[0], p[1], p[2], 1.0]);
+        }
+    }
+}
@@ -81,6 +81,10 @@ impl<T> M4<T> {
         M4 { c0, c1, c2, c3 }
     }
 
+    pub fn columns(self) -> [V4<T>; 4] {
+        [self.c0, self.c1, self.c2, self.c3]
+    }
+
     pub fn transpose(self) -> M4<T> {
         M4 {
             c0: V4::new(self.c0.x, self.c1.x, self.c2.x, self.c3.x),
@@ -2,6 +2,7 @@
 
 use core_simd::{f32x2, f32x4, simd_swizzle, SimdFloat};
 
+use std::fmt;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use super::F;
@@ -9,7 +10,7 @@ use super::F;
 type Fx4 = f32x4;
 type Fx2 = f32x2;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct V2 {
     inner: Fx2,
 }
@@ -39,7 +40,9 @@ impl V2 {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// The w lane is padding, it takes whatever value the lane arithmetic leaves in it and must
+/// never be observed, so that results match the generic backend.
+#[derive(Copy, Clone)]
 pub struct V3 {
     inner: Fx4,
 }
@@ -55,7 +58,7 @@ impl V3 {
     #[inline(always)]
     pub fn fill(v: F) -> Self {
         Self {
-            inner: Fx4::splat(v),
+            inner: Fx4::from_array([v, v, v, 1.0]),
         }
     }
 
@@ -123,7 +126,7 @@ impl V3 {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub struct V4 {
     inner: Fx4,
 }
@@ -274,6 +277,42 @@ impl Neg for V3 {
     }
 }
 
+impl PartialEq for V3 {
+    fn eq(&self, other: &Self) -> bool {
+        self.x() == other.x() && self.y() == other.y() && self.z() == other.z()
+    }
+}
+
+impl fmt::Debug for V2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V2")
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .finish()
+    }
+}
+
+impl fmt::Debug for V3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V3")
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .field("z", &self.z())
+            .finish()
+    }
+}
+
+impl fmt::Debug for V4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("V4")
+            .field("x", &self.x())
+            .field("y", &self.y())
+            .field("z", &self.z())
+            .field("w", &self.w())
+            .finish()
+    }
+}
+
 impl From<[F; 4]> for V4 {
     fn from(other: [F; 4]) -> Self {
         Self::new(other[0], other[1], other[2], other[3])
@@ -308,6 +347,15 @@ impl M4 {
         }
     }
 
+    pub fn columns(self) -> [V4; 4] {
+        [
+            V4 { inner: self.c0 },
+            V4 { inner: self.c1 },
+            V4 { inner: self.c2 },
+            V4 { inner: self.c3 },
+        ]
+    }
+
     pub fn transpose(self) -> Self {
         let a0: [F; 4] = self.c0.into();
         let a1: [F; 4] = self.c1.into();
@@ -342,6 +390,21 @@ impl M4 {
     }
 }
 
+impl Mul<V4> for M4 {
+    type Output = V4;
+
+    fn mul(self, rhs: V4) -> Self::Output {
+        let vx = self.c0 * Fx4::splat(rhs.x());
+        let vy = self.c1 * Fx4::splat(rhs.y());
+        let vz = self.c2 * Fx4::splat(rhs.z());
+        let vw = self.c3 * Fx4::splat(rhs.w());
+
+        V4 {
+            inner: vx + vy + vz + vw,
+        }
+    }
+}
+
 impl Mul for M4 {
     type Output = Self;
 
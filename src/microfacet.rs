@@ -0,0 +1,245 @@
+//! The GGX microfacet distribution shared by the rough materials. A rough surface is treated as
+//! many tiny mirrors whose normals follow the distribution, with directions given in a local
+//! `Frame` where the shading normal is +z.
+
+use crate::math::{V2, V3};
+
+/// The smallest alpha used, any smoother and the distribution is too sharp for `f32`.
+const MIN_ALPHA: f32 = 1e-3;
+
+/// An orthonormal basis around a shading normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Frame {
+    tangent: V3,
+    bitangent: V3,
+    normal: V3,
+}
+
+impl Frame {
+    pub fn new(normal: V3) -> Self {
+        let helper = if normal.x().abs() > 0.9 {
+            V3::new(0.0, 1.0, 0.0)
+        } else {
+            V3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = helper.cross(normal).unit();
+        let bitangent = normal.cross(tangent);
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    /// A basis around `normal` with its tangent along `tangent` where there is one, so that
+    /// anisotropic distributions line up with the surface's texture coordinates.
+    pub fn oriented(normal: V3, tangent: Option<V3>) -> Self {
+        let tangent = match tangent {
+            Some(tangent) => tangent - normal * normal.dot(tangent),
+            None => return Self::new(normal),
+        };
+        if tangent.near_zero() {
+            return Self::new(normal);
+        }
+
+        let tangent = tangent.unit();
+        Self {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, direction: V3) -> V3 {
+        V3::new(
+            direction.dot(self.tangent),
+            direction.dot(self.bitangent),
+            direction.dot(self.normal),
+        )
+    }
+
+    pub fn to_world(&self, direction: V3) -> V3 {
+        self.tangent * direction.x() + self.bitangent * direction.y() + self.normal * direction.z()
+    }
+}
+
+/// The GGX, or Trowbridge-Reitz, distribution of microfacet normals.
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl Ggx {
+    /// A distribution from a perceptual `roughness` between zero and one, squared to get alpha
+    /// so that it looks roughly linear.
+    pub fn new(roughness: f32) -> Self {
+        let alpha = (roughness * roughness).max(MIN_ALPHA);
+        Self {
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+
+    /// A distribution stretched to be rougher along the tangent or the bitangent of its `Frame`,
+    /// as on brushed metal.
+    pub fn anisotropic(roughness_x: f32, roughness_y: f32) -> Self {
+        Self {
+            alpha_x: (roughness_x * roughness_x).max(MIN_ALPHA),
+            alpha_y: (roughness_y * roughness_y).max(MIN_ALPHA),
+        }
+    }
+
+    /// The density of microfacet normal `m`.
+    pub fn d(&self, m: V3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let x = m.x() / self.alpha_x;
+        let y = m.y() / self.alpha_y;
+        let t = x * x + y * y + m.z() * m.z();
+        1.0 / (std::f32::consts::PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    fn lambda(&self, w: V3) -> f32 {
+        let cos_squared = w.z() * w.z();
+        if cos_squared == 0.0 {
+            return f32::INFINITY;
+        }
+
+        let x = w.x() * self.alpha_x;
+        let y = w.y() * self.alpha_y;
+        let tan_squared = (x * x + y * y) / cos_squared;
+        ((1.0 + tan_squared).sqrt() - 1.0) / 2.0
+    }
+
+    /// The fraction of the microfacets facing `w` that aren't hidden by others.
+    pub fn g1(&self, w: V3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// The fraction of the microfacets visible from both `wo` and `wi`.
+    pub fn g(&self, wo: V3, wi: V3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Picks a microfacet normal in proportion to how much of it `wo` sees, using the uniform
+    /// numbers in `u`, following Heitz's "Sampling the GGX Distribution of Visible Normals".
+    pub fn sample_visible(&self, wo: V3, u: V2) -> V3 {
+        let stretched = V3::new(wo.x() * self.alpha_x, wo.y() * self.alpha_y, wo.z()).unit();
+        let length_squared = stretched.x() * stretched.x() + stretched.y() * stretched.y();
+        let t1 = if length_squared > 0.0 {
+            V3::new(-stretched.y(), stretched.x(), 0.0) / length_squared.sqrt()
+        } else {
+            V3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = stretched.cross(t1);
+
+        let radius = u.x().sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u.y();
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + stretched.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * radius * phi.sin();
+        let normal = t1 * p1 + t2 * p2 + stretched * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        V3::new(
+            normal.x() * self.alpha_x,
+            normal.y() * self.alpha_y,
+            normal.z().max(1e-6),
+        )
+        .unit()
+    }
+
+    /// The density `sample_visible` picks microfacet normal `m` with, seen from `wo`.
+    pub fn visible_pdf(&self, wo: V3, m: V3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z()
+    }
+
+    /// Picks a direction for light to arrive from that reflects off a visible microfacet
+    /// towards `wo`, `None` if it would come from below the surface.
+    pub fn sample_reflection(&self, wo: V3, u: V2) -> Option<V3> {
+        let m = self.sample_visible(wo, u);
+        let wi = reflect(wo, m);
+        if wi.z() <= 0.0 {
+            None
+        } else {
+            Some(wi)
+        }
+    }
+
+    /// The light reflected from `wi` towards `wo` off the microfacets between them, including
+    /// the cosine but without the Fresnel term, followed by the density `sample_reflection`
+    /// picks `wi` with and the microfacet normal. Fresnel depends on the angle between `wo` and
+    /// that normal.
+    pub fn reflection(&self, wo: V3, wi: V3) -> Option<(f32, f32, V3)> {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+
+        let m = (wo + wi).unit();
+        let d = self.d(m);
+        let value = d * self.g(wo, wi) / (4.0 * wo.z());
+        let pdf = self.visible_pdf(wo, m) / (4.0 * wo.dot(m).abs());
+        Some((value, pdf, m))
+    }
+}
+
+/// `w` mirrored about unit normal `m`, both pointing away from the surface.
+pub fn reflect(w: V3, m: V3) -> V3 {
+    m * (2.0 * w.dot(m)) - w
+}
+
+/// `w` bent through unit normal `m` into a material, pointing away from the surface on the
+/// other side, where `refraction_ratio` is the index `w` is in over the index it enters.
+/// `None` past the critical angle.
+pub fn refract(w: V3, m: V3, refraction_ratio: f32) -> Option<V3> {
+    let cos_i = w.dot(m);
+    let sin_squared = refraction_ratio * refraction_ratio * (1.0 - cos_i * cos_i).max(0.0);
+    if sin_squared >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin_squared).sqrt();
+    Some(m * (refraction_ratio * cos_i - cos_t) - w * refraction_ratio)
+}
+
+/// The Fresnel reflectance of a conductor with a complex refraction index of `eta` plus `k`
+/// times i for red, green and blue, lit at `cosine` to the normal.
+pub fn fresnel_conductor(cosine: f32, eta: V3, k: V3) -> V3 {
+    let cosine = cosine.clamp(0.0, 1.0);
+    let channel = |eta: f32, k: f32| {
+        let cos_squared = cosine * cosine;
+        let sin_squared = 1.0 - cos_squared;
+        let t0 = eta * eta - k * k - sin_squared;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+        let t1 = a2_plus_b2 + cos_squared;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cosine * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos_squared * a2_plus_b2 + sin_squared * sin_squared;
+        let t4 = t2 * sin_squared;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+
+    V3::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+/// Schlick's approximation of Fresnel reflectance for a surface reflecting `f0` head on.
+pub fn schlick(f0: V3, cosine: f32) -> V3 {
+    f0 + (V3::one() - f0) * schlick_weight(cosine)
+}
+
+/// How far Schlick's approximation moves from the head on reflectance towards one.
+pub fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
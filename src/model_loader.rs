@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::geom::{Acceleration, Displacement, Intersect, Mesh, Model, Triangle};
+use crate::material::Material;
+use crate::math::V3;
+use crate::obj_loader::{ObjLoader, SimpleTexturedBuilder};
+use crate::ply_loader::{PlyLoader, PlyVertex};
+use crate::stl_loader::StlLoader;
+use crate::texture::{Surface, WrapMode};
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    UnknownExtension(PathBuf),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnknownExtension(path) => {
+                write!(f, "no model loader for '{}'", path.display())
+            }
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+/// Loads a `Model` from a file, picking the loader from its extension.
+///
+/// OBJ files get their MTL materials through `SimpleTexturedBuilder`, PLY files keep their
+/// normals and uvs, and STL files are plain triangles. Models without materials of their own
+/// are given one with `Instance::with_material`. There is no glTF loader, `.gltf` and `.glb`
+/// files are an unknown extension like any other.
+pub struct ModelLoader {
+    path: PathBuf,
+    acceleration: Acceleration,
+    cached: bool,
+    wrapping: WrapMode,
+    displacement: Option<Displacement<Arc<dyn Surface>>>,
+}
+
+impl ModelLoader {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            acceleration: Acceleration::Bvh,
+            cached: false,
+            wrapping: WrapMode::Repeat,
+            displacement: None,
+        }
+    }
+
+    pub fn with_acceleration(mut self, acceleration: Acceleration) -> Self {
+        self.acceleration = acceleration;
+        self
+    }
+
+    /// Caches the BVH on disk keyed by the contents of the file, only BVH models are cached.
+    pub fn cached(mut self) -> Self {
+        self.cached = true;
+        self
+    }
+
+    /// How textures referenced by OBJ materials wrap.
+    pub fn with_wrapping(mut self, wrapping: WrapMode) -> Self {
+        self.wrapping = wrapping;
+        self
+    }
+
+    /// Subdivides and displaces every triangle with texture coordinates as it is loaded.
+    /// Displaced models aren't cached, the cache only knows the contents of the model file.
+    pub fn with_displacement(mut self, displacement: Displacement<Arc<dyn Surface>>) -> Self {
+        self.displacement = Some(displacement);
+        self
+    }
+
+    pub fn load(self) -> Result<Model<()>, Box<dyn std::error::Error>> {
+        let extension = self
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("obj") => {
+                let mut builder = SimpleTexturedBuilder::new(self.wrapping);
+                let triangles = ObjLoader::load(&self.path, &mut builder)?;
+                Ok(self
+                    .build(triangles)
+                    .with_materials(builder.into_materials()))
+            }
+            Some("ply") => self.load_ply(),
+            Some("stl") => {
+                let triangles = StlLoader::load_binary(&self.path, V3::new, |a, b, c| {
+                    Triangle::new((), a, b, c)
+                })?;
+                Ok(self.build(triangles))
+            }
+            _ => Err(Error::UnknownExtension(self.path.clone()))?,
+        }
+    }
+
+    fn load_ply(self) -> Result<Model<()>, Box<dyn std::error::Error>> {
+        let vertex = |v: PlyVertex| (v.position, v.normal, v.uv);
+        #[cfg(feature = "mmap")]
+        let (vertices, faces) = PlyLoader::load_indexed_mapped(&self.path, vertex)?;
+        #[cfg(not(feature = "mmap"))]
+        let (vertices, faces) = PlyLoader::load_indexed_with(&self.path, vertex)?;
+
+        let mut positions = Vec::with_capacity(vertices.len());
+        let mut normals = Vec::with_capacity(vertices.len());
+        let mut uvs = Vec::with_capacity(vertices.len());
+        for (position, normal, uv) in vertices {
+            positions.push(position);
+            normals.push(normal);
+            uvs.push(uv);
+        }
+
+        let normals = normals.into_iter().collect::<Option<Vec<_>>>();
+        let uvs = uvs.into_iter().collect::<Option<Vec<_>>>();
+
+        // Displacement splits faces apart, so the shared vertices become separate triangles
+        if let (Some(_), Some(uvs)) = (self.displacement.as_ref(), uvs.as_ref()) {
+            let triangles = faces.iter().map(|&[a, b, c]| {
+                let face_normal = (positions[b as usize] - positions[a as usize])
+                    .cross(positions[c as usize] - positions[a as usize])
+                    .unit();
+                let corner = |index: u32| {
+                    let index = index as usize;
+                    let normal = normals.as_ref().map_or(face_normal, |n| n[index]);
+                    (positions[index], normal, uvs[index])
+                };
+                Triangle::with_norms_and_uvs((), corner(a), corner(b), corner(c))
+            });
+            return Ok(self.build(triangles.collect()));
+        }
+
+        let mut mesh = Mesh::new((), positions, faces);
+        if let Some(normals) = normals {
+            mesh = mesh.with_normals(normals);
+        }
+        if let Some(uvs) = uvs {
+            mesh = mesh.with_uvs(uvs);
+        }
+
+        let model = match self.acceleration {
+            Acceleration::Bvh if self.cached => Model::from_mesh_cached(&self.path, mesh),
+            Acceleration::Bvh => Model::from_mesh(mesh),
+            acceleration => {
+                let mesh = Arc::new(mesh);
+                let triangles = mesh
+                    .triangles()
+                    .map(|t| Box::new(t) as Box<dyn Intersect>)
+                    .collect();
+                Model::from_objects(acceleration, triangles)
+            }
+        };
+
+        Ok(model)
+    }
+
+    fn build<M: 'static + Material + Clone>(&self, triangles: Vec<Triangle<M>>) -> Model<()> {
+        let triangles = match self.displacement.as_ref() {
+            Some(displacement) => triangles
+                .iter()
+                .flat_map(|triangle| displacement.displace(triangle))
+                .collect(),
+            None => triangles,
+        };
+
+        if self.cached && self.displacement.is_none() && self.acceleration == Acceleration::Bvh {
+            Model::new_cached(&self.path, triangles)
+        } else {
+            Model::new_with(self.acceleration, triangles)
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::geom::Intersect;
+use crate::material::{Approximation, Material};
+use crate::math::V3;
+
+/// Writes `objects` to an OBJ file at `path` with every instance baked into world space, and an
+/// MTL file next to it approximating each distinct material. Each object becomes its own OBJ
+/// object so it can be selected separately after importing.
+///
+/// Vertices are not shared between triangles, the export is meant for layout checks rather
+/// than as a compact interchange format.
+pub fn export<'a, P: AsRef<Path>, O: IntoIterator<Item = &'a dyn Intersect>>(
+    path: P,
+    objects: O,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let mtl_path = path.with_extension("mtl");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    if let Some(mtl_name) = mtl_path.file_name() {
+        writeln!(obj, "mtllib {}", mtl_name.to_string_lossy())?;
+    }
+
+    let mut materials = Materials::default();
+    let mut vertex_count = 0;
+    for (index, object) in objects.into_iter().enumerate() {
+        writeln!(obj, "o object_{}", index)?;
+
+        let mut current_material = None;
+        let mut result = Ok(());
+        object.visit_triangles(&mut |vertices, material| {
+            if result.is_err() {
+                return;
+            }
+
+            let material = materials.index(material);
+            result = write_triangle(
+                &mut obj,
+                vertices,
+                material,
+                &mut current_material,
+                &mut vertex_count,
+            );
+        });
+        result?;
+    }
+    obj.flush()?;
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    for (index, approximation) in materials.approximations.iter().enumerate() {
+        write_material(&mut mtl, index, approximation)?;
+    }
+    mtl.flush()?;
+
+    Ok(())
+}
+
+fn write_triangle<W: Write>(
+    obj: &mut W,
+    vertices: [V3; 3],
+    material: usize,
+    current_material: &mut Option<usize>,
+    vertex_count: &mut usize,
+) -> std::io::Result<()> {
+    if *current_material != Some(material) {
+        writeln!(obj, "usemtl material_{}", material)?;
+        *current_material = Some(material);
+    }
+
+    for v in vertices.iter() {
+        writeln!(obj, "v {} {} {}", v.x(), v.y(), v.z())?;
+    }
+    writeln!(
+        obj,
+        "f {} {} {}",
+        *vertex_count + 1,
+        *vertex_count + 2,
+        *vertex_count + 3
+    )?;
+    *vertex_count += 3;
+
+    Ok(())
+}
+
+/// Writes the approximation with the PBR extensions to MTL alongside the classic Phong terms,
+/// for importers that only read the latter.
+fn write_material<W: Write>(
+    mtl: &mut W,
+    index: usize,
+    approximation: &Approximation,
+) -> std::io::Result<()> {
+    let Approximation {
+        color,
+        metallic,
+        roughness,
+        emission,
+        refraction_index,
+    } = *approximation;
+    let specular = color * metallic + V3::fill(0.04) * (1.0 - metallic);
+    let shininess = (1.0 - roughness).powi(2) * 1000.0;
+
+    writeln!(mtl, "newmtl material_{}", index)?;
+    writeln!(mtl, "Kd {} {} {}", color.x(), color.y(), color.z())?;
+    writeln!(mtl, "Ks {} {} {}", specular.x(), specular.y(), specular.z())?;
+    writeln!(mtl, "Ns {}", shininess)?;
+    if emission != V3::zero() {
+        writeln!(mtl, "Ke {} {} {}", emission.x(), emission.y(), emission.z())?;
+    }
+    writeln!(mtl, "Pr {}", roughness)?;
+    writeln!(mtl, "Pm {}", metallic)?;
+    match refraction_index {
+        Some(refraction_index) => {
+            writeln!(mtl, "Ni {}", refraction_index)?;
+            writeln!(mtl, "illum 7")?;
+        }
+        None if metallic > 0.5 => writeln!(mtl, "illum 3")?,
+        None => writeln!(mtl, "illum 2")?,
+    }
+    writeln!(mtl)?;
+
+    Ok(())
+}
+
+/// Assigns each material an MTL index. Materials are first looked up by address, many
+/// triangles share one material, then by their approximation, since loaders such as the OBJ
+/// loader give each triangle its own handle to a shared material.
+#[derive(Default)]
+struct Materials {
+    by_address: HashMap<usize, usize>,
+    approximations: Vec<Approximation>,
+}
+
+impl Materials {
+    fn index(&mut self, material: &dyn Material) -> usize {
+        let address = material as *const dyn Material as *const () as usize;
+        if let Some(&index) = self.by_address.get(&address) {
+            return index;
+        }
+
+        let approximation = material.approximate();
+        let index = match self.approximations.iter().position(|a| *a == approximation) {
+            Some(index) => index,
+            None => {
+                self.approximations.push(approximation);
+                self.approximations.len() - 1
+            }
+        };
+        self.by_address.insert(address, index);
+
+        index
+    }
+}
@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::geom::Triangle;
-use crate::material::Lambertian;
+use crate::material::{Bump, Emissive, Lambertian, Material, MaterialIndex};
 use crate::math::{V2, V3};
 use crate::texture::{SharedTexture, SolidColor, Surface, Texture, WrapMode};
 
@@ -30,7 +30,15 @@ pub trait ObjBuilder {
         true
     }
     fn load_materials(&mut self, _context: &ObjContext) {}
-    fn build_vertex(&mut self, context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Vertex;
+    /// `color` is set when the vertex uses the `v x y z r g b` extension.
+    fn build_vertex(
+        &mut self,
+        context: &ObjContext,
+        x: f32,
+        y: f32,
+        z: f32,
+        color: Option<V3>,
+    ) -> Self::Vertex;
     fn build_normal(&mut self, context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Normal;
     fn build_uv(&mut self, context: &ObjContext, x: f32, y: f32) -> Self::Texture;
     fn build_face(
@@ -42,6 +50,46 @@ pub trait ObjBuilder {
     ) -> Result<Self::Face, Self::Error>;
 }
 
+/// Lets a builder be lent to the loader and read back afterwards.
+impl<B: ObjBuilder + ?Sized> ObjBuilder for &mut B {
+    type Vertex = B::Vertex;
+    type Normal = B::Normal;
+    type Texture = B::Texture;
+    type Face = B::Face;
+    type Error = B::Error;
+    fn include_group(&mut self, context: &ObjContext) -> bool {
+        B::include_group(self, context)
+    }
+    fn load_materials(&mut self, context: &ObjContext) {
+        B::load_materials(self, context)
+    }
+    fn build_vertex(
+        &mut self,
+        context: &ObjContext,
+        x: f32,
+        y: f32,
+        z: f32,
+        color: Option<V3>,
+    ) -> Self::Vertex {
+        B::build_vertex(self, context, x, y, z, color)
+    }
+    fn build_normal(&mut self, context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Normal {
+        B::build_normal(self, context, x, y, z)
+    }
+    fn build_uv(&mut self, context: &ObjContext, x: f32, y: f32) -> Self::Texture {
+        B::build_uv(self, context, x, y)
+    }
+    fn build_face(
+        &mut self,
+        context: &ObjContext,
+        face_a: (Self::Vertex, Self::Normal, Self::Texture),
+        face_b: (Self::Vertex, Self::Normal, Self::Texture),
+        face_c: (Self::Vertex, Self::Normal, Self::Texture),
+    ) -> Result<Self::Face, Self::Error> {
+        B::build_face(self, context, face_a, face_b, face_c)
+    }
+}
+
 pub fn obj_fns<V, N, UV, F, FV, FN, FUV, FF, OF>(
     vertex_fn: FV,
     normal_fn: FN,
@@ -127,7 +175,14 @@ where
     type Face = F;
     type Error = std::convert::Infallible;
 
-    fn build_vertex(&mut self, _context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Vertex {
+    fn build_vertex(
+        &mut self,
+        _context: &ObjContext,
+        x: f32,
+        y: f32,
+        z: f32,
+        _color: Option<V3>,
+    ) -> Self::Vertex {
         (self.vertex_fn)(x, y, z)
     }
 
@@ -160,8 +215,15 @@ where
 pub struct SimpleTexturedBuilder {
     textures: HashMap<String, SharedTexture>,
     diffuse: HashMap<String, V3>,
+    /// Emission from `Ke` colors and `map_Ke` textures.
+    emission: HashMap<String, Arc<dyn Surface>>,
+    /// Height maps from `bump` and `map_bump`, with their `-bm` multiplier.
+    bumps: HashMap<String, (SharedTexture, f32)>,
+    material_indexes: HashMap<String, u32>,
+    materials: Vec<Box<dyn Material>>,
     filtered_groups: HashSet<String>,
     wrapping: WrapMode,
+    mipmaps: bool,
 }
 
 impl SimpleTexturedBuilder {
@@ -169,8 +231,13 @@ impl SimpleTexturedBuilder {
         SimpleTexturedBuilder {
             textures: HashMap::new(),
             diffuse: HashMap::new(),
+            emission: HashMap::new(),
+            bumps: HashMap::new(),
+            material_indexes: HashMap::new(),
+            materials: Vec::new(),
             filtered_groups: HashSet::new(),
             wrapping,
+            mipmaps: false,
         }
     }
 
@@ -183,8 +250,33 @@ impl SimpleTexturedBuilder {
         SimpleTexturedBuilder {
             textures: HashMap::new(),
             diffuse: HashMap::new(),
+            emission: HashMap::new(),
+            bumps: HashMap::new(),
+            material_indexes: HashMap::new(),
+            materials: Vec::new(),
             filtered_groups,
             wrapping,
+            mipmaps: false,
+        }
+    }
+
+    /// Builds mip pyramids for the material library's textures.
+    pub fn with_mipmaps(mut self) -> Self {
+        self.mipmaps = true;
+        self
+    }
+
+    /// The materials indexed by the faces built so far, for `Model::with_materials`.
+    pub fn into_materials(self) -> Vec<Box<dyn Material>> {
+        self.materials
+    }
+
+    fn load_texture(&self, path: PathBuf) -> Result<SharedTexture, Box<dyn std::error::Error>> {
+        let texture = Texture::load(path, self.wrapping)?;
+        if self.mipmaps {
+            Ok(texture.with_mipmaps().shared())
+        } else {
+            Ok(texture.shared())
         }
     }
 
@@ -217,12 +309,55 @@ impl SimpleTexturedBuilder {
                         }
                     }
                 }
+                Some("Ke") => {
+                    if let Some(current_material) = current_material.as_ref() {
+                        let x = parts.get(1).and_then(|n| n.parse::<f32>().ok());
+                        let y = parts.get(2).and_then(|n| n.parse::<f32>().ok());
+                        let z = parts.get(3).and_then(|n| n.parse::<f32>().ok());
+                        if let (Some(x), Some(y), Some(z)) = (x, y, z) {
+                            let emission = V3::new(x, y, z);
+                            // Exporters write a black Ke for every material that doesn't glow
+                            if !emission.near_zero()
+                                && !self.emission.contains_key(current_material)
+                            {
+                                self.emission.insert(
+                                    current_material.clone(),
+                                    Arc::new(SolidColor(emission.expand(1.0))),
+                                );
+                            }
+                        }
+                    }
+                }
+                Some("map_Ke") => {
+                    if let (Some(texture_file), Some(current_material)) =
+                        (parts.get(1), current_material.as_ref())
+                    {
+                        let texture = self.load_texture(path.with_file_name(texture_file))?;
+                        self.emission.insert(current_material.clone(), texture);
+                    }
+                }
+                Some("bump") | Some("map_bump") | Some("map_Bump") => {
+                    // Options come before the file name, only the bump multiplier is used
+                    let strength = parts
+                        .iter()
+                        .position(|&p| p == "-bm")
+                        .and_then(|i| parts.get(i + 1))
+                        .and_then(|n| n.parse::<f32>().ok())
+                        .unwrap_or(1.0);
+                    if let (Some(texture_file), Some(current_material)) = (
+                        parts.last().filter(|_| parts.len() > 1),
+                        current_material.as_ref(),
+                    ) {
+                        let texture = self.load_texture(path.with_file_name(texture_file))?;
+                        self.bumps
+                            .insert(current_material.clone(), (texture, strength));
+                    }
+                }
                 Some("map_Kd") => {
                     if let (Some(texture_file), Some(current_material)) =
                         (parts.get(1), current_material.as_ref())
                     {
-                        let texture_path = path.with_file_name(texture_file);
-                        let texture = Texture::load_png(texture_path, self.wrapping)?.shared();
+                        let texture = self.load_texture(path.with_file_name(texture_file))?;
                         self.textures.insert(current_material.clone(), texture);
                     }
                 }
@@ -232,6 +367,38 @@ impl SimpleTexturedBuilder {
 
         Ok(())
     }
+
+    /// Adds the materials of the libraries loaded so far, materials that already have an index
+    /// keep it so faces built before another library was loaded still point at them.
+    fn build_material_table(&mut self) {
+        let names: HashSet<&String> = self
+            .textures
+            .keys()
+            .chain(self.diffuse.keys())
+            .chain(self.emission.keys())
+            .chain(self.bumps.keys())
+            .filter(|name| !self.material_indexes.contains_key(*name))
+            .collect();
+
+        for name in names {
+            let surface: Arc<dyn Surface> = match (self.textures.get(name), self.diffuse.get(name))
+            {
+                (Some(texture), _) => texture.clone(),
+                (None, Some(diffuse)) => Arc::new(SolidColor(diffuse.expand(1.0))),
+                (None, None) => Arc::new(SolidColor(V3::fill(0.8).expand(1.0))),
+            };
+            let mut material: Box<dyn Material> = Box::new(Lambertian::new(surface));
+            if let Some(emission) = self.emission.get(name) {
+                material = Box::new(Emissive::new(material, emission.clone()));
+            }
+            if let Some((height, strength)) = self.bumps.get(name) {
+                material = Box::new(Bump::new(material, height.clone()).with_strength(*strength));
+            }
+            self.material_indexes
+                .insert(name.clone(), self.materials.len() as u32);
+            self.materials.push(material);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -248,10 +415,10 @@ impl std::fmt::Display for SimpleTexturedBuilderError {
 impl std::error::Error for SimpleTexturedBuilderError {}
 
 impl ObjBuilder for SimpleTexturedBuilder {
-    type Vertex = V3;
+    type Vertex = (V3, Option<V3>);
     type Normal = V3;
     type Texture = V2;
-    type Face = Triangle<Lambertian<Arc<dyn Surface>>>;
+    type Face = Triangle<MaterialIndex>;
     type Error = SimpleTexturedBuilderError;
 
     fn load_materials(&mut self, context: &ObjContext) {
@@ -260,11 +427,19 @@ impl ObjBuilder for SimpleTexturedBuilder {
                 Err(e) => eprintln!("unable to load material library: {} {:?}", e, e),
                 _ => (),
             }
+            self.build_material_table();
         }
     }
 
-    fn build_vertex(&mut self, _context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Vertex {
-        V3::new(x, y, z)
+    fn build_vertex(
+        &mut self,
+        _context: &ObjContext,
+        x: f32,
+        y: f32,
+        z: f32,
+        color: Option<V3>,
+    ) -> Self::Vertex {
+        (V3::new(x, y, z), color)
     }
 
     fn build_normal(&mut self, _context: &ObjContext, x: f32, y: f32, z: f32) -> Self::Normal {
@@ -282,17 +457,26 @@ impl ObjBuilder for SimpleTexturedBuilder {
         face_b: (Self::Vertex, Self::Normal, Self::Texture),
         face_c: (Self::Vertex, Self::Normal, Self::Texture),
     ) -> Result<Self::Face, Self::Error> {
-        if let Some(texture) = context.material().and_then(|m| self.textures.get(m)) {
-            let material: Lambertian<Arc<dyn Surface>> = Lambertian::new(texture.clone());
-            Ok(Triangle::with_norms_and_uvs(
-                material, face_a, face_b, face_c,
-            ))
-        } else if let Some(diffuse) = context.material().and_then(|m| self.diffuse.get(m)) {
-            let color = SolidColor(diffuse.expand(1.0));
-            let material: Lambertian<Arc<dyn Surface>> = Lambertian::new(Arc::new(color));
-            Ok(Triangle::with_norms_and_uvs(
-                material, face_a, face_b, face_c,
-            ))
+        if let Some(&index) = context
+            .material()
+            .and_then(|m| self.material_indexes.get(m))
+        {
+            let material = MaterialIndex(index);
+            let ((vertex_a, color_a), normal_a, uv_a) = face_a;
+            let ((vertex_b, color_b), normal_b, uv_b) = face_b;
+            let ((vertex_c, color_c), normal_c, uv_c) = face_c;
+            let triangle = Triangle::with_norms_and_uvs(
+                material,
+                (vertex_a, normal_a, uv_a),
+                (vertex_b, normal_b, uv_b),
+                (vertex_c, normal_c, uv_c),
+            );
+
+            if let (Some(a), Some(b), Some(c)) = (color_a, color_b, color_c) {
+                Ok(triangle.with_colors(a, b, c))
+            } else {
+                Ok(triangle)
+            }
         } else {
             Err(SimpleTexturedBuilderError::NoMaterialForFace)
         }
@@ -307,7 +491,7 @@ impl ObjBuilder for SimpleTexturedBuilder {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct ObjContext {
     group_name: Option<String>,
     material_name: Option<String>,
@@ -328,7 +512,20 @@ impl ObjContext {
 
 pub struct ObjLoader;
 
+/// A face corner as zero based indices into the position, uv and normal lists.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct PendingFace {
+    context: usize,
+    smoothing_group: u32,
+    corners: [Corner; 3],
+}
+
 impl ObjLoader {
+    /// Loads the faces of an OBJ file through `builder`. Corners without a `vn` are given
+    /// area weighted normals averaged over the faces sharing their vertex within the same `s`
+    /// smoothing group, with `s off` faces kept flat. Files without any `s` records are smoothed
+    /// as a single group. Corners without a `vt` get a uv of zero.
     pub fn load<P: AsRef<Path>, B: ObjBuilder>(
         path: P,
         mut builder: B,
@@ -336,14 +533,18 @@ impl ObjLoader {
         let path = path.as_ref();
         let mut file = BufReader::new(File::open(path)?);
 
+        let mut positions = Vec::new();
         let mut vertexes = Vec::new();
         let mut normals = Vec::new();
         let mut uvs = Vec::new();
-        let mut faces = Vec::new();
+        let mut pending = Vec::new();
 
         let mut line = String::new();
 
         let mut context = ObjContext::default();
+        let mut contexts: Vec<ObjContext> = Vec::new();
+        let mut context_changed = true;
+        let mut smoothing_group = 1;
 
         let mut include_faces = builder.include_group(&context);
         loop {
@@ -361,9 +562,18 @@ impl ObjLoader {
                     let y = parts.get(2).and_then(|n| n.parse().ok());
                     let z = parts.get(3).and_then(|n| n.parse().ok());
 
+                    let r = parts.get(4).and_then(|n| n.parse().ok());
+                    let g = parts.get(5).and_then(|n| n.parse().ok());
+                    let b = parts.get(6).and_then(|n| n.parse().ok());
+                    let color = match (r, g, b) {
+                        (Some(r), Some(g), Some(b)) => Some(V3::new(r, g, b)),
+                        _ => None,
+                    };
+
                     if let (Some(x), Some(y), Some(z)) = (x, y, z) {
-                        let vert = builder.build_vertex(&context, x, y, z);
+                        let vert = builder.build_vertex(&context, x, y, z, color);
                         vertexes.push(vert);
+                        positions.push(V3::new(x, y, z));
                     } else {
                         return Err(format!("unable to parse vertex: {}", line))?;
                     }
@@ -395,59 +605,127 @@ impl ObjLoader {
                     if !include_faces {
                         continue;
                     }
-                    let read_face = |s: Option<&&str>| {
-                        s.and_then(|s| {
-                            if s.contains("//") {
-                                let mut splits =
-                                    s.split('/').filter_map(|n| n.parse::<usize>().ok());
-                                splits
-                                    .next()
-                                    .and_then(|vi| vertexes.get(vi - 1))
-                                    .zip(uvs.get(0))
-                                    .zip(splits.next().and_then(|ni| normals.get(ni - 1)))
-                                    .map(|((v, uv), n)| (*v, *n, *uv))
-                            } else {
-                                let mut splits =
-                                    s.split('/').filter_map(|n| n.parse::<usize>().ok());
-                                splits
-                                    .next()
-                                    .and_then(|vi| vertexes.get(vi - 1))
-                                    .zip(splits.next().and_then(|uvi| uvs.get(uvi - 1)))
-                                    .zip(splits.next().and_then(|ni| normals.get(ni - 1)))
-                                    .map(|((v, uv), n)| (*v, *n, *uv))
+                    let read_corner = |s: &str| -> Option<Corner> {
+                        let mut splits = s.split('/');
+                        let mut index = |len: usize| -> Option<Option<usize>> {
+                            match splits.next() {
+                                None | Some("") => Some(None),
+                                Some(n) => n
+                                    .parse::<usize>()
+                                    .ok()
+                                    .filter(|&n| n >= 1 && n <= len)
+                                    .map(|n| Some(n - 1)),
                             }
-                        })
+                        };
+                        let vertex = index(vertexes.len())??;
+                        let uv = index(uvs.len())?;
+                        let normal = index(normals.len())?;
+                        Some((vertex, uv, normal))
                     };
-                    let a = read_face(parts.get(1));
-                    let b = read_face(parts.get(2));
-                    let c = read_face(parts.get(3));
-                    if let (Some(a), Some(b), Some(c)) = (a, b, c) {
-                        let face = builder.build_face(&context, a, b, c)?;
-                        faces.push(face);
-                    } else {
-                        return Err(format!("unable to parse face: {}", line))?;
+                    let corners: Option<Vec<_>> =
+                        parts[1..].iter().map(|s| read_corner(s)).collect();
+                    let corners = match corners {
+                        Some(corners) if corners.len() >= 3 => corners,
+                        _ => return Err(format!("unable to parse face: {}", line))?,
+                    };
+
+                    if context_changed {
+                        contexts.push(context.clone());
+                        context_changed = false;
+                    }
+
+                    // Quads and n-gons are split into a fan around the first corner, which is
+                    // exact for the convex polygons exporters produce
+                    for i in 1..corners.len() - 1 {
+                        pending.push(PendingFace {
+                            context: contexts.len() - 1,
+                            smoothing_group,
+                            corners: [corners[0], corners[i], corners[i + 1]],
+                        });
                     }
                 }
+                Some("s") => match parts.get(1).map(|s| *s) {
+                    Some("off") => smoothing_group = 0,
+                    Some(group) => smoothing_group = group.parse().unwrap_or(0),
+                    None => (),
+                },
                 Some("o") | Some("g") => {
                     if let Some(group_name) = parts.get(1) {
                         context.group_name = Some(group_name.to_string());
+                        context_changed = true;
                         include_faces = builder.include_group(&context);
                     }
                 }
                 Some("usemtl") => {
                     if let Some(material_name) = parts.get(1) {
                         context.material_name = Some(material_name.to_string());
+                        context_changed = true;
                     }
                 }
                 Some("mtllib") => {
                     let material_file = parts[1..].join(" ");
                     context.material_library = Some(path.with_file_name(material_file));
+                    context_changed = true;
                     builder.load_materials(&context);
                 }
                 _ => (),
             }
         }
 
+        let face_normal = |face: &PendingFace| {
+            let [(a, ..), (b, ..), (c, ..)] = face.corners;
+            (positions[b] - positions[a]).cross(positions[c] - positions[a])
+        };
+
+        // The unnormalized cross product is proportional to the face area, so summing them
+        // weights each face's contribution by its size
+        let mut smooth_normals = HashMap::new();
+        for face in pending.iter() {
+            if face.smoothing_group == 0 || face.corners.iter().all(|c| c.2.is_some()) {
+                continue;
+            }
+            let normal = face_normal(face);
+            for &(vertex, ..) in face.corners.iter() {
+                *smooth_normals
+                    .entry((vertex, face.smoothing_group))
+                    .or_insert(V3::zero()) += normal;
+            }
+        }
+
+        let mut faces = Vec::with_capacity(pending.len());
+        for face in pending.iter() {
+            let context = &contexts[face.context];
+            let flat = face_normal(face);
+            let mut corner = |(vertex, uv, normal): Corner| {
+                let normal = match normal {
+                    Some(normal) => normals[normal],
+                    None => {
+                        let smooth = smooth_normals
+                            .get(&(vertex, face.smoothing_group))
+                            .copied()
+                            .unwrap_or(flat);
+                        let n = if smooth.length() > 0.0 {
+                            smooth.unit()
+                        } else if flat.length() > 0.0 {
+                            flat.unit()
+                        } else {
+                            V3::new(0.0, 1.0, 0.0)
+                        };
+                        builder.build_normal(context, n.x(), n.y(), n.z())
+                    }
+                };
+                let uv = match uv {
+                    Some(uv) => uvs[uv],
+                    None => builder.build_uv(context, 0.0, 0.0),
+                };
+                (vertexes[vertex], normal, uv)
+            };
+
+            let [a, b, c] = face.corners;
+            let (a, b, c) = (corner(a), corner(b), corner(c));
+            faces.push(builder.build_face(context, a, b, c)?);
+        }
+
         Ok(faces)
     }
 }
@@ -0,0 +1,206 @@
+use crate::math::V2;
+use crate::texture::{Surface, Texture};
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameInfo {
+    pub scene: String,
+    pub frame: u32,
+    pub samples: u32,
+}
+
+pub struct Watermark {
+    texture: Texture,
+    opacity: f32,
+}
+
+#[derive(Default)]
+pub struct Overlay {
+    burn_in: Option<u32>,
+    watermark: Option<Watermark>,
+}
+
+impl Overlay {
+    pub fn with_burn_in(mut self, scale: u32) -> Self {
+        self.burn_in = Some(scale.max(1));
+        self
+    }
+
+    pub fn with_watermark(mut self, texture: Texture, opacity: f32) -> Self {
+        self.watermark = Some(Watermark {
+            texture,
+            opacity: opacity.min(1.0).max(0.0),
+        });
+        self
+    }
+
+    /// Composites the overlay into top-down rgb8 `pixels`.
+    pub fn apply(&self, pixels: &mut [u8], width: u32, height: u32, info: &FrameInfo) {
+        if let Some(watermark) = self.watermark.as_ref() {
+            watermark.apply(pixels, width, height);
+        }
+
+        if let Some(scale) = self.burn_in {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_else(|e| e.duration())
+                .as_secs();
+
+            let lines = [
+                info.scene.clone(),
+                format!("FRAME {:05}", info.frame),
+                format!("{} SPP", info.samples),
+                format_date(now),
+            ];
+
+            let line_height = (GLYPH_HEIGHT + 3) * scale;
+            for (row, line) in lines.iter().enumerate() {
+                let x = 2 * scale;
+                let y = 2 * scale + row as u32 * line_height;
+                draw_text(pixels, width, height, x + scale, y + scale, scale, line, 0);
+                draw_text(pixels, width, height, x, y, scale, line, 255);
+            }
+        }
+    }
+}
+
+impl Watermark {
+    fn apply(&self, pixels: &mut [u8], width: u32, height: u32) {
+        let margin = 16;
+        let mark_width = self.texture.width().min(width);
+        let mark_height = self.texture.height().min(height);
+        let left = width.saturating_sub(mark_width + margin);
+        let top = height.saturating_sub(mark_height + margin);
+
+        for y in 0..mark_height {
+            for x in 0..mark_width {
+                let uv = V2::new(
+                    x as f32 / (mark_width - 1).max(1) as f32,
+                    y as f32 / (mark_height - 1).max(1) as f32,
+                );
+                let color = self.texture.get_f(uv);
+                let alpha = color.w() * self.opacity;
+                let color = color.contract() * 255.0;
+
+                let index = (((top + y) * width + (left + x)) * 3) as usize;
+                let blend = |dst: u8, src: f32| (dst as f32 * (1.0 - alpha) + src * alpha) as u8;
+                pixels[index + 0] = blend(pixels[index + 0], color.x());
+                pixels[index + 1] = blend(pixels[index + 1], color.y());
+                pixels[index + 2] = blend(pixels[index + 2], color.z());
+            }
+        }
+    }
+}
+
+fn draw_text(
+    pixels: &mut [u8],
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    scale: u32,
+    text: &str,
+    value: u8,
+) {
+    for (column, c) in text.chars().enumerate() {
+        let glyph = glyph(c);
+        let glyph_x = x + column as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (glyph_row, bits) in glyph.iter().enumerate() {
+            for glyph_column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - glyph_column)) == 0 {
+                    continue;
+                }
+
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        let px = glyph_x + glyph_column * scale + sx;
+                        let py = y + glyph_row as u32 * scale + sy;
+                        if px >= width || py >= height {
+                            continue;
+                        }
+
+                        let index = ((py * width + px) * 3) as usize;
+                        pixels[index + 0] = value;
+                        pixels[index + 1] = value;
+                        pixels[index + 2] = value;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn format_date(unix_seconds: u64) -> String {
+    let days = (unix_seconds / 86400) as i64;
+    let seconds = unix_seconds % 86400;
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds / 60) % 60
+    )
+}
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+/// Spaces work out to a fixed rate. Deadlines that are missed are dropped rather than caught up
+/// on, so a slow frame is followed by a normal one instead of a burst.
+pub struct Pacer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl Pacer {
+    /// Paces to `fps` frames a second, the first is due one frame from now.
+    pub fn new(fps: u32) -> Self {
+        let interval = Duration::from_secs(1) / fps.max(1);
+        Self {
+            interval,
+            next: Instant::now() + interval,
+        }
+    }
+
+    /// When the next frame is due.
+    pub fn deadline(&self) -> Instant {
+        self.next
+    }
+
+    /// Whether the next frame is due, starting it if so.
+    pub fn tick(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next {
+            return false;
+        }
+
+        self.advance(now);
+        true
+    }
+
+    /// Sleeps until the next frame is due and starts it.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        if now < self.next {
+            std::thread::sleep(self.next - now);
+        }
+
+        self.advance(Instant::now());
+    }
+
+    fn advance(&mut self, now: Instant) {
+        self.next += self.interval;
+        if self.next < now {
+            self.next = now + self.interval;
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use image::io::Reader;
+use image::ImageFormat;
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+
+use crate::material::Background;
+use crate::math::{V2, V4};
+use crate::texture::{bilinear, Surface, Texture, WrapMode};
+use crate::world::{Camera, World};
+
+const PAGE_DIR: &str = "./cache/pages";
+const TILE_SIZE: u32 = 64;
+const PREFETCH_GRID: u32 = 16;
+
+static BUDGET: AtomicUsize = AtomicUsize::new(usize::MAX);
+static RESIDENT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_PAGE_FILE: AtomicU64 = AtomicU64::new(0);
+static CLOCK: Mutex<VecDeque<(Weak<PageFile>, usize)>> = Mutex::new(VecDeque::new());
+
+/// Limits the memory held by textures loaded through `load_png`. Textures that no longer fit
+/// are written to disk in tiles and read back on demand, evicting the least recently used tiles
+/// to stay under the budget.
+///
+/// Memory mapped meshes are already file backed and paged by the OS, so they are not counted.
+pub fn set_budget(bytes: usize) {
+    BUDGET.store(bytes, Ordering::Relaxed);
+    enforce_budget();
+}
+
+pub fn budget() -> Option<usize> {
+    match BUDGET.load(Ordering::Relaxed) {
+        usize::MAX => None,
+        budget => Some(budget),
+    }
+}
+
+pub fn resident_bytes() -> usize {
+    RESIDENT.load(Ordering::Relaxed)
+}
+
+/// Claims `bytes` of the budget for data that is never paged out, fails if they do not fit.
+fn reserve(bytes: usize) -> bool {
+    let budget = BUDGET.load(Ordering::Relaxed);
+    RESIDENT
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |resident| {
+            resident.checked_add(bytes).filter(|&total| total <= budget)
+        })
+        .is_ok()
+}
+
+/// Loads a PNG, keeping it in memory if it fits within the budget and paging it otherwise.
+/// Textures kept in memory hold their share of the budget for the life of the process.
+pub fn load_png<P: AsRef<Path>>(
+    path: P,
+    wrapping: WrapMode,
+) -> Result<Box<dyn Surface>, Box<dyn std::error::Error>> {
+    let file = BufReader::new(File::open(path)?);
+    let image = Reader::with_format(file, ImageFormat::Png).decode()?;
+    let image = image.to_rgba8();
+
+    let width = image.width();
+    let height = image.height();
+    let bytes = width as usize * height as usize * std::mem::size_of::<V4>();
+
+    if reserve(bytes) {
+        Ok(Box::new(Texture::load_bytes(
+            image.into_raw(),
+            width,
+            height,
+            wrapping,
+        )))
+    } else {
+        Ok(Box::new(PagedTexture::from_rgba8(
+            &image.into_raw(),
+            width,
+            height,
+            wrapping,
+        )?))
+    }
+}
+
+/// Pulls in the geometry and textures visible from `camera` by shading a coarse grid of
+/// primary rays, so the first samples of a frame don't all stall on the same page reads.
+pub fn prefetch<B: Background>(world: &World<B>, camera: &Camera) {
+    for y in 0..PREFETCH_GRID {
+        for x in 0..PREFETCH_GRID {
+            let u = (x as f32 + 0.5) / PREFETCH_GRID as f32;
+            let v = (y as f32 + 0.5) / PREFETCH_GRID as f32;
+            camera.albedo_normal(world, camera.ray(u, v));
+        }
+    }
+}
+
+/// Evicts resident pages in clock order until the budget is met, pages that were read since
+/// they were last considered get a second chance.
+fn enforce_budget() {
+    let mut clock = CLOCK.lock().unwrap();
+    let mut chances = clock.len();
+
+    while RESIDENT.load(Ordering::Relaxed) > BUDGET.load(Ordering::Relaxed) {
+        let (file, index) = match clock.pop_front() {
+            Some(entry) => entry,
+            None => break,
+        };
+        let pages = match file.upgrade() {
+            Some(pages) => pages,
+            None => continue,
+        };
+
+        if chances > 0 && pages.accessed[index].swap(false, Ordering::Relaxed) {
+            chances -= 1;
+            clock.push_back((file, index));
+            continue;
+        }
+
+        if pages.pages[index].write().unwrap().take().is_some() {
+            RESIDENT.fetch_sub(pages.page_len, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Fixed size pages stored in a file on disk, with an in memory copy of each page kept while it
+/// is resident.
+struct PageFile {
+    file: Mutex<File>,
+    path: PathBuf,
+    page_len: usize,
+    pages: Vec<RwLock<Option<Arc<[u8]>>>>,
+    accessed: Vec<AtomicBool>,
+}
+
+impl PageFile {
+    fn create<I: Iterator<Item = Vec<u8>>>(page_len: usize, pages: I) -> std::io::Result<Self> {
+        std::fs::create_dir_all(PAGE_DIR)?;
+        let path = Path::new(PAGE_DIR).join(format!(
+            "pages_{}_{}.bin",
+            std::process::id(),
+            NEXT_PAGE_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut file = BufWriter::new(File::create(&path)?);
+        let mut count = 0;
+        for mut page in pages {
+            page.resize(page_len, 0);
+            file.write_all(&page)?;
+            count += 1;
+        }
+        file.flush()?;
+        drop(file);
+
+        Ok(Self {
+            file: Mutex::new(File::open(&path)?),
+            path,
+            page_len,
+            pages: (0..count).map(|_| RwLock::new(None)).collect(),
+            accessed: (0..count).map(|_| AtomicBool::new(false)).collect(),
+        })
+    }
+
+    fn page(self: &Arc<Self>, index: usize) -> Arc<[u8]> {
+        if let Some(page) = self.pages[index].read().unwrap().as_ref() {
+            self.accessed[index].store(true, Ordering::Relaxed);
+            return page.clone();
+        }
+
+        let page = {
+            let mut slot = self.pages[index].write().unwrap();
+            if let Some(page) = slot.as_ref() {
+                return page.clone();
+            }
+
+            let mut bytes = vec![0; self.page_len];
+            {
+                let mut file = self.file.lock().unwrap();
+                file.seek(SeekFrom::Start((index * self.page_len) as u64))
+                    .and_then(|_| file.read_exact(&mut bytes))
+                    .expect("Unable to read page");
+            }
+
+            let page: Arc<[u8]> = bytes.into();
+            *slot = Some(page.clone());
+            page
+        };
+
+        RESIDENT.fetch_add(self.page_len, Ordering::Relaxed);
+        CLOCK
+            .lock()
+            .unwrap()
+            .push_back((Arc::downgrade(self), index));
+        enforce_budget();
+
+        page
+    }
+}
+
+impl Drop for PageFile {
+    fn drop(&mut self) {
+        let resident = self
+            .pages
+            .iter_mut()
+            .filter_map(|p| p.get_mut().ok())
+            .filter(|p| p.is_some())
+            .count();
+        RESIDENT.fetch_sub(resident * self.page_len, Ordering::Relaxed);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A texture split into square tiles that are read from disk as they are sampled.
+pub struct PagedTexture {
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    wrapping: WrapMode,
+    pages: Arc<PageFile>,
+}
+
+impl PagedTexture {
+    pub fn from_rgba8(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        wrapping: WrapMode,
+    ) -> std::io::Result<Self> {
+        let tiles_x = (width + TILE_SIZE - 1) / TILE_SIZE;
+        let tiles_y = (height + TILE_SIZE - 1) / TILE_SIZE;
+        let page_len = (TILE_SIZE * TILE_SIZE * 4) as usize;
+
+        let tiles = (0..tiles_y).flat_map(move |tile_y| {
+            (0..tiles_x).map(move |tile_x| {
+                let mut page = Vec::with_capacity(page_len);
+                for row in 0..TILE_SIZE {
+                    let y = (tile_y * TILE_SIZE + row).min(height - 1);
+                    let line = (y * width) as usize * 4;
+                    let start = (tile_x * TILE_SIZE) as usize;
+                    let end = (start + TILE_SIZE as usize).min(width as usize);
+                    page.extend_from_slice(&bytes[line + start * 4..line + end * 4]);
+                    page.resize(((row + 1) * TILE_SIZE * 4) as usize, 0);
+                }
+                page
+            })
+        });
+
+        Ok(Self {
+            width,
+            height,
+            tiles_x,
+            wrapping,
+            pages: Arc::new(PageFile::create(page_len, tiles)?),
+        })
+    }
+
+    fn texel(&self, x: usize, y: usize) -> V4 {
+        let (x, y) = (x as u32, y as u32);
+        let tile = (y / TILE_SIZE) * self.tiles_x + x / TILE_SIZE;
+        let page = self.pages.page(tile as usize);
+
+        let offset = (((y % TILE_SIZE) * TILE_SIZE + x % TILE_SIZE) * 4) as usize;
+        let normalize_component = |c| c as f32 / 255.0;
+        V4::new(
+            normalize_component(page[offset]),
+            normalize_component(page[offset + 1]),
+            normalize_component(page[offset + 2]),
+            normalize_component(page[offset + 3]),
+        )
+    }
+}
+
+impl Surface for PagedTexture {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        bilinear(
+            index,
+            self.width,
+            self.height,
+            self.wrapping.into(),
+            |x, y| self.texel(x, y),
+        )
+    }
+}
@@ -0,0 +1,28 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes linear rgb `pixels` as a little-endian PFM image, rows are expected bottom first.
+pub fn write_pfm<P: AsRef<Path>, I: IntoIterator<Item = [f32; 3]>>(
+    path: P,
+    width: u32,
+    height: u32,
+    pixels: I,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+
+    write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+    for p in pixels {
+        file.write_f32::<LittleEndian>(p[0])?;
+        file.write_f32::<LittleEndian>(p[1])?;
+        file.write_f32::<LittleEndian>(p[2])?;
+    }
+
+    Ok(())
+}
@@ -4,6 +4,10 @@ use std::path::Path;
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 
+#[cfg(feature = "mmap")]
+use crate::mapped;
+use crate::math::{V2, V3};
+
 #[derive(Debug, Copy, Clone)]
 enum Format {
     Ascii,
@@ -165,6 +169,29 @@ enum DataType {
     Double,
 }
 
+impl DataType {
+    /// The size of a binary value in bytes.
+    #[cfg(feature = "mmap")]
+    fn size(&self) -> usize {
+        match self {
+            DataType::Char | DataType::UChar => 1,
+            DataType::Short | DataType::UShort => 2,
+            DataType::Int | DataType::UInt | DataType::Float => 4,
+            DataType::Double => 8,
+        }
+    }
+
+    /// Maps integer color components onto `0.0..=1.0`, floats are assumed to already be.
+    fn normalize_color(&self, value: f32) -> f32 {
+        match self {
+            DataType::UChar => value / u8::MAX as f32,
+            DataType::UShort => value / u16::MAX as f32,
+            DataType::UInt => value / u32::MAX as f32,
+            _ => value,
+        }
+    }
+}
+
 impl std::str::FromStr for DataType {
     type Err = Error;
 
@@ -267,6 +294,77 @@ enum Property {
     List(String, DataType, DataType),
 }
 
+/// Every vertex attribute PlyLoader understands, attributes missing from the file are `None`.
+/// Texture coordinates are read from `s`/`t`, `u`/`v` or `texture_u`/`texture_v`, and integer
+/// colors are normalized to `0.0..=1.0`.
+#[derive(Debug, Copy, Clone)]
+pub struct PlyVertex {
+    pub position: V3,
+    pub normal: Option<V3>,
+    pub uv: Option<V2>,
+    pub color: Option<V3>,
+}
+
+/// A vertex property that is stored in `PlyVertex`, with the component it fills.
+#[derive(Debug, Copy, Clone)]
+enum Attribute {
+    Position(usize),
+    Normal(usize),
+    Uv(usize),
+    Color(usize),
+}
+
+impl Attribute {
+    fn from_name(name: &str) -> Option<Self> {
+        let attribute = match name {
+            "x" => Attribute::Position(0),
+            "y" => Attribute::Position(1),
+            "z" => Attribute::Position(2),
+            "nx" => Attribute::Normal(0),
+            "ny" => Attribute::Normal(1),
+            "nz" => Attribute::Normal(2),
+            "s" | "u" | "texture_u" => Attribute::Uv(0),
+            "t" | "v" | "texture_v" => Attribute::Uv(1),
+            "red" => Attribute::Color(0),
+            "green" => Attribute::Color(1),
+            "blue" => Attribute::Color(2),
+            _ => return None,
+        };
+
+        Some(attribute)
+    }
+}
+
+#[derive(Debug, Default)]
+struct VertexValues {
+    position: [Option<f32>; 3],
+    normal: [Option<f32>; 3],
+    uv: [Option<f32>; 2],
+    color: [Option<f32>; 3],
+}
+
+impl VertexValues {
+    fn set(&mut self, attribute: Attribute, kind: DataType, value: f32) {
+        match attribute {
+            Attribute::Position(i) => self.position[i] = Some(value),
+            Attribute::Normal(i) => self.normal[i] = Some(value),
+            Attribute::Uv(i) => self.uv[i] = Some(value),
+            Attribute::Color(i) => self.color[i] = Some(kind.normalize_color(value)),
+        }
+    }
+
+    /// The vertex read so far, or None if it has no position.
+    fn vertex(&self) -> Option<PlyVertex> {
+        let v3 = |v: [Option<f32>; 3]| Some(V3::new(v[0]?, v[1]?, v[2]?));
+        Some(PlyVertex {
+            position: v3(self.position)?,
+            normal: v3(self.normal),
+            uv: self.uv[0].zip(self.uv[1]).map(|(u, v)| V2::new(u, v)),
+            color: v3(self.color),
+        })
+    }
+}
+
 pub struct PlyLoader;
 
 impl PlyLoader {
@@ -279,85 +377,222 @@ impl PlyLoader {
     >(
         path: P,
         mut vertex_fn: FV,
+        face_fn: FF,
+    ) -> Result<Vec<F>, Box<dyn std::error::Error>> {
+        Self::load_with(
+            path,
+            |v| vertex_fn(v.position.x(), v.position.y(), v.position.z()),
+            face_fn,
+        )
+    }
+
+    pub fn load_indexed<P: AsRef<Path>, FV: FnMut(f32, f32, f32) -> V, V>(
+        path: P,
+        mut vertex_fn: FV,
+    ) -> Result<(Vec<V>, Vec<[u32; 3]>), Box<dyn std::error::Error>> {
+        Self::load_indexed_with(path, |v| {
+            vertex_fn(v.position.x(), v.position.y(), v.position.z())
+        })
+    }
+
+    /// Like `load`, but passes every vertex attribute found in the file to `vertex_fn`.
+    pub fn load_with<
+        P: AsRef<Path>,
+        FV: FnMut(PlyVertex) -> V,
+        FF: FnMut(V, V, V) -> F,
+        V: Copy,
+        F,
+    >(
+        path: P,
+        vertex_fn: FV,
         mut face_fn: FF,
     ) -> Result<Vec<F>, Box<dyn std::error::Error>> {
-        let path = path.as_ref();
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+        let (_vertexes, faces) = Self::read(path, vertex_fn, |vertexes, a, b, c| {
+            face_fn(vertexes[a], vertexes[b], vertexes[c])
+        })?;
 
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
+        Ok(faces)
+    }
 
-        if line.trim() != "ply" {
-            return Err(Error::InvalidFile)?;
+    /// Like `load_indexed`, but passes every vertex attribute found in the file to `vertex_fn`.
+    pub fn load_indexed_with<P: AsRef<Path>, FV: FnMut(PlyVertex) -> V, V>(
+        path: P,
+        vertex_fn: FV,
+    ) -> Result<(Vec<V>, Vec<[u32; 3]>), Box<dyn std::error::Error>> {
+        Self::read(path, vertex_fn, |_vertexes, a, b, c| {
+            [a as u32, b as u32, c as u32]
+        })
+    }
+
+    /// Like `load_with`, but parses binary files straight out of a memory mapping and builds
+    /// faces across every cpu. `vertex_fn` and `face_fn` may be called from several threads at
+    /// once and in any order.
+    #[cfg(feature = "mmap")]
+    pub fn load_mapped<
+        P: AsRef<Path>,
+        FV: Fn(PlyVertex) -> V + Sync,
+        FF: Fn(V, V, V) -> F + Sync,
+        V: Copy + Send + Sync,
+        F: Send,
+    >(
+        path: P,
+        vertex_fn: FV,
+        face_fn: FF,
+    ) -> Result<Vec<F>, Box<dyn std::error::Error>> {
+        let (vertexes, faces) = Self::load_indexed_mapped(path, vertex_fn)?;
+
+        Ok(mapped::parallel_chunks(faces.len(), |range| {
+            faces[range]
+                .iter()
+                .map(|&[a, b, c]| {
+                    face_fn(
+                        vertexes[a as usize],
+                        vertexes[b as usize],
+                        vertexes[c as usize],
+                    )
+                })
+                .collect()
+        }))
+    }
+
+    /// Like `load_indexed_with`, but parses binary files straight out of a memory mapping,
+    /// decoding vertices across every cpu. ASCII files fall back to `load_indexed_with`.
+    #[cfg(feature = "mmap")]
+    pub fn load_indexed_mapped<P: AsRef<Path>, FV: Fn(PlyVertex) -> V + Sync, V: Send>(
+        path: P,
+        vertex_fn: FV,
+    ) -> Result<(Vec<V>, Vec<[u32; 3]>), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let map = mapped::open(path)?;
+
+        let mut body = &map[..];
+        let ply_description = read_header(&mut body)?;
+        let format = ply_description.format;
+        if let Format::Ascii = format {
+            drop(map);
+            return Self::load_indexed_with(path, vertex_fn);
         }
 
-        let mut reading_header = true;
-        let mut ply_description = PlyDescription::new();
-        while reading_header {
-            line.clear();
-            reader.read_line(&mut line)?;
-
-            let mut split = line.trim().split(' ');
-            let command = split.next();
-
-            match command {
-                Some("end_header") => reading_header = false,
-                Some("format") => {
-                    let format = split.next();
-                    let version = split.next();
-                    let format = match format.zip(version) {
-                        Some(("ascii", "1.0")) => Format::Ascii,
-                        Some(("binary_little_endian", "1.0")) => Format::BinaryLE,
-                        Some(("binary_big_endian", "1.0")) => Format::BinaryBE,
-                        _ => {
-                            let format = String::from(format.unwrap_or(""));
-                            let version = String::from(version.unwrap_or(""));
-                            return Err(Error::InvalidFormat(format, version))?;
+        let mut vertexes = Vec::new();
+        let mut faces = Vec::new();
+
+        for element in ply_description.elements.iter() {
+            let stride: Option<usize> = element
+                .properties
+                .iter()
+                .map(|prop| match prop {
+                    Property::Field(_name, kind) => Some(kind.size()),
+                    Property::List(..) => None,
+                })
+                .sum();
+
+            match (element.name.as_str(), stride) {
+                ("vertex", Some(stride)) => {
+                    let len = stride * element.count;
+                    if body.len() < len {
+                        return Err("ply vertex data is truncated")?;
+                    }
+                    let (data, rest) = body.split_at(len);
+                    body = rest;
+
+                    let mut offset = 0;
+                    let mut fields = Vec::new();
+                    for prop in element.properties.iter() {
+                        if let Property::Field(name, kind) = prop {
+                            if let Some(attribute) = Attribute::from_name(name) {
+                                fields.push((offset, attribute, *kind));
+                            }
+                            offset += kind.size();
                         }
-                    };
-                    ply_description.format = format;
+                    }
+
+                    vertexes = mapped::parallel_chunks(element.count, |range| {
+                        range
+                            .filter_map(|i| {
+                                let record = &data[i * stride..(i + 1) * stride];
+                                let mut values = VertexValues::default();
+                                for &(offset, attribute, kind) in fields.iter() {
+                                    let mut field = &record[offset..];
+                                    if let Ok(value) = format.read_f32(&mut field, kind) {
+                                        values.set(attribute, kind, value);
+                                    }
+                                }
+                                values.vertex().map(&vertex_fn)
+                            })
+                            .collect()
+                    });
                 }
-                Some("comment") => (),
-                Some("element") => {
-                    let name = split.next();
-                    let count: Option<usize> = split.next().and_then(|n| n.parse().ok());
-                    let (name, count) = name
-                        .zip(count)
-                        .ok_or_else(|| Error::InvalidElement(line.to_string()))?;
-                    ply_description.add_element(name, count);
+                ("vertex", None) => {
+                    return Err("ply vertices with list properties can not be mapped")?;
                 }
-                Some("property") => {
-                    let kind = split.next();
-                    match kind {
-                        Some("list") => {
-                            let count_kind: Option<DataType> =
-                                split.next().and_then(|k| k.parse().ok());
-                            let property_kind: Option<DataType> =
-                                split.next().and_then(|k| k.parse().ok());
-                            let name = split.next();
-                            let ((name, count_kind), property_kind) = name
-                                .zip(count_kind)
-                                .zip(property_kind)
-                                .ok_or_else(|| Error::InvalidProperty(line.to_string()))?;
-                            ply_description.add_property_list(name, count_kind, property_kind);
-                        }
-                        Some(kind) => {
-                            let kind: Option<DataType> = kind.parse().ok();
-                            let name = split.next();
-                            let (name, kind) = name
-                                .zip(kind)
-                                .ok_or_else(|| Error::InvalidProperty(line.to_string()))?;
-                            ply_description.add_property(name, kind);
+                (_, Some(stride)) => {
+                    let len = stride * element.count;
+                    if body.len() < len {
+                        return Err(format!("ply {} data is truncated", element.name))?;
+                    }
+                    body = &body[len..];
+                }
+                (name, None) => {
+                    let is_face = name == "face";
+                    let mut indices = Vec::new();
+                    for _ in 0..element.count {
+                        for prop in element.properties.iter() {
+                            match prop {
+                                Property::Field(_name, kind) => format.skip(&mut body, *kind)?,
+                                Property::List(_name, count_kind, value_kind) => {
+                                    let count = format.read_usize(&mut body, *count_kind)?;
+                                    if is_face && count >= 3 {
+                                        indices.clear();
+                                        for _ in 0..count {
+                                            let index =
+                                                format.read_usize(&mut body, *value_kind)?;
+                                            indices.push(index as u32);
+                                        }
+
+                                        for i in 1..count - 1 {
+                                            faces.push([indices[0], indices[i], indices[i + 1]]);
+                                        }
+                                    } else {
+                                        for _ in 0..count {
+                                            format.skip(&mut body, *value_kind)?;
+                                        }
+                                    }
+                                }
+                            }
                         }
-                        None => (),
                     }
                 }
-                Some(unknown) => eprintln!("unknown ply header found: '{}'", unknown),
-                None => (),
             }
         }
 
+        if faces
+            .iter()
+            .flatten()
+            .any(|&i| i as usize >= vertexes.len())
+        {
+            return Err("ply face references a missing vertex")?;
+        }
+
+        Ok((vertexes, faces))
+    }
+
+    fn read<
+        P: AsRef<Path>,
+        FV: FnMut(PlyVertex) -> V,
+        FF: FnMut(&[V], usize, usize, usize) -> F,
+        V,
+        F,
+    >(
+        path: P,
+        mut vertex_fn: FV,
+        mut face_fn: FF,
+    ) -> Result<(Vec<V>, Vec<F>), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+
+        let ply_description = read_header(&mut reader)?;
+
         let mut vertexes = Vec::new();
         let mut faces = Vec::new();
 
@@ -370,44 +605,40 @@ impl PlyLoader {
             }
 
             for _ in 0..element.count {
-                let mut x = None;
-                let mut y = None;
-                let mut z = None;
+                let mut values = VertexValues::default();
                 for prop in &element.properties {
                     match prop {
-                        Property::Field(name, kind) => match (is_vertex, name.as_str()) {
-                            (true, "x") => {
-                                x = Some(ply_description.format.read_f32(&mut reader, *kind)?);
-                            }
-                            (true, "y") => {
-                                y = Some(ply_description.format.read_f32(&mut reader, *kind)?);
-                            }
-                            (true, "z") => {
-                                z = Some(ply_description.format.read_f32(&mut reader, *kind)?);
-                            }
-                            _ => {
-                                ply_description.format.skip(&mut reader, *kind)?;
+                        Property::Field(name, kind) => {
+                            match Attribute::from_name(name).filter(|_| is_vertex) {
+                                Some(attribute) => {
+                                    let value =
+                                        ply_description.format.read_f32(&mut reader, *kind)?;
+                                    values.set(attribute, *kind, value);
+                                }
+                                None => ply_description.format.skip(&mut reader, *kind)?,
                             }
-                        },
+                        }
                         Property::List(_name, count_kind, value_kind) => {
                             let count = ply_description
                                 .format
                                 .read_usize(&mut reader, *count_kind)?;
-                            if is_face && count == 3 {
-                                let a_idx = ply_description
-                                    .format
-                                    .read_usize(&mut reader, *value_kind)?;
-                                let b_idx = ply_description
-                                    .format
-                                    .read_usize(&mut reader, *value_kind)?;
-                                let c_idx = ply_description
-                                    .format
-                                    .read_usize(&mut reader, *value_kind)?;
-
-                                let face =
-                                    face_fn(vertexes[a_idx], vertexes[b_idx], vertexes[c_idx]);
-
-                                faces.push(face);
+                            if is_face && count >= 3 {
+                                let mut indices = Vec::with_capacity(count);
+                                for _ in 0..count {
+                                    indices.push(
+                                        ply_description
+                                            .format
+                                            .read_usize(&mut reader, *value_kind)?,
+                                    );
+                                }
+
+                                // Quads and n-gons are split into a fan around the first corner,
+                                // which is exact for convex polygons
+                                for i in 1..count - 1 {
+                                    let face =
+                                        face_fn(&vertexes, indices[0], indices[i], indices[i + 1]);
+                                    faces.push(face);
+                                }
                             } else {
                                 for _ in 0..count {
                                     ply_description.format.skip(&mut reader, *value_kind)?;
@@ -418,14 +649,94 @@ impl PlyLoader {
                 }
 
                 if is_vertex {
-                    if let (Some(x), Some(y), Some(z)) = (x, y, z) {
-                        let vert = vertex_fn(x, y, z);
-                        vertexes.push(vert);
+                    if let Some(vertex) = values.vertex() {
+                        vertexes.push(vertex_fn(vertex));
                     }
                 }
             }
         }
 
-        Ok(faces)
+        Ok((vertexes, faces))
     }
 }
+
+/// Parses everything up to and including `end_header`, leaving `reader` at the start of the
+/// element data.
+fn read_header<R: BufRead>(reader: &mut R) -> Result<PlyDescription, Box<dyn std::error::Error>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    if line.trim() != "ply" {
+        return Err(Error::InvalidFile)?;
+    }
+
+    let mut reading_header = true;
+    let mut ply_description = PlyDescription::new();
+    while reading_header {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::InvalidFile)?;
+        }
+
+        let mut split = line.trim().split(' ');
+        let command = split.next();
+
+        match command {
+            Some("end_header") => reading_header = false,
+            Some("format") => {
+                let format = split.next();
+                let version = split.next();
+                let format = match format.zip(version) {
+                    Some(("ascii", "1.0")) => Format::Ascii,
+                    Some(("binary_little_endian", "1.0")) => Format::BinaryLE,
+                    Some(("binary_big_endian", "1.0")) => Format::BinaryBE,
+                    _ => {
+                        let format = String::from(format.unwrap_or(""));
+                        let version = String::from(version.unwrap_or(""));
+                        return Err(Error::InvalidFormat(format, version))?;
+                    }
+                };
+                ply_description.format = format;
+            }
+            Some("comment") => (),
+            Some("element") => {
+                let name = split.next();
+                let count: Option<usize> = split.next().and_then(|n| n.parse().ok());
+                let (name, count) = name
+                    .zip(count)
+                    .ok_or_else(|| Error::InvalidElement(line.to_string()))?;
+                ply_description.add_element(name, count);
+            }
+            Some("property") => {
+                let kind = split.next();
+                match kind {
+                    Some("list") => {
+                        let count_kind: Option<DataType> =
+                            split.next().and_then(|k| k.parse().ok());
+                        let property_kind: Option<DataType> =
+                            split.next().and_then(|k| k.parse().ok());
+                        let name = split.next();
+                        let ((name, count_kind), property_kind) = name
+                            .zip(count_kind)
+                            .zip(property_kind)
+                            .ok_or_else(|| Error::InvalidProperty(line.to_string()))?;
+                        ply_description.add_property_list(name, count_kind, property_kind);
+                    }
+                    Some(kind) => {
+                        let kind: Option<DataType> = kind.parse().ok();
+                        let name = split.next();
+                        let (name, kind) = name
+                            .zip(kind)
+                            .ok_or_else(|| Error::InvalidProperty(line.to_string()))?;
+                        ply_description.add_property(name, kind);
+                    }
+                    None => (),
+                }
+            }
+            Some(unknown) => eprintln!("unknown ply header found: '{}'", unknown),
+            None => (),
+        }
+    }
+
+    Ok(ply_description)
+}
@@ -0,0 +1,187 @@
+use crate::math::V3;
+
+/// Transforms Stokes vectors `[I, Q, U, V]` expressed relative to the plane of incidence, with
+/// the reference axis perpendicular to it (the s polarization direction).
+#[derive(Debug, Copy, Clone)]
+pub struct Mueller([[f32; 4]; 4]);
+
+impl Mueller {
+    /// Scrambles any incoming polarization, as rough and diffuse surfaces do.
+    pub fn depolarizer() -> Self {
+        Mueller([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+        ])
+    }
+
+    /// A perfect conductor, which keeps linear polarization and flips the handedness of circular
+    /// polarization.
+    pub fn mirror() -> Self {
+        Mueller([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, -1.0, 0.0],
+            [0.0, 0.0, 0.0, -1.0],
+        ])
+    }
+
+    /// An ideal linear polarizer with its transmission axis `angle` radians from the reference
+    /// axis.
+    pub fn linear_polarizer(angle: f32) -> Self {
+        let c = (2.0 * angle).cos();
+        let s = (2.0 * angle).sin();
+        Mueller([
+            [0.5, 0.5 * c, 0.5 * s, 0.0],
+            [0.5 * c, 0.5 * c * c, 0.5 * c * s, 0.0],
+            [0.5 * s, 0.5 * c * s, 0.5 * s * s, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+        ])
+    }
+
+    /// Re-expresses a Stokes vector in a frame whose reference axis is rotated by `angle`.
+    fn rotation(angle: f32) -> Self {
+        let c = (2.0 * angle).cos();
+        let s = (2.0 * angle).sin();
+        Mueller([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, c, s, 0.0],
+            [0.0, -s, c, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Fresnel reflection or transmission at a smooth dielectric boundary, `cos_theta` is the
+    /// cosine of the incident angle and `refraction_ratio` is the index of the incident side
+    /// over the far side. Total internal reflection shifts the phase between s and p, turning
+    /// linear polarization elliptical.
+    ///
+    /// The matrix is normalized to pass unpolarized light unchanged, materials already choose
+    /// between reflection and refraction in proportion to their reflectance.
+    pub fn dielectric(cos_theta: f32, refraction_ratio: f32, reflected: bool) -> Self {
+        let cos_i = cos_theta.abs().min(1.0);
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        let sin_t = refraction_ratio * sin_i;
+
+        if sin_t >= 1.0 {
+            if !reflected {
+                return Self::depolarizer();
+            }
+
+            let n = 1.0 / refraction_ratio;
+            let root = (sin_i * sin_i - n * n).max(0.0).sqrt();
+            let delta_s = 2.0 * (root / cos_i.max(f32::EPSILON)).atan();
+            let delta_p = 2.0 * (root / (n * n * cos_i).max(f32::EPSILON)).atan();
+            let (sin_d, cos_d) = (delta_s - delta_p).sin_cos();
+
+            return Mueller([
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, cos_d, sin_d],
+                [0.0, 0.0, -sin_d, cos_d],
+            ]);
+        }
+
+        let cos_t = (1.0 - sin_t * sin_t).sqrt();
+        let rs = (refraction_ratio * cos_i - cos_t) / (refraction_ratio * cos_i + cos_t);
+        let rp = (cos_i - refraction_ratio * cos_t) / (cos_i + refraction_ratio * cos_t);
+
+        let (s, p, cross) = if reflected {
+            (rs * rs, rp * rp, rs * rp)
+        } else {
+            let (ts, tp) = (1.0 - rs * rs, 1.0 - rp * rp);
+            (ts, tp, (ts * tp).sqrt())
+        };
+
+        let sum = s + p;
+        if sum <= 0.0 {
+            return Self::depolarizer();
+        }
+        let diff = (s - p) / sum;
+        let cross = 2.0 * cross / sum;
+
+        Mueller([
+            [1.0, diff, 0.0, 0.0],
+            [diff, 1.0, 0.0, 0.0],
+            [0.0, 0.0, cross, 0.0],
+            [0.0, 0.0, 0.0, cross],
+        ])
+    }
+}
+
+/// The response of the sensor's polarizing filter, carried backwards along a camera path.
+///
+/// Only the first row of the Mueller matrices accumulated from the sensor is needed, since
+/// emitters are unpolarized and only the intensity reaching the sensor is recorded.
+#[derive(Debug, Copy, Clone)]
+pub struct PathFilter {
+    row: [f32; 4],
+    reference: V3,
+}
+
+impl PathFilter {
+    /// A polarizer at `angle` radians from `right`, in front of a sensor looking along
+    /// `direction`.
+    pub fn new(angle: f32, direction: V3, right: V3) -> Self {
+        let polarizer = Mueller::linear_polarizer(angle);
+        Self {
+            row: polarizer.0[0],
+            reference: perpendicular(right, direction),
+        }
+    }
+
+    /// The fraction of unpolarized light along this path that passes the filter.
+    pub fn weight(&self) -> f32 {
+        self.row[0]
+    }
+
+    /// Follows the path through a surface with `normal`, arriving along `incoming` and leaving
+    /// along `outgoing`, where `mueller` is in the surface's plane of incidence.
+    pub fn interact(self, mueller: Mueller, incoming: V3, normal: V3, outgoing: V3) -> Self {
+        let incoming = incoming.unit();
+        let s = incoming.cross(normal);
+        let s = if s.near_zero() {
+            self.reference
+        } else {
+            s.unit()
+        };
+
+        let cos_phi = self.reference.dot(s);
+        let sin_phi = self.reference.cross(s).dot(incoming);
+        let phi = sin_phi.atan2(cos_phi);
+
+        let row = multiply(self.row, Mueller::rotation(-phi));
+        let row = multiply(row, mueller);
+
+        Self {
+            row,
+            reference: perpendicular(s, outgoing.unit()),
+        }
+    }
+}
+
+fn multiply(row: [f32; 4], matrix: Mueller) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for (j, value) in result.iter_mut().enumerate() {
+        *value = (0..4).map(|i| row[i] * matrix.0[i][j]).sum();
+    }
+    result
+}
+
+/// The unit component of `axis` perpendicular to `direction`, or any perpendicular axis if they
+/// are parallel.
+fn perpendicular(axis: V3, direction: V3) -> V3 {
+    let direction = direction.unit();
+    let axis = axis - direction * axis.dot(direction);
+    if axis.near_zero() {
+        let fallback = if direction.x().abs() > 0.9 {
+            V3::new(0.0, 1.0, 0.0)
+        } else {
+            V3::new(1.0, 0.0, 0.0)
+        };
+        direction.cross(fallback).unit()
+    } else {
+        axis.unit()
+    }
+}
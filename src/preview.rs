@@ -0,0 +1,284 @@
+use glium::{implement_vertex, uniform, Display, DrawParameters, Program, Surface, VertexBuffer};
+use winit::event::VirtualKeyCode;
+
+use crate::geom::Intersect;
+use crate::material::Background;
+use crate::math::V3;
+use crate::world::{Camera, World};
+use crate::{Input, InputCollection};
+
+/// Radians turned per second while an arrow key is held.
+const TURN_SPEED: f32 = 1.5;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PreviewVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: [f32; 3],
+}
+
+implement_vertex!(PreviewVertex, position, normal, color);
+
+/// The triangles of a world flattened for the rasterizer, each vertex carrying the face normal
+/// and the color of its material's approximation.
+#[derive(Debug)]
+pub struct Geometry {
+    vertices: Vec<PreviewVertex>,
+    camera: PreviewCamera,
+    speed: f32,
+}
+
+impl Geometry {
+    pub fn collect<B: Background>(world: &World<B>, camera: &Camera) -> Self {
+        let mut vertices = Vec::new();
+        world.visit_triangles(&mut |[a, b, c], material| {
+            let normal = (b - a).cross(c - a).unit();
+            let approximation = material.approximate();
+            let color = approximation.color + approximation.emission;
+            for v in [a, b, c] {
+                vertices.push(PreviewVertex {
+                    position: [v.x(), v.y(), v.z()],
+                    normal: [normal.x(), normal.y(), normal.z()],
+                    color: [color.x(), color.y(), color.z()],
+                });
+            }
+        });
+
+        // Cross the scene in about four seconds, unbounded worlds fall back to a fixed speed
+        let speed = world
+            .bounds()
+            .map(|bounds| bounds.size().length() / 4.0)
+            .filter(|speed| speed.is_finite() && *speed > 0.0)
+            .unwrap_or(1.0);
+
+        Self {
+            vertices,
+            camera: PreviewCamera::from_camera(camera),
+            speed,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Shading {
+    Flat,
+    Normal,
+}
+
+/// A fly camera starting at the scene camera, it keeps the world's up axis and drops any roll.
+#[derive(Debug, Copy, Clone)]
+struct PreviewCamera {
+    position: V3,
+    yaw: f32,
+    pitch: f32,
+    vertical_fov: f32,
+}
+
+impl PreviewCamera {
+    fn from_camera(camera: &Camera) -> Self {
+        let direction = camera.direction();
+        Self {
+            position: camera.origin(),
+            yaw: direction.x().atan2(-direction.z()),
+            pitch: direction.y().max(-1.0).min(1.0).asin(),
+            vertical_fov: camera.vertical_fov(),
+        }
+    }
+
+    fn forward(&self) -> V3 {
+        V3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+
+    fn view_projection(&self, aspect_ratio: f32, near: f32, far: f32) -> [[f32; 4]; 4] {
+        let forward = self.forward();
+        let right = forward.cross(V3::new(0.0, 1.0, 0.0)).unit();
+        let up = right.cross(forward);
+        let p = self.position;
+
+        let f = 1.0 / (self.vertical_fov.to_radians() / 2.0).tan();
+        let depth = near - far;
+
+        // Column major, the view rotation and translation folded into the projection
+        let rows = [
+            [
+                f / aspect_ratio * right.x(),
+                f / aspect_ratio * right.y(),
+                f / aspect_ratio * right.z(),
+                -f / aspect_ratio * right.dot(p),
+            ],
+            [f * up.x(), f * up.y(), f * up.z(), -f * up.dot(p)],
+            [
+                -forward.x() * (far + near) / depth,
+                -forward.y() * (far + near) / depth,
+                -forward.z() * (far + near) / depth,
+                forward.dot(p) * (far + near) / depth + 2.0 * far * near / depth,
+            ],
+            [forward.x(), forward.y(), forward.z(), -forward.dot(p)],
+        ];
+
+        let mut columns = [[0.0; 4]; 4];
+        for (r, row) in rows.iter().enumerate() {
+            for (c, value) in row.iter().enumerate() {
+                columns[c][r] = *value;
+            }
+        }
+        columns
+    }
+}
+
+/// Rasterizes the world's triangles with an interactive camera, so the layout of a scene can be
+/// checked before any samples have been traced. WASD moves, R and F rise and fall and the arrow
+/// keys turn.
+pub struct Preview {
+    vertex_buffer: VertexBuffer<PreviewVertex>,
+    program: Program,
+    camera: PreviewCamera,
+    speed: f32,
+    shading: Option<Shading>,
+}
+
+impl Preview {
+    pub fn new(display: &Display, geometry: Geometry) -> Self {
+        let vertex_buffer = VertexBuffer::new(display, &geometry.vertices)
+            .expect("Unable to create preview vertex buffer");
+        let program = Program::from_source(display, VERTEX_SRC, FRAGMENT_SRC, None)
+            .expect("Unable to create preview gl program");
+
+        Self {
+            vertex_buffer,
+            program,
+            camera: geometry.camera,
+            speed: geometry.speed,
+            shading: Some(Shading::Flat),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.shading.is_some()
+    }
+
+    /// Steps from flat to normal shading to hidden and back, returning whether the preview is
+    /// now visible.
+    pub fn cycle(&mut self) -> bool {
+        self.shading = match self.shading {
+            None => Some(Shading::Flat),
+            Some(Shading::Flat) => Some(Shading::Normal),
+            Some(Shading::Normal) => None,
+        };
+        self.is_visible()
+    }
+
+    /// Moves the camera for the keys held over the last `dt` seconds, returning whether it
+    /// moved.
+    pub fn update(&mut self, input: &InputCollection, dt: f32) -> bool {
+        let held = |key| input.is_pressed(Input::Key(key));
+        let axis = |positive, negative| match (held(positive), held(negative)) {
+            (true, false) => 1.0,
+            (false, true) => -1.0,
+            _ => 0.0,
+        };
+
+        let forward = axis(VirtualKeyCode::W, VirtualKeyCode::S);
+        let right = axis(VirtualKeyCode::D, VirtualKeyCode::A);
+        let up = axis(VirtualKeyCode::R, VirtualKeyCode::F);
+        let yaw = axis(VirtualKeyCode::Right, VirtualKeyCode::Left);
+        let pitch = axis(VirtualKeyCode::Up, VirtualKeyCode::Down);
+
+        if forward == 0.0 && right == 0.0 && up == 0.0 && yaw == 0.0 && pitch == 0.0 {
+            return false;
+        }
+
+        let camera = &mut self.camera;
+        camera.yaw += yaw * TURN_SPEED * dt;
+        camera.pitch = (camera.pitch + pitch * TURN_SPEED * dt)
+            .max(-1.55)
+            .min(1.55);
+
+        let direction = camera.forward();
+        let right_direction = direction.cross(V3::new(0.0, 1.0, 0.0)).unit();
+        let movement = direction * forward + right_direction * right + V3::new(0.0, up, 0.0);
+        camera.position += movement * self.speed * dt;
+
+        true
+    }
+
+    pub fn draw<S: Surface>(&self, target: &mut S, aspect_ratio: f32) {
+        let shading = match self.shading {
+            Some(shading) => shading,
+            None => return,
+        };
+
+        let near = self.speed * 0.001;
+        let far = self.speed * 100.0;
+        let uniforms = uniform! {
+            view_projection: self.camera.view_projection(aspect_ratio, near, far),
+            light_direction: {
+                let forward = self.camera.forward();
+                [-forward.x(), -forward.y(), -forward.z()]
+            },
+            normal_shading: shading == Shading::Normal,
+        };
+
+        let parameters = DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        target.clear_color_and_depth((0.1, 0.1, 0.1, 1.0), 1.0);
+        target
+            .draw(
+                &self.vertex_buffer,
+                glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                &self.program,
+                &uniforms,
+                &parameters,
+            )
+            .expect("Unable to draw preview");
+    }
+}
+
+const VERTEX_SRC: &'static str = "
+#version 420
+
+in vec3 position;
+in vec3 normal;
+in vec3 color;
+
+out vec3 v_normal;
+out vec3 v_color;
+
+uniform mat4 view_projection;
+
+void main() {
+   v_normal = normal;
+   v_color = color;
+   gl_Position = view_projection * vec4(position, 1.0);
+}";
+
+const FRAGMENT_SRC: &'static str = "
+#version 420
+
+in vec3 v_normal;
+in vec3 v_color;
+
+out vec4 f_color;
+
+uniform vec3 light_direction;
+uniform bool normal_shading;
+
+void main () {
+   if (normal_shading) {
+      f_color = vec4(v_normal * 0.5 + 0.5, 1.0);
+   } else {
+      float light = 0.2 + 0.8 * abs(dot(normalize(v_normal), normalize(light_direction)));
+      f_color = vec4(v_color * light, 1.0);
+   }
+}";
@@ -0,0 +1,277 @@
+//! Python bindings, built into an extension module with `maturin develop --features python`.
+//!
+//! ```python
+//! import mass_raytrace as mr
+//!
+//! world = mr.World()
+//! world.add_sphere((0.0, 0.0, -1.0), 0.5, mr.Material.lambertian((0.8, 0.3, 0.3)))
+//! camera = mr.Camera((0.0, 0.0, 1.0), (0.0, 0.0, -1.0))
+//! image = world.render(camera, 320, 180, samples=64)
+//! ```
+
+use std::sync::Arc;
+
+use numpy::PyArray;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::geom::{Instance, Intersect, Mesh, Model, Sphere};
+use crate::material::{
+    Background, Dielectric, DiffuseLight, Lambertian, Material, Metal, SkyBackground,
+    SolidBackground,
+};
+use crate::math::V3;
+use crate::model_loader::ModelLoader;
+use crate::texture::SolidColor;
+use crate::world::{Camera, World};
+
+type Vector = (f32, f32, f32);
+
+fn v3((x, y, z): Vector) -> V3 {
+    V3::new(x, y, z)
+}
+
+#[derive(Debug, Copy, Clone)]
+enum MaterialKind {
+    Lambertian(V3),
+    Metal(V3, f32),
+    Dielectric(f32),
+    Light(V3),
+}
+
+/// A material, created through one of the static constructors.
+#[pyclass(name = "Material")]
+#[derive(Debug, Copy, Clone)]
+struct PyMaterial {
+    kind: MaterialKind,
+}
+
+#[pymethods]
+impl PyMaterial {
+    #[staticmethod]
+    fn lambertian(color: Vector) -> Self {
+        Self {
+            kind: MaterialKind::Lambertian(v3(color)),
+        }
+    }
+
+    #[staticmethod]
+    #[args(fuzz = "0.0")]
+    fn metal(color: Vector, fuzz: f32) -> Self {
+        Self {
+            kind: MaterialKind::Metal(v3(color), fuzz),
+        }
+    }
+
+    #[staticmethod]
+    fn dielectric(refraction_index: f32) -> Self {
+        Self {
+            kind: MaterialKind::Dielectric(refraction_index),
+        }
+    }
+
+    #[staticmethod]
+    fn light(color: Vector) -> Self {
+        Self {
+            kind: MaterialKind::Light(v3(color)),
+        }
+    }
+}
+
+impl PyMaterial {
+    fn build(&self) -> Box<dyn Material> {
+        match self.kind {
+            MaterialKind::Lambertian(color) => {
+                Box::new(Lambertian::new(SolidColor(color.expand(1.0))))
+            }
+            MaterialKind::Metal(color, fuzz) => {
+                Box::new(Metal::fuzzed(fuzz, SolidColor(color.expand(1.0))))
+            }
+            MaterialKind::Dielectric(refraction_index) => {
+                Box::new(Dielectric::new(refraction_index))
+            }
+            MaterialKind::Light(color) => Box::new(DiffuseLight::new(color)),
+        }
+    }
+}
+
+/// A camera looking from `look_from` towards `look_at`, focused on `look_at` unless a
+/// `focus_distance` is given. The aspect ratio comes from the rendered image.
+#[pyclass(name = "Camera")]
+#[derive(Debug, Copy, Clone)]
+struct PyCamera {
+    look_from: V3,
+    look_at: V3,
+    view_up: V3,
+    vertical_fov: f32,
+    aperture: f32,
+    focus_distance: Option<f32>,
+}
+
+#[pymethods]
+impl PyCamera {
+    #[new]
+    #[args(
+        view_up = "(0.0, 1.0, 0.0)",
+        vertical_fov = "40.0",
+        aperture = "0.0",
+        focus_distance = "None"
+    )]
+    fn new(
+        look_from: Vector,
+        look_at: Vector,
+        view_up: Vector,
+        vertical_fov: f32,
+        aperture: f32,
+        focus_distance: Option<f32>,
+    ) -> Self {
+        Self {
+            look_from: v3(look_from),
+            look_at: v3(look_at),
+            view_up: v3(view_up),
+            vertical_fov,
+            aperture,
+            focus_distance,
+        }
+    }
+}
+
+impl PyCamera {
+    fn build(&self, aspect_ratio: f32) -> Camera {
+        let focus_distance = self
+            .focus_distance
+            .unwrap_or_else(|| (self.look_at - self.look_from).length());
+
+        Camera::new(
+            self.vertical_fov,
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            aspect_ratio,
+            self.aperture,
+            focus_distance,
+        )
+    }
+}
+
+/// The objects of a scene, lit by a sky gradient unless a solid `background` color is given.
+#[pyclass(name = "World")]
+struct PyWorld {
+    objects: Vec<Arc<dyn Intersect>>,
+    background: Option<V3>,
+}
+
+#[pymethods]
+impl PyWorld {
+    #[new]
+    #[args(background = "None")]
+    fn new(background: Option<Vector>) -> Self {
+        Self {
+            objects: Vec::new(),
+            background: background.map(v3),
+        }
+    }
+
+    fn add_sphere(&mut self, center: Vector, radius: f32, material: PyRef<PyMaterial>) {
+        let sphere = Sphere::new(material.build(), v3(center), radius);
+        self.objects.push(Arc::new(sphere));
+    }
+
+    /// Adds a triangle mesh from a list of vertex positions and a list of index triples.
+    fn add_mesh(
+        &mut self,
+        positions: Vec<Vector>,
+        faces: Vec<(u32, u32, u32)>,
+        material: PyRef<PyMaterial>,
+    ) -> PyResult<()> {
+        if faces
+            .iter()
+            .any(|&(a, b, c)| a.max(b).max(c) as usize >= positions.len())
+        {
+            return Err(PyValueError::new_err("face index out of range"));
+        }
+
+        let vertices = positions.into_iter().map(v3).collect();
+        let faces = faces.into_iter().map(|(a, b, c)| [a, b, c]).collect();
+        let mesh = Mesh::new(material.build(), vertices, faces);
+        self.objects.push(Arc::new(Model::from_mesh(mesh)));
+
+        Ok(())
+    }
+
+    /// Loads an OBJ, PLY or STL model, `rotation` is in degrees. Models without materials of
+    /// their own need a `material`.
+    #[args(
+        translation = "(0.0, 0.0, 0.0)",
+        rotation = "(0.0, 0.0, 0.0)",
+        scale = "1.0",
+        material = "None"
+    )]
+    fn add_model(
+        &mut self,
+        path: &str,
+        translation: Vector,
+        rotation: Vector,
+        scale: f32,
+        material: Option<PyMaterial>,
+    ) -> PyResult<()> {
+        let model = ModelLoader::new(path)
+            .load()
+            .map_err(|error| PyIOError::new_err(error.to_string()))?;
+
+        let rotation = v3(rotation) / 360.0;
+        let instance = Instance::<()>::of(model, v3(translation), rotation, V3::fill(scale));
+        match material {
+            Some(material) => self
+                .objects
+                .push(Arc::new(instance.with_material(material.build()))),
+            None => self.objects.push(Arc::new(instance)),
+        }
+
+        Ok(())
+    }
+
+    /// Renders `samples` paths per pixel, returning linear float32 RGB as a
+    /// `(height, width, 3)` array with the top row first. The GIL is released while tracing.
+    #[args(samples = "16", max_depth = "50")]
+    fn render<'py>(
+        &self,
+        py: Python<'py>,
+        camera: PyRef<PyCamera>,
+        width: u32,
+        height: u32,
+        samples: u32,
+        max_depth: u32,
+    ) -> PyResult<&'py PyArray<f32, numpy::Ix3>> {
+        if width == 0 || height == 0 {
+            return Err(PyValueError::new_err("image dimensions must be non-zero"));
+        }
+
+        let background: Box<dyn Background> = match self.background {
+            Some(color) => Box::new(SolidBackground::new(color)),
+            None => Box::new(SkyBackground),
+        };
+        let mut world = World::new(background);
+        for object in self.objects.iter() {
+            world.add(object.clone());
+        }
+        world.build_bvh();
+        let camera = camera.build(width as f32 / height as f32);
+
+        let colors = py.allow_threads(|| world.render(&camera, width, height, samples, max_depth));
+        let data = colors
+            .into_iter()
+            .flat_map(|color| [color.x(), color.y(), color.z()])
+            .collect::<Vec<_>>();
+
+        PyArray::from_vec(py, data).reshape([height as usize, width as usize, 3])
+    }
+}
+
+#[pymodule]
+fn mass_raytrace(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyMaterial>()?;
+    module.add_class::<PyCamera>()?;
+    module.add_class::<PyWorld>()?;
+    Ok(())
+}
@@ -0,0 +1,340 @@
+//! A brute force path tracer to check the renderer against. Only the path throughput and the
+//! sums of the samples are kept in f64, intersections and materials run in f32 through the same
+//! `Intersect` and `Material` code the renderer uses. The reference is there to show the bias of
+//! the renderer's light sampling, MIS, clamping and BVH, none of which f32 geometry or shading
+//! hides since both sides share it, while summing millions of samples in f32 would drop the low
+//! bits a small bias shows up in.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use mass_raytrace::sampler::RandomSampler;
+
+use crate::geom::Intersect;
+use crate::light::Lights;
+use crate::material::Background;
+use crate::math::{Num, V3};
+use crate::pfm::write_pfm;
+use crate::world::{Camera, Ray, World};
+
+/// Renders `crop` with the reference path tracer by brute force against the un-accelerated
+/// `world`, then again with `Camera::trace` through the BVH, and reports the difference between
+/// the two. A clamped `camera` shows up as bias.
+pub fn validate<B: 'static + Background>(
+    world: World<B>,
+    camera: Camera,
+    image_width: u32,
+    image_height: u32,
+    crop: Crop,
+    strata: u32,
+    max_depth: u32,
+) {
+    let world = Arc::new(world);
+    let camera = Arc::new(camera);
+
+    let reference = ReferenceImage::render(
+        world.clone(),
+        camera.clone(),
+        image_width,
+        image_height,
+        crop,
+        strata,
+        max_depth,
+        Integrator::Reference,
+    );
+
+    let mut world = Arc::try_unwrap(world)
+        .ok()
+        .expect("reference world still shared");
+    world.build_bvh();
+
+    let test = ReferenceImage::render(
+        Arc::new(world),
+        camera,
+        image_width,
+        image_height,
+        crop,
+        strata,
+        max_depth,
+        Integrator::Camera,
+    );
+
+    println!(
+        "Reference comparison at {} spp:\n{}",
+        reference.samples(),
+        reference.compare(&test)
+    );
+
+    let path = format!(
+        "./export/reference_{}_{}_{}x{}.pfm",
+        crop.x, crop.y, crop.width, crop.height
+    );
+    match reference.write_pfm(&path) {
+        Ok(()) => println!("Reference saved to: {}", path),
+        Err(error) => eprintln!("Unable to save reference: {:?}", error),
+    }
+}
+
+/// Follows `ray` through `scene` for up to `max_depth` surfaces, picking each bounce by sampling
+/// the BSDF alone and carrying the path throughput in f64. Lights are only found by scattering
+/// into them, without light sampling, MIS or clamping, so the estimate is unbiased but slow to
+/// converge for small lights. Point and spot lights can never be reached this way, and the
+/// camera's polarizer is ignored.
+fn trace<I: Intersect + Background + Lights>(scene: &I, ray: Ray, max_depth: u32) -> [f64; 3] {
+    let mut color = [0.0f64; 3];
+    let mut throughput = [1.0f64; 3];
+    let mut add = |throughput: &[f64; 3], light: V3| {
+        color[0] += throughput[0] * light.x() as f64;
+        color[1] += throughput[1] * light.y() as f64;
+        color[2] += throughput[2] * light.z() as f64;
+    };
+
+    let lights = scene.lights();
+    let mut ray = ray;
+    for bounce in 0..max_depth {
+        let hit = match scene.intersect(ray, 0.001, f32::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let background = if bounce == 0 {
+                    scene.camera_background(ray)
+                } else {
+                    scene.background(ray)
+                };
+                let direction = ray.direction.unit();
+                let escaped = lights
+                    .iter()
+                    .enumerate()
+                    .filter(|&(index, _)| lights.shines_on(index, ray.source))
+                    .fold(background, |sum, (_, light)| sum + light.escaped(direction));
+                add(&throughput, escaped);
+                break;
+            }
+        };
+
+        // Lights are only seen from the objects they're linked to
+        let linked = hit
+            .object
+            .and_then(|object| lights.surface_light(object))
+            .map_or(true, |light| lights.shines_on(light, ray.source));
+        if linked {
+            add(&throughput, hit.emit());
+        }
+
+        let sample = match hit.sample(ray, &mut RandomSampler) {
+            Some(sample) => sample,
+            None => break,
+        };
+        throughput[0] *= sample.weight.x() as f64;
+        throughput[1] *= sample.weight.y() as f64;
+        throughput[2] *= sample.weight.z() as f64;
+        if throughput.iter().all(|&t| t == 0.0) {
+            break;
+        }
+        ray = hit.spawn_ray(ray, &sample);
+    }
+
+    color
+}
+
+/// How a `ReferenceImage` follows each camera ray.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Integrator {
+    /// The independent path tracer `trace`.
+    Reference,
+    /// The renderer's own `Camera::trace`, to be checked against the reference.
+    Camera,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct ReferenceImage {
+    crop: Crop,
+    samples: u32,
+    pixels: Vec<[f64; 3]>,
+}
+
+pub struct Comparison {
+    pub reference_mean: [f64; 3],
+    pub test_mean: [f64; 3],
+    pub rmse: f64,
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bias = |c: usize| self.test_mean[c] - self.reference_mean[c];
+        let relative = |c: usize| bias(c) / self.reference_mean[c].max(f64::EPSILON) * 100.0;
+        writeln!(
+            f,
+            "reference mean: {:.6} {:.6} {:.6}",
+            self.reference_mean[0], self.reference_mean[1], self.reference_mean[2]
+        )?;
+        writeln!(
+            f,
+            "test mean:      {:.6} {:.6} {:.6}",
+            self.test_mean[0], self.test_mean[1], self.test_mean[2]
+        )?;
+        writeln!(
+            f,
+            "bias:           {:+.3}% {:+.3}% {:+.3}%",
+            relative(0),
+            relative(1),
+            relative(2)
+        )?;
+        write!(f, "rmse:           {:.6}", self.rmse)
+    }
+}
+
+impl ReferenceImage {
+    /// Renders `crop` with a stratified `strata` x `strata` grid of samples per pixel,
+    /// accumulating in f64. The `Integrator::Reference` paths share none of the light sampling
+    /// or radiance clamping of the renderer they check.
+    pub fn render<I: 'static + Intersect + Background + Lights>(
+        scene: Arc<I>,
+        camera: Arc<Camera>,
+        image_width: u32,
+        image_height: u32,
+        crop: Crop,
+        strata: u32,
+        max_depth: u32,
+        integrator: Integrator,
+    ) -> Self {
+        let pixels = Arc::new(Mutex::new(vec![
+            [0.0; 3];
+            (crop.width * crop.height) as usize
+        ]));
+        let row = Arc::new(AtomicU32::new(0));
+        let cpus = num_cpus::get().max(1);
+
+        let mut handles = Vec::new();
+        for i in 0..cpus {
+            let scene = scene.clone();
+            let camera = camera.clone();
+            let pixels = pixels.clone();
+            let row = row.clone();
+
+            let builder = std::thread::Builder::new()
+                .name(format!("reference:{}", i))
+                .stack_size(32 * 1024 * 1024);
+
+            let handle = builder
+                .spawn(move || {
+                    let mut row_pixels = Vec::with_capacity(crop.width as usize);
+                    let mut y = row.fetch_add(1, Ordering::Acquire);
+                    while y < crop.height {
+                        row_pixels.clear();
+                        for x in 0..crop.width {
+                            let mut sum = [0.0f64; 3];
+                            for sy in 0..strata {
+                                for sx in 0..strata {
+                                    let jitter_x = (sx as f64 + f64::rand()) / strata as f64;
+                                    let jitter_y = (sy as f64 + f64::rand()) / strata as f64;
+                                    let u =
+                                        ((crop.x + x) as f64 + jitter_x) / (image_width - 1) as f64;
+                                    let v = ((crop.y + y) as f64 + jitter_y)
+                                        / (image_height - 1) as f64;
+                                    let ray = camera.ray(u as f32, v as f32);
+                                    let color = match integrator {
+                                        Integrator::Reference => trace(&*scene, ray, max_depth),
+                                        Integrator::Camera => {
+                                            let (color, _depth) = camera.trace(
+                                                &*scene,
+                                                ray,
+                                                max_depth,
+                                                &mut RandomSampler,
+                                            );
+                                            [color.x() as f64, color.y() as f64, color.z() as f64]
+                                        }
+                                    };
+                                    sum[0] += color[0];
+                                    sum[1] += color[1];
+                                    sum[2] += color[2];
+                                }
+                            }
+                            let count = (strata * strata) as f64;
+                            row_pixels.push([sum[0] / count, sum[1] / count, sum[2] / count]);
+                        }
+
+                        let start = (y * crop.width) as usize;
+                        pixels.lock().unwrap()[start..start + crop.width as usize]
+                            .copy_from_slice(&row_pixels);
+
+                        if i == 0 {
+                            println!("reference: {:.2}%", y as f64 / crop.height as f64 * 100.0);
+                        }
+                        y = row.fetch_add(1, Ordering::Acquire);
+                    }
+                })
+                .expect("Unable to spawn reference thread");
+
+            handles.push(handle);
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let pixels = Arc::try_unwrap(pixels).unwrap().into_inner().unwrap();
+
+        Self {
+            crop,
+            samples: strata * strata,
+            pixels,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Linear radiance per pixel, rows stored bottom first.
+    pub fn pixels(&self) -> &[[f64; 3]] {
+        &self.pixels
+    }
+
+    pub fn mean(&self) -> [f64; 3] {
+        let mut sum = [0.0; 3];
+        for p in self.pixels.iter() {
+            sum[0] += p[0];
+            sum[1] += p[1];
+            sum[2] += p[2];
+        }
+        let count = self.pixels.len().max(1) as f64;
+        [sum[0] / count, sum[1] / count, sum[2] / count]
+    }
+
+    pub fn compare(&self, test: &ReferenceImage) -> Comparison {
+        assert_eq!(self.pixels.len(), test.pixels.len());
+
+        let mut squared_error = 0.0;
+        for (r, t) in self.pixels.iter().zip(test.pixels.iter()) {
+            for c in 0..3 {
+                squared_error += (r[c] - t[c]).powi(2);
+            }
+        }
+
+        Comparison {
+            reference_mean: self.mean(),
+            test_mean: test.mean(),
+            rmse: (squared_error / (self.pixels.len().max(1) * 3) as f64).sqrt(),
+        }
+    }
+
+    /// Writes the linear radiance as a little-endian PFM image.
+    pub fn write_pfm<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        write_pfm(
+            path,
+            self.crop.width,
+            self.crop.height,
+            self.pixels
+                .iter()
+                .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]),
+        )
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const THUMBNAIL_WIDTH: u32 = 160;
+const REFRESH_SECONDS: u32 = 10;
+
+struct FinishedFrame {
+    frame: u32,
+    render_time: Duration,
+    samples: u32,
+}
+
+/// Keeps an `index.html` in the animation directory listing every exported frame with a
+/// thumbnail per view, so a long sequence can be reviewed in a browser while it renders. The
+/// page reloads itself until the animation is complete.
+pub struct RenderQueue {
+    dir: PathBuf,
+    total_frames: u32,
+    views: Vec<String>,
+    finished: Vec<FinishedFrame>,
+    started: Instant,
+    last_finished: Instant,
+}
+
+impl RenderQueue {
+    pub fn new<P: AsRef<Path>>(dir: P, total_frames: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            total_frames,
+            views: Vec::new(),
+            finished: Vec::new(),
+            started: now,
+            last_finished: now,
+        }
+    }
+
+    /// The path of `frame` for a view, relative to the animation directory. The first view is
+    /// stored at the top level.
+    pub fn frame_path(view: usize, name: &str, frame: u32) -> String {
+        if view == 0 {
+            format!("frame_{:05}.png", frame)
+        } else {
+            format!("{}/frame_{:05}.png", name, frame)
+        }
+    }
+
+    /// Writes thumbnails for a frame whose views have just been exported and updates the
+    /// index. `views` holds each view's name and its top row first RGB8 pixels.
+    pub fn finish_frame(
+        &mut self,
+        frame: u32,
+        samples: u32,
+        width: u32,
+        height: u32,
+        views: &[(&str, Vec<u8>)],
+    ) {
+        if views.len() > self.views.len() {
+            self.views = views.iter().map(|(name, _)| name.to_string()).collect();
+        }
+
+        for (view, (name, pixels)) in views.iter().enumerate() {
+            let path = self
+                .dir
+                .join("thumbnails")
+                .join(Self::frame_path(view, name, frame));
+            if let Err(error) = write_thumbnail(&path, pixels, width, height) {
+                eprintln!("Unable to save thumbnail: {:?}", error);
+            }
+        }
+
+        let now = Instant::now();
+        self.finished.push(FinishedFrame {
+            frame,
+            render_time: now - self.last_finished,
+            samples,
+        });
+        self.last_finished = now;
+
+        if let Err(error) = std::fs::write(self.dir.join("index.html"), self.html()) {
+            eprintln!("Unable to save render queue index: {:?}", error);
+        }
+    }
+
+    fn html(&self) -> String {
+        let done = self.finished.len() as u32 >= self.total_frames;
+        let elapsed = self.started.elapsed().as_secs_f32();
+        let remaining = self.total_frames.saturating_sub(self.finished.len() as u32);
+        let per_frame = elapsed / self.finished.len().max(1) as f32;
+
+        let mut html = String::new();
+        let _ = writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        );
+        if !done {
+            let _ = writeln!(
+                html,
+                "<meta http-equiv=\"refresh\" content=\"{}\">",
+                REFRESH_SECONDS
+            );
+        }
+        let _ = writeln!(html, "<title>Render queue</title>");
+        let _ = writeln!(
+            html,
+            "<style>\
+             body {{ font-family: sans-serif; background: #222; color: #ddd; }}\
+             table {{ border-collapse: collapse; }}\
+             td, th {{ padding: 4px 8px; text-align: left; }}\
+             img {{ width: {}px; display: block; }}\
+             .rendering {{ color: #fc6; }}\
+             </style>",
+            THUMBNAIL_WIDTH
+        );
+        let _ = writeln!(html, "</head>\n<body>");
+        let _ = writeln!(
+            html,
+            "<p>{} of {} frames finished, {:.1} minutes elapsed, ~{:.1} minutes remaining</p>",
+            self.finished.len(),
+            self.total_frames,
+            elapsed / 60.0,
+            remaining as f32 * per_frame / 60.0
+        );
+
+        let _ = write!(html, "<table>\n<tr><th>Frame</th><th>Status</th>");
+        for view in self.views.iter() {
+            let _ = write!(html, "<th>{}</th>", escape(view));
+        }
+        let _ = writeln!(html, "</tr>");
+
+        if let Some(last) = self.finished.last().filter(|_| !done) {
+            let _ = writeln!(
+                html,
+                "<tr class=\"rendering\"><td>{}</td><td>rendering</td></tr>",
+                last.frame + 1
+            );
+        }
+
+        for finished in self.finished.iter().rev() {
+            let _ = write!(
+                html,
+                "<tr><td>{}</td><td>done in {:.1}s, {} samples</td>",
+                finished.frame,
+                finished.render_time.as_secs_f32(),
+                finished.samples
+            );
+            for (view, name) in self.views.iter().enumerate() {
+                let path = escape(&Self::frame_path(view, name, finished.frame));
+                let _ = write!(
+                    html,
+                    "<td><a href=\"{0}\"><img src=\"thumbnails/{0}\" loading=\"lazy\"></a></td>",
+                    path
+                );
+            }
+            let _ = writeln!(html, "</tr>");
+        }
+
+        let _ = writeln!(html, "</table>\n</body>\n</html>");
+        html
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Box filters `pixels` down to `THUMBNAIL_WIDTH` wide and saves it as a PNG.
+fn write_thumbnail(
+    path: &Path,
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let scale = (width as f32 / THUMBNAIL_WIDTH as f32).max(1.0);
+    let thumb_width = ((width as f32 / scale) as u32).max(1);
+    let thumb_height = ((height as f32 / scale) as u32).max(1);
+
+    let mut thumbnail = Vec::with_capacity((thumb_width * thumb_height * 3) as usize);
+    for ty in 0..thumb_height {
+        let y0 = (ty as f32 * scale) as u32;
+        let y1 = (((ty + 1) as f32 * scale) as u32).max(y0 + 1).min(height);
+        for tx in 0..thumb_width {
+            let x0 = (tx as f32 * scale) as u32;
+            let x1 = (((tx + 1) as f32 * scale) as u32).max(x0 + 1).min(width);
+
+            let mut sum = [0u32; 3];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let offset = ((y * width + x) * 3) as usize;
+                    for (channel, sum) in sum.iter_mut().enumerate() {
+                        *sum += pixels[offset + channel] as u32;
+                    }
+                }
+            }
+
+            let count = (y1 - y0) * (x1 - x0);
+            thumbnail.extend(sum.iter().map(|s| (s / count) as u8));
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image::save_buffer_with_format(
+        path,
+        &thumbnail,
+        thumb_width,
+        thumb_height,
+        image::ColorType::Rgb8,
+        image::ImageFormat::Png,
+    )?;
+
+    Ok(())
+}
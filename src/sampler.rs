@@ -0,0 +1,308 @@
+//! Sources of the random numbers that place each sample. A sampler is started at a pixel and
+//! sample index, then hands out numbers one dimension at a time, so each use of a random number
+//! along a sample, such as the pixel jitter or lens position, gets its own stream. Samplers that
+//! know which pixel and sample they are on can spread a pixel's samples more evenly than
+//! independent random numbers do.
+
+use crate::math::{Num, V2};
+
+/// Dimensions taken by the pixel jitter and camera ray of each sample, the bounces along its
+/// path start after them.
+pub const CAMERA_DIMENSIONS: u32 = 4;
+/// Dimensions set aside for each bounce, the first two pick a light and a point on it and the
+/// rest are for sampling the BSDF. Materials asking for more than that share dimensions with
+/// the next bounce.
+pub const BOUNCE_DIMENSIONS: u32 = 8;
+const LIGHT_DIMENSIONS: u32 = 2;
+
+pub trait Sampler: Send {
+    /// Starts sample `index` of `pixel`, the next number returned is from its first dimension.
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    /// Skips to `dimension` of the current sample, so what follows always gets the same
+    /// dimensions however many were asked for before it.
+    fn start_dimension(&mut self, dimension: u32);
+
+    /// The next dimension of the current sample, in `0.0..1.0`.
+    fn get_1d(&mut self) -> f32;
+
+    /// The next two dimensions of the current sample, each in `0.0..1.0`.
+    fn get_2d(&mut self) -> V2;
+
+    /// Skips to the dimensions for the light sample taken at the surface `bounce` bounces
+    /// after the camera.
+    fn start_light(&mut self, bounce: u32) {
+        self.start_dimension(CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS);
+    }
+
+    /// Skips to the dimensions for the BSDF sample taken at the surface `bounce` bounces after
+    /// the camera.
+    fn start_bsdf(&mut self, bounce: u32) {
+        self.start_dimension(CAMERA_DIMENSIONS + bounce * BOUNCE_DIMENSIONS + LIGHT_DIMENSIONS);
+    }
+}
+
+/// The samplers the viewer can be configured with.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplerKind {
+    Random,
+    Stratified { strata: u32 },
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn build(&self) -> Box<dyn Sampler> {
+        match *self {
+            SamplerKind::Random => Box::new(RandomSampler),
+            SamplerKind::Stratified { strata } => Box::new(StratifiedSampler::new(strata)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new()),
+        }
+    }
+}
+
+/// Independent uniform random numbers, ignoring the pixel and sample.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RandomSampler;
+
+impl Sampler for RandomSampler {
+    fn start_pixel_sample(&mut self, _pixel: (u32, u32), _index: u32) {}
+
+    fn start_dimension(&mut self, _dimension: u32) {}
+
+    fn get_1d(&mut self) -> f32 {
+        f32::rand()
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        V2::new(f32::rand(), f32::rand())
+    }
+}
+
+/// Jittered stratification, each dimension is split into `strata * strata` cells and every run
+/// of that many consecutive samples of a pixel visits each cell once, in a shuffled order that
+/// differs per pixel and dimension. Two dimension requests are split into a `strata` by `strata`
+/// grid. As the viewer accumulates samples without a fixed count the cells are revisited in a
+/// fresh order each run.
+#[derive(Debug, Copy, Clone)]
+pub struct StratifiedSampler {
+    strata: u32,
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(strata: u32) -> Self {
+        Self {
+            strata: strata.max(1),
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The cell the current sample falls in for the next dimension.
+    fn next_cell(&mut self) -> u32 {
+        let cells = self.strata * self.strata;
+        let run = self.index / cells;
+        let seed = hash(self.pixel_seed ^ hash(self.dimension ^ hash(run)));
+        self.dimension += 1;
+
+        permute(self.index % cells, cells, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = hash(pixel.0 ^ hash(pixel.1));
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn start_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let cells = self.strata * self.strata;
+        let cell = self.next_cell();
+        ((cell as f32 + f32::rand()) / cells as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        let cell = self.next_cell();
+        let x = cell % self.strata;
+        let y = cell / self.strata;
+        V2::new(
+            ((x as f32 + f32::rand()) / self.strata as f32).min(ONE_MINUS_EPSILON),
+            ((y as f32 + f32::rand()) / self.strata as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+/// Owen-scrambled Sobol points, following Burley's "Practical Hash-based Owen Scrambling". Each
+/// request takes the next dimension of the sample: one dimension requests use the first Sobol
+/// dimension and two dimension requests the first two, with the sample index shuffled
+/// separately for every request so that dimensions aren't correlated with each other. The
+/// shuffle and scrambling are seeded per pixel, keeping neighbouring pixels independent while
+/// every power of two run of a pixel's samples stays well stratified, with no fixed sample
+/// count.
+///
+/// Dimensions are allocated in the order they are asked for along a sample, so callers should
+/// request them in the same order every sample, as `Camera::sample_ray` does, or skip to a
+/// fixed dimension first, as `Camera::trace` does at each bounce.
+#[derive(Debug, Clone)]
+pub struct SobolSampler {
+    directions: [u32; 32],
+    pixel_seed: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new() -> Self {
+        // The second dimension's generator matrix is Pascal's triangle mod 2, m = 1, 3, 5, 15..
+        let mut directions = [0; 32];
+        let mut direction = 1 << 31;
+        for d in directions.iter_mut() {
+            *d = direction;
+            direction ^= direction >> 1;
+        }
+
+        Self {
+            directions,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// The seed of the next dimension of the current sample.
+    fn next_seed(&mut self) -> u32 {
+        let seed = hash(self.pixel_seed ^ hash(self.dimension));
+        self.dimension += 1;
+        seed
+    }
+
+    fn second_dimension(&self, index: u32) -> u32 {
+        self.directions
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| index >> bit & 1 == 1)
+            .fold(0, |sum, (_, direction)| sum ^ direction)
+    }
+}
+
+impl Default for SobolSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = hash(pixel.0 ^ hash(pixel.1 ^ 0x5bd1e995));
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn start_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        let x = nested_uniform_scramble(index.reverse_bits(), hash(seed ^ 1));
+        to_unit(x)
+    }
+
+    fn get_2d(&mut self) -> V2 {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed);
+        let x = nested_uniform_scramble(index.reverse_bits(), hash(seed ^ 1));
+        let y = nested_uniform_scramble(self.second_dimension(index), hash(seed ^ 2));
+        V2::new(to_unit(x), to_unit(y))
+    }
+}
+
+/// An Owen scramble of the bits of `x`, each bit flipped by a hash of the bits above it.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+fn to_unit(x: u32) -> f32 {
+    (x as f32 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+/// The largest `f32` below one, samples are kept under it so `0.0..1.0` holds after rounding.
+pub(crate) const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Maps a point in the unit square onto the unit disk, keeping stratified points evenly spread.
+pub fn concentric_disk(point: V2) -> V2 {
+    let x = point.x() * 2.0 - 1.0;
+    let y = point.y() * 2.0 - 1.0;
+    if x == 0.0 && y == 0.0 {
+        return V2::new(0.0, 0.0);
+    }
+
+    let quarter_pi = std::f32::consts::FRAC_PI_4;
+    let (radius, angle) = if x.abs() > y.abs() {
+        (x, quarter_pi * (y / x))
+    } else {
+        (y, std::f32::consts::FRAC_PI_2 - quarter_pi * (x / y))
+    };
+
+    V2::new(radius * angle.cos(), radius * angle.sin())
+}
+
+pub(crate) fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Position of `index` in a random permutation of `0..len` chosen by `seed`, without storing
+/// the permutation. From Kensler's "Correlated Multi-Jittered Sampling".
+fn permute(mut index: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170893d);
+        index ^= seed >> 16;
+        index ^= (index & mask) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929eb3f);
+        index ^= seed >> 23;
+        index ^= (index & mask) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935fa69);
+        index ^= (index & mask) >> 11;
+        index = index.wrapping_mul(0x74dcb303);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0x9e501cc3);
+        index ^= (index & mask) >> 2;
+        index = index.wrapping_mul(0xc860a3df);
+        index &= mask;
+        index ^= index >> 5;
+
+        if index < len {
+            return ((index as u64 + seed as u64) % len as u64) as u32;
+        }
+    }
+}
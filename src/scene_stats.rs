@@ -0,0 +1,111 @@
+use std::collections::BTreeSet;
+
+use crate::geom::BoundingBox;
+use crate::material::Background;
+use crate::world::{Camera, World};
+
+/// Stands in for an infinite far plane, large enough for any scene while keeping the frustum's
+/// plane distances finite.
+const FAR: f32 = 1.0e30;
+
+/// What one object in a `World` costs and whether the camera can see it.
+pub struct ObjectStats {
+    pub index: usize,
+    pub primitives: usize,
+    pub bounds: Option<BoundingBox>,
+    pub materials: BTreeSet<String>,
+    pub memory_bytes: usize,
+    pub in_frustum: bool,
+}
+
+/// Gathers `ObjectStats` for every object in `world` against the frustum of `camera`. Every
+/// triangle is visited to find the materials in use, so this is meant to be run on demand.
+pub fn collect<B: Background>(world: &World<B>, camera: &Camera) -> Vec<ObjectStats> {
+    let frustum = camera.frustum(0.0, FAR);
+
+    (0..world.object_count())
+        .filter_map(|index| world.object(index).map(|object| (index, object)))
+        .map(|(index, object)| {
+            let mut materials = BTreeSet::new();
+            object.visit_triangles(&mut |_, material| {
+                materials.insert(material.name());
+            });
+
+            let bounds = object.bounding_box();
+            ObjectStats {
+                index,
+                primitives: object.primitive_count(),
+                bounds,
+                materials,
+                memory_bytes: object.memory_bytes(),
+                in_frustum: bounds.map_or(true, |bounds| frustum.intersects(bounds)),
+            }
+        })
+        .collect()
+}
+
+/// Prints a table of `collect`'s statistics, followed by totals.
+pub fn report<B: Background>(world: &World<B>, camera: &Camera) {
+    let stats = collect(world, camera);
+
+    println!(
+        "{:>6} {:>10} {:>10} {:>7}  {:<44} materials",
+        "object", "primitives", "memory", "visible", "bounds"
+    );
+    for object in stats.iter() {
+        let bounds = match object.bounds {
+            Some(bounds) => {
+                let (min, max) = (bounds.minimum(), bounds.maximum());
+                format!(
+                    "({:.1}, {:.1}, {:.1})..({:.1}, {:.1}, {:.1})",
+                    min.x(),
+                    min.y(),
+                    min.z(),
+                    max.x(),
+                    max.y(),
+                    max.z()
+                )
+            }
+            None => String::from("unbounded"),
+        };
+        let materials = if object.materials.is_empty() {
+            String::from("-")
+        } else {
+            object
+                .materials
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        println!(
+            "{:>6} {:>10} {:>10} {:>7}  {:<44} {}",
+            object.index,
+            object.primitives,
+            format_bytes(object.memory_bytes),
+            if object.in_frustum { "yes" } else { "no" },
+            bounds,
+            materials
+        );
+    }
+
+    let visible = stats.iter().filter(|o| o.in_frustum).count();
+    println!(
+        "{} objects ({} in view), {} primitives, {} (shared data counted per object)",
+        stats.len(),
+        visible,
+        stats.iter().map(|o| o.primitives).sum::<usize>(),
+        format_bytes(stats.iter().map(|o| o.memory_bytes).sum())
+    );
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
@@ -1,8 +1,8 @@
 #![allow(dead_code)]
 
+use crate::input::InputCollection;
 use crate::material::Background;
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 mod cornell;
 pub use cornell::CornellBox;
@@ -10,6 +10,12 @@ pub use cornell::CornellBox;
 mod eve;
 pub use eve::Eve;
 
+mod fleet;
+pub use fleet::{Fleet, FlightPath, Formation};
+
+mod file;
+pub use file::FileScene;
+
 mod lucy;
 pub use lucy::Lucy;
 
@@ -19,15 +25,33 @@ pub use sphere_grid::SphereGrid;
 mod menger;
 pub use menger::Menger;
 
+#[cfg(not(target_arch = "wasm32"))]
 mod mario;
+#[cfg(not(target_arch = "wasm32"))]
 pub use mario::Mario;
 
+mod randomized;
+pub use randomized::{FrameLabels, ObjectLabel, Randomized};
+
 pub trait Scene {
     type Background: Background;
+    fn name(&self) -> &str;
     fn generate(
         &mut self,
         animation_t: f32,
         frame: u32,
         input: &InputCollection,
     ) -> (World<Self::Background>, Camera);
+
+    /// Generates the world along with any number of named cameras, each rendered into its own
+    /// image in the same pass. The first camera is the one displayed.
+    fn generate_views(
+        &mut self,
+        animation_t: f32,
+        frame: u32,
+        input: &InputCollection,
+    ) -> (World<Self::Background>, Vec<(String, Camera)>) {
+        let (world, camera) = self.generate(animation_t, frame, input);
+        (world, vec![(String::from("main"), camera)])
+    }
 }
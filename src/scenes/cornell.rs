@@ -1,11 +1,12 @@
 use super::Scene;
-use crate::geom::{Model, Sphere, Triangle};
+use crate::geom::Sphere;
+use crate::input::InputCollection;
+use crate::light::QuadLight;
 use crate::material::{Dielectric, DiffuseLight, Lambertian, SolidBackground};
 use crate::math::{V3, V4};
-use crate::ply_loader::PlyLoader;
+use crate::model_loader::ModelLoader;
 use crate::texture::SolidColor;
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 pub struct CornellBox {
     aspect_ratio: f32,
@@ -20,6 +21,10 @@ impl CornellBox {
 impl Scene for CornellBox {
     type Background = SolidBackground;
 
+    fn name(&self) -> &str {
+        "Cornell Box"
+    }
+
     fn generate(
         &mut self,
         _animation_t: f32,
@@ -34,10 +39,7 @@ impl Scene for CornellBox {
         let light = DiffuseLight::new(V3::fill(8.0));
         let sphere_material = Dielectric::new(1.3);
 
-        let cube =
-            PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
-
-        let cube = Model::new(cube);
+        let cube = ModelLoader::new("cube.ply").load().unwrap();
 
         world.add(
             cube.instance(V3::new(-10.0, 5.0, 0.0), V3::zero(), V3::fill(5.0))
@@ -62,14 +64,12 @@ impl Scene for CornellBox {
 
         world.add(Sphere::new(sphere_material, V3::new(1.75, 2.0, 2.25), 2.0));
 
-        world.add(
-            cube.instance(
-                V3::new(0.0, 10.0 - 0.00011, 0.0),
-                V3::zero(),
-                V3::new(1.0, 0.0001, 1.0),
-            )
-            .with_material(light),
-        );
+        world.add_light(QuadLight::new(
+            light,
+            V3::new(-1.0, 10.0 - 0.0002, -1.0),
+            V3::new(2.0, 0.0, 0.0),
+            V3::new(0.0, 0.0, 2.0),
+        ));
 
         world.add(
             cube.instance(
@@ -1,72 +1,119 @@
+use super::fleet::{Fleet, FlightPath, Formation};
 use super::Scene;
-use crate::geom::{Sphere, Volume};
+use crate::animation::{Animator, Curve};
+use crate::eve;
+use crate::geom::{Density, Fog, Model, Sphere};
+use crate::input::InputCollection;
+use crate::light::SphereLight;
 use crate::material::{Background, DiffuseLight};
-use crate::math::{Num, V3};
+use crate::math::{M4, V3};
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 pub struct Eve {
     aspect_ratio: f32,
+    animator: Animator,
+    venture: Model<()>,
+    orca: Model<()>,
+    fleets: Vec<Fleet>,
 }
 
 impl Eve {
     pub fn new(aspect_ratio: f32) -> Self {
-        Self { aspect_ratio }
+        let mut animator = Animator::new();
+        let engine_glow = animator.bind(Curve::pulse(0.3, 1.5, 20));
+
+        let venture = eve::load_ship_with_glow(eve::Hull::Stratios, &engine_glow);
+        let orca = eve::load_ship(eve::Hull::Nestor);
+
+        // The mining wing drifts past the camera toward the orca while an escort wedge sweeps
+        // across in front of it, trailing one ship after another
+        let wing = Fleet::new(
+            36,
+            Formation::Grid {
+                columns: 6,
+                spacing: 190.0,
+            },
+            FlightPath::new()
+                .key(0.0, V3::new(-95.0, 0.0, -35.0))
+                .key(1.0, V3::new(-95.0, 0.0, -155.0)),
+        )
+        .with_scatter(V3::new(0.0, 150.0, 0.0))
+        .with_wobble(V3::fill(0.016))
+        .with_attitude(V3::new(-0.03, 0.0, 0.0))
+        .with_scale(0.2)
+        .with_seed(1);
+
+        let escort = Fleet::new(
+            5,
+            Formation::Wedge {
+                spacing: 45.0,
+                sweep: 60.0,
+            },
+            FlightPath::new()
+                .key(0.0, V3::new(700.0, 120.0, 250.0))
+                .key(0.5, V3::new(100.0, 40.0, 150.0))
+                .key(1.0, V3::new(-700.0, -20.0, 50.0)),
+        )
+        .with_stagger(0.01)
+        .with_wobble(V3::fill(0.01))
+        .with_scale(0.2)
+        .with_seed(2);
+
+        Self {
+            aspect_ratio,
+            animator,
+            venture,
+            orca,
+            fleets: vec![wing, escort],
+        }
     }
 }
 
 impl Scene for Eve {
     type Background = Box<dyn Background>;
 
+    fn name(&self) -> &str {
+        "Eve"
+    }
+
     fn generate(
         &mut self,
-        _animation_t: f32,
+        animation_t: f32,
         _frame: u32,
         _input: &InputCollection,
     ) -> (World<Self::Background>, Camera) {
-        use crate::eve;
+        self.animator.update(animation_t);
+
         let cube_map = eve::environment("wormhole_class_05", V3::zero());
         let mut world = World::new(Box::new(cube_map) as Self::Background);
 
-        let venture = eve::load_ship(eve::Hull::Stratios);
-        let orca = eve::load_ship(eve::Hull::Nestor);
-
         let orca_pos = V3::new(-1250.0, 5.0, 0.0);
-        world.add(orca.instance(
+        world.add(self.orca.instance(
             orca_pos,
             V3::zero() + ((V3::rand() - 0.5) / 60.0),
             V3::one(),
         ));
 
-        world.add(Volume::new(
+        world.add(Fog::new(
             Sphere::new((), orca_pos, 1700.0),
-            0.0006,
+            Density::falloff(0.0006, 1.0 / 1200.0, M4::translation(orca_pos)),
             V3::fill(0.4),
         ));
 
-        let sun = Sphere::new(
+        let sun = SphereLight::new(
             DiffuseLight::new(V3::new(4.0, 4.0, 5.0) * 10.0),
             V3::new(10000.0, -4000.0, 4800.0),
             1500.0,
         );
-        world.add(sun);
+        world.add_light(sun);
 
         let look_from = V3::new(0.0, -20.0, 500.0);
-        let rotation = V3::new(-0.03, 0.0, 0.0);
-
-        for x in 0..6 {
-            for z in 0..6 {
-                let x = (x as f32 - 3.0) * 190.0;
-                let z = (z as f32 - 3.0) * 190.0;
-                let y = (f32::rand() * 2.0 - 1.0) * 150.0;
-                let pos = V3::new(x, y, z);
-                if pos.distance(look_from) > 50.0 {
-                    let instance = venture.instance(
-                        pos,
-                        rotation + ((V3::rand() - 0.5) / 30.5),
-                        V3::fill(0.2),
-                    );
-                    world.add(instance);
+
+        for fleet in self.fleets.iter() {
+            for transform in fleet.transforms(animation_t) {
+                let position = transform.columns()[3].contract();
+                if position.distance(look_from) > 50.0 {
+                    world.add(self.venture.instance_transform(transform));
                 }
             }
         }
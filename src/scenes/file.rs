@@ -0,0 +1,648 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::Scene;
+use crate::geom::{Cuboid, Instance, Intersect, Model, Sphere};
+use crate::input::InputCollection;
+use crate::json::{self, Value};
+use crate::light::{DirectionalLight, SphereLight, SpotLight};
+use crate::material::{
+    Background, Conductor, Dielectric, DiffuseLight, Lambertian, MaterialTable, Metal, OrenNayar,
+    PreethamSky, Principled, SkyBackground, SkySphere, SolidBackground, Specular, TableMaterial,
+};
+use crate::math::{V3, V4};
+use crate::model_loader::ModelLoader;
+use crate::texture::{SolidColor, Surface, Texture, WrapMode};
+use crate::world::{Camera, World};
+
+/// A scene described by a JSON file rather than code. The file is read once, models and
+/// textures are loaded up front and relative paths are resolved against the file's directory.
+///
+/// ```json
+/// {
+///   "name": "Example",
+///   "camera": { "look_from": [0, 5, 20], "look_at": [0, 5, 0], "fov": 37 },
+///   "background": { "type": "solid", "color": [0, 0, 0] },
+///   "models": { "cube": "cube.ply" },
+///   "materials": {
+///     "white": { "type": "lambertian", "color": [1, 1, 1] },
+///     "glass": { "type": "dielectric", "refraction_index": 1.5 }
+///   },
+///   "objects": [
+///     { "model": "cube", "material": "white", "scale": [5, 0.1, 5] },
+///     { "sphere": { "center": [0, 2, 0], "radius": 2 }, "material": "glass" }
+///   ],
+///   "lights": [{ "position": [0, 10, 0], "radius": 1, "color": [1, 1, 1], "strength": 8 }]
+/// }
+/// ```
+///
+/// The camera also takes `up`, `aperture` and `focus_distance`. Backgrounds are `solid`, `sky`,
+/// `sky_sphere` with a `texture`, or `preetham` with a `sun_direction`, `turbidity` and `strength`,
+/// which also adds its sun. Materials are `lambertian`, `oren_nayar` with `sigma` in degrees,
+/// `metal` with `roughness`, an optional `bitangent_roughness` for brushed metal, or the older
+/// `fuzz`, `conductor` with a `preset` of `gold`, `silver`, `copper`, `aluminum` or `iron`, or an
+/// `eta` and `k`, and a `roughness`, `dielectric` with a `refraction_index`, `roughness` and per
+/// color `absorption`, `specular` with a `refraction_index`, `principled` with `metallic`,
+/// `roughness`, `specular`, `sheen`, `sheen_tint`, `clearcoat`, `clearcoat_roughness`,
+/// `transmission` and `refraction_index`, and `light` with `strength` and `group`, colored by
+/// `color` or a `texture` in any format `Texture::load` reads. Objects are a `model`, `sphere` or
+/// `cuboid` with `minimum` and `maximum`, placed by `translation`, `rotation` in degrees and
+/// `scale`. Models without a `material` keep the materials they were loaded with. Lights with a
+/// `direction` towards them instead of a `position` are distant, like the sun, with an
+/// `angular_radius` in degrees and `strength` as the irradiance on a surface facing them. Lights
+/// with a `target` are spot lights shining on it, fading out from `inner_angle` to `angle` in
+/// degrees.
+pub struct FileScene {
+    name: String,
+    aspect_ratio: f32,
+    camera: CameraDescription,
+    background: BackgroundDescription,
+    objects: Vec<ObjectDescription>,
+}
+
+struct CameraDescription {
+    look_from: V3,
+    look_at: V3,
+    up: V3,
+    fov: f32,
+    aperture: f32,
+    focus_distance: f32,
+}
+
+enum BackgroundDescription {
+    Solid(V3),
+    Sky,
+    SkySphere(Arc<Texture>),
+    Preetham(V3, f32, f32),
+}
+
+enum Shape {
+    Model(Model<()>, Option<TableMaterial>),
+    Sphere(V3, f32, TableMaterial),
+    Cuboid(V3, V3, TableMaterial),
+    Light(V3, f32, DiffuseLight),
+    Directional(V3, f32, DiffuseLight),
+    Spot(V3, V3, f32, f32, DiffuseLight),
+}
+
+struct ObjectDescription {
+    shape: Shape,
+    translation: V3,
+    rotation: V3,
+    scale: V3,
+}
+
+impl FileScene {
+    pub fn load<P: AsRef<Path>>(path: P, aspect_ratio: f32) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut scene = Self::parse(&text, base, aspect_ratio)?;
+        if scene.name.is_empty() {
+            scene.name = path.display().to_string();
+        }
+
+        Ok(scene)
+    }
+
+    /// Reads a scene from JSON text, resolving relative paths against `base`.
+    pub fn parse<P: AsRef<Path>>(
+        text: &str,
+        base: P,
+        aspect_ratio: f32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let description = json::parse(text)?;
+        let parser = Parser {
+            base: base.as_ref().to_path_buf(),
+        };
+
+        let name = match description.get("name") {
+            Some(name) => parser.string(name, "name")?.to_string(),
+            None => String::new(),
+        };
+
+        let camera = parser.camera(parser.field(&description, "camera", "")?)?;
+
+        let background = match description.get("background") {
+            Some(background) => parser.background(background)?,
+            None => BackgroundDescription::Sky,
+        };
+
+        let mut models = HashMap::new();
+        if let Some(entries) = description.get("models") {
+            for (name, model) in parser.object(entries, "models")? {
+                let model_path = parser.string(model, &format!("models.{}", name))?;
+                let model = ModelLoader::new(parser.resolve(model_path))
+                    .load()
+                    .map_err(|error| format!("models.{}: {}", name, error))?;
+                models.insert(name.as_str(), model);
+            }
+        }
+
+        let mut table = MaterialTable::new();
+        let mut material_indexes = HashMap::new();
+        if let Some(entries) = description.get("materials") {
+            for (name, material) in parser.object(entries, "materials")? {
+                let index =
+                    parser.material(&mut table, material, &format!("materials.{}", name))?;
+                material_indexes.insert(name.as_str(), index);
+            }
+        }
+
+        let lights = description
+            .get("lights")
+            .map(|lights| parser.array(lights, "lights"))
+            .transpose()?
+            .unwrap_or(&[]);
+        let mut light_materials = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            let context = format!("lights[{}]", i);
+            light_materials.push(parser.diffuse_light(light, &context)?);
+        }
+
+        let table = table.shared();
+        let material = |value: &Value, context: &str| -> Result<TableMaterial, Box<dyn Error>> {
+            let name = parser.string(value, context)?;
+            match material_indexes.get(name) {
+                Some(&index) => Ok(TableMaterial::new(table.clone(), index)),
+                None => Err(format!("{}: unknown material '{}'", context, name))?,
+            }
+        };
+
+        let mut objects = Vec::new();
+        if let Some(entries) = description.get("objects") {
+            for (i, object) in parser.array(entries, "objects")?.iter().enumerate() {
+                let context = format!("objects[{}]", i);
+                let material_context = format!("{}.material", context);
+
+                let shape = if let Some(model) = object.get("model") {
+                    let name = parser.string(model, &format!("{}.model", context))?;
+                    let model = models
+                        .get(name)
+                        .ok_or_else(|| format!("{}: unknown model '{}'", context, name))?;
+                    let model_material = object
+                        .get("material")
+                        .map(|m| material(m, &material_context))
+                        .transpose()?;
+                    Shape::Model(model.clone(), model_material)
+                } else if let Some(sphere) = object.get("sphere") {
+                    let sphere_context = format!("{}.sphere", context);
+                    Shape::Sphere(
+                        parser.vector(
+                            parser.field(sphere, "center", &sphere_context)?,
+                            &sphere_context,
+                        )?,
+                        parser.number(
+                            parser.field(sphere, "radius", &sphere_context)?,
+                            &sphere_context,
+                        )?,
+                        material(
+                            parser.field(object, "material", &context)?,
+                            &material_context,
+                        )?,
+                    )
+                } else if let Some(cuboid) = object.get("cuboid") {
+                    let cuboid_context = format!("{}.cuboid", context);
+                    Shape::Cuboid(
+                        parser.vector(
+                            parser.field(cuboid, "minimum", &cuboid_context)?,
+                            &cuboid_context,
+                        )?,
+                        parser.vector(
+                            parser.field(cuboid, "maximum", &cuboid_context)?,
+                            &cuboid_context,
+                        )?,
+                        material(
+                            parser.field(object, "material", &context)?,
+                            &material_context,
+                        )?,
+                    )
+                } else {
+                    return Err(format!("{}: expected a model, sphere or cuboid", context))?;
+                };
+
+                let rotation = parser.vector_or(object, "rotation", V3::zero(), &context)?;
+                objects.push(ObjectDescription {
+                    shape,
+                    translation: parser.vector_or(object, "translation", V3::zero(), &context)?,
+                    rotation: rotation / 360.0,
+                    scale: parser.vector_or(object, "scale", V3::one(), &context)?,
+                });
+            }
+        }
+
+        for (i, (light, material)) in lights.iter().zip(light_materials).enumerate() {
+            let context = format!("lights[{}]", i);
+            let shape = match light.get("direction") {
+                Some(direction) => Shape::Directional(
+                    parser.vector(direction, &context)?,
+                    parser
+                        .number_or(light, "angular_radius", 0.0, &context)?
+                        .to_radians(),
+                    material,
+                ),
+                None => {
+                    let position =
+                        parser.vector(parser.field(light, "position", &context)?, &context)?;
+                    match light.get("target") {
+                        Some(target) => {
+                            let angle = parser.number_or(light, "angle", 30.0, &context)?;
+                            Shape::Spot(
+                                position,
+                                parser.vector(target, &context)? - position,
+                                parser
+                                    .number_or(light, "inner_angle", angle, &context)?
+                                    .to_radians(),
+                                angle.to_radians(),
+                                material,
+                            )
+                        }
+                        None => Shape::Light(
+                            position,
+                            parser.number_or(light, "radius", 0.5, &context)?,
+                            material,
+                        ),
+                    }
+                }
+            };
+            objects.push(ObjectDescription {
+                shape,
+                translation: V3::zero(),
+                rotation: V3::zero(),
+                scale: V3::one(),
+            });
+        }
+
+        Ok(Self {
+            name,
+            aspect_ratio,
+            camera,
+            background,
+            objects,
+        })
+    }
+}
+
+impl Scene for FileScene {
+    type Background = Box<dyn Background>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn generate(
+        &mut self,
+        _animation_t: f32,
+        _frame: u32,
+        _input: &InputCollection,
+    ) -> (World<Self::Background>, Camera) {
+        let mut sun = None;
+        let background: Box<dyn Background> = match &self.background {
+            BackgroundDescription::Solid(color) => Box::new(SolidBackground::new(*color)),
+            BackgroundDescription::Sky => Box::new(SkyBackground),
+            BackgroundDescription::SkySphere(texture) => Box::new(SkySphere::new(texture.clone())),
+            BackgroundDescription::Preetham(sun_direction, turbidity, strength) => {
+                let sky = PreethamSky::new(*sun_direction, *turbidity).with_strength(*strength);
+                sun = Some(sky.sun());
+                Box::new(sky)
+            }
+        };
+        let mut world = World::new(background);
+        if let Some(sun) = sun {
+            world.add_sampled_light(sun);
+        }
+
+        for object in self.objects.iter() {
+            object.add_to(&mut world);
+        }
+
+        let camera = Camera::new(
+            self.camera.fov,
+            self.camera.look_from,
+            self.camera.look_at,
+            self.camera.up,
+            self.aspect_ratio,
+            self.camera.aperture,
+            self.camera.focus_distance,
+        );
+
+        (world, camera)
+    }
+}
+
+impl ObjectDescription {
+    fn add_to<B: Background>(&self, world: &mut World<B>) {
+        match &self.shape {
+            Shape::Model(model, material) => {
+                let instance = model.instance(self.translation, self.rotation, self.scale);
+                match material {
+                    Some(material) => world.add(instance.with_material(material.clone())),
+                    None => world.add(instance),
+                }
+            }
+            Shape::Sphere(center, radius, material) => {
+                self.add_shape(world, Sphere::new(material.clone(), *center, *radius))
+            }
+            Shape::Cuboid(minimum, maximum, material) => {
+                self.add_shape(world, Cuboid::new(material.clone(), *minimum, *maximum))
+            }
+            Shape::Light(center, radius, material) => {
+                world.add_light(SphereLight::new(material.clone(), *center, *radius))
+            }
+            Shape::Directional(direction, radius, material) => world.add_sampled_light(
+                DirectionalLight::new(material.clone(), *direction).with_angular_radius(*radius),
+            ),
+            Shape::Spot(position, direction, inner, outer, material) => world.add_sampled_light(
+                SpotLight::new(material.clone(), *position, *direction, *inner, *outer),
+            ),
+        }
+    }
+
+    fn add_shape<B: Background, I: 'static + Intersect>(&self, world: &mut World<B>, shape: I) {
+        if self.translation == V3::zero() && self.rotation == V3::zero() && self.scale == V3::one()
+        {
+            world.add(shape);
+        } else {
+            world.add(Instance::<()>::of(
+                shape,
+                self.translation,
+                self.rotation,
+                self.scale,
+            ));
+        }
+    }
+}
+
+struct Parser {
+    base: PathBuf,
+}
+
+impl Parser {
+    fn resolve(&self, path: &str) -> PathBuf {
+        self.base.join(path)
+    }
+
+    fn field<'a>(
+        &self,
+        value: &'a Value,
+        key: &str,
+        context: &str,
+    ) -> Result<&'a Value, Box<dyn Error>> {
+        match value.get(key) {
+            Some(field) => Ok(field),
+            None if context.is_empty() => Err(format!("missing '{}'", key))?,
+            None => Err(format!("{}: missing '{}'", context, key))?,
+        }
+    }
+
+    fn string<'a>(&self, value: &'a Value, context: &str) -> Result<&'a str, Box<dyn Error>> {
+        value
+            .as_str()
+            .ok_or_else(|| format!("{}: expected a string", context).into())
+    }
+
+    fn number(&self, value: &Value, context: &str) -> Result<f32, Box<dyn Error>> {
+        value
+            .as_f32()
+            .ok_or_else(|| format!("{}: expected a number", context).into())
+    }
+
+    fn number_or(
+        &self,
+        value: &Value,
+        key: &str,
+        default: f32,
+        context: &str,
+    ) -> Result<f32, Box<dyn Error>> {
+        match value.get(key) {
+            Some(number) => self.number(number, &format!("{}.{}", context, key)),
+            None => Ok(default),
+        }
+    }
+
+    fn array<'a>(&self, value: &'a Value, context: &str) -> Result<&'a [Value], Box<dyn Error>> {
+        value
+            .as_array()
+            .ok_or_else(|| format!("{}: expected an array", context).into())
+    }
+
+    fn object<'a>(
+        &self,
+        value: &'a Value,
+        context: &str,
+    ) -> Result<&'a std::collections::BTreeMap<String, Value>, Box<dyn Error>> {
+        value
+            .as_object()
+            .ok_or_else(|| format!("{}: expected an object", context).into())
+    }
+
+    fn vector(&self, value: &Value, context: &str) -> Result<V3, Box<dyn Error>> {
+        match value.as_array() {
+            Some([x, y, z]) => Ok(V3::new(
+                self.number(x, context)?,
+                self.number(y, context)?,
+                self.number(z, context)?,
+            )),
+            _ => Err(format!("{}: expected an array of 3 numbers", context))?,
+        }
+    }
+
+    fn vector_or(
+        &self,
+        value: &Value,
+        key: &str,
+        default: V3,
+        context: &str,
+    ) -> Result<V3, Box<dyn Error>> {
+        match value.get(key) {
+            Some(vector) => self.vector(vector, &format!("{}.{}", context, key)),
+            None => Ok(default),
+        }
+    }
+
+    fn texture(&self, value: &Value, context: &str) -> Result<Arc<Texture>, Box<dyn Error>> {
+        let path = self.resolve(self.string(value, context)?);
+        let texture = Texture::load(&path, WrapMode::Repeat)
+            .map_err(|error| format!("{}: {}: {}", context, path.display(), error))?;
+        Ok(Arc::new(texture))
+    }
+
+    fn camera(&self, camera: &Value) -> Result<CameraDescription, Box<dyn Error>> {
+        let look_from = self.vector(self.field(camera, "look_from", "camera")?, "camera")?;
+        let look_at = self.vector(self.field(camera, "look_at", "camera")?, "camera")?;
+
+        Ok(CameraDescription {
+            look_from,
+            look_at,
+            up: self.vector_or(camera, "up", V3::new(0.0, 1.0, 0.0), "camera")?,
+            fov: self.number_or(camera, "fov", 40.0, "camera")?,
+            aperture: self.number_or(camera, "aperture", 0.0, "camera")?,
+            focus_distance: self.number_or(
+                camera,
+                "focus_distance",
+                (look_from - look_at).length(),
+                "camera",
+            )?,
+        })
+    }
+
+    fn background(&self, background: &Value) -> Result<BackgroundDescription, Box<dyn Error>> {
+        let kind = self.string(
+            self.field(background, "type", "background")?,
+            "background.type",
+        )?;
+        match kind {
+            "solid" => Ok(BackgroundDescription::Solid(self.vector(
+                self.field(background, "color", "background")?,
+                "background.color",
+            )?)),
+            "sky" => Ok(BackgroundDescription::Sky),
+            "preetham" => Ok(BackgroundDescription::Preetham(
+                self.vector(
+                    self.field(background, "sun_direction", "background")?,
+                    "background.sun_direction",
+                )?,
+                self.number_or(background, "turbidity", 3.0, "background")?,
+                self.number_or(background, "strength", 1.0, "background")?,
+            )),
+            "sky_sphere" => Ok(BackgroundDescription::SkySphere(self.texture(
+                self.field(background, "texture", "background")?,
+                "background.texture",
+            )?)),
+            _ => Err(format!("background: unknown type '{}'", kind))?,
+        }
+    }
+
+    fn surface(&self, material: &Value, context: &str) -> Result<Arc<dyn Surface>, Box<dyn Error>> {
+        if let Some(texture) = material.get("texture") {
+            Ok(self.texture(texture, &format!("{}.texture", context))?)
+        } else {
+            let color = self.vector_or(material, "color", V3::fill(0.8), context)?;
+            Ok(Arc::new(SolidColor(V4::new(
+                color.x(),
+                color.y(),
+                color.z(),
+                1.0,
+            ))))
+        }
+    }
+
+    fn material(
+        &self,
+        table: &mut MaterialTable,
+        material: &Value,
+        context: &str,
+    ) -> Result<u32, Box<dyn Error>> {
+        let kind = self.string(
+            self.field(material, "type", context)?,
+            &format!("{}.type", context),
+        )?;
+        let index = match kind {
+            "lambertian" => table.add(Lambertian::new(self.surface(material, context)?)),
+            "oren_nayar" => table.add(OrenNayar::new(
+                self.number_or(material, "sigma", 20.0, context)?
+                    .to_radians(),
+                self.surface(material, context)?,
+            )),
+            "metal" => match material.get("roughness") {
+                Some(_) => {
+                    let roughness = self.number_or(material, "roughness", 0.0, context)?;
+                    table.add(Metal::anisotropic(
+                        roughness,
+                        self.number_or(material, "bitangent_roughness", roughness, context)?,
+                        self.surface(material, context)?,
+                    ))
+                }
+                None => table.add(Metal::fuzzed(
+                    self.number_or(material, "fuzz", 0.0, context)?,
+                    self.surface(material, context)?,
+                )),
+            },
+            "conductor" => {
+                let conductor = match material.get("preset") {
+                    Some(preset) => {
+                        let preset_context = format!("{}.preset", context);
+                        let name = self.string(preset, &preset_context)?;
+                        Conductor::preset(name).ok_or_else(|| {
+                            format!("{}: unknown conductor '{}'", preset_context, name)
+                        })?
+                    }
+                    None => Conductor::new(
+                        self.vector(self.field(material, "eta", context)?, context)?,
+                        self.vector(self.field(material, "k", context)?, context)?,
+                    ),
+                };
+                let roughness = self.number_or(material, "roughness", 0.0, context)?;
+                table.add(conductor.with_anisotropic_roughness(
+                    roughness,
+                    self.number_or(material, "bitangent_roughness", roughness, context)?,
+                ))
+            }
+            "dielectric" => table.add(
+                Dielectric::new(self.number_or(material, "refraction_index", 1.5, context)?)
+                    .with_roughness(self.number_or(material, "roughness", 0.0, context)?)
+                    .with_absorption(self.vector_or(
+                        material,
+                        "absorption",
+                        V3::zero(),
+                        context,
+                    )?),
+            ),
+            "specular" => table.add(Specular::new(
+                self.number_or(material, "refraction_index", 1.5, context)?,
+                self.surface(material, context)?,
+            )),
+            "principled" => table.add(
+                Principled::new(self.surface(material, context)?)
+                    .with_metallic(self.number_or(material, "metallic", 0.0, context)?)
+                    .with_roughness(self.number_or(material, "roughness", 0.5, context)?)
+                    .with_specular(self.number_or(material, "specular", 0.5, context)?)
+                    .with_sheen(
+                        self.number_or(material, "sheen", 0.0, context)?,
+                        self.number_or(material, "sheen_tint", 0.5, context)?,
+                    )
+                    .with_clearcoat(
+                        self.number_or(material, "clearcoat", 0.0, context)?,
+                        self.number_or(material, "clearcoat_roughness", 0.03, context)?,
+                    )
+                    .with_transmission(self.number_or(material, "transmission", 0.0, context)?)
+                    .with_refraction_index(self.number_or(
+                        material,
+                        "refraction_index",
+                        1.45,
+                        context,
+                    )?),
+            ),
+            "light" => self.light(table, material, context)?,
+            _ => Err(format!("{}: unknown material type '{}'", context, kind))?,
+        };
+
+        Ok(index)
+    }
+
+    fn light(
+        &self,
+        table: &mut MaterialTable,
+        light: &Value,
+        context: &str,
+    ) -> Result<u32, Box<dyn Error>> {
+        Ok(table.add(self.diffuse_light(light, context)?))
+    }
+
+    fn diffuse_light(&self, light: &Value, context: &str) -> Result<DiffuseLight, Box<dyn Error>> {
+        let color = self.vector_or(light, "color", V3::one(), context)?;
+        let strength = self.number_or(light, "strength", 1.0, context)?;
+        let group = match light.get("group") {
+            Some(group) => group
+                .as_u32()
+                .ok_or_else(|| format!("{}.group: expected a whole number", context))?,
+            None => 0,
+        };
+
+        Ok(DiffuseLight::new(color)
+            .with_strength(strength)
+            .with_group(group as usize))
+    }
+}
@@ -0,0 +1,268 @@
+use crate::geom::{Instance, Model};
+use crate::math::{M4, V3};
+
+/// Time step used to find which way a ship is travelling.
+const HEADING_STEP: f32 = 0.001;
+
+/// A position keyed over `animation_t`, passing through every key along a Catmull-Rom spline.
+/// Before the first key and after the last the path holds their positions.
+#[derive(Debug, Clone)]
+pub struct FlightPath {
+    keys: Vec<(f32, V3)>,
+    heading: V3,
+}
+
+impl FlightPath {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            heading: V3::new(0.0, 0.0, -1.0),
+        }
+    }
+
+    pub fn fixed(position: V3) -> Self {
+        Self::new().key(0.0, position)
+    }
+
+    pub fn key(mut self, t: f32, position: V3) -> Self {
+        let index = self.keys.partition_point(|(k, _)| *k <= t);
+        self.keys.insert(index, (t, position));
+        self
+    }
+
+    /// The direction the formation faces while the path isn't moving, defaults to -z.
+    pub fn with_heading(mut self, heading: V3) -> Self {
+        self.heading = heading.unit();
+        self
+    }
+
+    pub fn position(&self, t: f32) -> V3 {
+        let next = self.keys.partition_point(|(k, _)| *k <= t);
+        let point = |index: usize| self.keys[index.min(self.keys.len() - 1)].1;
+
+        match (next.checked_sub(1), self.keys.get(next)) {
+            (None, None) => V3::zero(),
+            (Some(from), None) => point(from),
+            (None, Some(&(_, to))) => to,
+            (Some(from), Some(&(to_t, _))) => {
+                let from_t = self.keys[from].0;
+                let span = (to_t - from_t).max(f32::EPSILON);
+                let x = ((t - from_t) / span).min(1.0).max(0.0);
+
+                let p0 = point(from.saturating_sub(1));
+                let p1 = point(from);
+                let p2 = point(from + 1);
+                let p3 = point(from + 2);
+
+                let x2 = x * x;
+                let x3 = x2 * x;
+                (p1 * 2.0
+                    + (p2 - p0) * x
+                    + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * x2
+                    + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * x3)
+                    * 0.5
+            }
+        }
+    }
+
+    /// The direction of travel at `t`, or the path's heading where it is standing still.
+    pub fn direction(&self, t: f32) -> V3 {
+        let delta = self.position(t + HEADING_STEP) - self.position(t - HEADING_STEP);
+        if delta.near_zero() {
+            self.heading
+        } else {
+            delta.unit()
+        }
+    }
+}
+
+impl Default for FlightPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How ships are arranged around their flight path. Offsets are in the path's frame, +x to the
+/// right of the direction of travel, +y up and +z ahead.
+#[derive(Debug, Copy, Clone)]
+pub enum Formation {
+    /// Ships evenly spread along `spacing`, centered on the path.
+    Line { spacing: V3 },
+    /// A leader on the path with the rest alternating to either side, each rank `spacing` further
+    /// out and `sweep` further back.
+    Wedge { spacing: f32, sweep: f32 },
+    /// Ships evenly spread around a level circle, circling it `turns` times over `0.0..1.0`.
+    Orbit { radius: f32, turns: f32 },
+    /// Rows of `columns` ships, `spacing` apart across and along the path.
+    Grid { columns: u32, spacing: f32 },
+}
+
+impl Formation {
+    fn offset(&self, index: u32, count: u32, t: f32) -> V3 {
+        let index = index as f32;
+        match *self {
+            Formation::Line { spacing } => spacing * (index - (count as f32 - 1.0) / 2.0),
+            Formation::Wedge { spacing, sweep } => {
+                let rank = ((index + 1.0) / 2.0).floor();
+                let side = if index as u32 % 2 == 0 { 1.0 } else { -1.0 };
+                V3::new(side * rank * spacing, 0.0, -rank * sweep)
+            }
+            Formation::Orbit { radius, turns } => {
+                let angle = (index / count.max(1) as f32 + turns * t) * std::f32::consts::PI * 2.0;
+                V3::new(angle.cos() * radius, 0.0, angle.sin() * radius)
+            }
+            Formation::Grid { columns, spacing } => {
+                let columns = columns.max(1);
+                let rows = (count + columns - 1) / columns;
+                let column = index as u32 % columns;
+                let row = index as u32 / columns;
+                V3::new(
+                    (column as f32 - (columns as f32 - 1.0) / 2.0) * spacing,
+                    0.0,
+                    ((rows as f32 - 1.0) / 2.0 - row as f32) * spacing,
+                )
+            }
+        }
+    }
+}
+
+/// A group of ships following one `FlightPath` in a `Formation`. Each ship samples the timeline
+/// at its own phase so a fleet can string out along its path, and faces the way it is moving.
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    path: FlightPath,
+    formation: Formation,
+    ships: u32,
+    phases: Vec<f32>,
+    stagger: f32,
+    scatter: V3,
+    wobble: V3,
+    attitude: V3,
+    scale: f32,
+    seed: u32,
+}
+
+impl Fleet {
+    pub fn new(ships: u32, formation: Formation, path: FlightPath) -> Self {
+        Self {
+            path,
+            formation,
+            ships,
+            phases: Vec::new(),
+            stagger: 0.0,
+            scatter: V3::zero(),
+            wobble: V3::zero(),
+            attitude: V3::zero(),
+            scale: 1.0,
+            seed: 0,
+        }
+    }
+
+    /// Per ship offsets added to `animation_t`, ships past the end of the list use none.
+    pub fn with_phases(mut self, phases: Vec<f32>) -> Self {
+        self.phases = phases;
+        self
+    }
+
+    /// Delays each ship by `stagger` more than the one before it.
+    pub fn with_stagger(mut self, stagger: f32) -> Self {
+        self.stagger = stagger;
+        self
+    }
+
+    /// Moves each ship by a fixed random amount up to `scatter` either way, in the path's frame.
+    pub fn with_scatter(mut self, scatter: V3) -> Self {
+        self.scatter = scatter;
+        self
+    }
+
+    /// Tilts each ship by a fixed random rotation up to `wobble` turns either way.
+    pub fn with_wobble(mut self, wobble: V3) -> Self {
+        self.wobble = wobble;
+        self
+    }
+
+    /// Rotation in turns applied to the model before it is pointed along its path, for hulls
+    /// whose nose isn't along +z.
+    pub fn with_attitude(mut self, attitude: V3) -> Self {
+        self.attitude = attitude;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Picks a different set of scatter and wobble offsets.
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn ship_position(&self, index: u32, t: f32) -> V3 {
+        let center = self.path.position(t);
+        let forward = self.path.direction(t);
+        let right = forward.cross(V3::new(0.0, 1.0, 0.0));
+        let right = if right.near_zero() {
+            V3::new(1.0, 0.0, 0.0)
+        } else {
+            right.unit()
+        };
+        let up = right.cross(forward);
+
+        let offset = self.formation.offset(index, self.ships, t)
+            + self.scatter * (self.random(index, 0) * 2.0 - 1.0);
+        center + right * offset.x() + up * offset.y() + forward * offset.z()
+    }
+
+    /// The transform of every ship at `animation_t`.
+    pub fn transforms(&self, animation_t: f32) -> Vec<M4> {
+        (0..self.ships)
+            .map(|index| {
+                let phase = self.phases.get(index as usize).copied().unwrap_or(0.0);
+                let t = animation_t + phase - self.stagger * index as f32;
+
+                let position = self.ship_position(index, t);
+                let travel = self.ship_position(index, t + HEADING_STEP)
+                    - self.ship_position(index, t - HEADING_STEP);
+                let heading = if travel.near_zero() {
+                    self.path.direction(t)
+                } else {
+                    travel.unit()
+                };
+
+                let rotation = self.attitude + self.wobble * (self.random(index, 1) * 2.0 - 1.0);
+                M4::look_at(position, position + heading, V3::new(0.0, 1.0, 0.0))
+                    * M4::rotate_x(rotation.x())
+                    * M4::rotate_y(rotation.y())
+                    * M4::rotate_z(rotation.z())
+                    * M4::scale(V3::fill(self.scale))
+            })
+            .collect()
+    }
+
+    /// Instances `model` once per ship at `animation_t`.
+    pub fn instances(&self, model: &Model<()>, animation_t: f32) -> Vec<Instance<()>> {
+        self.transforms(animation_t)
+            .into_iter()
+            .map(|transform| model.instance_transform(transform))
+            .collect()
+    }
+
+    /// Three values in `0.0..1.0` fixed for a ship, so its offsets hold steady between frames.
+    fn random(&self, index: u32, salt: u32) -> V3 {
+        let seed = hash(self.seed ^ hash(index.wrapping_mul(3).wrapping_add(salt)));
+        let unit = |x: u32| hash(seed.wrapping_add(x)) as f32 / u32::MAX as f32;
+        V3::new(unit(0), unit(1), unit(2))
+    }
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
@@ -1,25 +1,54 @@
 use super::Scene;
-use crate::geom::{Model, Sphere, Triangle};
+use crate::geom::{Mesh, Model};
+use crate::input::InputCollection;
+use crate::light::SphereLight;
 use crate::material::{DiffuseLight, Lambertian, SolidBackground};
 use crate::math::{Num, V3, V4};
-use crate::ply_loader::PlyLoader;
+use crate::model_loader::ModelLoader;
+use crate::ply_loader::{PlyLoader, PlyVertex};
 use crate::texture::SolidColor;
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 pub struct Lucy {
     aspect_ratio: f32,
+    lucy: Model<()>,
+    /// Scales the model to two units across.
+    scale: f32,
 }
 
 impl Lucy {
     pub fn new(aspect_ratio: f32) -> Self {
-        Self { aspect_ratio }
+        let swizzle = |v: V3| V3::new(v.y(), v.z(), v.x());
+        let vertex = |v: PlyVertex| (swizzle(v.position), v.normal.map(swizzle));
+        #[cfg(feature = "mmap")]
+        let (vertices, faces) = PlyLoader::load_indexed_mapped("models/lucy.ply", vertex).unwrap();
+        #[cfg(not(feature = "mmap"))]
+        let (vertices, faces) = PlyLoader::load_indexed_with("models/lucy.ply", vertex).unwrap();
+        let (vertices, normals): (Vec<_>, Vec<_>) = vertices.into_iter().unzip();
+        let max_dim = vertices.iter().fold(0.0, |max_dim: f32, p| {
+            max_dim.max(p.x().abs()).max(p.y().abs()).max(p.z().abs())
+        });
+        let mesh = Mesh::new((), vertices, faces);
+        let mesh = match normals.into_iter().collect::<Option<Vec<_>>>() {
+            Some(normals) => mesh.with_normals(normals),
+            None => mesh,
+        };
+
+        Self {
+            aspect_ratio,
+            lucy: Model::from_mesh_cached("models/lucy.ply", mesh),
+            scale: 2.0 / max_dim,
+        }
     }
 }
 
 impl Scene for Lucy {
     type Background = SolidBackground;
 
+    fn name(&self) -> &str {
+        "Lucy"
+    }
+
     fn generate(
         &mut self,
         _animation_t: f32,
@@ -28,23 +57,8 @@ impl Scene for Lucy {
     ) -> (World<Self::Background>, Camera) {
         let mut world = World::new(SolidBackground::new(V3::zero()));
 
-        let mut max_dim = 0.0;
-
-        let lucy = PlyLoader::load(
-            "models/lucy.ply",
-            |x, y, z| {
-                max_dim = max_dim.max(x.abs()).max(y.abs()).max(z.abs());
-                V3::new(y, z, x)
-            },
-            |a, b, c| Triangle::new((), a, b, c),
-        )
-        .unwrap();
-        let lucy = Model::new(lucy);
-
         let white = Lambertian::new(SolidColor(V4::one()));
-        let cube =
-            PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
-        let cube = Model::new(cube);
+        let cube = ModelLoader::new("cube.ply").load().unwrap();
         let ground = cube
             .instance(V3::new(0.0, -1000.0, 0.0), V3::zero(), V3::fill(1000.0))
             .with_material(white);
@@ -59,22 +73,23 @@ impl Scene for Lucy {
                     1.0,
                 )));
                 world.add(
-                    lucy.instance(
-                        V3::new(x as f32 * 3.0, 1.0, z as f32 * 3.0),
-                        V3::new(0.0, f32::rand(), 0.0),
-                        V3::fill(1.0 / max_dim) * 2.0,
-                    )
-                    .with_material(material),
+                    self.lucy
+                        .instance(
+                            V3::new(x as f32 * 3.0, 1.0, z as f32 * 3.0),
+                            V3::new(0.0, f32::rand(), 0.0),
+                            V3::fill(self.scale),
+                        )
+                        .with_material(material),
                 );
             }
         }
 
-        let sun = Sphere::new(
+        let sun = SphereLight::new(
             DiffuseLight::new(V3::new(4.0, 4.0, 5.0) * 10.0),
             V3::new(10000.0, 4000.0, 4800.0),
             1500.0,
         );
-        world.add(sun);
+        world.add_light(sun);
 
         let look_from = V3::new(6.0, 8.0, 5.0);
         let look_at = V3::new(0.0, 0.0, 0.0);
@@ -4,18 +4,144 @@ use libsm64::{DynamicSurface, LevelTriangle, MarioInput, Sm64};
 use winit::event::VirtualKeyCode;
 
 use crate::geom::{Model, Triangle};
+use crate::input::{Input, InputCollection};
 use crate::material::{Dielectric, Lambertian, Material, SkySphere};
 use crate::math::{Num, M4, V2, V3, V4};
 use crate::obj_loader::{ObjLoader, SimpleTexturedBuilder};
 use crate::ply_loader::PlyLoader;
-use crate::texture::{SharedTexture, SolidColorFallback, Surface, Texture, WrapMode};
-use crate::world::{Camera, World};
-use crate::{Input, InputCollection};
+use crate::texture::{FilterMode, SharedTexture, SolidColorFallback, Texture, WrapMode};
+use crate::world::{Camera, Raycast, World};
 
 use std::io::Cursor;
-use std::sync::Arc;
 
 const COLLISION_LEVEL_SCALE: f32 = 1000.0;
+/// Where the first Mario spawns, each further player starts `SPAWN_SPACING` along +x.
+const SPAWN: (i16, i16, i16) = (1100, 100, -4310);
+const SPAWN_SPACING: i16 = 150;
+const ORBIT_FRAMES: f32 = 900.0;
+
+/// How the camera frames the players, cycled with C while the scene runs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MarioCamera {
+    /// Watches from beside the castle door, rising to keep the players in view.
+    Fixed,
+    /// Trails behind the players in the direction they are running.
+    Follow,
+    /// Circles the players once every `ORBIT_FRAMES` frames.
+    Orbit,
+}
+
+impl MarioCamera {
+    fn next(self) -> Self {
+        match self {
+            MarioCamera::Fixed => MarioCamera::Follow,
+            MarioCamera::Follow => MarioCamera::Orbit,
+            MarioCamera::Orbit => MarioCamera::Fixed,
+        }
+    }
+}
+
+/// The keys driving one Mario.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct KeyBindings {
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub a: VirtualKeyCode,
+    pub b: VirtualKeyCode,
+    pub z: VirtualKeyCode,
+}
+
+impl KeyBindings {
+    /// WASD to run, J, K and L for A, B and Z.
+    pub fn wasd() -> Self {
+        Self {
+            up: VirtualKeyCode::W,
+            down: VirtualKeyCode::S,
+            left: VirtualKeyCode::A,
+            right: VirtualKeyCode::D,
+            a: VirtualKeyCode::J,
+            b: VirtualKeyCode::K,
+            z: VirtualKeyCode::L,
+        }
+    }
+
+    /// The arrow keys to run, numpad 1, 2 and 3 for A, B and Z.
+    pub fn arrows() -> Self {
+        Self {
+            up: VirtualKeyCode::Up,
+            down: VirtualKeyCode::Down,
+            left: VirtualKeyCode::Left,
+            right: VirtualKeyCode::Right,
+            a: VirtualKeyCode::Numpad1,
+            b: VirtualKeyCode::Numpad2,
+            z: VirtualKeyCode::Numpad3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gamepad {
+    /// Follows every connected gamepad.
+    Any,
+    Id(usize),
+}
+
+/// The controls of one player, either of which can move their Mario.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bindings {
+    pub keys: Option<KeyBindings>,
+    pub gamepad: Option<Gamepad>,
+}
+
+impl Bindings {
+    fn read(&self, input: &InputCollection) -> MarioInput {
+        let keys = self.keys;
+        let key = |key: fn(KeyBindings) -> VirtualKeyCode| {
+            keys.map_or(false, |keys| input.is_pressed(Input::Key(key(keys))))
+        };
+        let button = |button| match self.gamepad {
+            Some(Gamepad::Any) => input.is_pressed(Input::Button(button)),
+            Some(Gamepad::Id(id)) => input.is_pressed(Input::GamepadButton(id, button)),
+            None => false,
+        };
+        let axis = |axis| match self.gamepad {
+            Some(Gamepad::Any) => input.axis(axis),
+            Some(Gamepad::Id(id)) => input.gamepad_axis(id, axis),
+            None => 0.0,
+        };
+
+        let mut mario_input = MarioInput::default();
+        mario_input.button_a = key(|k| k.a) || button(Button::South);
+        mario_input.button_b = key(|k| k.b) || button(Button::East);
+        mario_input.button_z = key(|k| k.z) || button(Button::West);
+
+        if key(|k| k.up) {
+            mario_input.stick_y = -1.0;
+        } else if key(|k| k.down) {
+            mario_input.stick_y = 1.0;
+        } else {
+            mario_input.stick_y = axis(Axis::LeftStickY) * -1.0;
+        }
+
+        if key(|k| k.left) {
+            mario_input.stick_x = -1.0;
+        } else if key(|k| k.right) {
+            mario_input.stick_x = 1.0;
+        } else {
+            mario_input.stick_x = axis(Axis::LeftStickX);
+        }
+
+        mario_input
+    }
+}
+
+struct Player {
+    handle: libsm64::Mario,
+    bindings: Bindings,
+    last_pos: V3,
+}
 
 pub struct Mario {
     aspect_ratio: f32,
@@ -25,10 +151,14 @@ pub struct Mario {
     output_buf: Vec<u8>,
     sm64: Sm64,
     platform: DynamicSurface,
-    handle: libsm64::Mario,
-    last_pos: V3,
+    players: Vec<Player>,
+    camera: MarioCamera,
+    camera_key_held: bool,
+    look_from: V3,
+    last_center: V3,
+    heading: V3,
     texture: SharedTexture,
-    castle_triangles: Vec<Triangle<Lambertian<Arc<dyn Surface>>>>,
+    castle: Model<()>,
     platform_triangles: Vec<Triangle<()>>,
     sky_texture: SharedTexture,
 }
@@ -49,11 +179,12 @@ impl Mario {
         let texture = sm64.texture();
         let texture =
             Texture::load_bytes(texture.data, texture.width, texture.height, WrapMode::Clamp)
+                .with_filter(FilterMode::Nearest)
                 .shared();
 
-        let builder = SimpleTexturedBuilder::new(WrapMode::Repeat);
-        let castle_triangles =
-            ObjLoader::load("models/mario/castle/Peaches Castle.obj", builder).unwrap();
+        let mut builder = SimpleTexturedBuilder::new(WrapMode::Repeat).with_mipmaps();
+        let castle_path = "models/mario/castle/Peaches Castle.obj";
+        let castle_triangles = ObjLoader::load(castle_path, &mut builder).unwrap();
         let castle_scale = M4::scale(V3::fill(COLLISION_LEVEL_SCALE));
         let castle_geo = castle_triangles
             .iter()
@@ -61,6 +192,8 @@ impl Mario {
             .collect::<Vec<_>>();
 
         sm64.load_level_geometry(castle_geo.as_slice());
+        let castle = Model::new_cached(castle_path, castle_triangles)
+            .with_materials(builder.into_materials());
 
         let platform_triangles =
             PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
@@ -85,8 +218,6 @@ impl Mario {
         };
         let platform = sm64.create_dynamic_surface(&*platform_geo, platform_transform);
 
-        let handle = sm64.create_mario(1100, 100, -4310).unwrap();
-
         let sky_texture = Texture::load_png("models/mario/mario_sky.png", WrapMode::Clamp)
             .unwrap()
             .shared();
@@ -97,15 +228,113 @@ impl Mario {
             write_input,
             input_buf,
             output_buf,
-            handle,
             sm64,
-            last_pos: V3::zero(),
+            players: Vec::new(),
+            camera: MarioCamera::Fixed,
+            camera_key_held: false,
+            look_from: V3::new(0.4, 1.4455, -1.0005),
+            last_center: V3::zero(),
+            heading: V3::new(0.0, 0.0, -1.0),
             texture,
             platform,
-            castle_triangles,
+            castle,
             platform_triangles,
             sky_texture,
         }
+        .with_player(Bindings {
+            keys: Some(KeyBindings::wasd()),
+            gamepad: Some(Gamepad::Any),
+        })
+    }
+
+    /// Adds another Mario driven by `bindings`, spawned beside the others.
+    pub fn with_player(mut self, bindings: Bindings) -> Self {
+        let offset = SPAWN_SPACING * self.players.len() as i16;
+        let handle = self
+            .sm64
+            .create_mario(SPAWN.0 + offset, SPAWN.1, SPAWN.2)
+            .unwrap();
+
+        self.players.push(Player {
+            handle,
+            bindings,
+            last_pos: V3::zero(),
+        });
+        self
+    }
+
+    /// Replaces the players with `count` Marios. The first is driven by the WASD keys and the
+    /// second by the arrow keys, and each by the gamepad matching their order.
+    pub fn with_players(mut self, count: usize) -> Self {
+        self.players.clear();
+        for player in 0..count {
+            let keys = match player {
+                0 => Some(KeyBindings::wasd()),
+                1 => Some(KeyBindings::arrows()),
+                _ => None,
+            };
+            self = self.with_player(Bindings {
+                keys,
+                gamepad: Some(Gamepad::Id(player)),
+            });
+        }
+        self
+    }
+
+    pub fn with_camera(mut self, camera: MarioCamera) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    /// The middle of every player's last position.
+    fn center(&self) -> V3 {
+        let sum = self
+            .players
+            .iter()
+            .fold(V3::zero(), |sum, player| sum + player.last_pos);
+        sum / self.players.len().max(1) as f32
+    }
+
+    /// Moves the camera for the coming frame, framing where the players were at the end of the
+    /// last one.
+    fn place_camera(&mut self, frame: u32) -> V3 {
+        let center = self.center();
+        let spread = self
+            .players
+            .iter()
+            .map(|player| player.last_pos.distance(center))
+            .fold(0.0, f32::max);
+        let distance = 0.6 + spread;
+
+        let look_from = match self.camera {
+            MarioCamera::Fixed => V3::new(0.4, 1.4455.max(center.y() + 0.3), -1.0005),
+            MarioCamera::Follow => {
+                let movement = center - self.last_center;
+                let movement = V3::new(movement.x(), 0.0, movement.z());
+                if movement.length() > 0.001 {
+                    self.heading = (self.heading * 0.9 + movement.unit() * 0.1).unit();
+                }
+                let target = center - self.heading * distance + V3::new(0.0, 0.3, 0.0);
+                self.look_from + (target - self.look_from) * 0.2
+            }
+            MarioCamera::Orbit => {
+                let angle = frame as f32 / ORBIT_FRAMES * std::f32::consts::PI * 2.0;
+                center + V3::new(angle.cos() * distance, 0.3, angle.sin() * distance)
+            }
+        };
+
+        // Lifts the camera clear of the ground just beneath it, a roof or ledge above the camera
+        // is never mistaken for the ground
+        let look_from = match self.castle.ground_height(look_from, 10.0) {
+            Some(ground) if ground + 0.1 > look_from.y() => {
+                V3::new(look_from.x(), ground + 0.1, look_from.z())
+            }
+            _ => look_from,
+        };
+
+        self.look_from = look_from;
+        self.last_center = center;
+        look_from
     }
 }
 
@@ -121,6 +350,10 @@ impl Drop for Mario {
 impl super::Scene for Mario {
     type Background = SkySphere<SharedTexture>;
 
+    fn name(&self) -> &str {
+        "Mario"
+    }
+
     fn generate(
         &mut self,
         _animation_t: f32,
@@ -130,10 +363,16 @@ impl super::Scene for Mario {
         let sky = SkySphere::new(self.sky_texture.clone());
         let mut world = World::new(sky);
 
-        let castle = Model::new(self.castle_triangles.clone());
-        world.add(castle);
+        world.add(self.castle.clone());
 
-        let look_from = V3::new(0.4, 1.4455.max(self.last_pos.y() + 0.3), -1.0005);
+        let cycle_camera = input.is_pressed(Input::Key(VirtualKeyCode::C));
+        if cycle_camera && !self.camera_key_held {
+            self.camera = self.camera.next();
+            println!("Mario camera: {:?}", self.camera);
+        }
+        self.camera_key_held = cycle_camera;
+
+        let look_from = self.place_camera(frame);
 
         let platform_scale = V3::new(1.0, 0.1, 0.3);
         let platform_position = V3::new(3.4, 1.3 + ((frame as f32 / 30.0).sin() / 0.8), -1.0);
@@ -164,101 +403,34 @@ impl super::Scene for Mario {
             .with_material(Dielectric::new(1.7)),
         );
 
-        let mut mario_input = MarioInput::default();
-        if self.read_input {
-            mario_input.from_bytes(&mut self.input_buf).unwrap();
-        } else {
-            mario_input.button_a = input.is_pressed(Input::Key(VirtualKeyCode::J))
-                || input.is_pressed(Input::Button(Button::South));
-            mario_input.button_b = input.is_pressed(Input::Key(VirtualKeyCode::K))
-                || input.is_pressed(Input::Button(Button::East));
-            mario_input.button_z = input.is_pressed(Input::Key(VirtualKeyCode::L))
-                || input.is_pressed(Input::Button(Button::West));
-
-            if input.is_pressed(Input::Key(VirtualKeyCode::W)) {
-                mario_input.stick_y = -1.0;
-            } else if input.is_pressed(Input::Key(VirtualKeyCode::S)) {
-                mario_input.stick_y = 1.0;
-            } else {
-                mario_input.stick_y = input.axis(Axis::LeftStickY) * -1.0;
-            }
-
-            if input.is_pressed(Input::Key(VirtualKeyCode::A)) {
-                mario_input.stick_x = -1.0;
-            } else if input.is_pressed(Input::Key(VirtualKeyCode::D)) {
-                mario_input.stick_x = 1.0;
+        let scale = M4::scale(V3::fill(1.0 / COLLISION_LEVEL_SCALE));
+        for player in self.players.iter_mut() {
+            let mut mario_input = if self.read_input {
+                let mut mario_input = MarioInput::default();
+                mario_input.from_bytes(&mut self.input_buf).unwrap();
+                mario_input
             } else {
-                mario_input.stick_x = input.axis(Axis::LeftStickX);
+                player.bindings.read(input)
+            };
+            if self.write_input {
+                mario_input.to_bytes(&mut self.output_buf).unwrap();
             }
-        }
-        if self.write_input {
-            mario_input.to_bytes(&mut self.output_buf).unwrap();
-        }
 
-        mario_input.cam_look_x = self.last_pos.x() - look_from.x();
-        mario_input.cam_look_z = self.last_pos.z() - look_from.z();
+            mario_input.cam_look_x = player.last_pos.x() - look_from.x();
+            mario_input.cam_look_z = player.last_pos.z() - look_from.z();
 
-        let tex = self.texture.clone();
+            let mario_state = player.handle.tick(mario_input);
+            let mario_pos = V3::new(
+                mario_state.position.x,
+                mario_state.position.y,
+                mario_state.position.z,
+            );
+            player.last_pos = scale.transform_point(mario_pos);
 
-        let mario_state = self.handle.tick(mario_input);
-        let scale = M4::scale(V3::fill(1.0 / COLLISION_LEVEL_SCALE));
-
-        let mario_tris = self
-            .handle
-            .geometry()
-            .triangles()
-            .map(|mario_tri| {
-                let color = V4::new(
-                    mario_tri.0.color.r,
-                    mario_tri.0.color.g,
-                    mario_tri.0.color.b,
-                    1.0,
-                );
-
-                let m_color = SolidColorFallback::new(color, tex.clone());
-                let material = Lambertian::new(m_color);
-
-                let to_v3 = |point: libsm64::Point3<f32>| V3::new(point.x, point.y, point.z);
-                let to_v2 = |point: libsm64::Point2<f32>| V2::new(point.x, point.y);
-
-                let v_a = to_v3(mario_tri.0.position);
-                let v_b = to_v3(mario_tri.1.position);
-                let v_c = to_v3(mario_tri.2.position);
-
-                let v_a = scale.transform_point(v_a);
-                let v_b = scale.transform_point(v_b);
-                let v_c = scale.transform_point(v_c);
-
-                let n_a = to_v3(mario_tri.0.normal);
-                let n_b = to_v3(mario_tri.1.normal);
-                let n_c = to_v3(mario_tri.2.normal);
-
-                let uv_a = to_v2(mario_tri.0.uv);
-                let uv_b = to_v2(mario_tri.1.uv);
-                let uv_c = to_v2(mario_tri.2.uv);
-
-                let a = (v_a, n_a, uv_a);
-                let b = (v_b, n_b, uv_b);
-                let c = (v_c, n_c, uv_c);
-
-                Triangle::with_norms_and_uvs(material, a, b, c)
-            })
-            .collect::<Vec<_>>();
-
-        let mario_pos = V3::new(
-            mario_state.position.x,
-            mario_state.position.y,
-            mario_state.position.z,
-        );
-        let mario_pos = scale.transform_point(mario_pos);
-
-        self.last_pos = mario_pos;
-
-        let mario = Model::new(mario_tris);
-
-        world.add(mario);
+            world.add(mario_model(&player.handle, &self.texture, scale));
+        }
 
-        let look_at = mario_pos;
+        let look_at = self.center();
         let focus_distance = (look_from - look_at).length();
         let aperture = 0.00;
 
@@ -276,6 +448,52 @@ impl super::Scene for Mario {
     }
 }
 
+/// Mario's current triangles, colored by their vertex colors where they aren't textured.
+fn mario_model(handle: &libsm64::Mario, texture: &SharedTexture, scale: M4) -> Model<()> {
+    let mario_tris = handle
+        .geometry()
+        .triangles()
+        .map(|mario_tri| {
+            let color = V4::new(
+                mario_tri.0.color.r,
+                mario_tri.0.color.g,
+                mario_tri.0.color.b,
+                1.0,
+            );
+
+            let m_color = SolidColorFallback::new(color, texture.clone());
+            let material = Lambertian::new(m_color);
+
+            let to_v3 = |point: libsm64::Point3<f32>| V3::new(point.x, point.y, point.z);
+            let to_v2 = |point: libsm64::Point2<f32>| V2::new(point.x, point.y);
+
+            let v_a = to_v3(mario_tri.0.position);
+            let v_b = to_v3(mario_tri.1.position);
+            let v_c = to_v3(mario_tri.2.position);
+
+            let v_a = scale.transform_point(v_a);
+            let v_b = scale.transform_point(v_b);
+            let v_c = scale.transform_point(v_c);
+
+            let n_a = to_v3(mario_tri.0.normal);
+            let n_b = to_v3(mario_tri.1.normal);
+            let n_c = to_v3(mario_tri.2.normal);
+
+            let uv_a = to_v2(mario_tri.0.uv);
+            let uv_b = to_v2(mario_tri.1.uv);
+            let uv_c = to_v2(mario_tri.2.uv);
+
+            let a = (v_a, n_a, uv_a);
+            let b = (v_b, n_b, uv_b);
+            let c = (v_c, n_c, uv_c);
+
+            Triangle::with_norms_and_uvs(material, a, b, c)
+        })
+        .collect::<Vec<_>>();
+
+    Model::new(mario_tris)
+}
+
 fn create_level_triangle<M: Material>(
     triangle: &Triangle<M>,
     transform: M4,
@@ -1,11 +1,10 @@
 use super::Scene;
-use crate::geom::{Model, Triangle};
+use crate::input::InputCollection;
 use crate::material::{Background, Lambertian, Metal};
 use crate::math::{Num, V3, V4};
-use crate::ply_loader::PlyLoader;
+use crate::model_loader::ModelLoader;
 use crate::texture::SolidColor;
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 pub struct Menger {
     aspect_ratio: f32,
@@ -20,6 +19,10 @@ impl Menger {
 impl Scene for Menger {
     type Background = Box<dyn Background>;
 
+    fn name(&self) -> &str {
+        "Menger"
+    }
+
     fn generate(
         &mut self,
         _animation_t: f32,
@@ -29,11 +32,9 @@ impl Scene for Menger {
         let cube_map = crate::eve::environment("j02", V3::new(0.4, 0.2, 0.1));
         let mut world = World::new(Box::new(cube_map) as Self::Background);
 
-        let cube =
-            PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
-        let cube = Model::new(cube);
+        let cube = ModelLoader::new("cube.ply").load().unwrap();
 
-        let foggy = Metal::new(0.7, SolidColor(V3::fill(0.5).expand(1.0)));
+        let foggy = Metal::fuzzed(0.7, SolidColor(V3::fill(0.5).expand(1.0)));
 
         menger_gen(&mut world);
 
@@ -68,8 +69,7 @@ impl Scene for Menger {
 fn menger_gen(world: &mut World<impl Background>) {
     let dims = 2.0;
     let material = Lambertian::new(SolidColor(V4::fill(1.0)));
-    let cube = PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
-    let cube = Model::new(cube);
+    let cube = ModelLoader::new("cube.ply").load().unwrap();
     let mut min = 0.0;
     let mut max = 0.0;
     let mut add_cube = |xyz| {
@@ -0,0 +1,175 @@
+use super::Scene;
+use crate::geom::{Model, Sphere};
+use crate::input::InputCollection;
+use crate::light::SphereLight;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal, SolidBackground};
+use crate::math::{Num, V3, V4};
+use crate::model_loader::ModelLoader;
+use crate::texture::SolidColor;
+use crate::world::{Camera, World};
+
+/// Ground truth for a single world object, `id` is its index in the world plus one so that zero
+/// can stand for the background.
+#[derive(Debug, Clone)]
+pub struct ObjectLabel {
+    pub id: u32,
+    pub class: &'static str,
+    pub material: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameLabels {
+    pub look_from: V3,
+    pub look_at: V3,
+    pub vertical_fov: f32,
+    pub objects: Vec<ObjectLabel>,
+}
+
+/// A procedurally randomized scene of primitives on a ground plane. Placement, materials,
+/// lighting and the camera are drawn from a seed derived from the frame number, so any frame
+/// can be regenerated exactly.
+pub struct Randomized {
+    aspect_ratio: f32,
+    seed: u64,
+    cube: Model<()>,
+    labels: FrameLabels,
+}
+
+impl Randomized {
+    pub fn new(aspect_ratio: f32, seed: u64) -> Self {
+        Self {
+            aspect_ratio,
+            seed,
+            cube: ModelLoader::new("cube.ply").load().unwrap(),
+            labels: FrameLabels {
+                look_from: V3::zero(),
+                look_at: V3::zero(),
+                vertical_fov: 0.0,
+                objects: Vec::new(),
+            },
+        }
+    }
+
+    /// Labels for the most recently generated frame.
+    pub fn labels(&self) -> &FrameLabels {
+        &self.labels
+    }
+}
+
+fn random_color() -> SolidColor {
+    SolidColor(V4::new(f32::rand(), f32::rand(), f32::rand(), 1.0))
+}
+
+fn random_range(min: f32, max: f32) -> f32 {
+    min + f32::rand() * (max - min)
+}
+
+impl Scene for Randomized {
+    type Background = SolidBackground;
+
+    fn name(&self) -> &str {
+        "Randomized"
+    }
+
+    fn generate(
+        &mut self,
+        _animation_t: f32,
+        frame: u32,
+        _input: &InputCollection,
+    ) -> (World<Self::Background>, Camera) {
+        fastrand::seed(self.seed.wrapping_mul(0x9e3779b97f4a7c15) ^ frame as u64);
+
+        let sky = random_range(0.05, 1.0);
+        let mut world = World::new(SolidBackground::new(V3::new(sky * 0.7, sky * 0.8, sky)));
+        let mut objects = Vec::new();
+
+        let ground = self
+            .cube
+            .instance(V3::new(0.0, -1000.0, 0.0), V3::zero(), V3::fill(1000.0))
+            .with_material(Lambertian::new(random_color()));
+        world.add(ground);
+        objects.push(("ground", "lambertian"));
+
+        let count = fastrand::u32(3..12);
+        for _ in 0..count {
+            let size = random_range(0.3, 1.2);
+            let position = V3::new(random_range(-5.0, 5.0), size, random_range(-5.0, 5.0));
+            let sphere = fastrand::bool();
+
+            let (name, material): (_, Box<dyn Material>) = match fastrand::u8(0..3) {
+                0 => ("lambertian", Box::new(Lambertian::new(random_color()))),
+                1 => (
+                    "metal",
+                    Box::new(Metal::fuzzed(random_range(0.0, 0.5), random_color())),
+                ),
+                _ => (
+                    "dielectric",
+                    Box::new(Dielectric::new(random_range(1.3, 1.8))),
+                ),
+            };
+
+            if sphere {
+                world.add(Sphere::new(material, position, size));
+            } else {
+                let rotation = V3::new(0.0, f32::rand(), 0.0);
+                world.add(
+                    self.cube
+                        .instance(position, rotation, V3::fill(size))
+                        .with_material(material),
+                );
+            }
+
+            objects.push((if sphere { "sphere" } else { "cube" }, name));
+        }
+
+        let light_position = V3::new(
+            random_range(-8.0, 8.0),
+            random_range(6.0, 12.0),
+            random_range(-8.0, 8.0),
+        );
+        let light_power = random_range(2.0, 20.0);
+        world.add_light(SphereLight::new(
+            DiffuseLight::new(V3::fill(light_power)),
+            light_position,
+            random_range(0.5, 2.0),
+        ));
+        objects.push(("light", "diffuse_light"));
+
+        let angle = f32::rand() * std::f32::consts::PI * 2.0;
+        let distance = random_range(10.0, 18.0);
+        let look_from = V3::new(
+            angle.cos() * distance,
+            random_range(2.0, 8.0),
+            angle.sin() * distance,
+        );
+        let look_at = V3::new(0.0, 0.5, 0.0);
+        let vertical_fov = random_range(30.0, 50.0);
+
+        self.labels = FrameLabels {
+            look_from,
+            look_at,
+            vertical_fov,
+            objects: objects
+                .into_iter()
+                .enumerate()
+                .map(|(index, (class, material))| ObjectLabel {
+                    id: index as u32 + 1,
+                    class,
+                    material,
+                })
+                .collect(),
+        };
+
+        let camera = Camera::new(
+            vertical_fov,
+            look_from,
+            look_at,
+            V3::new(0.0, 1.0, 0.0),
+            self.aspect_ratio,
+            0.0,
+            (look_from - look_at).length(),
+        );
+
+        (world, camera)
+    }
+}
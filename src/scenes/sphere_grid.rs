@@ -1,11 +1,11 @@
 use super::Scene;
-use crate::geom::{Model, Sphere, Triangle};
+use crate::geom::{Acceleration, Intersect, Model, Sphere};
+use crate::input::InputCollection;
 use crate::material::{Dielectric, DiffuseLight, Lambertian, Metal, SolidBackground};
 use crate::math::{V3, V4};
-use crate::ply_loader::PlyLoader;
+use crate::model_loader::ModelLoader;
 use crate::texture::SolidColor;
 use crate::world::{Camera, World};
-use crate::InputCollection;
 
 pub struct SphereGrid {
     aspect_ratio: f32,
@@ -20,6 +20,10 @@ impl SphereGrid {
 impl Scene for SphereGrid {
     type Background = SolidBackground;
 
+    fn name(&self) -> &str {
+        "Sphere Grid"
+    }
+
     fn generate(
         &mut self,
         _animation_t: f32,
@@ -29,9 +33,7 @@ impl Scene for SphereGrid {
         let mut world = World::new(SolidBackground::new(V3::zero()));
 
         let white = Lambertian::new(SolidColor(V4::one()));
-        let cube =
-            PlyLoader::load("cube.ply", V3::new, |a, b, c| Triangle::new((), a, b, c)).unwrap();
-        let cube = Model::new(cube);
+        let cube = ModelLoader::new("cube.ply").load().unwrap();
         let ground = cube
             .instance(V3::new(0.0, -1000.0, 0.0), V3::zero(), V3::fill(1000.0))
             .with_material(white);
@@ -42,6 +44,7 @@ impl Scene for SphereGrid {
         let d = r * 2.0;
         let a = (d.powi(2) - r.powi(2)).sqrt();
 
+        let mut spheres: Vec<Box<dyn Intersect>> = Vec::new();
         let dim = 50;
         for i in -dim..dim {
             for j in -dim..dim {
@@ -57,24 +60,26 @@ impl Scene for SphereGrid {
                         let m = DiffuseLight::new(V3::fill(3.0));
                         let s = Sphere::new(m, V3::new(x, y, z), r);
 
-                        world.add(s);
+                        spheres.push(Box::new(s));
                     }
                     (-1, 0) | (1, 0) | (1, -1) | (0, -1) | (1, 1) | (0, 1) => {
                         let m = Dielectric::new(1.8);
                         let s = Sphere::new(m, V3::new(x, y, z), r);
 
-                        world.add(s);
+                        spheres.push(Box::new(s));
                     }
                     (_, _) => {
                         let m = Metal::new(0.0, SolidColor(V3::rand().expand(1.0)));
                         let s = Sphere::new(m, V3::new(x, y, z), r);
 
-                        world.add(s);
+                        spheres.push(Box::new(s));
                     }
                 }
             }
         }
 
+        world.add(Model::from_objects(Acceleration::Grid, spheres));
+
         let look_from = V3::new(6.0, 8.0, 5.0);
         let look_at = V3::new(0.0, 0.0, 0.0);
         let focus_distance = (look_from - look_at).length();
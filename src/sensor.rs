@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::math::V3;
+
+/// Per channel response curves sampled at evenly spaced exposures from 0.0 to 1.0.
+#[derive(Debug, Clone)]
+pub struct ResponseCurves {
+    curves: [Vec<f32>; 3],
+}
+
+impl ResponseCurves {
+    /// A display gamma applied equally to every channel, the image formation used without a
+    /// sensor.
+    pub fn gamma(gamma: f32) -> Self {
+        let curve: Vec<f32> = (0..256)
+            .map(|i| (i as f32 / 255.0).powf(1.0 / gamma))
+            .collect();
+        Self {
+            curves: [curve.clone(), curve.clone(), curve],
+        }
+    }
+
+    /// Loads a 1D LUT of whitespace separated `r g b` rows, as found in `.cube` files. Lines
+    /// starting with `#` or a keyword such as `LUT_1D_SIZE` are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = BufReader::new(File::open(path)?);
+        let mut curves = [Vec::new(), Vec::new(), Vec::new()];
+
+        for line in file.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(char::is_alphabetic) {
+                continue;
+            }
+
+            let values: Vec<f32> = line
+                .split_whitespace()
+                .map(|n| n.parse())
+                .collect::<Result<_, _>>()?;
+            if values.len() != 3 {
+                return Err(format!("invalid response curve row: {}", line))?;
+            }
+
+            for (curve, value) in curves.iter_mut().zip(values) {
+                curve.push(value);
+            }
+        }
+
+        if curves[0].len() < 2 {
+            return Err("response curve needs at least two rows")?;
+        }
+
+        Ok(Self { curves })
+    }
+
+    fn apply(&self, color: V3) -> V3 {
+        let sample = |curve: &[f32], x: f32| {
+            let x = x.min(1.0).max(0.0) * (curve.len() - 1) as f32;
+            let i = (x.floor() as usize).min(curve.len() - 2);
+            let t = x - i as f32;
+            curve[i] * (1.0 - t) + curve[i + 1] * t
+        };
+
+        V3::new(
+            sample(&self.curves[0], color.x()),
+            sample(&self.curves[1], color.y()),
+            sample(&self.curves[2], color.z()),
+        )
+    }
+}
+
+/// Simulates a camera sensor when forming the displayed and exported image, for producing
+/// synthetic footage that looks like it came from a real camera.
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    response: ResponseCurves,
+    noise: Option<(f32, f32)>,
+    rolling_shutter: Option<f32>,
+}
+
+impl Default for Sensor {
+    fn default() -> Self {
+        Self {
+            response: ResponseCurves::gamma(2.2),
+            noise: None,
+            rolling_shutter: None,
+        }
+    }
+}
+
+impl Sensor {
+    pub fn with_response(mut self, response: ResponseCurves) -> Self {
+        self.response = response;
+        self
+    }
+
+    /// Adds shot noise from `gain` photons per unit of exposure, plus gaussian read noise with
+    /// a standard deviation of `read_noise`.
+    pub fn with_noise(mut self, gain: f32, read_noise: f32) -> Self {
+        self.noise = Some((gain.max(f32::EPSILON), read_noise.max(0.0)));
+        self
+    }
+
+    /// Reads rows out over `readout` of the frame's time, from the top of the image down, so
+    /// moving objects skew the way they do with a CMOS rolling shutter.
+    pub fn with_rolling_shutter(mut self, readout: f32) -> Self {
+        self.rolling_shutter = Some(readout.min(1.0).max(0.0));
+        self
+    }
+
+    /// Shifts a ray `time` sampled from the shutter interval to when `row` is exposed, rows
+    /// count up from the bottom of an image `height` rows tall.
+    pub fn row_time(&self, time: f32, row: u32, height: u32) -> f32 {
+        match self.rolling_shutter {
+            Some(readout) => {
+                let from_top = 1.0 - row as f32 / height.max(1) as f32;
+                time * (1.0 - readout) + readout * from_top
+            }
+            None => time,
+        }
+    }
+
+    /// Converts the linear `exposure` of a pixel into a display value. Noise is seeded by the
+    /// pixel `index` and `samples` so that a given image always forms the same way.
+    pub fn form(&self, exposure: V3, index: usize, samples: u32) -> V3 {
+        let exposure = match self.noise {
+            Some((gain, read_noise)) => {
+                let seed =
+                    (index as u32).wrapping_mul(0x9e3779b9) ^ samples.wrapping_mul(0x85ebca6b);
+                let noisy = |value: f32, channel: u32| {
+                    let value = value.max(0.0);
+                    let sigma = (value / gain + read_noise * read_noise).sqrt();
+                    value + sigma * gaussian(seed.wrapping_add(channel.wrapping_mul(0xc2b2ae35)))
+                };
+                V3::new(
+                    noisy(exposure.x(), 0),
+                    noisy(exposure.y(), 1),
+                    noisy(exposure.z(), 2),
+                )
+            }
+            None => exposure,
+        };
+
+        self.response.apply(exposure)
+    }
+}
+
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+/// A standard normal sample derived from `seed` with the Box-Muller transform.
+fn gaussian(seed: u32) -> f32 {
+    let a = hash(seed);
+    let b = hash(a);
+    let u1 = (a as f32 + 1.0) / (u32::MAX as f32 + 2.0);
+    let u2 = b as f32 / u32::MAX as f32;
+    (-2.0 * u1.ln()).sqrt() * (std::f32::consts::PI * 2.0 * u2).cos()
+}
@@ -0,0 +1,132 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use mass_raytrace::json;
+
+use crate::DisplayMode;
+
+/// Viewer preferences kept between sessions. Anything missing from the file keeps its default.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub display_mode: DisplayMode,
+    /// Stops of exposure applied to the displayed and exported image.
+    pub exposure: f32,
+    pub window_size: (u32, u32),
+    pub export_dir: String,
+}
+
+impl Settings {
+    pub fn new(window_size: (u32, u32)) -> Self {
+        Self {
+            display_mode: DisplayMode::Default,
+            exposure: 0.0,
+            window_size,
+            export_dir: String::from("./export"),
+        }
+    }
+
+    /// Reads the settings in `path` over the defaults, a file that doesn't exist yet leaves
+    /// them all as they are.
+    pub fn load<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(self),
+            Err(error) => return Err(error)?,
+        };
+        let value = json::parse(&text)?;
+
+        if let Some(mode) = value.get("display_mode").and_then(|v| v.as_str()) {
+            self.display_mode =
+                parse_mode(mode).ok_or_else(|| format!("unknown display mode: {}", mode))?;
+        }
+        if let Some(exposure) = value.get("exposure").and_then(|v| v.as_f32()) {
+            self.exposure = exposure;
+        }
+        if let Some(size) = value.get("window_size").and_then(|v| v.as_array()) {
+            match size {
+                [width, height] => {
+                    let width = width.as_u32().ok_or("invalid window width")?;
+                    let height = height.as_u32().ok_or("invalid window height")?;
+                    self.window_size = (width.max(1), height.max(1));
+                }
+                _ => return Err("window_size should be [width, height]")?,
+            }
+        }
+        if let Some(export_dir) = value.get("export_dir").and_then(|v| v.as_str()) {
+            self.export_dir = export_dir.trim_end_matches('/').to_string();
+        }
+
+        Ok(self)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        let mut text = String::new();
+        writeln!(text, "{{")?;
+        writeln!(
+            text,
+            "  \"display_mode\": {},",
+            quote(&mode_name(self.display_mode))
+        )?;
+        writeln!(text, "  \"exposure\": {},", self.exposure)?;
+        writeln!(
+            text,
+            "  \"window_size\": [{}, {}],",
+            self.window_size.0, self.window_size.1
+        )?;
+        writeln!(text, "  \"export_dir\": {}", quote(&self.export_dir))?;
+        writeln!(text, "}}")?;
+
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// A path in the export directory named with the current time.
+    pub fn export_path(&self) -> String {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_else(|e| e.duration())
+            .as_secs();
+        format!("{}/raytrace_{}.png", self.export_dir, timestamp)
+    }
+}
+
+fn mode_name(mode: DisplayMode) -> String {
+    match mode {
+        DisplayMode::Default => String::from("default"),
+        DisplayMode::Denoise => String::from("denoise"),
+        DisplayMode::Depth => String::from("depth"),
+        DisplayMode::Albedo => String::from("albedo"),
+        DisplayMode::Normal => String::from("normal"),
+        DisplayMode::LightGroup(group) => format!("light_group_{}", group),
+        DisplayMode::Range => String::from("range"),
+    }
+}
+
+fn parse_mode(name: &str) -> Option<DisplayMode> {
+    let mode = match name {
+        "default" => DisplayMode::Default,
+        "denoise" => DisplayMode::Denoise,
+        "depth" => DisplayMode::Depth,
+        "albedo" => DisplayMode::Albedo,
+        "normal" => DisplayMode::Normal,
+        "range" => DisplayMode::Range,
+        _ => DisplayMode::LightGroup(name.strip_prefix("light_group_")?.parse().ok()?),
+    };
+
+    Some(mode)
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
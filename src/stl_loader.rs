@@ -4,6 +4,14 @@ use std::path::Path;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
+#[cfg(feature = "mmap")]
+use crate::mapped;
+
+#[cfg(feature = "mmap")]
+const HEADER_LEN: usize = 84;
+#[cfg(feature = "mmap")]
+const RECORD_LEN: usize = 50;
+
 pub struct StlLoader;
 
 impl StlLoader {
@@ -63,4 +71,55 @@ impl StlLoader {
 
         Ok(faces)
     }
+
+    /// Like `load_binary`, but parses straight out of a memory mapping and builds faces across
+    /// every cpu. `vertex_fn` and `face_fn` may be called from several threads at once and in
+    /// any order.
+    #[cfg(feature = "mmap")]
+    pub fn load_binary_mapped<
+        P: AsRef<Path>,
+        FV: Fn(f32, f32, f32) -> V + Sync,
+        FF: Fn(V, V, V) -> F + Sync,
+        V: Copy,
+        F: Send,
+    >(
+        path: P,
+        vertex_fn: FV,
+        face_fn: FF,
+    ) -> Result<Vec<F>, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let map = mapped::open(path)?;
+        if map.len() < HEADER_LEN {
+            return Err("stl file is truncated")?;
+        }
+
+        let tri_count = mapped::read_u32(&map, HEADER_LEN - 4) as usize;
+
+        // Files that use the attribute byte count to extend records can't be indexed directly
+        if map.len() != HEADER_LEN + tri_count * RECORD_LEN {
+            drop(map);
+            return Self::load_binary(path, vertex_fn, face_fn);
+        }
+
+        eprintln!("loading stl with {} triangles", tri_count);
+
+        let records = &map[HEADER_LEN..];
+        Ok(mapped::parallel_chunks(tri_count, |range| {
+            range
+                .map(|i| {
+                    let record = i * RECORD_LEN;
+                    let vertex = |offset: usize| {
+                        let offset = record + offset;
+                        vertex_fn(
+                            mapped::read_f32(records, offset),
+                            mapped::read_f32(records, offset + 4),
+                            mapped::read_f32(records, offset + 8),
+                        )
+                    };
+
+                    face_fn(vertex(12), vertex(24), vertex(36))
+                })
+                .collect()
+        }))
+    }
 }
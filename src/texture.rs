@@ -1,5 +1,6 @@
+use image::codecs::hdr::HdrDecoder;
 use image::io::Reader;
-use image::{ImageFormat, Pixel};
+use image::{DynamicImage, ImageFormat, Pixel};
 
 use std::fs::File;
 use std::io::BufReader;
@@ -9,21 +10,67 @@ use std::sync::Arc;
 
 use crate::math::{M4, V2, V3, V4};
 
+mod noise;
+mod pattern;
+mod worley;
+pub use noise::{Noise, NoiseBasis};
+pub use pattern::{Checker, Grid, Ramp};
+pub use worley::{Worley, WorleyFeature, WorleyMetric};
+
 pub trait Surface: Send + Sync {
     fn width(&self) -> u32;
     fn height(&self) -> u32;
 
     fn get_f(&self, index: V2) -> V4;
+
+    /// The color at `index` averaged over an area `footprint` wide in texture coordinates,
+    /// surfaces without prefiltered levels return the color at `index`.
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        let _ = footprint;
+        self.get_f(index)
+    }
+
+    /// The color at `point` in the object's own space, surfaces mapped by texture coordinates
+    /// look up `index` filtered over `footprint` instead.
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        let _ = point;
+        self.get_filtered(index, footprint)
+    }
 }
 
 pub type SharedTexture = Arc<Texture>;
 
+/// Where a procedural surface is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coordinates {
+    /// At the texture coordinates, following the surface's uv layout.
+    Uv,
+    /// At the hit point in the object's own space, carving the pattern out of a solid block
+    /// so it needs no uv layout and doesn't stretch across seams.
+    Object,
+}
+
+impl Coordinates {
+    /// The point a procedural surface is evaluated at for a hit at `point` with texture
+    /// coordinates `index`.
+    pub fn point(&self, point: V3, index: V2) -> V3 {
+        match self {
+            Coordinates::Uv => V3::new(index.x(), index.y(), 0.0),
+            Coordinates::Object => point,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Texture {
     width: u32,
     height: u32,
     pixels: Vec<V4>,
-    wrapping: WrapMode,
+    wrapping: Wrapping,
+    filter: FilterMode,
+    /// Successively halved copies down to a single texel, empty unless built with
+    /// `with_mipmaps`.
+    mips: Vec<Texture>,
 }
 
 impl Texture {
@@ -36,6 +83,37 @@ impl Texture {
         let file = BufReader::new(File::open(path)?);
 
         let image = Reader::with_format(file, ImageFormat::Png).decode()?;
+
+        Ok(Self::from_image(image, wrapping))
+    }
+
+    /// Loads an image in any format detected from its contents, such as PNG, JPEG, TGA, BMP or
+    /// WebP. Files ending in `.hdr` are loaded with `load_hdr`, and with the `openexr` feature
+    /// those ending in `.exr` with `load_exr`. Grayscale images are spread across red, green
+    /// and blue, images without alpha are opaque.
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        wrapping: WrapMode,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("hdr") => return Self::load_hdr(path, wrapping),
+            #[cfg(feature = "openexr")]
+            Some("exr") => return Self::load_exr(path, wrapping),
+            _ => (),
+        }
+
+        let image = Reader::open(path)?.with_guessed_format()?.decode()?;
+
+        Ok(Self::from_image(image, wrapping))
+    }
+
+    fn from_image(image: DynamicImage, wrapping: WrapMode) -> Self {
         let image = image.to_rgba8();
 
         let width = image.width();
@@ -60,11 +138,102 @@ impl Texture {
             }
         }
 
-        Ok(Texture {
+        Texture {
             width,
             height,
             pixels,
-            wrapping,
+            wrapping: wrapping.into(),
+            filter: FilterMode::Bilinear,
+            mips: Vec::new(),
+        }
+    }
+
+    /// Loads a Radiance HDR file, keeping the full range of its radiance rather than clamping
+    /// it to one like `load_png`. Alpha is one throughout.
+    pub fn load_hdr<P: AsRef<Path>>(
+        path: P,
+        wrapping: WrapMode,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = BufReader::new(File::open(path.as_ref())?);
+
+        let decoder = HdrDecoder::new(file)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|p| V4::new(p[0], p[1], p[2], 1.0))
+            .collect();
+
+        Ok(Texture {
+            width: metadata.width,
+            height: metadata.height,
+            pixels,
+            wrapping: wrapping.into(),
+            filter: FilterMode::Bilinear,
+            mips: Vec::new(),
+        })
+    }
+
+    /// Loads the first layer of an OpenEXR file at full resolution, keeping the full range of
+    /// its half or full float samples. Channels are matched by the last part of their name, so
+    /// `diffuse.R` is read as red, and files without color channels, such as height maps, are
+    /// read as grayscale from `Y` or their first channel. Alpha is one if the file has none.
+    #[cfg(feature = "openexr")]
+    pub fn load_exr<P: AsRef<Path>>(
+        path: P,
+        wrapping: WrapMode,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        use exr::prelude::*;
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_file(path)?;
+        let layer = image.layer_data;
+        let channels = &layer.channel_data.list;
+
+        let values = |channel: &AnyChannel<FlatSamples>| -> Vec<f32> {
+            channel.sample_data.values_as_f32().collect()
+        };
+        let find = |short: &str| {
+            channels
+                .iter()
+                .find(|channel| {
+                    let name = channel.name.to_string();
+                    name.rsplit('.').next() == Some(short)
+                })
+                .map(values)
+        };
+
+        let gray = find("Y")
+            .or_else(|| channels.first().map(values))
+            .ok_or("EXR file has no channels")?;
+        let red = find("R").unwrap_or_else(|| gray.clone());
+        let green = find("G").unwrap_or_else(|| gray.clone());
+        let blue = find("B").unwrap_or_else(|| gray.clone());
+        let alpha = find("A");
+
+        let pixels = (0..gray.len())
+            .map(|i| {
+                V4::new(
+                    red[i],
+                    green[i],
+                    blue[i],
+                    alpha.as_ref().map_or(1.0, |alpha| alpha[i]),
+                )
+            })
+            .collect();
+
+        Ok(Texture {
+            width: layer.size.width() as u32,
+            height: layer.size.height() as u32,
+            pixels,
+            wrapping: wrapping.into(),
+            filter: FilterMode::Bilinear,
+            mips: Vec::new(),
         })
     }
 
@@ -97,7 +266,84 @@ impl Texture {
             width,
             height,
             pixels,
-            wrapping,
+            wrapping: wrapping.into(),
+            filter: FilterMode::Bilinear,
+            mips: Vec::new(),
+        }
+    }
+
+    /// Wraps texture coordinates along u with `u` and along v with `v`, including in its mips.
+    pub fn with_wrapping(mut self, u: WrapMode, v: WrapMode) -> Self {
+        self.wrapping = Wrapping::new(u, v);
+        for mip in self.mips.iter_mut() {
+            mip.wrapping = self.wrapping;
+        }
+        self
+    }
+
+    /// Looks up texels with `filter` rather than bilinear filtering, including in its mips.
+    pub fn with_filter(mut self, filter: FilterMode) -> Self {
+        self.filter = filter;
+        for mip in self.mips.iter_mut() {
+            mip.filter = filter;
+        }
+        self
+    }
+
+    /// Builds the mip pyramid, letting lookups over a wide footprint blend the two levels
+    /// nearest its size instead of aliasing between distant texels.
+    pub fn with_mipmaps(mut self) -> Self {
+        if self.pixels.is_empty() {
+            return self;
+        }
+
+        let mut mips: Vec<Texture> = Vec::new();
+        loop {
+            let last = mips.last().unwrap_or(&self);
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let half = last.half();
+            mips.push(half);
+        }
+
+        self.mips = mips;
+        self
+    }
+
+    /// The texture at half the resolution, each texel the average of the two by two it covers.
+    fn half(&self) -> Texture {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+
+        for y in 0..height {
+            let y0 = (y * 2).min(self.height - 1) as usize;
+            let y1 = (y * 2 + 1).min(self.height - 1) as usize;
+            for x in 0..width {
+                let x0 = (x * 2).min(self.width - 1) as usize;
+                let x1 = (x * 2 + 1).min(self.width - 1) as usize;
+                let sum = self[(x0, y0)] + self[(x1, y0)] + self[(x0, y1)] + self[(x1, y1)];
+                pixels.push(sum * 0.25);
+            }
+        }
+
+        Texture {
+            width,
+            height,
+            pixels,
+            wrapping: self.wrapping,
+            filter: self.filter,
+            mips: Vec::new(),
+        }
+    }
+
+    /// Mip `level`, where zero is the full resolution texture and levels past the smallest
+    /// return the smallest.
+    fn level(&self, level: usize) -> &Texture {
+        match level.min(self.mips.len()) {
+            0 => self,
+            level => &self.mips[level - 1],
         }
     }
 
@@ -124,28 +370,146 @@ impl Surface for Texture {
     }
 
     fn get_f(&self, index: V2) -> V4 {
-        let index = self.wrapping.wrap(index);
-        let x = index.x();
-        let y = index.y();
+        self.filter
+            .filter(index, self.width, self.height, self.wrapping, |x, y| {
+                self[(x, y)]
+            })
+    }
+
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        let texels = footprint * self.width.max(self.height) as f32;
+        if self.mips.is_empty() || texels <= 1.0 {
+            return self.get_f(index);
+        }
+
+        let level = texels.log2().min(self.mips.len() as f32);
+        let fine = level.floor() as usize;
+        let t = level - fine as f32;
+
+        let color = self.level(fine).get_f(index);
+        if t > 0.0 {
+            color * (1.0 - t) + self.level(fine + 1).get_f(index) * t
+        } else {
+            color
+        }
+    }
+}
+
+/// How texels are combined into the color between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    /// The closest texel, keeping the hard edges of pixel art.
+    Nearest,
+    /// Blends the four closest texels.
+    Bilinear,
+    /// A Catmull-Rom spline through the sixteen closest texels, sharper than bilinear when
+    /// magnified.
+    Bicubic,
+}
+
+impl FilterMode {
+    /// Filters the texels around `index`, fetching each through `texel`.
+    pub(crate) fn filter<F: Fn(usize, usize) -> V4>(
+        &self,
+        index: V2,
+        width: u32,
+        height: u32,
+        wrapping: Wrapping,
+        texel: F,
+    ) -> V4 {
+        match self {
+            FilterMode::Nearest => nearest(index, width, height, wrapping, texel),
+            FilterMode::Bilinear => bilinear(index, width, height, wrapping, texel),
+            FilterMode::Bicubic => bicubic(index, width, height, wrapping, texel),
+        }
+    }
+}
+
+/// The texel closest to `index`, fetched through `texel`.
+fn nearest<F: Fn(usize, usize) -> V4>(
+    index: V2,
+    width: u32,
+    height: u32,
+    wrapping: Wrapping,
+    texel: F,
+) -> V4 {
+    let index = wrapping.wrap(index);
+
+    let x = (index.x() * (width - 1) as f32).round() as usize;
+    let y = (index.y() * (height - 1) as f32).round() as usize;
+
+    texel(x, y)
+}
+
+/// Filters the four texels around `index`, fetching each through `texel`.
+pub(crate) fn bilinear<F: Fn(usize, usize) -> V4>(
+    index: V2,
+    width: u32,
+    height: u32,
+    wrapping: Wrapping,
+    texel: F,
+) -> V4 {
+    let index = wrapping.wrap(index);
+    let x = index.x();
+    let y = index.y();
+
+    let x = x * (width - 1) as f32;
+    let y = y * (height - 1) as f32;
 
-        let x = x * (self.width() - 1) as f32;
-        let y = y * (self.height() - 1) as f32;
+    let x0 = x.floor() as usize;
+    let x1 = x.ceil() as usize;
 
-        let x0 = x.floor() as usize;
-        let x1 = x.ceil() as usize;
+    let y0 = y.floor() as usize;
+    let y1 = y.ceil() as usize;
 
-        let y0 = y.floor() as usize;
-        let y1 = y.ceil() as usize;
+    let t = x - x0 as f32;
 
-        let t = x - x0 as f32;
+    let p0 = texel(x0, y0) * (1.0 - t) + texel(x1, y0) * t;
+    let p1 = texel(x0, y1) * (1.0 - t) + texel(x1, y1) * t;
 
-        let p0 = self[(x0, y0)] * (1.0 - t) + self[(x1, y0)] * t;
-        let p1 = self[(x0, y1)] * (1.0 - t) + self[(x1, y1)] * t;
+    let t = y - y0 as f32;
 
-        let t = y - y0 as f32;
+    p1 * t + p0 * (1.0 - t)
+}
 
-        p1 * t + p0 * (1.0 - t)
+/// Filters the sixteen texels around `index` with a Catmull-Rom spline, fetching each through
+/// `texel`. The spline overshoots at hard edges, negative results are clamped to zero.
+fn bicubic<F: Fn(usize, usize) -> V4>(
+    index: V2,
+    width: u32,
+    height: u32,
+    wrapping: Wrapping,
+    texel: F,
+) -> V4 {
+    let index = wrapping.wrap(index);
+
+    let x = index.x() * (width - 1) as f32;
+    let y = index.y() * (height - 1) as f32;
+
+    let x0 = x.floor();
+    let y0 = y.floor();
+
+    let weights = |t: f32| {
+        [
+            ((-0.5 * t + 1.0) * t - 0.5) * t,
+            (1.5 * t - 2.5) * t * t + 1.0,
+            ((-1.5 * t + 2.0) * t + 0.5) * t,
+            (0.5 * t - 0.5) * t * t,
+        ]
+    };
+    let weights_x = weights(x - x0);
+    let weights_y = weights(y - y0);
+
+    let mut color = V4::zero();
+    for (j, weight_y) in weights_y.iter().enumerate() {
+        let row = wrapping.v.wrap_texel(y0 as isize + j as isize - 1, height);
+        for (i, weight_x) in weights_x.iter().enumerate() {
+            let column = wrapping.u.wrap_texel(x0 as isize + i as isize - 1, width);
+            color = color + texel(column, row) * (weight_x * weight_y);
+        }
     }
+
+    color.max(V4::zero())
 }
 
 impl<S: Surface + ?Sized> Surface for Arc<S> {
@@ -160,6 +524,14 @@ impl<S: Surface + ?Sized> Surface for Arc<S> {
     fn get_f(&self, index: V2) -> V4 {
         (**self).get_f(index)
     }
+
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        (**self).get_filtered(index, footprint)
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        (**self).get_point(point, index, footprint)
+    }
 }
 
 impl<S: Surface + ?Sized> Surface for Box<S> {
@@ -174,6 +546,14 @@ impl<S: Surface + ?Sized> Surface for Box<S> {
     fn get_f(&self, index: V2) -> V4 {
         (**self).get_f(index)
     }
+
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        (**self).get_filtered(index, footprint)
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        (**self).get_point(point, index, footprint)
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -269,36 +649,82 @@ impl BlendMode {
 
 #[derive(Debug, Clone, Copy)]
 pub enum WrapMode {
+    /// Repeats the texture, flipping every other copy so its edges meet their reflection.
     Mirror,
     Repeat,
     Clamp,
 }
 
 impl WrapMode {
-    fn wrap(&self, orig: V2) -> V2 {
+    /// Wraps one texture coordinate into the zero to one range.
+    fn wrap(&self, orig: f32) -> f32 {
         match self {
             WrapMode::Mirror => {
-                unimplemented!("Mirror wrapping is not implemented")
+                let period = orig.rem_euclid(2.0);
+                if period > 1.0 {
+                    2.0 - period
+                } else {
+                    period
+                }
             }
             WrapMode::Repeat => {
-                let x = orig.x();
-                let y = orig.y();
-                let x = if x < 0.0 { 1.0 - x.abs().fract() } else { x };
-                let y = if y < 0.0 { 1.0 - y.abs().fract() } else { y };
-                let x = if x > 1.0 { x.fract() } else { x };
-                let y = if y > 1.0 { y.fract() } else { y };
-                V2::new(x, y)
+                let x = if orig < 0.0 {
+                    1.0 - orig.abs().fract()
+                } else {
+                    orig
+                };
+                if x > 1.0 {
+                    x.fract()
+                } else {
+                    x
+                }
             }
-            WrapMode::Clamp => {
-                let x = orig.x().min(1.0).max(0.0);
-                let y = orig.y().min(1.0).max(0.0);
+            WrapMode::Clamp => orig.min(1.0).max(0.0),
+        }
+    }
 
-                V2::new(x, y)
+    /// The texel at `index` along an axis `size` texels long, for filters reaching past its
+    /// ends.
+    fn wrap_texel(&self, index: isize, size: u32) -> usize {
+        let size = size as isize;
+        match self {
+            WrapMode::Mirror => {
+                let period = index.rem_euclid(2 * size);
+                if period >= size {
+                    (2 * size - 1 - period) as usize
+                } else {
+                    period as usize
+                }
             }
+            WrapMode::Repeat => index.rem_euclid(size) as usize,
+            WrapMode::Clamp => index.clamp(0, size - 1) as usize,
         }
     }
 }
 
+/// Wrap modes for each texture axis, a single `WrapMode` converts into the same mode for both.
+#[derive(Debug, Clone, Copy)]
+pub struct Wrapping {
+    pub u: WrapMode,
+    pub v: WrapMode,
+}
+
+impl Wrapping {
+    pub fn new(u: WrapMode, v: WrapMode) -> Self {
+        Self { u, v }
+    }
+
+    fn wrap(&self, orig: V2) -> V2 {
+        V2::new(self.u.wrap(orig.x()), self.v.wrap(orig.y()))
+    }
+}
+
+impl From<WrapMode> for Wrapping {
+    fn from(mode: WrapMode) -> Self {
+        Self::new(mode, mode)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TextureBlend<L: Surface, R: Surface> {
     blend_mode: BlendMode,
@@ -331,6 +757,20 @@ impl<L: Surface, R: Surface> Surface for TextureBlend<L, R> {
 
         self.blend_mode.blend(l, r)
     }
+
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        let l = self.left.get_filtered(index, footprint);
+        let r = self.right.get_filtered(index, footprint);
+
+        self.blend_mode.blend(l, r)
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        let l = self.left.get_point(point, index, footprint);
+        let r = self.right.get_point(point, index, footprint);
+
+        self.blend_mode.blend(l, r)
+    }
 }
 
 pub struct SolidColorFallback<S: Surface> {
@@ -357,4 +797,14 @@ impl<S: Surface> Surface for SolidColorFallback<S> {
         let c = self.surface.get_f(index);
         (self.color * (1.0 - c.w())) + (c * c.w())
     }
+
+    fn get_filtered(&self, index: V2, footprint: f32) -> V4 {
+        let c = self.surface.get_filtered(index, footprint);
+        (self.color * (1.0 - c.w())) + (c * c.w())
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        let c = self.surface.get_point(point, index, footprint);
+        (self.color * (1.0 - c.w())) + (c * c.w())
+    }
 }
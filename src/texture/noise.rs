@@ -0,0 +1,283 @@
+use super::{Coordinates, Surface};
+use crate::math::{V2, V3, V4};
+
+/// The noise function summed over each octave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseBasis {
+    /// Gradient noise interpolated over a cubic lattice.
+    Perlin,
+    /// Gradient noise over a tetrahedral lattice, cheaper and without the axis aligned
+    /// artifacts of Perlin noise.
+    Simplex,
+}
+
+/// Grayscale fractal noise, summing octaves of a noise basis at rising frequencies and falling
+/// amplitudes. Plain fractal Brownian motion suits clouds and terrain, turbulence sums the
+/// absolute value of each octave for the creases of marble and fire.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    basis: NoiseBasis,
+    coordinates: Coordinates,
+    octaves: u32,
+    frequency: f32,
+    lacunarity: f32,
+    gain: f32,
+    turbulence: bool,
+    permutation: Vec<usize>,
+}
+
+impl Noise {
+    /// A single octave of `basis` with one lattice cell per unit of texture coordinates.
+    pub fn new(basis: NoiseBasis) -> Self {
+        Self {
+            basis,
+            coordinates: Coordinates::Uv,
+            octaves: 1,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+            turbulence: false,
+            permutation: permutation(0),
+        }
+    }
+
+    pub fn with_coordinates(self, coordinates: Coordinates) -> Self {
+        Self {
+            coordinates,
+            ..self
+        }
+    }
+
+    /// Sums `octaves` layers of noise, at least one.
+    pub fn with_octaves(self, octaves: u32) -> Self {
+        Self {
+            octaves: octaves.max(1),
+            ..self
+        }
+    }
+
+    /// Lattice cells per unit of the first octave.
+    pub fn with_frequency(self, frequency: f32) -> Self {
+        Self { frequency, ..self }
+    }
+
+    /// How much the frequency is multiplied by from one octave to the next.
+    pub fn with_lacunarity(self, lacunarity: f32) -> Self {
+        Self { lacunarity, ..self }
+    }
+
+    /// How much the amplitude is multiplied by from one octave to the next.
+    pub fn with_gain(self, gain: f32) -> Self {
+        Self { gain, ..self }
+    }
+
+    pub fn with_turbulence(self) -> Self {
+        Self {
+            turbulence: true,
+            ..self
+        }
+    }
+
+    /// Shuffles the lattice gradients, each seed gives a different pattern.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            permutation: permutation(seed),
+            ..self
+        }
+    }
+
+    /// The noise at `point`, between zero and one.
+    pub fn value(&self, point: V3) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+
+        for _ in 0..self.octaves {
+            let noise = self.basis(point * frequency);
+            sum += amplitude * if self.turbulence { noise.abs() } else { noise };
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+
+        let value = if total > 0.0 { sum / total } else { 0.0 };
+        if self.turbulence {
+            value.clamp(0.0, 1.0)
+        } else {
+            (value * 0.5 + 0.5).clamp(0.0, 1.0)
+        }
+    }
+
+    fn basis(&self, point: V3) -> f32 {
+        match self.basis {
+            NoiseBasis::Perlin => self.perlin(point),
+            NoiseBasis::Simplex => self.simplex(point),
+        }
+    }
+
+    /// Improved Perlin noise, between minus one and one.
+    fn perlin(&self, point: V3) -> f32 {
+        let p = &self.permutation;
+        let cell = |c: f32| (c.floor() as i32 & 255) as usize;
+        let (xi, yi, zi) = (cell(point.x()), cell(point.y()), cell(point.z()));
+        let x = point.x() - point.x().floor();
+        let y = point.y() - point.y().floor();
+        let z = point.z() - point.z().floor();
+
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let a = p[xi] + yi;
+        let aa = p[a] + zi;
+        let ab = p[a + 1] + zi;
+        let b = p[xi + 1] + yi;
+        let ba = p[b] + zi;
+        let bb = p[b + 1] + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, gradient(p[aa], x, y, z), gradient(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    gradient(p[ab], x, y - 1.0, z),
+                    gradient(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    gradient(p[aa + 1], x, y, z - 1.0),
+                    gradient(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    gradient(p[ab + 1], x, y - 1.0, z - 1.0),
+                    gradient(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// Simplex noise, between minus one and one.
+    fn simplex(&self, point: V3) -> f32 {
+        const SKEW: f32 = 1.0 / 3.0;
+        const UNSKEW: f32 = 1.0 / 6.0;
+
+        let p = &self.permutation;
+        let (x, y, z) = (point.x(), point.y(), point.z());
+
+        // The corner of the simplex cell in the skewed lattice
+        let skew = (x + y + z) * SKEW;
+        let i = (x + skew).floor();
+        let j = (y + skew).floor();
+        let k = (z + skew).floor();
+        let unskew = (i + j + k) * UNSKEW;
+        let x0 = x - (i - unskew);
+        let y0 = y - (j - unskew);
+        let z0 = z - (k - unskew);
+
+        // The order of the offsets picks which of the six tetrahedra holds the point
+        let (first, second) = if x0 >= y0 {
+            if y0 >= z0 {
+                ((1, 0, 0), (1, 1, 0))
+            } else if x0 >= z0 {
+                ((1, 0, 0), (1, 0, 1))
+            } else {
+                ((0, 0, 1), (1, 0, 1))
+            }
+        } else if y0 < z0 {
+            ((0, 0, 1), (0, 1, 1))
+        } else if x0 < z0 {
+            ((0, 1, 0), (0, 1, 1))
+        } else {
+            ((0, 1, 0), (1, 1, 0))
+        };
+
+        let cell = |c: f32| (c as i32 & 255) as usize;
+        let (ii, jj, kk) = (cell(i), cell(j), cell(k));
+        let corners = [(0, 0, 0), first, second, (1, 1, 1)];
+
+        corners
+            .iter()
+            .enumerate()
+            .map(|(n, &(ci, cj, ck))| {
+                let offset = n as f32 * UNSKEW;
+                let dx = x0 - ci as f32 + offset;
+                let dy = y0 - cj as f32 + offset;
+                let dz = z0 - ck as f32 + offset;
+                let t = 0.6 - dx * dx - dy * dy - dz * dz;
+                if t > 0.0 {
+                    let hash = p[ii + ci + p[jj + cj + p[kk + ck]]];
+                    t * t * t * t * gradient(hash, dx, dy, dz)
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f32>()
+            * 32.0
+    }
+}
+
+/// The dot product of the offset with one of twelve gradients along the edges of a cube,
+/// picked by `hash`.
+fn gradient(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    let hash = hash & 15;
+    let u = if hash < 8 { x } else { y };
+    let v = if hash < 4 {
+        y
+    } else if hash == 12 || hash == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if hash & 1 == 0 { u } else { -u }) + (if hash & 2 == 0 { v } else { -v })
+}
+
+/// A shuffle of 0 to 255 repeated twice, so lattice hashes can index past 255 without
+/// wrapping.
+fn permutation(seed: u64) -> Vec<usize> {
+    let mut state = seed ^ 0x9e37_79b9_7f4a_7c15;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut table: Vec<usize> = (0..256).collect();
+    for i in (1..table.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        table.swap(i, j);
+    }
+
+    table.extend_from_within(..);
+    table
+}
+
+impl Surface for Noise {
+    /// Enough texels to resolve the highest octave.
+    fn width(&self) -> u32 {
+        let frequency = self.frequency * self.lacunarity.powi(self.octaves as i32 - 1);
+        (frequency * 4.0).ceil().clamp(1.0, 4096.0) as u32
+    }
+
+    fn height(&self) -> u32 {
+        self.width()
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        let value = self.value(V3::new(index.x(), index.y(), 0.0));
+        V4::new(value, value, value, 1.0)
+    }
+
+    fn get_point(&self, point: V3, index: V2, _footprint: f32) -> V4 {
+        let value = self.value(self.coordinates.point(point, index));
+        V4::new(value, value, value, 1.0)
+    }
+}
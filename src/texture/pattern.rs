@@ -0,0 +1,244 @@
+use super::{Coordinates, Surface, KB, KG, KR};
+use crate::math::{V2, V3, V4};
+
+/// Alternates between two surfaces in a checkerboard of squares, or of cubes in object space.
+#[derive(Debug, Clone)]
+pub struct Checker<E: Surface, O: Surface> {
+    even: E,
+    odd: O,
+    scale: f32,
+    coordinates: Coordinates,
+}
+
+impl<E: Surface, O: Surface> Checker<E, O> {
+    /// Squares one unit of texture coordinates wide, starting with `even` at the origin.
+    pub fn new(even: E, odd: O) -> Self {
+        Self {
+            even,
+            odd,
+            scale: 1.0,
+            coordinates: Coordinates::Uv,
+        }
+    }
+
+    /// Squares per unit.
+    pub fn with_scale(self, scale: f32) -> Self {
+        Self { scale, ..self }
+    }
+
+    pub fn with_coordinates(self, coordinates: Coordinates) -> Self {
+        Self {
+            coordinates,
+            ..self
+        }
+    }
+
+    fn is_even(&self, point: V3) -> bool {
+        let point = point * self.scale;
+        let sum = point.x().floor() as i64 + point.y().floor() as i64 + point.z().floor() as i64;
+        sum.rem_euclid(2) == 0
+    }
+}
+
+impl<E: Surface, O: Surface> Surface for Checker<E, O> {
+    fn width(&self) -> u32 {
+        let squares = (self.scale * 2.0).ceil().clamp(1.0, 4096.0) as u32;
+        self.even.width().max(self.odd.width()).max(squares)
+    }
+
+    fn height(&self) -> u32 {
+        let squares = (self.scale * 2.0).ceil().clamp(1.0, 4096.0) as u32;
+        self.even.height().max(self.odd.height()).max(squares)
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        if self.is_even(V3::new(index.x(), index.y(), 0.0)) {
+            self.even.get_f(index)
+        } else {
+            self.odd.get_f(index)
+        }
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        if self.is_even(self.coordinates.point(point, index)) {
+            self.even.get_point(point, index, footprint)
+        } else {
+            self.odd.get_point(point, index, footprint)
+        }
+    }
+}
+
+/// Draws `line` along the edges of a grid of squares filled with `fill`, in object space the
+/// lines are where the surface crosses a lattice of planes.
+#[derive(Debug, Clone)]
+pub struct Grid<L: Surface, F: Surface> {
+    line: L,
+    fill: F,
+    scale: f32,
+    line_width: f32,
+    coordinates: Coordinates,
+}
+
+impl<L: Surface, F: Surface> Grid<L, F> {
+    /// Squares one unit of texture coordinates wide, with lines a tenth of a square wide.
+    pub fn new(line: L, fill: F) -> Self {
+        Self {
+            line,
+            fill,
+            scale: 1.0,
+            line_width: 0.1,
+            coordinates: Coordinates::Uv,
+        }
+    }
+
+    /// Squares per unit.
+    pub fn with_scale(self, scale: f32) -> Self {
+        Self { scale, ..self }
+    }
+
+    /// The width of the lines as a fraction of a square.
+    pub fn with_line_width(self, line_width: f32) -> Self {
+        Self {
+            line_width: line_width.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    pub fn with_coordinates(self, coordinates: Coordinates) -> Self {
+        Self {
+            coordinates,
+            ..self
+        }
+    }
+
+    fn is_line(&self, point: V3, coordinates: Coordinates) -> bool {
+        let point = point * self.scale;
+        let half_width = self.line_width / 2.0;
+        let near_line = |c: f32| {
+            let fract = c - c.floor();
+            fract < half_width || fract > 1.0 - half_width
+        };
+
+        // Texture coordinates have no depth, every point would sit on the z = 0 plane
+        near_line(point.x())
+            || near_line(point.y())
+            || (coordinates == Coordinates::Object && near_line(point.z()))
+    }
+}
+
+impl<L: Surface, F: Surface> Surface for Grid<L, F> {
+    /// Enough texels for each line to be at least one wide.
+    fn width(&self) -> u32 {
+        let texels = (self.scale / self.line_width.max(f32::EPSILON))
+            .ceil()
+            .clamp(1.0, 4096.0) as u32;
+        self.line.width().max(self.fill.width()).max(texels)
+    }
+
+    fn height(&self) -> u32 {
+        let texels = (self.scale / self.line_width.max(f32::EPSILON))
+            .ceil()
+            .clamp(1.0, 4096.0) as u32;
+        self.line.height().max(self.fill.height()).max(texels)
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        if self.is_line(V3::new(index.x(), index.y(), 0.0), Coordinates::Uv) {
+            self.line.get_f(index)
+        } else {
+            self.fill.get_f(index)
+        }
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        let coordinates = self.coordinates;
+        if self.is_line(coordinates.point(point, index), coordinates) {
+            self.line.get_point(point, index, footprint)
+        } else {
+            self.fill.get_point(point, index, footprint)
+        }
+    }
+}
+
+/// Maps a value through a gradient of color stops, the value is the u coordinate or the
+/// luminance of another surface, such as noise remapped into colors.
+pub struct Ramp {
+    input: Option<Box<dyn Surface>>,
+    stops: Vec<(f32, V4)>,
+}
+
+impl Ramp {
+    /// A ramp along the u coordinate, without stops it is a gray gradient from black to white.
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            stops: Vec::new(),
+        }
+    }
+
+    /// Maps the luminance of `input` instead of the u coordinate.
+    pub fn with_input<S: Surface + 'static>(self, input: S) -> Self {
+        Self {
+            input: Some(Box::new(input)),
+            ..self
+        }
+    }
+
+    /// Adds a stop of `color` at `position`, colors between stops are blended linearly and
+    /// values beyond the first and last stop take their color.
+    pub fn with_stop(mut self, position: f32, color: V4) -> Self {
+        let index = self.stops.partition_point(|&(p, _)| p <= position);
+        self.stops.insert(index, (position, color));
+        self
+    }
+
+    /// The color of the ramp at `value`.
+    pub fn color(&self, value: f32) -> V4 {
+        let next = self.stops.partition_point(|&(p, _)| p <= value);
+        match (self.stops.get(next.wrapping_sub(1)), self.stops.get(next)) {
+            (Some(&(start, low)), Some(&(end, high))) => {
+                let t = (value - start) / (end - start);
+                low * (1.0 - t) + high * t
+            }
+            (Some(&(_, color)), None) | (None, Some(&(_, color))) => color,
+            (None, None) => {
+                let value = value.clamp(0.0, 1.0);
+                V4::new(value, value, value, 1.0)
+            }
+        }
+    }
+}
+
+impl Default for Ramp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn luminance(color: V4) -> f32 {
+    color.x() * KR + color.y() * KG + color.z() * KB
+}
+
+impl Surface for Ramp {
+    fn width(&self) -> u32 {
+        self.input.as_ref().map_or(256, |input| input.width())
+    }
+
+    fn height(&self) -> u32 {
+        self.input.as_ref().map_or(1, |input| input.height())
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        match self.input.as_ref() {
+            Some(input) => self.color(luminance(input.get_f(index))),
+            None => self.color(index.x()),
+        }
+    }
+
+    fn get_point(&self, point: V3, index: V2, footprint: f32) -> V4 {
+        match self.input.as_ref() {
+            Some(input) => self.color(luminance(input.get_point(point, index, footprint))),
+            None => self.color(index.x()),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::{Coordinates, Surface};
+use crate::math::{V2, V3, V4};
+
+/// How the distance to a cell's feature point is measured, shaping the cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyMetric {
+    /// Straight line distance, giving rounded cells.
+    Euclidean,
+    /// The sum of the distances along each axis, giving diamond shaped cells.
+    Manhattan,
+    /// The largest distance along any axis, giving square cells.
+    Chebyshev,
+}
+
+impl WorleyMetric {
+    fn distance(&self, offset: V3) -> f32 {
+        let (x, y, z) = (offset.x().abs(), offset.y().abs(), offset.z().abs());
+        match self {
+            WorleyMetric::Euclidean => (x * x + y * y + z * z).sqrt(),
+            WorleyMetric::Manhattan => x + y + z,
+            WorleyMetric::Chebyshev => x.max(y).max(z),
+        }
+    }
+}
+
+/// Which distances to the nearest feature points make up the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyFeature {
+    /// The distance to the nearest point, dark at the center of each cell.
+    F1,
+    /// The distance to the second nearest point.
+    F2,
+    /// The difference between the two, dark along the borders between cells, for cracks and
+    /// scales.
+    F2MinusF1,
+}
+
+/// Grayscale cellular noise, scattering one feature point in each lattice cell and measuring
+/// the distance to the nearest of them.
+#[derive(Debug, Clone)]
+pub struct Worley {
+    coordinates: Coordinates,
+    frequency: f32,
+    metric: WorleyMetric,
+    feature: WorleyFeature,
+    jitter: f32,
+    seed: u32,
+}
+
+impl Worley {
+    /// Euclidean F1 noise with one cell per unit of texture coordinates.
+    pub fn new() -> Self {
+        Self {
+            coordinates: Coordinates::Uv,
+            frequency: 1.0,
+            metric: WorleyMetric::Euclidean,
+            feature: WorleyFeature::F1,
+            jitter: 1.0,
+            seed: 0,
+        }
+    }
+
+    pub fn with_coordinates(self, coordinates: Coordinates) -> Self {
+        Self {
+            coordinates,
+            ..self
+        }
+    }
+
+    /// Lattice cells per unit.
+    pub fn with_frequency(self, frequency: f32) -> Self {
+        Self { frequency, ..self }
+    }
+
+    pub fn with_metric(self, metric: WorleyMetric) -> Self {
+        Self { metric, ..self }
+    }
+
+    pub fn with_feature(self, feature: WorleyFeature) -> Self {
+        Self { feature, ..self }
+    }
+
+    /// How far feature points stray from the center of their cell, from zero for a regular
+    /// grid to one for anywhere in the cell.
+    pub fn with_jitter(self, jitter: f32) -> Self {
+        Self {
+            jitter: jitter.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// Moves the feature points, each seed gives a different pattern.
+    pub fn with_seed(self, seed: u32) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// The noise at `point`, clamped between zero and one.
+    pub fn value(&self, point: V3) -> f32 {
+        let point = point * self.frequency;
+        let cell = (
+            point.x().floor() as i32,
+            point.y().floor() as i32,
+            point.z().floor() as i32,
+        );
+
+        let mut nearest = f32::INFINITY;
+        let mut second = f32::INFINITY;
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let neighbour = (cell.0 + x, cell.1 + y, cell.2 + z);
+                    let distance = self.metric.distance(self.feature_point(neighbour) - point);
+                    if distance < nearest {
+                        second = nearest;
+                        nearest = distance;
+                    } else if distance < second {
+                        second = distance;
+                    }
+                }
+            }
+        }
+
+        let value = match self.feature {
+            WorleyFeature::F1 => nearest,
+            WorleyFeature::F2 => second,
+            WorleyFeature::F2MinusF1 => second - nearest,
+        };
+
+        value.clamp(0.0, 1.0)
+    }
+
+    /// The feature point scattered in `cell`.
+    fn feature_point(&self, cell: (i32, i32, i32)) -> V3 {
+        let random = |stream: u32| {
+            hash(cell, self.seed.wrapping_mul(3).wrapping_add(stream)) as f32 / u32::MAX as f32
+        };
+        let jitter = V3::new(random(0), random(1), random(2)) - V3::fill(0.5);
+
+        V3::new(cell.0 as f32, cell.1 as f32, cell.2 as f32) + V3::fill(0.5) + jitter * self.jitter
+    }
+}
+
+impl Default for Worley {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mixes the lattice `cell` and `seed` into well distributed bits.
+fn hash(cell: (i32, i32, i32), seed: u32) -> u32 {
+    let mut hash = seed.wrapping_mul(0x9e37_79b9)
+        ^ (cell.0 as u32).wrapping_mul(0x85eb_ca6b)
+        ^ (cell.1 as u32).wrapping_mul(0xc2b2_ae35)
+        ^ (cell.2 as u32).wrapping_mul(0x27d4_eb2f);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a_2d39);
+    hash ^= hash >> 15;
+    hash
+}
+
+impl Surface for Worley {
+    /// Enough texels to resolve the cells.
+    fn width(&self) -> u32 {
+        (self.frequency * 4.0).ceil().clamp(1.0, 4096.0) as u32
+    }
+
+    fn height(&self) -> u32 {
+        self.width()
+    }
+
+    fn get_f(&self, index: V2) -> V4 {
+        let value = self.value(V3::new(index.x(), index.y(), 0.0));
+        V4::new(value, value, value, 1.0)
+    }
+
+    fn get_point(&self, point: V3, index: V2, _footprint: f32) -> V4 {
+        let value = self.value(self.coordinates.point(point, index));
+        V4::new(value, value, value, 1.0)
+    }
+}
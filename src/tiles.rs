@@ -0,0 +1,149 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// A rectangle of one view's image, the unit of work handed to render threads.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub view: usize,
+    pub x: Range<u32>,
+    pub y: Range<u32>,
+}
+
+impl Tile {
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> + Clone {
+        let x = self.x.clone();
+        self.y
+            .clone()
+            .flat_map(move |y| x.clone().map(move |x| (x, y)))
+    }
+}
+
+/// The order tiles of each view are handed out in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TileOrder {
+    /// Rows of tiles from the top of the image down.
+    Scanline,
+    /// Along a Hilbert curve from the top left, keeping recently traced tiles close together.
+    Hilbert,
+    /// Rings of tiles outward from the center, where the subject of most images is.
+    Spiral,
+}
+
+impl TileOrder {
+    /// Sort key for the tile at `column` and `row` of a `columns` by `rows` grid, with row 0 at
+    /// the top.
+    fn key(&self, column: u32, row: u32, columns: u32, rows: u32) -> (u64, f32) {
+        match self {
+            TileOrder::Scanline => ((row as u64) << 32 | column as u64, 0.0),
+            TileOrder::Hilbert => {
+                let size = columns.max(rows).next_power_of_two();
+                (hilbert_index(size, column, row), 0.0)
+            }
+            TileOrder::Spiral => {
+                let dx = column as f32 - (columns as f32 - 1.0) / 2.0;
+                let dy = row as f32 - (rows as f32 - 1.0) / 2.0;
+                let ring = dx.abs().max(dy.abs()).round() as u64;
+                (ring, dy.atan2(dx))
+            }
+        }
+    }
+}
+
+/// The distance along a Hilbert curve covering a `size` by `size` grid to `x` and `y`, where
+/// `size` is a power of two.
+fn hilbert_index(size: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = size / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+/// The tiles of one pass over every view. Threads take the next tile until none are left, so
+/// faster threads simply take more of them, and cancelling stops every thread at its next
+/// tile.
+pub struct TileQueue {
+    tiles: Vec<Tile>,
+    next: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl TileQueue {
+    /// Splits each view of `dimensions` into tiles of `size` pixels square, smaller along the
+    /// right and top edges. Views are handed out one after another, each in `order`.
+    pub fn new(
+        dimensions: impl IntoIterator<Item = (u32, u32)>,
+        size: u32,
+        order: TileOrder,
+    ) -> Self {
+        let size = size.max(1);
+        let mut tiles = Vec::new();
+        for (view, (width, height)) in dimensions.into_iter().enumerate() {
+            let columns = (width + size - 1) / size;
+            let rows = (height + size - 1) / size;
+
+            let mut view_tiles = Vec::with_capacity((columns * rows) as usize);
+            for row in 0..rows {
+                for column in 0..columns {
+                    // Image rows start at the bottom, tile rows at the top
+                    let y = (rows - 1 - row) * size;
+                    let x = column * size;
+                    let tile = Tile {
+                        view,
+                        x: x..(x + size).min(width),
+                        y: y..(y + size).min(height),
+                    };
+                    view_tiles.push((order.key(column, row, columns, rows), tile));
+                }
+            }
+
+            view_tiles
+                .sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            tiles.extend(view_tiles.into_iter().map(|(_, tile)| tile));
+        }
+
+        Self {
+            tiles,
+            next: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Claims the next tile with its position in the pass.
+    pub fn next(&self) -> Option<(usize, &Tile)> {
+        if self.is_cancelled() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        self.tiles.get(index).map(|tile| (index, tile))
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Starts the next pass, only call once every thread has stopped taking tiles.
+    pub fn reset(&self) {
+        self.next.store(0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,206 @@
+//! A batched alternative to `Camera::trace`. Rather than following each path to its end before
+//! starting the next, every path of a batch is extended one bounce at a time: all of them are
+//! intersected with the scene, then all of the hits are shaded, and the paths still alive are
+//! queued for the next bounce. Neighbouring rays walk the same parts of the BVH and touch the
+//! same materials together, and each stage is a plain loop over many paths that later SIMD
+//! shading can work on.
+//!
+//! Each hit is shaded as `Camera::trace_light_groups` would, sampling a light and weighting the
+//! light found by scattering against it, and rays leaving the scene pick up lights without a
+//! surface. The camera's polarizer is ignored, and its radiance clamp limits each light found
+//! along a path rather than the total scattered by each surface, as no path keeps the light
+//! gathered behind it.
+
+use crate::geom::{Hit, Intersect};
+use crate::light::Lights;
+use crate::material::Background;
+use crate::math::V3;
+use crate::sampler::Sampler;
+use crate::world::{clamp, light_sampled_pdf, Camera, Ray, LIGHT_GROUPS};
+
+#[derive(Debug, Copy, Clone)]
+struct Path {
+    pixel: (u32, u32),
+    ray: Ray,
+    /// The weight of the first surface scattered from, kept apart from the rest of the
+    /// throughput as light arriving there is never clamped.
+    primary: V3,
+    throughput: V3,
+    depth: u32,
+    bounce: u32,
+    /// The density the last surface scattered the ray with, when a light sample taken there
+    /// could have found the same light.
+    bsdf_pdf: Option<f32>,
+}
+
+impl Path {
+    /// Weights `light` arriving along the path's ray by the throughput back to the camera,
+    /// clamped by `camera` as `Camera::trace` would.
+    fn contribution(&self, camera: &Camera, light: V3) -> V3 {
+        self.scattered(camera, clamp(light, camera.emitted_clamp(self.bounce)))
+    }
+
+    /// Weights `light` leaving the surface the path's ray hit back towards the camera.
+    fn scattered(&self, camera: &Camera, light: V3) -> V3 {
+        let scattered = camera.scattered_clamp(self.bounce.saturating_sub(1));
+        self.primary * clamp(self.throughput * light, scattered)
+    }
+}
+
+/// The light arriving along one camera ray, and the depth left when its path ended.
+#[derive(Debug, Copy, Clone)]
+pub struct PathResult {
+    pub light_groups: [V3; LIGHT_GROUPS],
+    pub depth: u32,
+}
+
+impl PathResult {
+    pub fn color(&self) -> V3 {
+        self.light_groups
+            .iter()
+            .fold(V3::zero(), |sum, &group| sum + group)
+    }
+}
+
+/// The queues of a batch, kept between batches so tracing doesn't allocate once warmed up.
+#[derive(Default)]
+pub struct Wavefront {
+    paths: Vec<Path>,
+    results: Vec<PathResult>,
+    active: Vec<u32>,
+    next: Vec<u32>,
+}
+
+impl Wavefront {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Traces the camera ray of every pixel in `rays` from `camera` up to `depth` bounces,
+    /// returning their results in the same order. The light and BSDF samples are taken from
+    /// sample `sample` of each pixel in `sampler`, at the same dimensions as `Camera::trace`.
+    pub fn trace<I: Intersect + Background + Lights>(
+        &mut self,
+        scene: &I,
+        camera: &Camera,
+        rays: impl IntoIterator<Item = ((u32, u32), Ray)>,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+        sample: u32,
+    ) -> &[PathResult] {
+        self.generate(rays, depth);
+
+        let mut hits: Vec<Option<Hit<'_>>> = Vec::with_capacity(self.active.len());
+        while !self.active.is_empty() {
+            hits.clear();
+            hits.extend(self.active.iter().map(|&index| {
+                let ray = self.paths[index as usize].ray;
+                scene.intersect(ray, 0.001, f32::INFINITY)
+            }));
+
+            self.shade(scene, camera, &hits, sampler, sample);
+        }
+
+        &self.results
+    }
+
+    /// Starts a path for each ray, paths with no depth to spend end straight away.
+    fn generate(&mut self, rays: impl IntoIterator<Item = ((u32, u32), Ray)>, depth: u32) {
+        self.paths.clear();
+        self.results.clear();
+        self.active.clear();
+
+        for (pixel, ray) in rays {
+            let index = self.paths.len() as u32;
+            self.paths.push(Path {
+                pixel,
+                ray,
+                primary: V3::one(),
+                throughput: V3::one(),
+                depth,
+                bounce: 0,
+                bsdf_pdf: None,
+            });
+            self.results.push(PathResult {
+                light_groups: [V3::zero(); LIGHT_GROUPS],
+                depth,
+            });
+            if depth > 0 {
+                self.active.push(index);
+            }
+        }
+    }
+
+    /// Adds the light found by this bounce to each path and queues the paths that scattered.
+    fn shade<I: Intersect + Background + Lights>(
+        &mut self,
+        scene: &I,
+        camera: &Camera,
+        hits: &[Option<Hit<'_>>],
+        sampler: &mut dyn Sampler,
+        sample: u32,
+    ) {
+        self.next.clear();
+        for (&index, hit) in self.active.iter().zip(hits) {
+            let path = &mut self.paths[index as usize];
+            let result = &mut self.results[index as usize];
+
+            let hit = match hit {
+                Some(hit) => hit,
+                None => {
+                    let background = if path.bounce == 0 {
+                        scene.camera_background(path.ray)
+                    } else {
+                        scene.background(path.ray)
+                    };
+                    result.light_groups[scene.light_group().min(LIGHT_GROUPS - 1)] +=
+                        path.contribution(camera, background);
+
+                    // Already clamped as they arrive
+                    let mut escaped = [V3::zero(); LIGHT_GROUPS];
+                    camera.escaped(scene, path.ray, path.bounce, path.bsdf_pdf, &mut escaped);
+                    for (group, escaped) in result.light_groups.iter_mut().zip(escaped.iter()) {
+                        *group += path.scattered(camera, *escaped);
+                    }
+                    result.depth = path.depth;
+                    continue;
+                }
+            };
+
+            let emitted = camera.emitted(scene, path.ray, hit, path.bsdf_pdf);
+            result.light_groups[hit.light_group().min(LIGHT_GROUPS - 1)] +=
+                path.contribution(camera, emitted);
+
+            sampler.start_pixel_sample(path.pixel, sample);
+            sampler.start_bsdf(path.bounce);
+            let bsdf_sample = hit.sample(path.ray, sampler);
+            if bsdf_sample.is_some() {
+                let direct =
+                    camera.direct_light(scene, path.ray, hit, path.depth, path.bounce, sampler);
+                if let Some((group, light)) = direct {
+                    result.light_groups[group.min(LIGHT_GROUPS - 1)] +=
+                        path.scattered(camera, light);
+                }
+            }
+
+            match bsdf_sample {
+                Some(sample) if path.depth > 1 => {
+                    path.ray = hit.spawn_ray(path.ray, &sample);
+                    path.bsdf_pdf = light_sampled_pdf(&sample);
+                    if path.bounce == 0 {
+                        path.primary = sample.weight;
+                    } else {
+                        path.throughput = path.throughput * sample.weight;
+                    }
+                    path.depth -= 1;
+                    path.bounce += 1;
+                    self.next.push(index);
+                }
+                Some(_) => result.depth = 0,
+                None => result.depth = path.depth,
+            }
+        }
+
+        std::mem::swap(&mut self.active, &mut self.next);
+    }
+}
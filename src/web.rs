@@ -0,0 +1,116 @@
+//! A browser front end drawing into a canvas, built with
+//! `wasm-pack build --target web -- --features web`. See `web/index.html` for the page that
+//! drives it.
+//!
+//! The browser has no threads here, so tracing is sliced into short bursts of rows between
+//! animation frames instead. Scenes are `FileScene` JSON limited to spheres and cuboids, since
+//! there is no file system to load models or textures from.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+use crate::input::InputCollection;
+use crate::material::Background;
+use crate::math::{Num, V3};
+use crate::sampler::RandomSampler;
+use crate::scenes::{FileScene, Scene};
+use crate::world::{Camera, World};
+
+const MAX_DEPTH: u32 = 50;
+
+#[wasm_bindgen]
+pub struct WebRenderer {
+    world: World<Box<dyn Background>>,
+    camera: Camera,
+    width: u32,
+    height: u32,
+    /// Summed samples with the top row first.
+    accumulation: Vec<V3>,
+    passes: u32,
+    next_row: u32,
+}
+
+#[wasm_bindgen]
+impl WebRenderer {
+    #[wasm_bindgen(constructor)]
+    pub fn new(scene_json: &str, width: u32, height: u32) -> Result<WebRenderer, JsValue> {
+        if width < 2 || height < 2 {
+            return Err(JsValue::from_str("canvas must be at least 2x2 pixels"));
+        }
+
+        // The default seed reads the clock through an API the browser doesn't provide
+        fastrand::seed(js_sys::Date::now() as u64);
+
+        let aspect_ratio = width as f32 / height as f32;
+        let mut scene = FileScene::parse(scene_json, "", aspect_ratio)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        let (mut world, camera) = scene.generate(0.0, 0, &InputCollection::new());
+        world.build_bvh();
+
+        Ok(Self {
+            world,
+            camera,
+            width,
+            height,
+            accumulation: vec![V3::zero(); (width * height) as usize],
+            passes: 0,
+            next_row: 0,
+        })
+    }
+
+    /// Traces rows until `budget_ms` milliseconds have passed, returning whether a pass over
+    /// the whole image finished. A budget below the frame time keeps the page responsive.
+    pub fn trace(&mut self, budget_ms: f64) -> bool {
+        let start = js_sys::Date::now();
+        let mut finished = false;
+
+        while js_sys::Date::now() - start < budget_ms {
+            let y = self.height - 1 - self.next_row;
+            for x in 0..self.width {
+                let u = (x as f32 + f32::rand()) / (self.width - 1) as f32;
+                let v = (y as f32 + f32::rand()) / (self.height - 1) as f32;
+                let ray = self.camera.ray(u, v);
+                let (color, _) = self
+                    .camera
+                    .trace(&self.world, ray, MAX_DEPTH, &mut RandomSampler);
+                self.accumulation[(self.next_row * self.width + x) as usize] += color;
+            }
+
+            self.next_row += 1;
+            if self.next_row == self.height {
+                self.next_row = 0;
+                self.passes += 1;
+                finished = true;
+            }
+        }
+
+        finished
+    }
+
+    /// Draws the image so far into `context`, rows of the pass in progress are averaged over
+    /// their one extra sample.
+    pub fn present(&self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        let mut rgba = Vec::with_capacity(self.accumulation.len() * 4);
+        for (index, &color) in self.accumulation.iter().enumerate() {
+            let row = index as u32 / self.width;
+            let samples = self.passes + if row < self.next_row { 1 } else { 0 };
+            let color = color / samples.max(1) as f32;
+            for c in [color.x(), color.y(), color.z()].iter() {
+                rgba.push((c.powf(1.0 / 2.2).min(1.0).max(0.0) * 255.0) as u8);
+            }
+            rgba.push(255);
+        }
+
+        let image = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&rgba[..]),
+            self.width,
+            self.height,
+        )?;
+        context.put_image_data(&image, 0.0, 0.0)
+    }
+
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+}
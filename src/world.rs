@@ -1,7 +1,48 @@
-use super::geom::{BoundingBox, BvhNode, Hit, Intersect};
-use super::material::Background;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::geom::{BoundingBox, BvhMemory, BvhNode, BvhStats, Hit, Intersect};
+use super::light::{power_heuristic, Light, LightLink, LightList, Lights};
+use super::material::{lobe_total, Background, BlurredBackground, BsdfSample, Lobe, Material};
+use super::obj_export;
+#[cfg(feature = "polarization")]
+use super::polarization::PathFilter;
+use super::sampler::{concentric_disk, RandomSampler, Sampler, SamplerKind};
 use crate::math::V3;
 
+/// The number of separately accumulated light groups, lights tagged with a higher group are
+/// folded into the last one.
+pub const LIGHT_GROUPS: usize = 4;
+
+/// The number of component AOVs the beauty pass is split into by `Camera::trace_components`.
+pub const COMPONENTS: usize = 8;
+
+/// The file name suffix of each component AOV, in the order returned by `trace_components`.
+pub const COMPONENT_NAMES: [&str; COMPONENTS] = [
+    "emission",
+    "background",
+    "diffuse_direct",
+    "diffuse_indirect",
+    "glossy_direct",
+    "glossy_indirect",
+    "transmission_direct",
+    "transmission_indirect",
+];
+
+/// A ray through `(s, t)` on the image leaves a point `lens_radius` across the lens along `u`
+/// and `v` from `origin`, passing through `lower_left_corner + horizontal * s + vertical * t`.
+#[derive(Debug, Copy, Clone)]
+pub struct Viewport {
+    pub origin: V3,
+    pub lower_left_corner: V3,
+    pub horizontal: V3,
+    pub vertical: V3,
+    pub u: V3,
+    pub v: V3,
+    pub lens_radius: f32,
+}
+
+#[derive(Clone)]
 pub struct Camera {
     origin: V3,
     lower_left_corner: V3,
@@ -10,6 +51,12 @@ pub struct Camera {
     u: V3,
     v: V3,
     lens_radius: f32,
+    shutter_open: f32,
+    shutter_close: f32,
+    direct_clamp: Option<f32>,
+    indirect_clamp: Option<f32>,
+    #[cfg(feature = "polarization")]
+    polarizer: Option<f32>,
 }
 
 impl Camera {
@@ -47,78 +94,1109 @@ impl Camera {
             u,
             v,
             lens_radius,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            direct_clamp: None,
+            indirect_clamp: None,
+            #[cfg(feature = "polarization")]
+            polarizer: None,
+        }
+    }
+
+    /// Spreads camera rays over the `open` to `close` interval, where 0.0 and 1.0 are the start
+    /// and end of an `Instance`'s motion.
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    /// Limits the brightest channel of the light arriving at each bounce, keeping its hue.
+    /// Light reaching the first surface straight from an emitter or the background is clamped
+    /// to `direct`, light arriving at it after a further bounce and all light arriving at later
+    /// surfaces to `indirect`. Trades bias for fewer fireflies from small bright lights, usually
+    /// with a lower indirect limit as those paths are the noisiest.
+    pub fn with_radiance_clamp(mut self, direct: Option<f32>, indirect: Option<f32>) -> Self {
+        self.direct_clamp = direct;
+        self.indirect_clamp = indirect;
+        self
+    }
+
+    /// Whether `with_radiance_clamp` set a limit, leaving the traced light biased.
+    pub fn is_radiance_clamped(&self) -> bool {
+        self.direct_clamp.is_some() || self.indirect_clamp.is_some()
+    }
+
+    /// Places a linear polarizing filter in front of the lens with its axis `angle` radians
+    /// counterclockwise from horizontal, tracing the polarization of light through each bounce.
+    #[cfg(feature = "polarization")]
+    pub fn with_polarizer(mut self, angle: f32) -> Self {
+        self.polarizer = Some(angle);
+        self
+    }
+
+    /// Widens or narrows the view to `vertical_fov` degrees, keeping the focus distance.
+    pub fn with_vertical_fov(mut self, vertical_fov: f32) -> Self {
+        let center = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let focus_distance = (center - self.origin).length();
+        let half_height = (vertical_fov * std::f32::consts::PI / 180.0 / 2.0).tan();
+        let scale = half_height * 2.0 * focus_distance / self.vertical.length();
+
+        self.horizontal = self.horizontal * scale;
+        self.vertical = self.vertical * scale;
+        self.lower_left_corner = center - self.horizontal / 2.0 - self.vertical / 2.0;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: f32) -> Self {
+        self.lens_radius = aperture / 2.0;
+        self
+    }
+
+    /// Moves the camera by `offset` without changing where it looks.
+    pub fn with_offset(mut self, offset: V3) -> Self {
+        self.origin += offset;
+        self.lower_left_corner += offset;
+        self
+    }
+
+    /// The angle in radians that a point at infinity is spread over by the lens when focused at
+    /// the focus distance.
+    pub fn background_blur(&self) -> f32 {
+        let focus =
+            self.origin - self.lower_left_corner - self.horizontal / 2.0 - self.vertical / 2.0;
+        self.lens_radius / focus.length()
+    }
+
+    pub fn origin(&self) -> V3 {
+        self.origin
+    }
+
+    /// The unit direction through the center of the image.
+    pub fn direction(&self) -> V3 {
+        (self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0 - self.origin).unit()
+    }
+
+    /// The vertical field of view in degrees.
+    pub fn vertical_fov(&self) -> f32 {
+        let focus = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        let half_height = self.vertical.length() / 2.0;
+        (half_height / (focus - self.origin).length()).atan() * 2.0 * 180.0 / std::f32::consts::PI
+    }
+
+    /// The lens and image plane that camera rays are built from, for renderers generating their
+    /// own rays.
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            origin: self.origin,
+            lower_left_corner: self.lower_left_corner,
+            horizontal: self.horizontal,
+            vertical: self.vertical,
+            u: self.u,
+            v: self.v,
+            lens_radius: self.lens_radius,
         }
     }
 
+    /// The volume seen by this camera between the `near` and `far` distances, ignoring the
+    /// lens radius.
+    pub fn frustum(&self, near: f32, far: f32) -> Frustum {
+        let bottom_left = self.lower_left_corner - self.origin;
+        Frustum::from_corners(
+            self.origin,
+            [
+                bottom_left,
+                bottom_left + self.horizontal,
+                bottom_left + self.vertical,
+                bottom_left + self.horizontal + self.vertical,
+            ],
+            near,
+            far,
+        )
+    }
+
+    /// The angle in radians between neighbouring rows of an image `rows` tall, the spread of
+    /// camera rays through its pixels.
+    pub fn pixel_spread(&self, rows: u32) -> f32 {
+        let focus = self.lower_left_corner + self.horizontal / 2.0 + self.vertical / 2.0;
+        self.vertical.length() / (focus - self.origin).length() / rows.max(1) as f32
+    }
+
     pub fn ray(&self, s: f32, t: f32) -> Ray {
-        let blur = V3::random_in_unit_disk() * self.lens_radius;
+        self.sample_ray(s, t, &mut RandomSampler)
+    }
+
+    /// A ray through `(s, t)` with its lens position, time and fade taken from `sampler`, in
+    /// that order.
+    pub fn sample_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let blur = concentric_disk(sampler.get_2d()) * self.lens_radius;
         let offset = self.u * blur.x() + self.v * blur.y();
 
+        let time = self.shutter_open + sampler.get_1d() * (self.shutter_close - self.shutter_open);
+
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + (self.horizontal * s) + (self.vertical * t)
                 - self.origin
                 - offset,
         )
+        .with_time(time)
+        .with_fade(sampler.get_1d())
+    }
+
+    /// How much of the image camera `ray` covers, one where it hits the scene and the camera
+    /// background's alpha where it escapes. The scene is only intersected when the background
+    /// is at all transparent.
+    pub fn alpha<I: Intersect + Background>(&self, scene: &I, ray: Ray) -> f32 {
+        let alpha = scene.camera_alpha(ray);
+        if alpha >= 1.0 || scene.intersect(ray, 0.001, f32::INFINITY).is_some() {
+            1.0
+        } else {
+            alpha
+        }
+    }
+
+    /// Traces `ray` up to `depth` bounces, taking each bounce's light and BSDF samples from
+    /// `sampler` at fixed dimensions.
+    pub fn trace<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> (V3, u32) {
+        self.trace_path(scene, ray, depth, sampler)
+    }
+
+    /// The first surface along a camera ray, to be continued later with `trace_from_hit`.
+    pub fn primary_hit<'a, I: Intersect>(&self, scene: &'a I, ray: Ray) -> Option<Hit<'a>> {
+        scene.intersect(ray, 0.001, f32::INFINITY)
     }
 
-    pub fn trace<I: Intersect + Background>(&self, scene: &I, ray: Ray, depth: u32) -> (V3, u32) {
+    /// Continues the path of camera `ray` from its primary hit, tracing a fresh set of
+    /// secondary bounces. Reusing one primary hit across samples skips the first intersection,
+    /// at the cost of antialiasing, depth of field and motion blur converging only with the
+    /// number of distinct primary hits. Ignores the polarizer.
+    pub fn trace_from_hit<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        hit: &Hit,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> (V3, u32) {
         if depth == 0 {
-            (V3::zero(), depth)
+            return (V3::zero(), depth);
+        }
+
+        let emitted = hit.emit();
+        sampler.start_bsdf(0);
+        match hit.sample(ray, sampler) {
+            Some(sample) => {
+                let direct = self.direct_light(scene, ray, hit, depth, 0, sampler);
+                let direct = direct.map_or(V3::zero(), |(_, light)| light);
+                let scattered = hit.spawn_ray(ray, &sample);
+                let pdf = light_sampled_pdf(&sample);
+                let (color, depth): (V3, _) =
+                    self.trace_ray(scene, scattered, depth - 1, 1, pdf, sampler);
+                (emitted + direct + color * sample.weight, depth)
+            }
+            None => (emitted, depth),
+        }
+    }
+
+    /// Traces `ray` keeping the light arriving from each light group separate, the groups sum
+    /// to the color returned by `trace`.
+    pub fn trace_light_groups<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> ([V3; LIGHT_GROUPS], u32) {
+        self.trace_path(scene, ray, depth, sampler)
+    }
+
+    /// Traces a camera `ray`, gathering its light into `R` so the beauty pass only keeps the
+    /// light groups apart when they are asked for.
+    fn trace_path<I: Intersect + Background + Lights, R: Radiance>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> (R, u32) {
+        #[cfg(feature = "polarization")]
+        if let Some(angle) = self.polarizer {
+            let filter = PathFilter::new(angle, ray.direction, self.u);
+            return self.trace_polarized(scene, ray, depth, 0, filter, sampler);
+        }
+
+        self.trace_ray(scene, ray, depth, 0, None, sampler)
+    }
+
+    /// Like `trace_ray`, weighting the light arriving along the path by how much of it passes
+    /// the camera's polarizer. Lights are only found by scattering into them, as shadow rays
+    /// don't follow the polarization of the path.
+    #[cfg(feature = "polarization")]
+    fn trace_polarized<I: Intersect + Background + Lights, R: Radiance>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        bounce: u32,
+        filter: PathFilter,
+        sampler: &mut dyn Sampler,
+    ) -> (R, u32) {
+        let mut groups = R::zero();
+        if depth == 0 {
+            (groups, depth)
         } else if let Some(hit) = scene.intersect(ray, 0.001, f32::INFINITY) {
-            let emitted = hit.emit();
-            if let Some(scatter) = hit.scatter(ray) {
-                let (color, depth) = self.trace(scene, scatter.scattered, depth - 1);
-                ((color * scatter.attenuation + emitted), depth)
+            sampler.start_bsdf(bounce);
+            let depth = if let Some(sample) = hit.sample(ray, sampler) {
+                let scattered = hit.spawn_ray(ray, &sample);
+                let mueller = hit.material.polarization(ray, &hit, scattered);
+                let child_filter =
+                    filter.interact(mueller, ray.direction, hit.normal, scattered.direction);
+                let (child, depth) = self.trace_polarized(
+                    scene,
+                    scattered,
+                    depth - 1,
+                    bounce + 1,
+                    child_filter,
+                    sampler,
+                );
+                groups = child;
+                groups.weight_by(sample.weight);
+                groups.clamp_total(self.scattered_clamp(bounce));
+                depth
             } else {
-                (emitted, depth)
-            }
+                depth
+            };
+            let emitted = clamp(hit.emit() * filter.weight(), self.emitted_clamp(bounce));
+            groups.add_light(hit.light_group(), emitted);
+            (groups, depth)
+        } else {
+            let background = if bounce == 0 {
+                scene.camera_background(ray)
+            } else {
+                scene.background(ray)
+            };
+            let mut escaped = R::zero();
+            self.escaped(scene, ray, bounce, None, &mut escaped);
+            escaped.weight_by(V3::fill(filter.weight()));
+            groups = escaped;
+            groups.add_light(
+                scene.light_group(),
+                clamp(background * filter.weight(), self.emitted_clamp(bounce)),
+            );
+            (groups, depth)
+        }
+    }
+
+    /// The light arriving along `ray`, which leaves the surface `bounce` bounces after the
+    /// camera, or the camera itself at bounce 0. `bsdf_pdf` is the density the surface picked
+    /// `ray` with, when it also sampled the lights directly, and weights the light emitted by
+    /// the next surface against that light sample.
+    fn trace_ray<I: Intersect + Background + Lights, R: Radiance>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        bounce: u32,
+        bsdf_pdf: Option<f32>,
+        sampler: &mut dyn Sampler,
+    ) -> (R, u32) {
+        let mut groups = R::zero();
+        if depth == 0 {
+            (groups, depth)
+        } else if let Some(hit) = scene.intersect(ray, 0.001, f32::INFINITY) {
+            sampler.start_bsdf(bounce);
+            let depth = if let Some(sample) = hit.sample(ray, sampler) {
+                let direct = self.direct_light(scene, ray, &hit, depth, bounce, sampler);
+                let scattered = hit.spawn_ray(ray, &sample);
+                let pdf = light_sampled_pdf(&sample);
+                let (child, depth) =
+                    self.trace_ray(scene, scattered, depth - 1, bounce + 1, pdf, sampler);
+                groups = child;
+                groups.weight_by(sample.weight);
+                groups.clamp_total(self.scattered_clamp(bounce));
+                if let Some((group, light)) = direct {
+                    groups.add_light(group, light);
+                }
+                depth
+            } else {
+                depth
+            };
+            let emitted = self.emitted(scene, ray, &hit, bsdf_pdf);
+            let emitted = clamp(emitted, self.emitted_clamp(bounce));
+            groups.add_light(hit.light_group(), emitted);
+            (groups, depth)
         } else {
-            (scene.background(ray), depth)
+            let background = if bounce == 0 {
+                scene.camera_background(ray)
+            } else {
+                scene.background(ray)
+            };
+            groups.add_light(
+                scene.light_group(),
+                clamp(background, self.emitted_clamp(bounce)),
+            );
+            self.escaped(scene, ray, bounce, bsdf_pdf, &mut groups);
+            (groups, depth)
         }
     }
 
-    pub fn albedo_normal<I: Intersect + Background>(&self, scene: &I, ray: Ray) -> (V3, V3) {
+    /// Light reaching `hit` straight from one of the scene's lights and scattered back along
+    /// `ray`, with the light group it belongs to. One light is picked at random and a shadow ray
+    /// cast towards a point on it, weighted against the chance of scattering into the light.
+    /// `hit` is the surface `bounce + 1` bounces after the camera, with `depth` bounces left.
+    pub(crate) fn direct_light<I: Intersect + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        hit: &Hit,
+        depth: u32,
+        bounce: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(usize, V3)> {
+        self.direct_light_lobes(scene, ray, hit, depth, bounce, sampler)
+            .map(|(group, lobes)| (group, lobe_total(lobes)))
+    }
+
+    /// `direct_light` split by the lobe of the material that scattered it, indexed by
+    /// `Lobe::index`. The lobes are clamped together so they sum to `direct_light`.
+    fn direct_light_lobes<I: Intersect + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        hit: &Hit,
+        depth: u32,
+        bounce: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(usize, [V3; Lobe::COUNT])> {
+        // Matches the light scattering would find, which needs a bounce left to reach it
+        if depth < 2 {
+            return None;
+        }
+
+        sampler.start_light(bounce);
+        let (index, light, chance) = scene.lights().pick(sampler.get_1d())?;
+        if !scene.lights().shines_on(index, hit.object) {
+            return None;
+        }
+        let sample = light.sample(hit.point, sampler.get_2d())?;
+        let mut reflected = hit.eval_lobes(ray, sample.direction);
+        let total = lobe_total(reflected);
+        if total.near_zero() {
+            return None;
+        }
+
+        let shadow = Ray::new(hit.point, sample.direction)
+            .with_time(ray.time)
+            .with_fade(ray.fade)
+            .with_kind(RayKind::Shadow);
+        if scene
+            .intersect(shadow, 0.001, sample.distance * 0.999)
+            .is_some()
+        {
+            return None;
+        }
+
+        let light_pdf = sample.pdf * chance;
+        let weight = if light.is_delta() {
+            1.0 / light_pdf
+        } else {
+            let bsdf_pdf = hit.material.pdf(ray, hit, sample.direction);
+            power_heuristic(light_pdf, bsdf_pdf) / light_pdf
+        };
+        let radiance = sample.radiance * weight;
+        let radiance = radiance * clamp_scale(total * radiance, self.emitted_clamp(bounce + 1));
+        for lobe in reflected.iter_mut() {
+            *lobe = *lobe * radiance;
+        }
+
+        Some((light.light_group(), reflected))
+    }
+
+    /// The light `hit` emits back along `ray`, weighted against the light sample taken at the
+    /// surface `ray` left from when `bsdf_pdf` is set.
+    pub(crate) fn emitted<I: Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        hit: &Hit,
+        bsdf_pdf: Option<f32>,
+    ) -> V3 {
+        let emitted = hit.emit();
+        let lights = scene.lights();
+        if !emitted.near_zero() {
+            // Lights are only seen from the objects they're linked to
+            let light = hit.object.and_then(|object| lights.surface_light(object));
+            if light.map_or(false, |light| !lights.shines_on(light, ray.source)) {
+                return V3::zero();
+            }
+        }
+
+        let bsdf_pdf = match bsdf_pdf {
+            Some(bsdf_pdf) if !lights.is_empty() && !emitted.near_zero() => bsdf_pdf,
+            _ => return emitted,
+        };
+
+        // Only the light hit first along the ray could have been reached by a light sample
+        let direction = ray.direction.unit();
+        let distance = hit.t * ray.direction.length();
+        let light_pdf = lights
+            .iter()
+            .enumerate()
+            .filter_map(|(index, light)| Some((index, light.pdf(ray.origin, direction)?)))
+            .filter(|&(_, (_, light_distance))| {
+                (light_distance - distance).abs() <= 1e-3 * distance.max(1.0)
+            })
+            .map(|(index, (pdf, _))| pdf * lights.probability(index))
+            .sum::<f32>();
+
+        emitted * power_heuristic(bsdf_pdf, light_pdf)
+    }
+
+    /// Adds the light from lights without a surface to `groups` for `ray`, which left the
+    /// scene `bounce` bounces after the camera, weighted against the light sample taken at the
+    /// surface it left from when `bsdf_pdf` is set.
+    pub(crate) fn escaped<I: Lights, R: Radiance>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        bounce: u32,
+        bsdf_pdf: Option<f32>,
+        groups: &mut R,
+    ) {
+        let lights = scene.lights();
+        let direction = ray.direction.unit();
+        for (index, light) in lights.iter().enumerate() {
+            let escaped = light.escaped(direction);
+            if escaped.near_zero() || !lights.shines_on(index, ray.source) {
+                continue;
+            }
+
+            let weight = match (bsdf_pdf, light.pdf(ray.origin, direction)) {
+                (Some(bsdf_pdf), Some((pdf, _))) => {
+                    power_heuristic(bsdf_pdf, pdf * lights.probability(index))
+                }
+                _ => 1.0,
+            };
+            groups.add_light(
+                light.light_group(),
+                clamp(escaped * weight, self.emitted_clamp(bounce)),
+            );
+        }
+    }
+
+    /// The clamp on light emitted towards the surface `bounce` bounces after the camera.
+    pub(crate) fn emitted_clamp(&self, bounce: u32) -> Option<f32> {
+        match bounce {
+            0 => None,
+            1 => self.direct_clamp,
+            _ => self.indirect_clamp,
+        }
+    }
+
+    /// The clamp on light scattered by the surface `bounce` bounces after the camera, towards
+    /// the one before it.
+    pub(crate) fn scattered_clamp(&self, bounce: u32) -> Option<f32> {
+        match bounce {
+            0 => None,
+            _ => self.indirect_clamp,
+        }
+    }
+
+    /// Traces `ray` splitting the light by the first scattering event along the path into
+    /// direct and indirect diffuse, glossy and transmission components, with directly visible
+    /// emitters and the background kept separate. The components sum to the color returned by
+    /// `trace`, ignoring the polarizer.
+    ///
+    /// Direct light is what reaches the first surface straight from an emitter or the
+    /// background, everything arriving after a further bounce is indirect.
+    pub fn trace_components<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> ([V3; COMPONENTS], u32) {
+        let mut components = [V3::zero(); COMPONENTS];
+        if depth == 0 {
+            return (components, depth);
+        }
+
+        let hit = match scene.intersect(ray, 0.001, f32::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let mut escaped = scene.camera_background(ray);
+                self.escaped(scene, ray, 0, None, &mut escaped);
+                components[1] = escaped;
+                return (components, depth);
+            }
+        };
+        components[0] = hit.emit();
+
+        sampler.start_bsdf(0);
+        let sample = match hit.sample(ray, sampler) {
+            Some(sample) => sample,
+            None => return (components, depth),
+        };
+        // Lights are sampled for the lobes `eval` covers, whichever lobe was picked, and each
+        // lobe's share goes to its own direct component
+        if let Some((_, lobes)) = self.direct_light_lobes(scene, ray, &hit, depth, 0, sampler) {
+            for (lobe, light) in lobes.iter().enumerate() {
+                components[2 + 2 * lobe] = *light;
+            }
+        }
+
+        let scattered = hit.spawn_ray(ray, &sample);
+        let pdf = light_sampled_pdf(&sample);
+        let (direct, indirect, depth) =
+            self.trace_direct_indirect(scene, scattered, depth - 1, pdf, sampler);
+
+        let slot = match sample.lobe {
+            Lobe::Diffuse => 2,
+            Lobe::Glossy => 4,
+            Lobe::Transmission => 6,
+        };
+        components[slot] += direct * sample.weight;
+        components[slot + 1] = indirect * sample.weight;
+
+        (components, depth)
+    }
+
+    /// The light arriving along a ray leaving the first surface, split into what the next
+    /// vertex emits and what it scatters.
+    fn trace_direct_indirect<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+        depth: u32,
+        bsdf_pdf: Option<f32>,
+        sampler: &mut dyn Sampler,
+    ) -> (V3, V3, u32) {
+        if depth == 0 {
+            return (V3::zero(), V3::zero(), depth);
+        }
+
+        match scene.intersect(ray, 0.001, f32::INFINITY) {
+            Some(hit) => {
+                let emitted = self.emitted(scene, ray, &hit, bsdf_pdf);
+                let direct = clamp(emitted, self.emitted_clamp(1));
+                sampler.start_bsdf(1);
+                match hit.sample(ray, sampler) {
+                    Some(sample) => {
+                        let sampled = self.direct_light(scene, ray, &hit, depth, 1, sampler);
+                        let scattered = hit.spawn_ray(ray, &sample);
+                        let pdf = light_sampled_pdf(&sample);
+                        let (indirect, depth): (V3, _) =
+                            self.trace_ray(scene, scattered, depth - 1, 2, pdf, sampler);
+                        let indirect = clamp(indirect * sample.weight, self.scattered_clamp(1))
+                            + sampled.map_or(V3::zero(), |(_, light)| light);
+                        (direct, indirect, depth)
+                    }
+                    None => (direct, V3::zero(), depth),
+                }
+            }
+            None => {
+                let mut direct = clamp(scene.background(ray), self.emitted_clamp(1));
+                self.escaped(scene, ray, 1, bsdf_pdf, &mut direct);
+                (direct, V3::zero(), depth)
+            }
+        }
+    }
+
+    pub fn albedo_normal<I: Intersect + Background + Lights>(
+        &self,
+        scene: &I,
+        ray: Ray,
+    ) -> (V3, V3) {
         if let Some(hit) = scene.intersect(ray, 0.001, f32::INFINITY) {
             let emitted = hit.emit();
-            if let Some(scatter) = hit.scatter(ray) {
-                (scatter.attenuation, hit.normal)
+            if let Some(sample) = hit.sample(ray, &mut RandomSampler) {
+                (sample.weight, hit.normal)
             } else {
                 (emitted, hit.normal)
             }
         } else {
-            (scene.background(ray), V3::zero())
+            (scene.camera_background(ray), V3::zero())
         }
     }
+
+    /// The first surface along `ray` as seen by a LiDAR sensor at the ray origin. Intensity is
+    /// the luminance of the surface albedo scaled by the cosine of the incidence angle, without
+    /// any falloff over distance.
+    pub fn range<I: Intersect>(&self, scene: &I, ray: Ray) -> Option<RangeSample> {
+        let hit = scene.intersect(ray, 0.001, f32::INFINITY)?;
+        let albedo = match hit.sample(ray, &mut RandomSampler) {
+            Some(sample) => sample.weight,
+            None => hit.emit(),
+        };
+        let luminance = albedo.x() * 0.2126 + albedo.y() * 0.7152 + albedo.z() * 0.0722;
+        let incidence = hit.normal.dot(ray.direction.unit()).abs();
+
+        Some(RangeSample {
+            distance: (hit.point - ray.origin).length(),
+            intensity: luminance * incidence,
+            point: hit.point,
+        })
+    }
+}
+
+/// The density `sample` was picked with, for weighing the light it finds against a light
+/// sample. Delta lobes can't be found by light sampling and are left unweighted.
+pub(crate) fn light_sampled_pdf(sample: &BsdfSample) -> Option<f32> {
+    if sample.delta || sample.pdf <= 0.0 {
+        None
+    } else {
+        Some(sample.pdf)
+    }
+}
+
+/// Scales `radiance` down so its brightest channel is at most `limit`.
+pub(crate) fn clamp(radiance: V3, limit: Option<f32>) -> V3 {
+    radiance * clamp_scale(radiance, limit)
+}
+
+/// Light gathered along a path, summed for the beauty pass or kept apart by light group for
+/// the light group AOVs.
+pub(crate) trait Radiance: Copy {
+    fn zero() -> Self;
+
+    /// Adds `light` belonging to light group `group`.
+    fn add_light(&mut self, group: usize, light: V3);
+
+    fn weight_by(&mut self, weight: V3);
+
+    fn total(&self) -> V3;
+
+    /// Applies `limit` to the total, scaling every group alike.
+    fn clamp_total(&mut self, limit: Option<f32>) {
+        let scale = clamp_scale(self.total(), limit);
+        if scale < 1.0 {
+            self.weight_by(V3::fill(scale));
+        }
+    }
+}
+
+impl Radiance for V3 {
+    fn zero() -> Self {
+        V3::zero()
+    }
+
+    fn add_light(&mut self, _group: usize, light: V3) {
+        *self += light;
+    }
+
+    fn weight_by(&mut self, weight: V3) {
+        *self = *self * weight;
+    }
+
+    fn total(&self) -> V3 {
+        *self
+    }
+}
+
+/// Lights tagged with a group past the last are folded into it.
+impl Radiance for [V3; LIGHT_GROUPS] {
+    fn zero() -> Self {
+        [V3::zero(); LIGHT_GROUPS]
+    }
+
+    fn add_light(&mut self, group: usize, light: V3) {
+        self[group.min(LIGHT_GROUPS - 1)] += light;
+    }
+
+    fn weight_by(&mut self, weight: V3) {
+        for group in self.iter_mut() {
+            *group = *group * weight;
+        }
+    }
+
+    fn total(&self) -> V3 {
+        self.iter().fold(V3::zero(), |sum, &group| sum + group)
+    }
+}
+
+fn clamp_scale(radiance: V3, limit: Option<f32>) -> f32 {
+    let brightest = radiance.x().max(radiance.y()).max(radiance.z());
+    match limit {
+        Some(limit) if brightest > limit => limit / brightest,
+        _ => 1.0,
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RangeSample {
+    pub distance: f32,
+    pub intensity: f32,
+    pub point: V3,
 }
 
 pub struct World<B: Background> {
     background: B,
-    objects: Vec<Box<dyn Intersect>>,
+    blurred_background: Option<BlurredBackground>,
+    objects: Vec<Arc<dyn Intersect>>,
+    /// The visibility of each object, by index.
+    visibility: Vec<Visibility>,
+    lights: LightList,
+    bvh: Option<BvhNode>,
 }
 
 impl<B: Background> World<B> {
     pub fn new(background: B) -> Self {
         Self {
             background,
+            blurred_background: None,
             objects: Vec::new(),
+            visibility: Vec::new(),
+            lights: LightList::new(),
+            bvh: None,
         }
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.visibility.clear();
+        self.lights.clear();
+        self.bvh = None;
     }
 
     pub fn add<O: 'static + Intersect>(&mut self, object: O) {
-        let b = Box::new(object);
-        self.objects.push(b);
+        self.add_with_visibility(object, Visibility::default());
+    }
+
+    /// Adds `object` seen only by the kinds of rays `visibility` allows.
+    pub fn add_with_visibility<O: 'static + Intersect>(
+        &mut self,
+        object: O,
+        visibility: Visibility,
+    ) {
+        self.objects.push(Arc::new(object));
+        self.visibility.push(visibility);
+        self.bvh = None;
+    }
+
+    /// Adds `light` as an object and to the lights sampled from every bounce.
+    pub fn add_light<L: 'static + Light + Intersect>(&mut self, light: L) {
+        let light = Arc::new(light);
+        self.objects.push(light.clone());
+        self.visibility.push(Visibility::default());
+        self.lights
+            .push_surface(light, Some(self.objects.len() - 1));
+        self.bvh = None;
+    }
+
+    /// Adds a light without a surface, such as a `DirectionalLight` or `SpotLight`, which is
+    /// reached by sampling it and by rays leaving the scene.
+    pub fn add_sampled_light<L: 'static + Light>(&mut self, light: L) {
+        self.lights.push(Arc::new(light));
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Limits the light at `index` to shining on the objects `link` allows, returning false if
+    /// there is no such light. Lights are indexed in the order they were added, with or
+    /// without a surface, and objects in the order they were added including light surfaces.
+    pub fn link_light(&mut self, index: usize, link: LightLink) -> bool {
+        self.lights.set_link(index, link)
+    }
+
+    /// Memory used by the top level BVH, if it has been built.
+    pub fn bvh_memory(&self) -> Option<BvhMemory> {
+        self.bvh.as_ref().map(|bvh| bvh.memory_usage())
+    }
+
+    /// Shape of the top level BVH, if it has been built.
+    pub fn bvh_stats(&self) -> Option<BvhStats> {
+        self.bvh.as_ref().map(|bvh| bvh.stats())
+    }
+
+    /// Shows camera rays a copy of the background blurred over `angle` radians, usually the
+    /// `background_blur` of the camera.
+    pub fn blur_camera_background(&mut self, angle: f32) {
+        self.blurred_background = BlurredBackground::capture(&self.background, angle);
     }
 
     pub fn build_bvh(&mut self) {
-        let new_items = Vec::new();
-        let objects = std::mem::replace(&mut self.objects, new_items);
-        let nodes = BvhNode::new(objects);
-        self.objects.push(Box::new(nodes));
+        if self.objects.is_empty() {
+            return;
+        }
+
+        let objects = self
+            .objects
+            .iter()
+            .zip(self.visibility.iter())
+            .enumerate()
+            .map(|(index, (o, &visibility))| {
+                Box::new(WorldObject {
+                    object: o.clone(),
+                    index,
+                    visibility,
+                }) as Box<dyn Intersect>
+            })
+            .collect();
+        self.bvh = Some(BvhNode::new(objects));
+
+        let radius = self
+            .bounding_box()
+            .map_or(0.0, |bounds| bounds.size().length() / 2.0);
+        self.lights.weigh_by_power(radius);
+    }
+
+    /// The bounds of everything in the world, `None` if it is empty or contains an unbounded
+    /// object.
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.bounding_box()
+    }
+
+    /// Renders `samples` paths per pixel of a `width` by `height` image through `camera`, with
+    /// rows split across every core. Returns the averaged linear colors with the top row first.
+    /// The viewer accumulates passes itself, this is for embedding the tracer elsewhere.
+    pub fn render(
+        &self,
+        camera: &Camera,
+        width: u32,
+        height: u32,
+        samples: u32,
+        max_depth: u32,
+    ) -> Vec<V3> {
+        let mut pixels = vec![V3::zero(); (width * height) as usize];
+        let threads = num_cpus::get().max(1) as u32;
+        let rows_per_thread = ((height + threads - 1) / threads).max(1);
+        let samples = samples.max(1);
+        // One run of strata covers every sample of a pixel
+        let sampler = SamplerKind::Stratified {
+            strata: (samples as f32).sqrt().ceil() as u32,
+        };
+
+        std::thread::scope(|scope| {
+            for (chunk, rows) in pixels
+                .chunks_mut((rows_per_thread * width).max(1) as usize)
+                .enumerate()
+            {
+                scope.spawn(move || {
+                    let mut sampler = sampler.build();
+                    let first_row = chunk as u32 * rows_per_thread;
+                    for (index, pixel) in rows.iter_mut().enumerate() {
+                        let x = index as u32 % width;
+                        let y = height - 1 - (first_row + index as u32 / width);
+
+                        let mut color = V3::zero();
+                        for sample in 0..samples {
+                            sampler.start_pixel_sample((x, y), sample);
+                            let jitter = sampler.get_2d();
+                            let u = (x as f32 + jitter.x()) / (width.max(2) - 1) as f32;
+                            let v = (y as f32 + jitter.y()) / (height.max(2) - 1) as f32;
+                            let ray = camera.sample_ray(u, v, &mut *sampler);
+                            color += camera.trace(self, ray, max_depth, &mut *sampler).0;
+                        }
+                        *pixel = color / samples as f32;
+                    }
+                });
+            }
+        });
+
+        pixels
+    }
+
+    /// Writes every object to an OBJ file, see `obj_export::export`.
+    pub fn export_obj<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        obj_export::export(path, self.objects.iter().map(|o| &**o))
+    }
+
+    pub fn object_count(&self) -> usize {
+        self.objects.len()
+    }
+
+    /// The object at `index`, indices follow the order objects were added in.
+    pub fn object(&self, index: usize) -> Option<&dyn Intersect> {
+        self.objects.get(index).map(|o| &**o)
+    }
+
+    /// The bounds of the object at `index`, indices follow the order objects were added in.
+    pub fn object_bounds(&self, index: usize) -> Option<BoundingBox> {
+        self.objects.get(index).and_then(|o| o.bounding_box())
+    }
+
+    /// The kinds of rays that see the object at `index`.
+    pub fn object_visibility(&self, index: usize) -> Option<Visibility> {
+        self.visibility.get(index).copied()
+    }
+
+    /// Changes which kinds of rays see the object at `index`, returning false if there is no
+    /// such object.
+    pub fn set_visibility(&mut self, index: usize, visibility: Visibility) -> bool {
+        match self.visibility.get_mut(index) {
+            Some(current) => {
+                *current = visibility;
+                self.bvh = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the object at `index` with `edit` applied to it, returning false if there is no
+    /// such object.
+    pub fn edit_object<F>(&mut self, index: usize, edit: F) -> bool
+    where
+        F: FnOnce(Arc<dyn Intersect>) -> Arc<dyn Intersect>,
+    {
+        match self.objects.get_mut(index) {
+            Some(object) => {
+                *object = edit(object.clone());
+                self.bvh = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Finds the nearest object along `ray`. This tests each object in turn rather than the
+    /// BVH, so it is meant for tooling like click selection rather than rendering.
+    pub fn pick(&self, ray: Ray) -> Option<Pick> {
+        let traversal = TraversalRay::new(ray);
+        let mut closest_so_far = f32::INFINITY;
+        let mut found = None;
+
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Some(bounds) = object.bounding_box() {
+                if !bounds.hit(&traversal, 0.001, closest_so_far) {
+                    continue;
+                }
+            }
+
+            if let Some(hit) = object.intersect(ray, 0.001, closest_so_far) {
+                closest_so_far = hit.t;
+                found = Some(Pick {
+                    object: index,
+                    t: hit.t,
+                    point: hit.point,
+                    normal: hit.normal,
+                });
+            }
+        }
+
+        found
+    }
+
+    /// Indices of the objects whose bounds overlap `region`.
+    pub fn objects_in_box(&self, region: BoundingBox) -> Vec<usize> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.bounding_box().map_or(true, |b| b.overlaps(region)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Indices of the objects whose bounds may be visible within `frustum`.
+    pub fn objects_in_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.bounding_box().map_or(true, |b| frustum.intersects(b)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RaycastHit {
+    pub distance: f32,
+    pub point: V3,
+    pub normal: V3,
+}
+
+/// Cheap geometric queries for scene logic such as ground snapping and line of sight. Any
+/// `Intersect` can be queried, so a `Model` kept between frames serves as a persistent
+/// collision structure, and a `World` can be queried before its BVH is built.
+pub trait Raycast: Intersect {
+    fn raycast(&self, origin: V3, direction: V3, max_distance: f32) -> Option<RaycastHit> {
+        let direction = direction.unit();
+        let hit = self.intersect(Ray::new(origin, direction), 0.0001, max_distance)?;
+
+        Some(RaycastHit {
+            distance: hit.t,
+            point: hit.point,
+            normal: hit.normal,
+        })
+    }
+
+    fn line_of_sight(&self, from: V3, to: V3) -> bool {
+        let offset = to - from;
+        let distance = offset.length();
+        distance == 0.0 || self.raycast(from, offset, distance).is_none()
+    }
+
+    /// The height of the first surface below `point`, searching at most `max_drop` down.
+    fn ground_height(&self, point: V3, max_drop: f32) -> Option<f32> {
+        self.raycast(point, V3::new(0.0, -1.0, 0.0), max_drop)
+            .map(|hit| hit.point.y())
+    }
+}
+
+impl<I: Intersect + ?Sized> Raycast for I {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Pick {
+    pub object: usize,
+    pub t: f32,
+    pub point: V3,
+    pub normal: V3,
+}
+
+/// The volume visible to a camera, bounded by six inward facing planes.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [(V3, f32); 6],
+}
+
+impl Frustum {
+    fn from_corners(origin: V3, corners: [V3; 4], near: f32, far: f32) -> Self {
+        let forward = corners.iter().fold(V3::zero(), |sum, &c| sum + c).unit();
+        let plane = |normal: V3, point: V3| {
+            let normal = if normal.dot(forward) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            (normal, -normal.dot(point))
+        };
+
+        let [bottom_left, bottom_right, top_left, top_right] = corners;
+
+        Self {
+            planes: [
+                plane(top_left.cross(bottom_left), origin),
+                plane(bottom_right.cross(top_right), origin),
+                plane(bottom_left.cross(bottom_right), origin),
+                plane(top_right.cross(top_left), origin),
+                plane(forward, origin + forward * near),
+                plane(-forward, origin + forward * far),
+            ],
+        }
+    }
+
+    /// A conservative test, boxes near the frustum's corners may be reported as overlapping.
+    pub fn intersects(&self, bounds: BoundingBox) -> bool {
+        self.planes.iter().all(|&(normal, distance)| {
+            bounds
+                .corners()
+                .any(|corner| normal.dot(corner) + distance >= 0.0)
+        })
+    }
+
+    pub fn contains(&self, point: V3) -> bool {
+        self.planes
+            .iter()
+            .all(|&(normal, distance)| normal.dot(point) + distance >= 0.0)
     }
 }
 
@@ -126,16 +1204,49 @@ impl<B: Background> Background for World<B> {
     fn background(&self, ray: Ray) -> V3 {
         self.background.background(ray)
     }
+
+    fn camera_background(&self, ray: Ray) -> V3 {
+        match self.blurred_background.as_ref() {
+            Some(blurred) => blurred.background(ray),
+            None => self.background.camera_background(ray),
+        }
+    }
+
+    fn camera_alpha(&self, ray: Ray) -> f32 {
+        self.background.camera_alpha(ray)
+    }
+
+    fn light_group(&self) -> usize {
+        self.background.light_group()
+    }
+}
+
+impl<B: Background> Lights for World<B> {
+    fn lights(&self) -> &LightList {
+        &self.lights
+    }
 }
 
 impl<B: Background> Intersect for World<B> {
     fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+        let ray = TraversalRay::new(ray);
+
+        if let Some(bvh) = self.bvh.as_ref() {
+            return bvh.intersect_traversal(&ray, t_min, t_max);
+        }
+
         let mut found_hit = None;
         let mut closest_so_far = t_max;
 
-        for obj in &self.objects {
-            if let Some(hit) = obj.intersect(ray, t_min, closest_so_far) {
+        for (index, (obj, visibility)) in
+            self.objects.iter().zip(self.visibility.iter()).enumerate()
+        {
+            if !visibility.sees(ray.ray.kind) {
+                continue;
+            }
+            if let Some(mut hit) = obj.intersect_traversal(&ray, t_min, closest_so_far) {
                 closest_so_far = hit.t;
+                hit.object = Some(index);
                 found_hit = Some(hit);
             }
         }
@@ -144,6 +1255,10 @@ impl<B: Background> Intersect for World<B> {
     }
 
     fn bounding_box(&self) -> Option<BoundingBox> {
+        if let Some(bvh) = self.bvh.as_ref() {
+            return bvh.bounding_box();
+        }
+
         if self.objects.len() == 0 {
             None
         } else {
@@ -163,20 +1278,327 @@ impl<B: Background> Intersect for World<B> {
             group_box
         }
     }
+
+    fn primitive_count(&self) -> usize {
+        self.objects.iter().map(|o| o.primitive_count()).sum()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        for object in self.objects.iter() {
+            object.visit_triangles(visit);
+        }
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self.bvh.as_ref().map_or(0, |bvh| bvh.memory_usage().bytes)
+            + self.objects.iter().map(|o| o.memory_bytes()).sum::<usize>()
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct Ray {
     pub origin: V3,
     pub direction: V3,
+    pub time: f32,
+    /// Decides which stochastically faded instances this ray sees, drawn once per camera path.
+    pub fade: f32,
+    /// What the ray is looking for, objects can be hidden from some kinds of rays.
+    pub kind: RayKind,
+    /// The index of the world object the ray left from, lights not linked to it aren't seen
+    /// along the ray.
+    pub source: Option<usize>,
+    /// Width of the cone of rays this one stands for at its origin, like the ray differentials
+    /// of a pixel, textures are filtered over the width it grows to.
+    pub footprint: f32,
+    /// How much the footprint widens per unit of distance travelled.
+    pub spread: f32,
 }
 
 impl Ray {
     pub fn new(origin: V3, direction: V3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+            fade: 0.0,
+            kind: RayKind::Camera,
+            source: None,
+            footprint: 0.0,
+            spread: 0.0,
+        }
+    }
+
+    pub fn with_time(mut self, time: f32) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_fade(mut self, fade: f32) -> Self {
+        self.fade = fade;
+        self
+    }
+
+    pub fn with_kind(mut self, kind: RayKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_source(mut self, source: Option<usize>) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_footprint(mut self, footprint: f32) -> Self {
+        self.footprint = footprint;
+        self
+    }
+
+    pub fn with_spread(mut self, spread: f32) -> Self {
+        self.spread = spread;
+        self
     }
 
     pub fn at(&self, t: f32) -> V3 {
         self.origin + (self.direction * t)
     }
+
+    /// The width of the ray's footprint at `t`.
+    pub fn footprint_at(&self, t: f32) -> f32 {
+        self.footprint + self.spread * t * self.direction.length()
+    }
+}
+
+/// The purpose of a ray, picked by how it was spawned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RayKind {
+    /// Leaving the camera, or any query that isn't part of a light path.
+    Camera,
+    /// Testing whether a light sample is blocked.
+    Shadow,
+    /// Scattered by a glossy, mirror or transmission lobe.
+    Reflection,
+    /// Scattered by a diffuse lobe, carrying indirect light.
+    Diffuse,
+}
+
+impl RayKind {
+    /// The kind of ray a surface scatters through `lobe`.
+    pub fn scattered(lobe: Lobe) -> Self {
+        match lobe {
+            Lobe::Diffuse => RayKind::Diffuse,
+            Lobe::Glossy | Lobe::Transmission => RayKind::Reflection,
+        }
+    }
+}
+
+/// Which kinds of rays see an object, objects are visible to every kind unless restricted.
+/// Hiding an object from the camera while it still blocks light, or from shadows while it is
+/// still seen, are common lighting tricks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub reflection: bool,
+    pub diffuse: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Self {
+            camera: true,
+            shadow: true,
+            reflection: true,
+            diffuse: true,
+        }
+    }
+}
+
+impl Visibility {
+    pub fn with_camera(self, camera: bool) -> Self {
+        Self { camera, ..self }
+    }
+
+    pub fn with_shadow(self, shadow: bool) -> Self {
+        Self { shadow, ..self }
+    }
+
+    pub fn with_reflection(self, reflection: bool) -> Self {
+        Self { reflection, ..self }
+    }
+
+    pub fn with_diffuse(self, diffuse: bool) -> Self {
+        Self { diffuse, ..self }
+    }
+
+    /// Whether rays of `kind` see the object.
+    pub fn sees(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Reflection => self.reflection,
+            RayKind::Diffuse => self.diffuse,
+        }
+    }
+}
+
+/// An object of the world as it is placed in the BVH, tagging its hits with its index and
+/// hidden from the kinds of rays its visibility excludes.
+struct WorldObject {
+    object: Arc<dyn Intersect>,
+    index: usize,
+    visibility: Visibility,
+}
+
+impl Intersect for WorldObject {
+    fn intersect(&self, ray: Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.intersect_traversal(&TraversalRay::new(ray), t_min, t_max)
+    }
+
+    fn intersect_traversal(&self, ray: &TraversalRay, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        if !self.visibility.sees(ray.ray.kind) {
+            return None;
+        }
+        let mut hit = self.object.intersect_traversal(ray, t_min, t_max)?;
+        hit.object = Some(self.index);
+        Some(hit)
+    }
+
+    fn bounding_box(&self) -> Option<BoundingBox> {
+        self.object.bounding_box()
+    }
+
+    fn primitive_count(&self) -> usize {
+        self.object.primitive_count()
+    }
+
+    fn visit_triangles(&self, visit: &mut dyn FnMut([V3; 3], &dyn Material)) {
+        self.object.visit_triangles(visit)
+    }
+
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of_val(self) + self.object.memory_bytes()
+    }
+}
+
+/// A ray prepared for acceleration structure traversal, carrying its reciprocal direction and
+/// the sign of each component so bounding box slab tests need only multiplies.
+#[derive(Copy, Clone, Debug)]
+pub struct TraversalRay {
+    pub ray: Ray,
+    pub inv_direction: V3,
+    pub negative: [bool; 3],
+}
+
+impl TraversalRay {
+    pub fn new(ray: Ray) -> Self {
+        let inv_direction = 1.0 / ray.direction;
+        let negative = [
+            inv_direction.x() < 0.0,
+            inv_direction.y() < 0.0,
+            inv_direction.z() < 0.0,
+        ];
+
+        Self {
+            ray,
+            inv_direction,
+            negative,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::Sphere;
+    use crate::light::SpotLight;
+    use crate::material::{
+        DiffuseLight, Lambertian, Metal, SolidBackground, SplitBackground, TransparentBackground,
+    };
+    use crate::math::V4;
+    use crate::texture::SolidColor;
+
+    /// A metal sphere lit by a spot light beside the camera, so light sampling finds the
+    /// highlight on its front.
+    fn metal_under_light() -> (World<SolidBackground>, Camera) {
+        let mut world = World::new(SolidBackground::new(V3::zero()));
+        world.add(Sphere::new(
+            Metal::new(0.5, SolidColor(V4::one())),
+            V3::zero(),
+            1.0,
+        ));
+        world.add_sampled_light(SpotLight::new(
+            DiffuseLight::new(V3::fill(10.0)),
+            V3::new(0.0, 1.0, 4.0),
+            V3::new(0.0, -1.0, -4.0),
+            0.5,
+            0.6,
+        ));
+
+        let camera = Camera::new(
+            20.0,
+            V3::new(0.0, 0.0, 3.0),
+            V3::zero(),
+            V3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            3.0,
+        );
+        (world, camera)
+    }
+
+    #[test]
+    fn light_sampled_metal_is_glossy_direct() {
+        let (world, camera) = metal_under_light();
+        let diffuse_direct = COMPONENT_NAMES
+            .iter()
+            .position(|&name| name == "diffuse_direct")
+            .unwrap();
+        let glossy_direct = COMPONENT_NAMES
+            .iter()
+            .position(|&name| name == "glossy_direct")
+            .unwrap();
+
+        let mut glossy = V3::zero();
+        for y in -2..=2 {
+            for x in -2..=2 {
+                let direction = V3::new(x as f32 * 0.05, y as f32 * 0.05, -1.0);
+                let ray = Ray::new(V3::new(0.0, 0.0, 3.0), direction);
+                let (components, _) = camera.trace_components(&world, ray, 4, &mut RandomSampler);
+                assert_eq!(components[diffuse_direct], V3::zero());
+                glossy += components[glossy_direct];
+            }
+        }
+        assert!(!glossy.near_zero(), "no light reached the glossy lobe");
+    }
+
+    #[test]
+    fn transparent_camera_background_has_zero_alpha() {
+        let mut world = World::new(SplitBackground::new(
+            TransparentBackground,
+            SolidBackground::new(V3::one()),
+        ));
+        world.add(Sphere::new(
+            Lambertian::new(SolidColor(V4::one())),
+            V3::zero(),
+            1.0,
+        ));
+        let camera = Camera::new(
+            20.0,
+            V3::new(0.0, 0.0, 3.0),
+            V3::zero(),
+            V3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            3.0,
+        );
+
+        let past = Ray::new(V3::new(0.0, 0.0, 3.0), V3::new(1.0, 0.0, -1.0));
+        assert_eq!(camera.alpha(&world, past), 0.0);
+        assert_eq!(world.camera_background(past), V3::zero());
+        assert_eq!(world.background(past), V3::one());
+
+        let through = Ray::new(V3::new(0.0, 0.0, 3.0), V3::new(0.0, 0.0, -1.0));
+        assert_eq!(camera.alpha(&world, through), 1.0);
+    }
 }